@@ -1,7 +1,9 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
 use std::fs::{copy, create_dir_all, read_dir, File};
 use std::io;
 use std::io::BufRead;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
 fn get_version_from_solidity_source() -> String {
     let contracts_path = std::env::var("CONTRACTS_DIR").expect(
@@ -23,6 +25,210 @@ fn get_version_from_solidity_source() -> String {
     panic!("Failed to load contract version from the sol file!");
 }
 
+// Typed bindings of the contract ABIs. Every ABI file gets a module with a
+// `Contract` marker (implementing `abi::AbiContract`), a struct of arguments
+// for each function (implementing `abi::AbiFunction`), a struct of its
+// outputs and a struct for each event (implementing `abi::AbiEvent`).
+// Tuples become nested structs named after the path to them, so a function
+// or a parameter renamed in the contracts breaks the build of the call sites
+// instead of a call on the network.
+
+const RUST_KEYWORDS: &[&str] = &[
+    "as", "async", "await", "box", "break", "const", "continue", "crate", "do", "dyn", "else",
+    "enum", "extern", "false", "final", "fn", "for", "if", "impl", "in", "let", "loop", "macro",
+    "match", "mod", "move", "mut", "override", "priv", "pub", "ref", "return", "static", "struct",
+    "super", "trait", "true", "try", "type", "typeof", "unsafe", "unsized", "use", "virtual",
+    "where", "while", "yield",
+];
+
+fn to_snake_case(name: &str) -> String {
+    let mut snake = String::new();
+    for (i, c) in name.chars().enumerate() {
+        if c.is_ascii_uppercase() {
+            if i > 0 && !snake.ends_with('_') {
+                snake.push('_');
+            }
+            snake.push(c.to_ascii_lowercase());
+        } else {
+            snake.push(c);
+        }
+    }
+    if RUST_KEYWORDS.contains(&snake.as_str()) {
+        snake.insert_str(0, "r#");
+    }
+    snake
+}
+
+/// `getAddrDao` -> `GetAddrDao`, a leading underscore is kept to tell
+/// `_deployBranch` from `deployBranch`
+fn to_camel_case(name: &str) -> String {
+    let prefix = if name.starts_with('_') { "_" } else { "" };
+    let camel: String = name
+        .split('_')
+        .filter(|part| !part.is_empty())
+        .map(|part| {
+            let mut chars = part.chars();
+            let first = chars.next().unwrap().to_ascii_uppercase();
+            std::iter::once(first).chain(chars).collect::<String>()
+        })
+        .collect();
+    format!("{prefix}{camel}")
+}
+
+/// Splits `map(K,V)` arguments at the top level comma
+fn split_map_args(args: &str) -> (&str, &str) {
+    let mut depth = 0;
+    for (i, c) in args.char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' => depth -= 1,
+            ',' if depth == 0 => return (&args[..i], &args[i + 1..]),
+            _ => {}
+        }
+    }
+    panic!("Malformed ABI type: map({args})");
+}
+
+struct Bindings {
+    code: String,
+}
+
+impl Bindings {
+    /// Rust type of the ABI type, structs for tuples are generated on the way
+    fn rust_type(
+        &mut self,
+        abi_type: &str,
+        param: &serde_json::Value,
+        struct_name: &str,
+    ) -> String {
+        if let Some(inner) = abi_type.strip_suffix("[]") {
+            return format!("Vec<{}>", self.rust_type(inner, param, struct_name));
+        }
+        if let Some(inner) = abi_type
+            .strip_prefix("optional(")
+            .and_then(|t| t.strip_suffix(')'))
+        {
+            return format!("Option<{}>", self.rust_type(inner, param, struct_name));
+        }
+        if let Some(args) = abi_type
+            .strip_prefix("map(")
+            .and_then(|t| t.strip_suffix(')'))
+        {
+            let (_, value) = split_map_args(args);
+            // keys of JSON objects are always strings
+            return format!(
+                "std::collections::BTreeMap<String, {}>",
+                self.rust_type(value, param, struct_name)
+            );
+        }
+        match abi_type {
+            "tuple" => {
+                let components = param["components"]
+                    .as_array()
+                    .unwrap_or_else(|| panic!("Tuple {struct_name} without components"));
+                self.write_struct(struct_name, components);
+                struct_name.to_owned()
+            }
+            "address" => "crate::blockchain::BlockchainContractAddress".to_owned(),
+            "bool" => "bool".to_owned(),
+            "string" | "bytes" | "cell" => "String".to_owned(),
+            _ => match abi_type.strip_prefix("uint").map(str::parse::<u32>) {
+                // the SDK returns integers as strings, larger ones don't fit u64
+                Some(Ok(bits)) if bits <= 64 => "crate::blockchain::NumberU64".to_owned(),
+                _ => "String".to_owned(),
+            },
+        }
+    }
+
+    fn write_struct(&mut self, name: &str, params: &[serde_json::Value]) {
+        let mut fields = String::new();
+        for param in params {
+            let param_name = param["name"].as_str().expect("ABI parameter name");
+            let param_type = param["type"].as_str().expect("ABI parameter type");
+            let nested_name = format!("{name}{}", to_camel_case(param_name));
+            let rust_type = self.rust_type(param_type, param, &nested_name);
+            writeln!(fields, "    #[serde(rename = \"{param_name}\")]").unwrap();
+            writeln!(
+                fields,
+                "    pub {}: {rust_type},",
+                to_snake_case(param_name)
+            )
+            .unwrap();
+        }
+        writeln!(
+            self.code,
+            "#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]\npub struct {name} {{\n{fields}}}\n"
+        )
+        .unwrap();
+    }
+
+    fn write_contract(&mut self, file_name: &str, abi_path: &Path, abi: &serde_json::Value) {
+        let module = to_snake_case(&file_name.trim_end_matches(".abi.json").replace('-', "_"));
+        writeln!(self.code, "pub mod {module} {{").unwrap();
+        writeln!(
+            self.code,
+            "pub struct Contract;\n\nimpl crate::abi::AbiContract for Contract {{\n    const FILE: &'static str = \"{file_name}\";\n    const ABI: &'static str = include_str!({:?});\n}}\n",
+            abi_path.display().to_string()
+        )
+        .unwrap();
+        let params = |item: &serde_json::Value, key: &str| -> Vec<serde_json::Value> {
+            item[key].as_array().cloned().unwrap_or_default()
+        };
+        for function in abi["functions"].as_array().into_iter().flatten() {
+            let name = function["name"].as_str().expect("ABI function name");
+            let struct_name = to_camel_case(name);
+            self.write_struct(&struct_name, &params(function, "inputs"));
+            self.write_struct(
+                &format!("{struct_name}Output"),
+                &params(function, "outputs"),
+            );
+            writeln!(
+                self.code,
+                "impl crate::abi::AbiFunction for {struct_name} {{\n    type Contract = Contract;\n    const NAME: &'static str = \"{name}\";\n    type Output = {struct_name}Output;\n}}\n"
+            )
+            .unwrap();
+        }
+        for event in abi["events"].as_array().into_iter().flatten() {
+            let name = event["name"].as_str().expect("ABI event name");
+            let struct_name = format!("{}Event", to_camel_case(name));
+            self.write_struct(&struct_name, &params(event, "inputs"));
+            writeln!(
+                self.code,
+                "impl crate::abi::AbiEvent for {struct_name} {{\n    type Contract = Contract;\n    const NAME: &'static str = \"{name}\";\n}}\n"
+            )
+            .unwrap();
+        }
+        writeln!(self.code, "}}\n").unwrap();
+    }
+}
+
+fn generate_bindings(resources: &Path) {
+    let mut abi_files: BTreeMap<String, (PathBuf, serde_json::Value)> = BTreeMap::new();
+    for entry in read_dir(resources).expect("read resources directory") {
+        let entry = entry.expect("read resources directory");
+        let file_name = entry.file_name().to_str().unwrap().to_owned();
+        if file_name.ends_with(".abi.json") {
+            let abi = std::fs::read(entry.path()).expect("read abi");
+            let abi = serde_json::from_slice(&abi)
+                .unwrap_or_else(|e| panic!("Failed to parse {file_name}: {e}"));
+            let path = entry
+                .path()
+                .canonicalize()
+                .unwrap_or_else(|e| panic!("Failed to locate {file_name}: {e}"));
+            abi_files.insert(file_name, (path, abi));
+        }
+    }
+    let mut bindings = Bindings {
+        code: String::new(),
+    };
+    for (file_name, (path, abi)) in abi_files {
+        bindings.write_contract(&file_name, &path, &abi);
+    }
+    let out_dir = std::env::var("OUT_DIR").expect("OUT_DIR is set by cargo");
+    std::fs::write(Path::new(&out_dir).join("abi_bindings.rs"), bindings.code)
+        .expect("write abi bindings");
+}
+
 fn main() {
     let resources = Path::new("./resources");
     create_dir_all(resources).expect("create resources directory");
@@ -36,7 +242,10 @@ fn main() {
         }
     }
 
+    generate_bindings(resources);
+
     println!("cargo:rerun-if-env-changed=CONTRACTS_DIR");
+    println!("cargo:rerun-if-changed={abi_dir}");
     println!("cargo:rerun-if-changed=.cargo/config.toml");
     println!("cargo:rerun-if-env-changed=GOSH_SUPPORTED_CONTRACT_VERSION");
     let mut supported_versions = get_version_from_solidity_source();
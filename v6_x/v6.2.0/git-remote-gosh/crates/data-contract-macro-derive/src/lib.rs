@@ -1,61 +1,259 @@
+//! `#[derive(DataContract)]` generates loaders of a type from the state of
+//! its contract:
+//!
+//! ```ignore
+//! #[derive(Deserialize, DataContract)]
+//! #[abi = "commit.abi.json"]
+//! #[abi_data_fn = "getCommit"]
+//! #[abi_getter(method = load_tree_address, function = "gettree", output = GetTreeResult)]
+//! pub struct GoshCommit { .. }
+//! ```
+//!
+//! - `abi` is the ABI file in `resources/`, `abi_data_fn` is the getter that
+//!   returns the type. They give `load`, `load_with_boc` and `load_many`;
+//! - every `abi_getter` adds `<method>` and `<method>_with_boc` that return
+//!   `output` of another getter of the same contract;
+//! - `#[abi_cached]` makes loads reuse any state of the account from the
+//!   account cache. Use it only for contracts whose state never changes
+//!   after the deploy.
+
 extern crate proc_macro;
-extern crate syn;
-#[macro_use]
-extern crate quote;
 
 use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::{format_ident, quote};
+use syn::{parse_macro_input, Attribute, DeriveInput, Expr, ExprLit, Ident, Lit, LitStr, Type};
 
-#[proc_macro_derive(DataContract, attributes(abi, abi_data_fn))]
+#[proc_macro_derive(DataContract, attributes(abi, abi_data_fn, abi_getter, abi_cached))]
 pub fn data_contract(input: TokenStream) -> TokenStream {
-    // Construct a string representation of the type definition
-    let s = input.to_string();
+    let input = parse_macro_input!(input as DeriveInput);
+    DataContract::parse(&input)
+        .map(|data_contract| data_contract.expand())
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
 
-    // Parse the string representation
-    let ast = syn::parse_derive_input(&s).unwrap();
+struct Getter {
+    method: Ident,
+    function: LitStr,
+    output: Type,
+}
 
-    // Build the impl
-    let gen = impl_data_contract(&ast);
+struct DataContract {
+    name: Ident,
+    abi: LitStr,
+    data_fn: LitStr,
+    getters: Vec<Getter>,
+    cached: bool,
+}
 
-    // Return the generated impl
-    gen.parse().unwrap()
+const GETTER_EXAMPLE: &str =
+    "#[abi_getter(method = load_name, function = \"getName\", output = GetNameResult)]";
+
+fn string_value(attr: &Attribute, name: &str) -> syn::Result<LitStr> {
+    let value = &attr.meta.require_name_value()?.value;
+    match value {
+        Expr::Lit(ExprLit {
+            lit: Lit::Str(value),
+            ..
+        }) => Ok(value.clone()),
+        _ => Err(syn::Error::new_spanned(
+            value,
+            format!("expected a string: #[{name} = \"...\"]"),
+        )),
+    }
 }
 
-fn require_attribute<'a>(ast: &'a syn::DeriveInput, name: &str) -> &'a syn::Lit {
-    let attribute = &ast
-        .attrs
-        .iter()
-        .find(|e| e.name() == name)
-        .expect(&format!("Named attribute {} is required", name));
-    if let syn::MetaItem::NameValue(_, attribute_value) = &attribute.value {
-        return attribute_value;
-    } else {
-        panic!("Attribute {} must have a value", name);
+fn set_once<T>(slot: &mut Option<T>, value: T, attr: &Attribute, name: &str) -> syn::Result<()> {
+    if slot.is_some() {
+        return Err(syn::Error::new_spanned(
+            attr,
+            format!("duplicate #[{name}] attribute"),
+        ));
     }
+    *slot = Some(value);
+    Ok(())
+}
+
+fn parse_getter(attr: &Attribute) -> syn::Result<Getter> {
+    let (mut method, mut function, mut output) = (None, None, None);
+    attr.parse_nested_meta(|meta| {
+        if meta.path.is_ident("method") {
+            method = Some(meta.value()?.parse::<Ident>()?);
+        } else if meta.path.is_ident("function") {
+            function = Some(meta.value()?.parse::<LitStr>()?);
+        } else if meta.path.is_ident("output") {
+            output = Some(meta.value()?.parse::<Type>()?);
+        } else {
+            return Err(meta.error(format!(
+                "unknown key of #[abi_getter], expected `method`, `function` or `output`: {GETTER_EXAMPLE}"
+            )));
+        }
+        Ok(())
+    })?;
+    let missing = |key: &str| {
+        syn::Error::new_spanned(
+            attr,
+            format!("#[abi_getter] requires `{key}`: {GETTER_EXAMPLE}"),
+        )
+    };
+    Ok(Getter {
+        method: method.ok_or_else(|| missing("method"))?,
+        function: function.ok_or_else(|| missing("function"))?,
+        output: output.ok_or_else(|| missing("output"))?,
+    })
 }
 
-fn impl_data_contract(ast: &syn::DeriveInput) -> quote::Tokens {
-    let name = &ast.ident;
-    let abi = require_attribute(ast, "abi");
-    let abi_data_fn = require_attribute(ast, "abi_data_fn");
-
-    quote! {
-        impl #name {
-            pub async fn load(
-                context: &std::sync::Arc<::ton_client::ClientContext>,
-                address: &crate::blockchain::BlockchainContractAddress,
-            ) -> anyhow::Result<#name>
-            {
-                let abi = include_str!(concat!(env!("CARGO_MANIFEST_DIR"), "/resources/", #abi));
-                let contract = crate::blockchain::contract::GoshContract::new(address, (#abi, abi));
-                let content = crate::blockchain::contract::ContractRead::read_state(
+impl DataContract {
+    fn parse(input: &DeriveInput) -> syn::Result<Self> {
+        if !input.generics.params.is_empty() {
+            return Err(syn::Error::new_spanned(
+                &input.generics,
+                "#[derive(DataContract)] doesn't support generic types",
+            ));
+        }
+        let (mut abi, mut data_fn, mut getters, mut cached) = (None, None, vec![], false);
+        for attr in &input.attrs {
+            if attr.path().is_ident("abi") {
+                set_once(&mut abi, string_value(attr, "abi")?, attr, "abi")?;
+            } else if attr.path().is_ident("abi_data_fn") {
+                let value = string_value(attr, "abi_data_fn")?;
+                set_once(&mut data_fn, value, attr, "abi_data_fn")?;
+            } else if attr.path().is_ident("abi_getter") {
+                getters.push(parse_getter(attr)?);
+            } else if attr.path().is_ident("abi_cached") {
+                attr.meta.require_path_only().map_err(|_| {
+                    syn::Error::new_spanned(attr, "#[abi_cached] takes no arguments")
+                })?;
+                cached = true;
+            }
+        }
+        let abi = abi.ok_or_else(|| {
+            syn::Error::new(
+                input.ident.span(),
+                "#[derive(DataContract)] requires the ABI file of the contract: #[abi = \"<name>.abi.json\"]",
+            )
+        })?;
+        let data_fn = data_fn.ok_or_else(|| {
+            syn::Error::new(
+                input.ident.span(),
+                "#[derive(DataContract)] requires the getter that returns the type: #[abi_data_fn = \"<function>\"]",
+            )
+        })?;
+        Ok(Self {
+            name: input.ident.clone(),
+            abi,
+            data_fn,
+            getters,
+            cached,
+        })
+    }
+
+    /// Reads `output` of `function` from the current state of the account
+    fn read(&self, function: &LitStr, output: TokenStream2) -> TokenStream2 {
+        let abi = &self.abi;
+        let read = if self.cached {
+            quote! { contract.run_static(context, #function, None).await }
+        } else {
+            quote! {
+                crate::blockchain::contract::ContractRead::read_state(
                     &contract,
                     context,
-                    #abi_data_fn,
-                    None
-                ).await?;
-                let obj = ::serde_json::from_value::<#name>(content)
-                    .map_err(|e| anyhow::Error::from(e));
-                return obj;
+                    #function,
+                    None,
+                )
+                .await
+            }
+        };
+        quote! {
+            let abi = include_str!(concat!(env!("CARGO_MANIFEST_DIR"), "/resources/", #abi));
+            let contract = crate::blockchain::contract::GoshContract::new(address, (#abi, abi));
+            let result: anyhow::Result<#output> = #read;
+            result
+        }
+    }
+
+    /// Reads `output` of `function` from the given state of the account
+    fn read_with_boc(&self, function: &LitStr, output: TokenStream2) -> TokenStream2 {
+        let abi = &self.abi;
+        quote! {
+            let abi = include_str!(concat!(env!("CARGO_MANIFEST_DIR"), "/resources/", #abi));
+            let contract = crate::blockchain::contract::GoshContract::new(address, (#abi, abi));
+            let result: anyhow::Result<#output> = contract
+                .read_state_with_boc(context, account_boc, #function, None)
+                .await;
+            result
+        }
+    }
+
+    fn expand(&self) -> TokenStream2 {
+        let name = &self.name;
+        let read = self.read(&self.data_fn, quote!(Self));
+        let read_with_boc = self.read_with_boc(&self.data_fn, quote!(Self));
+        let getters = self.getters.iter().map(|getter| {
+            let Getter {
+                method,
+                function,
+                output,
+            } = getter;
+            let method_with_boc = format_ident!("{}_with_boc", method);
+            let read = self.read(function, quote!(#output));
+            let read_with_boc = self.read_with_boc(function, quote!(#output));
+            quote! {
+                pub async fn #method(
+                    context: &crate::blockchain::EverClient,
+                    address: &crate::blockchain::BlockchainContractAddress,
+                ) -> anyhow::Result<#output> {
+                    #read
+                }
+
+                pub async fn #method_with_boc(
+                    context: &crate::blockchain::EverClient,
+                    address: &crate::blockchain::BlockchainContractAddress,
+                    account_boc: &str,
+                ) -> anyhow::Result<#output> {
+                    #read_with_boc
+                }
+            }
+        });
+
+        quote! {
+            impl #name {
+                pub async fn load(
+                    context: &crate::blockchain::EverClient,
+                    address: &crate::blockchain::BlockchainContractAddress,
+                ) -> anyhow::Result<Self> {
+                    #read
+                }
+
+                pub async fn load_with_boc(
+                    context: &crate::blockchain::EverClient,
+                    address: &crate::blockchain::BlockchainContractAddress,
+                    account_boc: &str,
+                ) -> anyhow::Result<Self> {
+                    #read_with_boc
+                }
+
+                /// Loads states of all accounts in batches, accounts that
+                /// don't exist are missing in the result
+                pub async fn load_many(
+                    context: &crate::blockchain::EverClient,
+                    addresses: &[crate::blockchain::BlockchainContractAddress],
+                ) -> anyhow::Result<
+                    std::collections::HashMap<crate::blockchain::BlockchainContractAddress, Self>,
+                > {
+                    let bocs = crate::blockchain::load_accounts_bocs(context, addresses).await?;
+                    let loads = bocs.iter().map(|(address, boc)| async move {
+                        let value = Self::load_with_boc(context, address, boc).await?;
+                        anyhow::Ok((address.clone(), value))
+                    });
+                    Ok(::futures::future::try_join_all(loads)
+                        .await?
+                        .into_iter()
+                        .collect())
+                }
+
+                #(#getters)*
             }
         }
     }
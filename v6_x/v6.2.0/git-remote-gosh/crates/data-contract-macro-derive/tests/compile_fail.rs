@@ -0,0 +1,5 @@
+#[test]
+fn ensure_malformed_attributes_fail_to_compile() {
+    let cases = trybuild::TestCases::new();
+    cases.compile_fail("tests/ui/*.rs");
+}
@@ -0,0 +1,9 @@
+use data_contract_macro_derive::DataContract;
+
+#[derive(DataContract)]
+#[abi = "snapshot.abi.json"]
+#[abi_data_fn = "getSnapshot"]
+#[abi_cached(forever)]
+pub struct Snapshot {}
+
+fn main() {}
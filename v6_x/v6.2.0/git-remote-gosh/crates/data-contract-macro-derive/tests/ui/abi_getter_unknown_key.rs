@@ -0,0 +1,9 @@
+use data_contract_macro_derive::DataContract;
+
+#[derive(DataContract)]
+#[abi = "commit.abi.json"]
+#[abi_data_fn = "getCommit"]
+#[abi_getter(method = load_tree_address, function = "gettree", result = GetTreeResult)]
+pub struct GoshCommit {}
+
+fn main() {}
@@ -1,44 +1,55 @@
-macro_rules! abi {
-    ($file: expr) => {
-        // NOTE: Run `make copy_abi` in case of `No such file...` error
-        ($file, include_str!(concat!("../resources/", $file)))
-    };
+type Abi = (&'static str, &'static str);
+
+/// Contract of an ABI file, implemented by the `Contract` marker of every
+/// module of `bindings`
+pub trait AbiContract {
+    /// ABI file of the contract, matches `GoshContract::pretty_name`
+    const FILE: &'static str;
+    const ABI: &'static str;
 }
 
-type Abi = (&'static str, &'static str);
+/// Function of a contract, implemented by the arguments structs of `bindings`
+pub trait AbiFunction: serde::Serialize {
+    /// Contract the function is declared in
+    type Contract: AbiContract;
+    const NAME: &'static str;
+    type Output: serde::de::DeserializeOwned + Send + 'static;
+}
 
-pub static DAO: Abi = abi!("goshdao.abi.json");
-pub static GOSH: Abi = abi!("systemcontract.abi.json");
-pub static WALLET: Abi = abi!("goshwallet.abi.json");
-pub static REPO: Abi = abi!("repository.abi.json");
-pub static COMMIT: Abi = abi!("commit.abi.json");
-pub static SNAPSHOT: Abi = abi!("snapshot.abi.json");
-pub static TREE: Abi = abi!("tree.abi.json");
-pub static DIFF: Abi = abi!("diff.abi.json");
-pub static VERSION_CONTROLLER: Abi = abi!("versioncontroller.abi.json");
-pub static TAG: Abi = abi!("tag.abi.json");
+/// Event of a contract, implemented by the event structs of `bindings`
+pub trait AbiEvent: serde::de::DeserializeOwned {
+    type Contract: AbiContract;
+    const NAME: &'static str;
+}
 
-#[derive(serde::Serialize)]
-struct GetCommitAddrArgs {
-    #[serde(rename = "nameCommit")]
-    pub name_commit: String,
+/// Typed arguments, outputs and events of the contracts, generated by
+/// `build.rs` from the ABI files. E.g. `bindings::repository::GetAddrBranch`
+#[allow(clippy::all, dead_code, non_camel_case_types)]
+pub mod bindings {
+    include!(concat!(env!("OUT_DIR"), "/abi_bindings.rs"));
+}
+
+const fn abi<C: AbiContract>() -> Abi {
+    (C::FILE, C::ABI)
 }
 
+pub static DAO: Abi = abi::<bindings::goshdao::Contract>();
+pub static GOSH: Abi = abi::<bindings::systemcontract::Contract>();
+pub static WALLET: Abi = abi::<bindings::goshwallet::Contract>();
+pub static REPO: Abi = abi::<bindings::repository::Contract>();
+pub static COMMIT: Abi = abi::<bindings::commit::Contract>();
+pub static SNAPSHOT: Abi = abi::<bindings::snapshot::Contract>();
+pub static TREE: Abi = abi::<bindings::tree::Contract>();
+pub static DIFF: Abi = abi::<bindings::diff::Contract>();
+pub static VERSION_CONTROLLER: Abi = abi::<bindings::versioncontroller::Contract>();
+pub static TAG: Abi = abi::<bindings::tag::Contract>();
+
 #[derive(serde::Serialize)]
 struct GetBlobAddrArgs {
     #[serde(rename = "nameBlob")]
     pub name_blob: String,
 }
 
-pub fn get_commit_addr_args(sha: &str) -> Option<serde_json::Value> {
-    Some(
-        serde_json::to_value(GetCommitAddrArgs {
-            name_commit: sha.to_owned(),
-        })
-        .unwrap(),
-    )
-}
-
 pub fn get_blob_addr_args(kind: &str, sha: &str) -> Option<serde_json::Value> {
     Some(
         serde_json::to_value(GetBlobAddrArgs {
@@ -16,6 +16,7 @@ fn shutdown(result: anyhow::Result<()>) -> ExitCode {
             ExitCode::FAILURE
         }
     };
+    git_remote_gosh::git_helper::save_endpoint_health();
     shutdown_tracer_provider();
     return exit_code;
 }
@@ -70,6 +71,30 @@ async fn main_internal() -> anyhow::Result<()> {
             Command::new("supported_contract_version")
                 .about("Get list of supported contract version"),
         )
+        .subcommand(
+            Command::new("discard_push_journal")
+                .about("Discard the journal of an interrupted push")
+                .arg(Arg::new("git_dir").help("Path to the git directory (default: $GIT_DIR or .git)")),
+        )
+        .subcommand(
+            Command::new("export_bundle")
+                .about("Export the repository to a bundle that can be cloned without network")
+                .arg(
+                    Arg::new("url")
+                        .required(true)
+                        .help("Remote url, e.g. gosh://0:.../dao/repo"),
+                )
+                .arg(
+                    Arg::new("path")
+                        .required(true)
+                        .help("Path to the bundle file"),
+                ),
+        )
+        .subcommand(
+            Command::new("doctor")
+                .about("Probe network endpoints and show their health")
+                .arg(Arg::new("network").help("Network to check (default: all known networks)")),
+        )
         .get_matches();
 
     match matches.subcommand() {
@@ -79,6 +104,29 @@ async fn main_internal() -> anyhow::Result<()> {
                 supported_contract_version
             );
         }
+        Some(("discard_push_journal", sub_matches)) => {
+            let git_dir = sub_matches
+                .get_one::<String>("git_dir")
+                .map(|s| s.to_string())
+                .or_else(|| std::env::var("GIT_DIR").ok())
+                .unwrap_or(".git".to_string());
+            if git_remote_gosh::git_helper::discard_push_journal(&git_dir)? {
+                println!("Push journal in {git_dir} was discarded");
+            } else {
+                println!("No push journal found in {git_dir}");
+            }
+        }
+        Some(("export_bundle", sub_matches)) => {
+            let url = sub_matches.get_one::<String>("url").unwrap();
+            let path = sub_matches.get_one::<String>("path").unwrap();
+            git_remote_gosh::git_helper::export_bundle(config, url, path).await?;
+        }
+        Some(("doctor", sub_matches)) => {
+            let network = sub_matches.get_one::<String>("network").map(|s| s.as_str());
+            for line in git_remote_gosh::git_helper::doctor(config, network).await? {
+                println!("{line}");
+            }
+        }
         _ => {
             if matches.get_flag("version") {
                 return Ok(());
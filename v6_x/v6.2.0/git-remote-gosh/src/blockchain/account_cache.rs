@@ -0,0 +1,291 @@
+//! States of accounts read by getters. States of trees and tags never
+//! change and are cached permanently, other states are reused while the last
+//! transaction of the account is the same, or within
+//! `GOSH_ACCOUNT_CACHE_TTL_SEC` of the last check.
+
+use super::{consistency, contract::GoshContract, gosh_abi, BlockchainContractAddress, EverClient};
+use crate::bundle;
+use crate::utilities::{env::parse_env_or, stats};
+use lru::LruCache;
+use once_cell::sync::Lazy;
+use std::num::NonZeroUsize;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tracing_futures::Instrument;
+use ton_client::net::ParamsOfQueryCollection;
+
+const GOSH_ACCOUNT_CACHE_SIZE: &str = "GOSH_ACCOUNT_CACHE_SIZE";
+const GOSH_ACCOUNT_CACHE_TTL_SEC: &str = "GOSH_ACCOUNT_CACHE_TTL_SEC";
+const DEFAULT_CACHE_SIZE: usize = 10000;
+const DEFAULT_CACHE_TTL: Duration = Duration::from_secs(0);
+
+static ACCOUNT_CACHE: Lazy<Mutex<AccountCache>> = Lazy::new(|| {
+    let size_limit =
+        parse_env_or(GOSH_ACCOUNT_CACHE_SIZE, DEFAULT_CACHE_SIZE).unwrap_or_else(|e| {
+            tracing::trace!("{e}");
+            DEFAULT_CACHE_SIZE
+        });
+    Mutex::new(AccountCache::new(size_limit))
+});
+
+fn get_cache_ttl() -> Duration {
+    parse_env_or(GOSH_ACCOUNT_CACHE_TTL_SEC, DEFAULT_CACHE_TTL).unwrap_or(DEFAULT_CACHE_TTL)
+}
+
+/// How recent the state returned by the cache must be
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Freshness {
+    /// Any cached state, for getters that return the same on every state
+    Any,
+    /// Permanently cached states of immutable contracts, the last state
+    /// of others
+    Current,
+}
+
+#[derive(Debug, Clone)]
+struct CachedAccount {
+    boc: String,
+    last_trans_lt: Option<String>,
+    immutable: bool,
+    checked_at: Instant,
+}
+
+struct AccountCache {
+    /// `None` when the cache is disabled
+    accounts: Option<LruCache<BlockchainContractAddress, CachedAccount>>,
+}
+
+impl AccountCache {
+    fn new(size_limit: usize) -> Self {
+        Self {
+            accounts: NonZeroUsize::new(size_limit).map(LruCache::new),
+        }
+    }
+
+    /// State that can be used without a check
+    fn get(
+        &mut self,
+        address: &BlockchainContractAddress,
+        freshness: Freshness,
+        ttl: Duration,
+    ) -> Option<&CachedAccount> {
+        let account = self.accounts.as_mut()?.get(address)?;
+        if freshness == Freshness::Any || account.immutable || account.checked_at.elapsed() < ttl {
+            Some(account)
+        } else {
+            None
+        }
+    }
+
+    fn last_trans_lt(&self, address: &BlockchainContractAddress) -> Option<String> {
+        self.accounts.as_ref()?.peek(address)?.last_trans_lt.clone()
+    }
+
+    /// Marks the state as the current one and returns it
+    fn confirm(&mut self, address: &BlockchainContractAddress) -> Option<String> {
+        let account = self.accounts.as_mut()?.get_mut(address)?;
+        account.checked_at = Instant::now();
+        Some(account.boc.clone())
+    }
+
+    fn insert(
+        &mut self,
+        address: BlockchainContractAddress,
+        boc: String,
+        last_trans_lt: Option<String>,
+        immutable: bool,
+    ) {
+        if let Some(accounts) = self.accounts.as_mut() {
+            let account = CachedAccount {
+                boc,
+                last_trans_lt,
+                immutable,
+                checked_at: Instant::now(),
+            };
+            accounts.put(address, account);
+        }
+    }
+
+    fn remove(&mut self, address: &BlockchainContractAddress) {
+        if let Some(accounts) = self.accounts.as_mut() {
+            accounts.pop(address);
+        }
+    }
+}
+
+// `acc_type` of deployed accounts
+const ACTIVE: u64 = 1;
+
+/// States of trees and tags are cached permanently
+pub fn is_immutable(contract: &GoshContract) -> bool {
+    [gosh_abi::TREE.0, gosh_abi::TAG.0].contains(&contract.pretty_name.as_str())
+}
+
+pub(crate) fn lt_of(account: &serde_json::Value) -> Option<String> {
+    account["last_trans_lt"].as_str().map(|lt| lt.to_owned())
+}
+
+/// Saves the state loaded elsewhere by `context`, e.g. by a batch query
+pub fn insert(
+    context: &EverClient,
+    address: &BlockchainContractAddress,
+    boc: &str,
+    last_trans_lt: Option<String>,
+    immutable: bool,
+) {
+    if consistency::is_endpoint_client(context) {
+        return;
+    }
+    ACCOUNT_CACHE
+        .lock()
+        .unwrap()
+        .insert(address.clone(), boc.to_owned(), last_trans_lt, immutable);
+}
+
+/// Drops the state, the next read loads it from the network
+pub fn invalidate(address: &BlockchainContractAddress) {
+    ACCOUNT_CACHE.lock().unwrap().remove(address);
+}
+
+/// State of the account, with `changed_since` only when its last
+/// transaction is not that one
+async fn query_account(
+    context: &EverClient,
+    address: &BlockchainContractAddress,
+    changed_since: Option<&str>,
+) -> anyhow::Result<Option<serde_json::Value>> {
+    let mut filter = serde_json::json!({
+        "id": { "eq": address }
+    });
+    if let Some(last_trans_lt) = changed_since {
+        filter["last_trans_lt"] = serde_json::json!({ "ne": last_trans_lt });
+    }
+    let query = bundle::query_accounts(
+        context,
+        ParamsOfQueryCollection {
+            collection: "accounts".to_owned(),
+            filter: Some(filter),
+            result: "id boc last_trans_lt acc_type".to_owned(),
+            limit: Some(1),
+            order: None,
+        },
+    )
+    .instrument(info_span!("account_cache sdk::query_collection").or_current())
+    .await?;
+    Ok(query.into_iter().next())
+}
+
+/// State of the account, `None` when the account doesn't exist
+#[instrument(level = "trace", skip_all)]
+pub async fn account_boc(
+    context: &EverClient,
+    contract: &GoshContract,
+    freshness: Freshness,
+) -> anyhow::Result<Option<String>> {
+    let address = &contract.address;
+    if consistency::is_endpoint_client(context) {
+        return match query_account(context, address, None).await? {
+            Some(account) => Ok(Some(boc_of(address, &account)?)),
+            None => Ok(None),
+        };
+    }
+    let cached_lt = {
+        let mut cache = ACCOUNT_CACHE.lock().unwrap();
+        if let Some(account) = cache.get(address, freshness, get_cache_ttl()) {
+            tracing::trace!("Account cache hit: {address}");
+            stats::record_account_cache_hit();
+            return Ok(Some(account.boc.clone()));
+        }
+        cache.last_trans_lt(address)
+    };
+    let mut account = query_account(context, address, cached_lt.as_deref()).await?;
+    if let (None, Some(cached_lt)) = (&account, &cached_lt) {
+        // nothing newer than the cached state
+        if let Some(boc) = ACCOUNT_CACHE.lock().unwrap().confirm(address) {
+            tracing::trace!("Account cache hit: {address} at {cached_lt}");
+            stats::record_account_cache_hit();
+            return Ok(Some(boc));
+        }
+        // evicted meanwhile
+        account = query_account(context, address, None).await?;
+    }
+    tracing::trace!("Account cache miss: {address}");
+    stats::record_account_cache_miss();
+    let account = match account {
+        Some(account) => account,
+        None => return Ok(None),
+    };
+    let boc = match boc_of(address, &account) {
+        Ok(boc) => boc,
+        Err(e) => {
+            // e.g. the account was deleted since it was cached
+            invalidate(address);
+            return Err(e);
+        }
+    };
+    // accounts that are not deployed yet will change
+    let immutable = is_immutable(contract) && account["acc_type"].as_u64() == Some(ACTIVE);
+    insert(context, address, &boc, lt_of(&account), immutable);
+    Ok(Some(boc))
+}
+
+fn boc_of(
+    address: &BlockchainContractAddress,
+    account: &serde_json::Value,
+) -> anyhow::Result<String> {
+    match account["boc"].as_str() {
+        Some(boc) => Ok(boc.to_owned()),
+        None => anyhow::bail!("account with address {address} does not contain boc"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn address(index: usize) -> BlockchainContractAddress {
+        BlockchainContractAddress::new(format!("0:{index:064}"))
+    }
+
+    #[test]
+    fn ensure_states_are_reused_by_freshness_and_evicted() {
+        let ttl = Duration::from_secs(0);
+        let mut cache = AccountCache::new(3);
+        cache.insert(address(0), "tag".to_owned(), Some("0x1".to_owned()), true);
+        cache.insert(address(1), "repo".to_owned(), Some("0x2".to_owned()), false);
+
+        assert!(cache.get(&address(0), Freshness::Current, ttl).is_some());
+        assert!(cache.get(&address(1), Freshness::Any, ttl).is_some());
+        // mutable states are checked unless they were checked recently
+        assert!(cache.get(&address(1), Freshness::Current, ttl).is_none());
+        assert!(
+            cache
+                .get(&address(1), Freshness::Current, Duration::from_secs(60))
+                .is_some()
+        );
+        assert_eq!(cache.last_trans_lt(&address(1)), Some("0x2".to_owned()));
+        assert_eq!(cache.confirm(&address(1)), Some("repo".to_owned()));
+
+        // the least recently used state is evicted
+        cache.insert(address(2), "tree".to_owned(), None, true);
+        cache.insert(address(3), "tree".to_owned(), None, true);
+        assert!(cache.get(&address(0), Freshness::Any, ttl).is_none());
+        assert!(cache.get(&address(1), Freshness::Any, ttl).is_some());
+
+        cache.remove(&address(1));
+        assert!(cache.get(&address(1), Freshness::Any, ttl).is_none());
+
+        let mut disabled = AccountCache::new(0);
+        disabled.insert(address(0), "tree".to_owned(), None, true);
+        assert!(disabled.get(&address(0), Freshness::Any, ttl).is_none());
+    }
+
+    #[test]
+    fn ensure_commits_are_not_cached_permanently() {
+        // `setCommit` changes the state of a deployed commit
+        assert!(!is_immutable(&GoshContract::new(address(0), gosh_abi::COMMIT)));
+        assert!(is_immutable(&GoshContract::new(address(0), gosh_abi::TREE)));
+        assert!(is_immutable(&GoshContract::new(address(0), gosh_abi::TAG)));
+        assert!(!is_immutable(&GoshContract::new(address(0), gosh_abi::REPO)));
+    }
+}
@@ -0,0 +1,457 @@
+//! Addresses of repository objects derived locally from the code cells of
+//! the repository, salted the way `GoshLib` does it. Derived code is checked
+//! against the getter of the repository, kinds that don't match keep using it.
+
+use super::{
+    calculate_boc_hash, calculate_contract_address,
+    contract::{GoshContract, TypedContract},
+    gosh_abi, load_accounts_bocs, BlockchainContractAddress, ContractKind, EverClient, ZERO_SHA,
+};
+use crate::abi::bindings::repository;
+use crate::git_helper::supported_contract_version;
+use once_cell::sync::Lazy;
+use std::collections::HashMap;
+use std::sync::Arc;
+use tokio::sync::RwLock;
+use ton_client::{
+    abi::{
+        decode_account_data, encode_boc, AbiParam, ParamsOfAbiEncodeBoc,
+        ParamsOfDecodeAccountData, ResultOfAbiEncodeBoc, ResultOfDecodeAccountData,
+    },
+    boc::{
+        parse_account, set_code_salt, ParamsOfParse, ParamsOfSetCodeSalt, ResultOfParse,
+        ResultOfSetCodeSalt,
+    },
+};
+
+// keys of the code cells in `_code` of the repository (`modifiers.sol`)
+const COMMIT_CODE: &str = "2";
+const TAG_CODE: &str = "4";
+const SNAPSHOT_CODE: &str = "5";
+const TREE_CODE: &str = "6";
+const DIFF_CODE: &str = "7";
+
+type Repository = TypedContract<repository::Contract>;
+
+/// Salted codes by the repository, only kinds that passed the check
+type DeployCodes = HashMap<ContractKind, String>;
+
+static DEPLOY_CODES: Lazy<RwLock<HashMap<BlockchainContractAddress, Arc<DeployCodes>>>> =
+    Lazy::new(|| RwLock::new(HashMap::new()));
+
+async fn encode_cell(
+    context: &EverClient,
+    params: &[(&str, &str)],
+    data: serde_json::Value,
+) -> anyhow::Result<String> {
+    let params = params
+        .iter()
+        .map(|(name, param_type)| AbiParam {
+            name: name.to_string(),
+            param_type: param_type.to_string(),
+            ..Default::default()
+        })
+        .collect();
+    let ResultOfAbiEncodeBoc { boc } = encode_boc(
+        Arc::clone(context),
+        ParamsOfAbiEncodeBoc {
+            params,
+            data,
+            boc_cache: None,
+        },
+    )
+    .await?;
+    Ok(boc)
+}
+
+/// Salt of `buildSnapshotCode`: the repository and the version
+async fn repo_version_cell(
+    context: &EverClient,
+    repo_addr: &BlockchainContractAddress,
+    version: &str,
+) -> anyhow::Result<String> {
+    encode_cell(
+        context,
+        &[("repo", "address"), ("version", "string")],
+        serde_json::json!({ "repo": repo_addr, "version": version }),
+    )
+    .await
+}
+
+/// Salt of `buildCommitCode` and friends: a hash of the given cell
+async fn hash_cell(context: &EverClient, cell: &str) -> anyhow::Result<String> {
+    let hash = calculate_boc_hash(context, cell).await?;
+    encode_cell(
+        context,
+        &[("hash", "uint256")],
+        serde_json::json!({ "hash": format!("0x{hash}") }),
+    )
+    .await
+}
+
+async fn salt_code(context: &EverClient, code: &str, salt: String) -> anyhow::Result<String> {
+    let ResultOfSetCodeSalt { code } = set_code_salt(
+        Arc::clone(context),
+        ParamsOfSetCodeSalt {
+            code: code.to_owned(),
+            salt,
+            boc_cache: None,
+        },
+    )
+    .await?;
+    Ok(code)
+}
+
+/// Original code cells stored by the repository
+async fn load_repo_codes(
+    context: &EverClient,
+    repo_addr: &BlockchainContractAddress,
+) -> anyhow::Result<serde_json::Value> {
+    let boc = load_accounts_bocs(context, &[repo_addr.clone()])
+        .await?
+        .remove(repo_addr)
+        .ok_or_else(|| anyhow::format_err!("Repository {repo_addr} is not deployed"))?;
+    let ResultOfParse { parsed } =
+        parse_account(Arc::clone(context), ParamsOfParse { boc }).await?;
+    let data = parsed["data"]
+        .as_str()
+        .ok_or_else(|| anyhow::format_err!("Repository {repo_addr} has no data"))?
+        .to_owned();
+    let ResultOfDecodeAccountData { mut data } = decode_account_data(
+        Arc::clone(context),
+        ParamsOfDecodeAccountData {
+            abi: ton_client::abi::Abi::Json(gosh_abi::REPO.1.to_owned()),
+            data,
+            allow_partial: true,
+        },
+    )
+    .await?;
+    Ok(data["_code"].take())
+}
+
+async fn build_deploy_codes(
+    context: &EverClient,
+    repo_addr: &BlockchainContractAddress,
+) -> anyhow::Result<DeployCodes> {
+    let codes = load_repo_codes(context, repo_addr).await?;
+    let code = |key: &str| {
+        codes[key]
+            .as_str()
+            .map(|code| code.to_owned())
+            .ok_or_else(|| anyhow::format_err!("Repository {repo_addr} has no code {key}"))
+    };
+    let version = supported_contract_version();
+    let repo_version = repo_version_cell(context, repo_addr, &version).await?;
+    let repo_version_hash = hash_cell(context, &repo_version).await?;
+    let version_cell = encode_cell(
+        context,
+        &[("version", "string")],
+        serde_json::json!({ "version": version }),
+    )
+    .await?;
+    let version_hash = hash_cell(context, &version_cell).await?;
+
+    let mut deploy_codes = DeployCodes::new();
+    for (kind, key, salt) in [
+        (ContractKind::Commit, COMMIT_CODE, &repo_version_hash),
+        (ContractKind::Diff, DIFF_CODE, &repo_version_hash),
+        (ContractKind::Tag, TAG_CODE, &repo_version_hash),
+        (ContractKind::Snapshot, SNAPSHOT_CODE, &repo_version),
+        (ContractKind::Tree, TREE_CODE, &version_hash),
+    ] {
+        let salted = salt_code(context, &code(key)?, salt.to_owned()).await?;
+        deploy_codes.insert(kind, salted);
+    }
+    Ok(deploy_codes)
+}
+
+/// Compares addresses derived from the codes with the getters of the
+/// repository and drops the codes that give other addresses
+async fn check_deploy_codes(
+    context: &EverClient,
+    repo_addr: &BlockchainContractAddress,
+    deploy_codes: &mut DeployCodes,
+) -> anyhow::Result<()> {
+    let repo_contract = Repository::new(repo_addr);
+    for kind in [
+        ContractKind::Commit,
+        ContractKind::Tree,
+        ContractKind::Snapshot,
+        ContractKind::Diff,
+        ContractKind::Tag,
+    ] {
+        let code = match deploy_codes.get(&kind) {
+            Some(code) => code.to_owned(),
+            None => continue,
+        };
+        let is_valid = if let ContractKind::Tag = kind {
+            let expected = repo_contract
+                .run_static_fn(context, repository::GetTagCode {})
+                .await?
+                .value0;
+            calculate_boc_hash(context, &code).await?
+                == calculate_boc_hash(context, &expected).await?
+        } else {
+            let (initial_data, expected) = probe(context, &repo_contract, kind).await?;
+            calculate_contract_address(context, kind, &code, Some(initial_data)).await? == expected
+        };
+        if !is_valid {
+            tracing::trace!("Code of {kind:?} derived for {repo_addr} doesn't match the getter");
+            deploy_codes.remove(&kind);
+        }
+    }
+    Ok(())
+}
+
+/// Static variables of a sample object and its address from the getter
+async fn probe(
+    context: &EverClient,
+    repo_contract: &Repository,
+    kind: ContractKind,
+) -> anyhow::Result<(serde_json::Value, BlockchainContractAddress)> {
+    let repo_addr = repo_contract.address();
+    Ok(match kind {
+        ContractKind::Commit => (
+            commit_data(ZERO_SHA),
+            get_commit_addr(context, repo_contract, ZERO_SHA).await?,
+        ),
+        ContractKind::Tree => (
+            tree_data(repo_addr, "0x0"),
+            get_tree_addr(context, repo_contract, "0x0").await?,
+        ),
+        ContractKind::Snapshot => (
+            snapshot_data(ZERO_SHA, ""),
+            get_snapshot_addr(context, repo_contract, ZERO_SHA, "").await?,
+        ),
+        ContractKind::Diff => (
+            diff_data(ZERO_SHA, 0, 0),
+            get_diff_addr(context, repo_contract, ZERO_SHA, 0, 0).await?,
+        ),
+        _ => anyhow::bail!("No address getter for {kind:?}"),
+    })
+}
+
+/// Salted code of the kind, `None` when it can't be derived
+async fn deploy_code(
+    context: &EverClient,
+    repo_addr: &BlockchainContractAddress,
+    kind: ContractKind,
+) -> anyhow::Result<Option<String>> {
+    if let Some(codes) = DEPLOY_CODES.read().await.get(repo_addr) {
+        return Ok(codes.get(&kind).cloned());
+    }
+    let mut cache = DEPLOY_CODES.write().await;
+    if let Some(codes) = cache.get(repo_addr) {
+        return Ok(codes.get(&kind).cloned());
+    }
+    let mut codes = match build_deploy_codes(context, repo_addr).await {
+        Ok(codes) => codes,
+        Err(e) => {
+            tracing::trace!("Codes of {repo_addr} are not derived: {e}");
+            DeployCodes::new()
+        }
+    };
+    check_deploy_codes(context, repo_addr, &mut codes).await?;
+    tracing::trace!("Derived codes of {repo_addr}: {:?}", codes.keys());
+    let code = codes.get(&kind).cloned();
+    cache.insert(repo_addr.clone(), Arc::new(codes));
+    Ok(code)
+}
+
+async fn derive(
+    context: &EverClient,
+    repo_addr: &BlockchainContractAddress,
+    kind: ContractKind,
+    initial_data: serde_json::Value,
+) -> anyhow::Result<Option<BlockchainContractAddress>> {
+    match deploy_code(context, repo_addr, kind).await? {
+        Some(code) => Ok(Some(
+            calculate_contract_address(context, kind, &code, Some(initial_data)).await?,
+        )),
+        None => Ok(None),
+    }
+}
+
+fn commit_data(commit_sha: &str) -> serde_json::Value {
+    serde_json::json!({ "_nameCommit": commit_sha })
+}
+
+fn tree_data(repo_addr: &BlockchainContractAddress, sha_inner_tree: &str) -> serde_json::Value {
+    serde_json::json!({ "_shaInnerTree": sha_inner_tree, "_repo": repo_addr })
+}
+
+fn snapshot_data(commit_sha: &str, file_path: &str) -> serde_json::Value {
+    serde_json::json!({ "NameOfFile": file_path, "_baseCommit": commit_sha })
+}
+
+fn diff_data(commit_sha: &str, index1: u32, index2: u32) -> serde_json::Value {
+    serde_json::json!({ "_nameCommit": commit_sha, "_index1": index1, "_index2": index2 })
+}
+
+async fn get_commit_addr(
+    context: &EverClient,
+    repo_contract: &Repository,
+    commit_sha: &str,
+) -> anyhow::Result<BlockchainContractAddress> {
+    let args = repository::GetCommitAddr {
+        name_commit: commit_sha.to_owned(),
+    };
+    Ok(repo_contract.run_static_fn(context, args).await?.value0)
+}
+
+async fn get_tree_addr(
+    context: &EverClient,
+    repo_contract: &Repository,
+    sha_inner_tree: &str,
+) -> anyhow::Result<BlockchainContractAddress> {
+    let args = repository::GetTreeAddr {
+        shainnertree: sha_inner_tree.to_owned(),
+    };
+    Ok(repo_contract.run_static_fn(context, args).await?.value0)
+}
+
+async fn get_snapshot_addr(
+    context: &EverClient,
+    repo_contract: &Repository,
+    commit_sha: &str,
+    file_path: &str,
+) -> anyhow::Result<BlockchainContractAddress> {
+    let args = repository::GetSnapshotAddr {
+        commitsha: commit_sha.to_owned(),
+        name: file_path.to_owned(),
+    };
+    Ok(repo_contract.run_static_fn(context, args).await?.value0)
+}
+
+async fn get_diff_addr(
+    context: &EverClient,
+    repo_contract: &Repository,
+    commit_sha: &str,
+    index1: u32,
+    index2: u32,
+) -> anyhow::Result<BlockchainContractAddress> {
+    let args = repository::GetDiffAddr {
+        commit_name: commit_sha.to_owned(),
+        index1: index1.to_string(),
+        index2: index2.to_string(),
+    };
+    Ok(repo_contract.run_static_fn(context, args).await?.value0)
+}
+
+#[instrument(level = "trace", skip_all)]
+pub async fn commit_address(
+    context: &EverClient,
+    repo_contract: &GoshContract,
+    commit_sha: &str,
+) -> anyhow::Result<BlockchainContractAddress> {
+    let repo_addr = &repo_contract.address;
+    let initial_data = commit_data(commit_sha);
+    match derive(context, repo_addr, ContractKind::Commit, initial_data).await? {
+        Some(address) => Ok(address),
+        None => get_commit_addr(context, &Repository::new(repo_addr), commit_sha).await,
+    }
+}
+
+#[instrument(level = "trace", skip_all)]
+pub async fn tree_address(
+    context: &EverClient,
+    repo_contract: &GoshContract,
+    sha_inner_tree: &str,
+) -> anyhow::Result<BlockchainContractAddress> {
+    let repo_addr = &repo_contract.address;
+    let initial_data = tree_data(repo_addr, sha_inner_tree);
+    match derive(context, repo_addr, ContractKind::Tree, initial_data).await? {
+        Some(address) => Ok(address),
+        None => get_tree_addr(context, &Repository::new(repo_addr), sha_inner_tree).await,
+    }
+}
+
+#[instrument(level = "trace", skip_all)]
+pub async fn snapshot_address(
+    context: &EverClient,
+    repo_contract: &GoshContract,
+    commit_sha: &str,
+    file_path: &str,
+) -> anyhow::Result<BlockchainContractAddress> {
+    let repo_addr = &repo_contract.address;
+    let initial_data = snapshot_data(commit_sha, file_path);
+    match derive(context, repo_addr, ContractKind::Snapshot, initial_data).await? {
+        Some(address) => Ok(address),
+        None => {
+            let repo_contract = Repository::new(repo_addr);
+            get_snapshot_addr(context, &repo_contract, commit_sha, file_path).await
+        }
+    }
+}
+
+#[instrument(level = "trace", skip_all)]
+pub async fn diff_address(
+    context: &EverClient,
+    repo_contract: &GoshContract,
+    commit_sha: &str,
+    index1: u32,
+    index2: u32,
+) -> anyhow::Result<BlockchainContractAddress> {
+    let repo_addr = &repo_contract.address;
+    let initial_data = diff_data(commit_sha, index1, index2);
+    match derive(context, repo_addr, ContractKind::Diff, initial_data).await? {
+        Some(address) => Ok(address),
+        None => {
+            let repo_contract = Repository::new(repo_addr);
+            get_diff_addr(context, &repo_contract, commit_sha, index1, index2).await
+        }
+    }
+}
+
+#[instrument(level = "trace", skip_all)]
+pub async fn tag_address(
+    context: &EverClient,
+    repo_addr: &BlockchainContractAddress,
+    tag_name: &str,
+) -> anyhow::Result<BlockchainContractAddress> {
+    let code = match deploy_code(context, repo_addr, ContractKind::Tag).await? {
+        Some(code) => code,
+        None => super::get_contract_code(context, repo_addr, ContractKind::Tag).await?,
+    };
+    calculate_contract_address(
+        context,
+        ContractKind::Tag,
+        &code,
+        Some(serde_json::json!({ "_nametag": tag_name })),
+    )
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ensure_static_variables_match_contract_abi() {
+        let repo_addr = BlockchainContractAddress::new(format!("0:{}", "0".repeat(64)));
+        for (abi, initial_data) in [
+            (gosh_abi::COMMIT, commit_data(ZERO_SHA)),
+            (gosh_abi::TREE, tree_data(&repo_addr, "0x0")),
+            (gosh_abi::SNAPSHOT, snapshot_data(ZERO_SHA, "README.md")),
+            (gosh_abi::DIFF, diff_data(ZERO_SHA, 0, 1)),
+        ] {
+            let name = abi.0;
+            let abi: serde_json::Value = serde_json::from_str(abi.1).unwrap();
+            let mut expected: Vec<&str> = abi["data"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|param| param["name"].as_str().unwrap())
+                .collect();
+            let mut actual: Vec<&str> = initial_data
+                .as_object()
+                .unwrap()
+                .keys()
+                .map(|key| key.as_str())
+                .collect();
+            expected.sort();
+            actual.sort();
+            assert_eq!(actual, expected, "static variables of {name}");
+        }
+    }
+}
@@ -1,8 +1,12 @@
 use super::{
-    contract::ContractInfo, BlockchainContractAddress, CallResult, Everscale, SendMessageResult,
+    concurrency::{self, Signal},
+    contract::ContractInfo,
+    error::ContractError,
+    fees, BlockchainContractAddress, CallResult, Everscale, SendMessageResult,
 };
 pub use crate::abi as gosh_abi;
 use crate::blockchain::{default_callback, BlockchainService, GoshContract};
+use crate::git_helper::endpoint_health;
 use async_trait::async_trait;
 use std::{
     sync::Arc,
@@ -87,16 +91,32 @@ impl BlockchainCall for Everscale {
         )
         .instrument(info_span!("blockchain_client::process_message").or_current())
         .await;
+        let sdk_result = endpoint_health::tracked(self.client(), sdk_result).await;
         if let Err(ref e) = sdk_result {
             tracing::trace!("process_message error: {:#?}", e);
         }
         let ResultOfProcessMessage {
             transaction, /* decoded, */
             ..
-        } = sdk_result?;
+        } = sdk_result.map_err(|e| {
+            match ContractError::from_client_error(
+                contract.get_kind(),
+                contract.get_address(),
+                function_name,
+                &e,
+            ) {
+                Some(contract_error) => anyhow::Error::from(contract_error),
+                None => anyhow::Error::from(e),
+            }
+        })?;
         let call_result: CallResult = serde_json::from_value(transaction)?;
 
-        tracing::trace!("trx id: {}", call_result.trx_id);
+        tracing::trace!(
+            "trx id: {}, total_fees: {}",
+            call_result.trx_id,
+            call_result.total_fees
+        );
+        fees::record_call(call_result.total_fees);
 
         Ok(call_result)
     }
@@ -153,10 +173,8 @@ impl BlockchainCall for Everscale {
             "sending message ({message_id}) to {}",
             contract.get_address()
         );
-        let ResultOfSendMessage {
-            shard_block_id,
-            sending_endpoints,
-        } = ton_client::processing::send_message(
+        let permit = concurrency::message_sends().acquire().await?;
+        let send_result = ton_client::processing::send_message(
             Arc::clone(self.client()),
             ParamsOfSendMessage {
                 abi: None,
@@ -166,8 +184,17 @@ impl BlockchainCall for Everscale {
             default_callback,
         )
         .instrument(info_span!("blockchain_client::send_message").or_current())
-        .await
-        .map_err(|e| anyhow::format_err!("send_message error: {e}"))?;
+        .await;
+        let send_result = endpoint_health::tracked(self.client(), send_result)
+            .await
+            .map_err(|e| anyhow::format_err!("send_message error: {e}"));
+        let latency = permit.elapsed();
+        permit.release(Signal::from_result(&send_result, latency));
+        let ResultOfSendMessage {
+            shard_block_id,
+            sending_endpoints,
+        } = send_result?;
+        fees::record_message_sent();
 
         if let Some(expected_address) = expected_address {
             let start = Instant::now();
@@ -181,6 +208,8 @@ impl BlockchainCall for Everscale {
                     break;
                 }
                 if start.elapsed() > timeout {
+                    // the message was most likely lost or expired on the way
+                    concurrency::message_sends().observe(Signal::Expired);
                     anyhow::bail!(
                         "Timeout exceeded: expected contract {expected_address} didn't appear within {}s",
                         timeout.as_secs(),
@@ -1,5 +1,6 @@
 use crate::abi as gosh_abi;
 use crate::blockchain::{AddrVersion, EverClient};
+use crate::bundle;
 use ton_client::abi::{decode_message_body, Abi, ParamsOfDecodeMessageBody};
 use ton_client::net::ParamsOfQuery;
 pub mod save;
@@ -9,9 +10,14 @@ use std::sync::Arc;
 
 use super::contract::GoshContract;
 
-#[derive(Deserialize, Debug, DataContract)]
+#[derive(Deserialize, Debug, PartialEq, DataContract)]
 #[abi = "commit.abi.json"]
 #[abi_data_fn = "getCommit"]
+#[abi_getter(
+    method = load_tree_address,
+    function = "gettree",
+    output = crate::blockchain::tree::load::GetTreeResult
+)]
 pub struct GoshCommit {
     #[serde(rename = "time")]
     _time: String,
@@ -99,8 +105,14 @@ pub async fn get_set_commit_created_at_time(
             None => "",
         };
 
-        let result = ton_client::net::query(
-            Arc::clone(context),
+        let extracted_messages = bundle::query_messages(
+            context,
+            bundle::MessagesPage {
+                address: &repo_contract.address,
+                cursor: cursor.as_deref(),
+                limit: None,
+                from_end: false,
+            },
             ParamsOfQuery {
                 query: query.clone(),
                 variables: Some(serde_json::json!({
@@ -110,12 +122,9 @@ pub async fn get_set_commit_created_at_time(
                 ..Default::default()
             },
         )
-        .await
-        .map(|r| r.result)
-        .map_err(|e| anyhow::format_err!("query error: {e}"))?;
+        .await?;
 
-        let extracted_messages = &result["data"]["blockchain"]["account"]["messages"];
-        let messages: Messages = serde_json::from_value(extracted_messages.clone())?;
+        let messages: Messages = serde_json::from_value(extracted_messages)?;
         cursor = if messages.page_info.has_next_page {
             Some(messages.page_info.end_cursor)
         } else {
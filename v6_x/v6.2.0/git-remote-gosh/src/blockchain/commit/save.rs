@@ -7,7 +7,9 @@ use crate::{
         self,
         call::BlockchainCall,
         contract::{ContractInfo, GoshContract},
+        error::ContractError,
         get_commit_address,
+        subscription::Subscription,
         user_wallet::BlockchainUserWalletService,
         BlockchainContractAddress, Everscale,
     },
@@ -16,15 +18,16 @@ use crate::{
 use anyhow::bail;
 use async_trait::async_trait;
 use git_hash::ObjectId;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::ops::Deref;
 use std::sync::Arc;
 use std::time::{Duration, Instant};
-use tokio::time::sleep;
 use ton_client::abi::{DecodedMessageBody, ParamsOfDecodeMessageBody};
 use ton_client::net::ParamsOfQuery;
 
 const GOSH_REMOTE_WAIT_TIMEOUT_ENV: &str = "GOSH_REMOTE_WAIT_TIMEOUT";
+// `status` of a transaction that is in a finalized block
+const TRX_STATUS_FINALIZED: u32 = 3;
 
 #[derive(Serialize, Debug, Deserialize)]
 pub struct DeployCommitParams {
@@ -178,6 +181,22 @@ impl BlockchainCommitPusher for Everscale {
             "numberChangedFiles": number_of_files_changed,
             "numberCommits": number_of_commits,
         });
+        let mut repo_contract = self.repo_contract.clone();
+        let commit_address = get_commit_address(
+            &self.ever_client,
+            &mut repo_contract,
+            &commit_id.to_string(),
+        )
+        .await?;
+        let commit_contract = GoshContract::new(commit_address.clone(), gosh_abi::COMMIT);
+
+        // failed messages that came before this push don't fail it
+        let stale_messages: HashSet<String> = query_all_messages(self, &commit_contract)
+            .await?
+            .into_iter()
+            .map(|message| message.id)
+            .collect();
+
         let wallet_contract = wallet.take_zero_wallet().await?;
         tracing::trace!("Acquired wallet: {}", wallet_contract.get_address());
         let result = self
@@ -195,15 +214,6 @@ impl BlockchainCommitPusher for Everscale {
         tracing::trace!("Set commit timeout: {} sec", timeout);
         let timeout = Duration::from_secs(timeout);
 
-        let mut repo_contract = self.repo_contract.clone();
-        let commit_address = get_commit_address(
-            &self.ever_client,
-            &mut repo_contract,
-            &commit_id.to_string(),
-        )
-        .await?;
-        let commit_contract = GoshContract::new(commit_address.clone(), gosh_abi::COMMIT);
-
         let mut filter = vec![
             "allCorrect".to_owned(),
             "cancelCommit".to_owned(),
@@ -213,9 +223,16 @@ impl BlockchainCommitPusher for Everscale {
             filter.push("treeAccept".to_owned());
         }
         let mut processed_messages = HashMap::new();
+        let subscription = Subscription::messages(&self.ever_client, &commit_address).await;
         loop {
-            let found =
-                find_messages(self, &commit_contract, &filter, &mut processed_messages).await?;
+            let found = find_messages(
+                self,
+                &commit_contract,
+                &filter,
+                &stale_messages,
+                &mut processed_messages,
+            )
+            .await?;
             tracing::trace!(
                 "did find new messages for {}: {}",
                 commit_contract.address,
@@ -255,7 +272,7 @@ impl BlockchainCommitPusher for Everscale {
             if start.elapsed() > timeout {
                 bail!("Time is up. Fix and retry");
             }
-            sleep(Duration::from_secs(5)).await;
+            subscription.wait(Duration::from_secs(5), start + timeout).await;
         }
         tracing::info!("Branch `{branch}` has been updated");
         Ok(())
@@ -335,6 +352,7 @@ pub async fn find_messages(
     context: &Everscale,
     contract: &GoshContract,
     filter: &Vec<String>,
+    stale_messages: &HashSet<String>,
     already_processed_messages: &mut HashMap<String, bool>,
 ) -> anyhow::Result<(Option<DecodedMessageBody>, bool)> {
     tracing::trace!(
@@ -375,7 +393,9 @@ pub async fn find_messages(
         tracing::trace!("... decoded message: {:#?}", decoded);
 
         if filter.contains(&decoded.name) {
-            let trx_status = is_transaction_ok(context, &message.id).await?;
+            let is_stale = stale_messages.contains(&message.id);
+            let trx_status =
+                is_transaction_ok(context, contract, &decoded.name, &message.id, is_stale).await?;
             if trx_status {
                 return Ok((Some(decoded.clone()), got_new_messages));
             }
@@ -384,8 +404,17 @@ pub async fn find_messages(
     Ok((None, got_new_messages))
 }
 
+/// `false` while the transaction of the message is not finalized, a failed
+/// transaction is a [`ContractError`] of `contract` in `function` unless the
+/// message is stale, i.e. came before the current push
 #[instrument(level = "info", skip_all)]
-pub async fn is_transaction_ok(context: &Everscale, msg_id: &String) -> anyhow::Result<bool> {
+pub async fn is_transaction_ok(
+    context: &Everscale,
+    contract: &GoshContract,
+    function: &str,
+    msg_id: &String,
+    is_stale: bool,
+) -> anyhow::Result<bool> {
     tracing::trace!("is_transaction_ok: msg_id={msg_id}");
     let query = r#"query($msg_id: String!) {
         transactions(filter: {
@@ -411,8 +440,60 @@ pub async fn is_transaction_ok(context: &Everscale, msg_id: &String) -> anyhow::
     .map_err(|e| anyhow::format_err!("query error: {e}"))?;
 
     let trx: Vec<TrxInfo> = serde_json::from_value(result["data"]["transactions"].clone())?;
+    transaction_status(trx.first(), contract, function, is_stale)
+}
 
-    let status = trx.len() > 0 && trx[0].status == 3 && trx[0].compute.exit_code == 0;
+fn transaction_status(
+    trx: Option<&TrxInfo>,
+    contract: &GoshContract,
+    function: &str,
+    is_stale: bool,
+) -> anyhow::Result<bool> {
+    match trx {
+        Some(trx) if trx.compute.exit_code != 0 => {
+            let code = trx.compute.exit_code as i32;
+            let error = ContractError::new(contract.kind(), &contract.address, function, code);
+            if is_stale {
+                tracing::trace!("Skipping the stale failed message: {error}");
+                Ok(false)
+            } else {
+                Err(error.into())
+            }
+        }
+        Some(trx) => Ok(trx.status == TRX_STATUS_FINALIZED),
+        None => Ok(false),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blockchain::error::exit_codes;
+
+    fn trx(status: u32, exit_code: u32) -> TrxInfo {
+        TrxInfo {
+            status,
+            compute: TrxCompute { exit_code },
+        }
+    }
 
-    Ok(status)
+    #[test]
+    fn ensure_only_failures_of_the_current_push_are_errors() {
+        let commit = GoshContract::new(BlockchainContractAddress::new("0:01"), gosh_abi::COMMIT);
+        let failed = trx(TRX_STATUS_FINALIZED, exit_codes::ERR_WRONG_BRANCH as u32);
+
+        // a failed `allCorrect` of an earlier push of the commit
+        let stale = transaction_status(Some(&failed), &commit, "allCorrect", true);
+        assert!(!stale.unwrap());
+
+        let current = transaction_status(Some(&failed), &commit, "allCorrect", false);
+        let error = current.unwrap_err();
+        let error = error.downcast_ref::<ContractError>().unwrap();
+        assert!(error.is(exit_codes::ERR_WRONG_BRANCH));
+
+        let done = trx(TRX_STATUS_FINALIZED, 0);
+        assert!(transaction_status(Some(&done), &commit, "allCorrect", false).unwrap());
+        assert!(!transaction_status(Some(&trx(1, 0)), &commit, "allCorrect", false).unwrap());
+        assert!(!transaction_status(None, &commit, "allCorrect", false).unwrap());
+    }
 }
@@ -0,0 +1,355 @@
+//! AIMD limits of message sends and state query batches: a window of fast
+//! successful operations raises the limit by one, a slow operation, expired
+//! message or endpoint error halves it, at most once per window.
+
+use once_cell::sync::Lazy;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+use super::MAX_ACCOUNTS_ADDRESSES_PER_QUERY;
+
+const GOSH_PARALLEL_SENDS: &str = "GOSH_PARALLEL_SENDS";
+const GOSH_MAX_PARALLEL_SENDS: &str = "GOSH_MAX_PARALLEL_SENDS";
+const GOSH_SEND_TARGET_LATENCY_MS: &str = "GOSH_SEND_TARGET_LATENCY_MS";
+
+const DEFAULT_PARALLEL_SENDS: usize = 1 << 6;
+const DEFAULT_MAX_PARALLEL_SENDS: usize = 1 << 9;
+const DEFAULT_SEND_TARGET_LATENCY_MS: u64 = 3000;
+
+const MIN_STATE_QUERY_BATCH: usize = 5;
+const STATE_QUERY_TARGET_LATENCY: Duration = Duration::from_secs(2);
+
+const DECREASE_COOLDOWN: Duration = Duration::from_secs(1);
+// weight of the last observation in the latency moving average
+const LATENCY_EWMA_WEIGHT: f64 = 0.2;
+
+static MESSAGE_SENDS: Lazy<Arc<AdaptiveSemaphore>> = Lazy::new(|| {
+    let max = get_max_parallel_sends();
+    let initial = get_env_usize(GOSH_PARALLEL_SENDS)
+        .unwrap_or(DEFAULT_PARALLEL_SENDS)
+        .clamp(1, max);
+    let target_latency = Duration::from_millis(
+        std::env::var(GOSH_SEND_TARGET_LATENCY_MS)
+            .ok()
+            .and_then(|num| num.parse::<u64>().ok())
+            .unwrap_or(DEFAULT_SEND_TARGET_LATENCY_MS),
+    );
+    Arc::new(AdaptiveSemaphore::new(Aimd::new(
+        "message_sends",
+        initial,
+        1,
+        max,
+        target_latency,
+    )))
+});
+
+static STATE_QUERY_BATCH: Lazy<Mutex<Aimd>> = Lazy::new(|| {
+    Mutex::new(Aimd::new(
+        "state_query_batch",
+        MAX_ACCOUNTS_ADDRESSES_PER_QUERY,
+        MIN_STATE_QUERY_BATCH,
+        MAX_ACCOUNTS_ADDRESSES_PER_QUERY,
+        STATE_QUERY_TARGET_LATENCY,
+    ))
+});
+
+fn get_env_usize(name: &str) -> Option<usize> {
+    std::env::var(name)
+        .ok()
+        .and_then(|num| num.parse::<usize>().ok())
+        .filter(|num| *num > 0)
+}
+
+/// Upper bound for the number of messages sent simultaneously
+pub fn get_max_parallel_sends() -> usize {
+    get_env_usize(GOSH_MAX_PARALLEL_SENDS).unwrap_or(DEFAULT_MAX_PARALLEL_SENDS)
+}
+
+/// Limits the number of messages being sent simultaneously
+pub fn message_sends() -> Arc<AdaptiveSemaphore> {
+    MESSAGE_SENDS.clone()
+}
+
+/// Current number of addresses requested in one `check_contracts_state` query
+pub fn state_query_batch_size() -> usize {
+    STATE_QUERY_BATCH.lock().unwrap().limit()
+}
+
+pub fn observe_state_query(signal: Signal) {
+    STATE_QUERY_BATCH.lock().unwrap().observe(signal);
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Signal {
+    Success(Duration),
+    Expired,
+    EndpointError,
+}
+
+impl Signal {
+    /// Message expiration can't be told apart from other SDK errors by type,
+    /// so it is recognized by the error text
+    pub fn from_result<T>(result: &anyhow::Result<T>, latency: Duration) -> Self {
+        match result {
+            Ok(_) => Signal::Success(latency),
+            Err(e) if format!("{e:#}").to_lowercase().contains("expired") => Signal::Expired,
+            Err(_) => Signal::EndpointError,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Aimd {
+    name: &'static str,
+    limit: usize,
+    min: usize,
+    max: usize,
+    target_latency: Duration,
+    latency_ewma: Option<Duration>,
+    successes: usize,
+    last_decrease: Option<Instant>,
+}
+
+impl Aimd {
+    fn new(
+        name: &'static str,
+        initial: usize,
+        min: usize,
+        max: usize,
+        target_latency: Duration,
+    ) -> Self {
+        Self {
+            name,
+            limit: initial.clamp(min, max),
+            min,
+            max,
+            target_latency,
+            latency_ewma: None,
+            successes: 0,
+            last_decrease: None,
+        }
+    }
+
+    pub fn limit(&self) -> usize {
+        self.limit
+    }
+
+    /// Updates the limit with the observed outcome and returns the new one
+    fn observe(&mut self, signal: Signal) -> usize {
+        match signal {
+            Signal::Success(latency) => {
+                let latency_ewma = match self.latency_ewma {
+                    None => latency,
+                    Some(prev) => {
+                        prev.mul_f64(1.0 - LATENCY_EWMA_WEIGHT)
+                            + latency.mul_f64(LATENCY_EWMA_WEIGHT)
+                    }
+                };
+                self.latency_ewma = Some(latency_ewma);
+                if latency_ewma > self.target_latency {
+                    self.decrease("latency");
+                } else {
+                    self.successes += 1;
+                    if self.successes >= self.limit {
+                        self.successes = 0;
+                        if self.limit < self.max {
+                            self.limit += 1;
+                            self.trace_state("increase");
+                        }
+                    }
+                }
+            }
+            Signal::Expired => self.decrease("message expired"),
+            Signal::EndpointError => self.decrease("endpoint error"),
+        }
+        self.limit
+    }
+
+    fn decrease(&mut self, reason: &str) {
+        if let Some(last_decrease) = self.last_decrease {
+            if last_decrease.elapsed() < DECREASE_COOLDOWN {
+                return;
+            }
+        }
+        self.last_decrease = Some(Instant::now());
+        self.successes = 0;
+        let limit = std::cmp::max(self.min, (self.limit + 1) / 2);
+        if limit != self.limit {
+            self.limit = limit;
+            self.trace_state(&format!("decrease: {reason}"));
+        }
+    }
+
+    fn trace_state(&self, event: &str) {
+        tracing::trace!(
+            "adaptive limit {}: {event}, limit={}, min={}, max={}, latency_ewma={:?}, target_latency={:?}",
+            self.name,
+            self.limit,
+            self.min,
+            self.max,
+            self.latency_ewma,
+            self.target_latency,
+        );
+    }
+}
+
+struct AdaptiveState {
+    aimd: Aimd,
+    // permits that have to be forgotten when released to reach the lowered limit
+    debt: usize,
+}
+
+/// Semaphore with the number of permits driven by [`Aimd`]
+pub struct AdaptiveSemaphore {
+    semaphore: Arc<Semaphore>,
+    state: Mutex<AdaptiveState>,
+}
+
+impl AdaptiveSemaphore {
+    fn new(aimd: Aimd) -> Self {
+        Self {
+            semaphore: Arc::new(Semaphore::new(aimd.limit())),
+            state: Mutex::new(AdaptiveState { aimd, debt: 0 }),
+        }
+    }
+
+    pub async fn acquire(self: &Arc<Self>) -> anyhow::Result<AdaptivePermit> {
+        let permit = self.semaphore.clone().acquire_owned().await?;
+        Ok(AdaptivePermit {
+            permit: Some(permit),
+            owner: self.clone(),
+            acquired_at: Instant::now(),
+        })
+    }
+
+    pub fn observe(&self, signal: Signal) {
+        let mut state = self.state.lock().unwrap();
+        let prev = state.aimd.limit();
+        let limit = state.aimd.observe(signal);
+        if limit > prev {
+            let mut extra = limit - prev;
+            let repaid = std::cmp::min(extra, state.debt);
+            state.debt -= repaid;
+            extra -= repaid;
+            self.semaphore.add_permits(extra);
+        } else if limit < prev {
+            let mut excess = prev - limit;
+            while excess > 0 {
+                match self.semaphore.try_acquire() {
+                    Ok(permit) => permit.forget(),
+                    Err(_) => break,
+                }
+                excess -= 1;
+            }
+            state.debt += excess;
+        }
+        if limit != prev {
+            tracing::trace!(
+                "adaptive semaphore {}: limit={limit}, available={}, debt={}",
+                state.aimd.name,
+                self.semaphore.available_permits(),
+                state.debt
+            );
+        }
+    }
+}
+
+/// Permit of [`AdaptiveSemaphore`]. The outcome of the guarded operation
+/// should be reported with [`AdaptivePermit::release`]
+pub struct AdaptivePermit {
+    permit: Option<OwnedSemaphorePermit>,
+    owner: Arc<AdaptiveSemaphore>,
+    acquired_at: Instant,
+}
+
+impl AdaptivePermit {
+    pub fn elapsed(&self) -> Duration {
+        self.acquired_at.elapsed()
+    }
+
+    pub fn release(self, signal: Signal) {
+        self.owner.observe(signal);
+    }
+}
+
+impl Drop for AdaptivePermit {
+    fn drop(&mut self) {
+        if let Some(permit) = self.permit.take() {
+            let mut state = self.owner.state.lock().unwrap();
+            if state.debt > 0 {
+                state.debt -= 1;
+                permit.forget();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FAST: Duration = Duration::from_millis(10);
+
+    fn aimd(initial: usize) -> Aimd {
+        Aimd::new("test", initial, 1, 8, Duration::from_secs(1))
+    }
+
+    #[test]
+    fn ensure_limit_grows_additively_and_drops_multiplicatively() {
+        let mut limit = aimd(4);
+        for _ in 0..4 {
+            limit.observe(Signal::Success(FAST));
+        }
+        assert_eq!(limit.limit(), 5);
+        for _ in 0..100 {
+            limit.observe(Signal::Success(FAST));
+        }
+        assert_eq!(limit.limit(), 8);
+
+        limit.observe(Signal::Expired);
+        assert_eq!(limit.limit(), 4);
+        // the same window of failures is counted once
+        limit.observe(Signal::EndpointError);
+        assert_eq!(limit.limit(), 4);
+
+        limit.last_decrease = None;
+        limit.observe(Signal::EndpointError);
+        assert_eq!(limit.limit(), 2);
+        limit.last_decrease = None;
+        limit.observe(Signal::EndpointError);
+        limit.last_decrease = None;
+        limit.observe(Signal::EndpointError);
+        assert_eq!(limit.limit(), 1);
+    }
+
+    #[test]
+    fn ensure_high_latency_lowers_limit() {
+        let mut limit = aimd(8);
+        limit.observe(Signal::Success(Duration::from_secs(5)));
+        assert_eq!(limit.limit(), 4);
+    }
+
+    #[tokio::test]
+    async fn ensure_semaphore_follows_limit() {
+        let semaphore = Arc::new(AdaptiveSemaphore::new(aimd(4)));
+        let mut held = vec![];
+        for _ in 0..3 {
+            held.push(semaphore.acquire().await.unwrap());
+        }
+        semaphore.observe(Signal::EndpointError);
+        // the free permit is forgotten immediately, one of the held on release
+        assert_eq!(semaphore.semaphore.available_permits(), 0);
+        assert_eq!(semaphore.state.lock().unwrap().debt, 1);
+        drop(held);
+        assert_eq!(semaphore.semaphore.available_permits(), 2);
+        assert_eq!(semaphore.state.lock().unwrap().debt, 0);
+
+        // a window of successes raises the limit by one
+        for _ in 0..2 {
+            let permit = semaphore.acquire().await.unwrap();
+            permit.release(Signal::Success(FAST));
+        }
+        assert_eq!(semaphore.semaphore.available_permits(), 3);
+    }
+}
@@ -0,0 +1,161 @@
+//! With `GOSH_CONSISTENCY_QUORUM` set, branch heads, commits and trees are
+//! read from every endpoint of the network and must be confirmed by the
+//! quorum. Diverging endpoints are reported even when the quorum is reached.
+
+use crate::blockchain::EverClient;
+use crate::utilities::stats;
+use futures::future::join_all;
+use once_cell::sync::OnceCell;
+use std::fmt::Debug;
+use std::future::Future;
+use std::sync::Arc;
+
+const GOSH_CONSISTENCY_QUORUM: &str = "GOSH_CONSISTENCY_QUORUM";
+
+static CHECKER: OnceCell<ConsistencyChecker> = OnceCell::new();
+
+#[derive(Debug, thiserror::Error)]
+#[error("Only {agreed} of {endpoints} endpoints confirmed {what}, quorum is {quorum}")]
+pub struct ConsistencyError {
+    pub what: String,
+    pub agreed: usize,
+    pub endpoints: usize,
+    pub quorum: usize,
+}
+
+/// Amount of endpoints that must agree on critical reads, verification is
+/// disabled when it is not set
+pub fn get_quorum() -> Option<usize> {
+    std::env::var(GOSH_CONSISTENCY_QUORUM)
+        .ok()
+        .and_then(|value| value.parse::<usize>().ok())
+        .filter(|quorum| *quorum > 0)
+}
+
+pub fn init(endpoint_clients: Vec<(String, EverClient)>, quorum: usize) -> anyhow::Result<()> {
+    if quorum > endpoint_clients.len() {
+        anyhow::bail!(
+            "{GOSH_CONSISTENCY_QUORUM}={quorum} is greater than the amount of endpoints ({})",
+            endpoint_clients.len()
+        );
+    }
+    tracing::trace!(
+        "Consistency checks are enabled: quorum {quorum} of {} endpoints",
+        endpoint_clients.len()
+    );
+    // the helper serves a single remote, the first initialization wins
+    let _ = CHECKER.set(ConsistencyChecker::new(endpoint_clients, quorum));
+    Ok(())
+}
+
+/// Checker of critical reads, `None` unless verification is enabled
+pub fn checker() -> Option<&'static ConsistencyChecker> {
+    CHECKER.get()
+}
+
+/// Whether `client` repeats reads for the checker. Such reads must reach
+/// the endpoint, not states cached from other endpoints
+pub fn is_endpoint_client(client: &EverClient) -> bool {
+    checker().is_some_and(|checker| checker.is_endpoint_client(client))
+}
+
+pub struct ConsistencyChecker {
+    endpoint_clients: Vec<(String, EverClient)>,
+    quorum: usize,
+}
+
+impl ConsistencyChecker {
+    fn new(endpoint_clients: Vec<(String, EverClient)>, quorum: usize) -> Self {
+        Self {
+            endpoint_clients,
+            quorum,
+        }
+    }
+
+    fn is_endpoint_client(&self, client: &EverClient) -> bool {
+        self.endpoint_clients
+            .iter()
+            .any(|(_, endpoint_client)| Arc::ptr_eq(endpoint_client, client))
+    }
+
+    /// Repeats `read` on every endpoint and compares results with `value`
+    /// returned by the main client
+    pub async fn verify<T, F, Fut>(&self, what: &str, value: &T, read: F) -> anyhow::Result<()>
+    where
+        T: PartialEq + Debug,
+        F: Fn(EverClient) -> Fut,
+        Fut: Future<Output = anyhow::Result<T>>,
+    {
+        let results = join_all(
+            self.endpoint_clients
+                .iter()
+                .map(|(_, client)| read(client.clone())),
+        )
+        .await;
+        let endpoints = self.endpoint_clients.iter().map(|(endpoint, _)| endpoint);
+        self.count_agreement(what, value, endpoints.zip(results))
+    }
+
+    fn count_agreement<'a, T>(
+        &self,
+        what: &str,
+        value: &T,
+        results: impl Iterator<Item = (&'a String, anyhow::Result<T>)>,
+    ) -> anyhow::Result<()>
+    where
+        T: PartialEq + Debug,
+    {
+        let mut agreed = 0;
+        let mut endpoints = 0;
+        for (endpoint, result) in results {
+            endpoints += 1;
+            match result {
+                Ok(endpoint_value) if &endpoint_value == value => agreed += 1,
+                Ok(endpoint_value) => {
+                    stats::record_divergence();
+                    tracing::warn!(
+                        "Endpoint {endpoint} diverges on {what}: expected {value:?}, got {endpoint_value:?}"
+                    );
+                }
+                Err(e) => {
+                    stats::record_divergence();
+                    tracing::warn!("Endpoint {endpoint} failed to confirm {what}: {e}");
+                }
+            }
+        }
+        if agreed < self.quorum {
+            return Err(ConsistencyError {
+                what: what.to_owned(),
+                agreed,
+                endpoints,
+                quorum: self.quorum,
+            }
+            .into());
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ensure_quorum_is_required() {
+        let checker = ConsistencyChecker::new(vec![], 2);
+        let endpoints = ["a".to_owned(), "b".to_owned(), "c".to_owned()];
+
+        let results = vec![Ok(1), Ok(1), Ok(2)];
+        checker
+            .count_agreement("branch heads", &1, endpoints.iter().zip(results))
+            .unwrap();
+
+        let results = vec![Ok(1), Ok(2), Err(anyhow::format_err!("timeout"))];
+        let error = checker
+            .count_agreement("branch heads", &1, endpoints.iter().zip(results))
+            .unwrap_err();
+        let error = error.downcast_ref::<ConsistencyError>().unwrap();
+        assert_eq!(error.agreed, 1);
+        assert_eq!(error.endpoints, 3);
+    }
+}
@@ -1,12 +1,20 @@
 pub mod wait_contracts_deployed;
 
-use super::{BlockchainContractAddress, EverClient, GetVersionResult};
-use crate::blockchain::{get_account_data, run_local, run_static};
+use super::{BlockchainContractAddress, ContractKind, EverClient, GetVersionResult};
+use crate::abi::{self as gosh_abi, AbiContract, AbiFunction};
+use crate::blockchain::account_cache::{account_boc, Freshness};
+use crate::blockchain::{run_local, run_local_with_boc, run_static};
 use async_trait::async_trait;
 use serde::{de, Deserialize};
 use std::fmt::Debug;
+use std::marker::PhantomData;
+use std::sync::Arc;
 
-use ton_client::{abi::Abi, crypto::KeyPair};
+use ton_client::{
+    abi::Abi,
+    boc::{parse_account, ParamsOfParse, ResultOfParse},
+    crypto::KeyPair,
+};
 
 // enum AccountType {
 //     Uninit,
@@ -23,10 +31,21 @@ pub struct ContractStatus {
     balance: u64,
 }
 
+impl ContractStatus {
+    /// Account balance in nanotokens
+    pub fn balance(&self) -> u64 {
+        self.balance
+    }
+}
+
 pub trait ContractInfo: Debug {
     fn get_abi(&self) -> &ton_client::abi::Abi;
     fn get_address(&self) -> &super::BlockchainContractAddress;
     fn get_keys(&self) -> &Option<ton_client::crypto::KeyPair>;
+    /// Kind of the contract, for decoding of its exit codes
+    fn get_kind(&self) -> Option<ContractKind> {
+        None
+    }
 }
 
 pub trait MirroredContractsPool: Debug {
@@ -134,6 +153,22 @@ impl GoshContract {
         }
     }
 
+    /// Kind of the contract by its ABI
+    pub fn kind(&self) -> Option<ContractKind> {
+        let kind = match self.pretty_name.as_str() {
+            name if name == gosh_abi::DAO.0 => ContractKind::Dao,
+            name if name == gosh_abi::WALLET.0 => ContractKind::Wallet,
+            name if name == gosh_abi::REPO.0 => ContractKind::Repo,
+            name if name == gosh_abi::COMMIT.0 => ContractKind::Commit,
+            name if name == gosh_abi::TREE.0 => ContractKind::Tree,
+            name if name == gosh_abi::SNAPSHOT.0 => ContractKind::Snapshot,
+            name if name == gosh_abi::DIFF.0 => ContractKind::Diff,
+            name if name == gosh_abi::TAG.0 => ContractKind::Tag,
+            _ => return None,
+        };
+        Some(kind)
+    }
+
     #[instrument(level = "info", skip_all)]
     pub async fn run_static<T>(
         &self,
@@ -164,6 +199,21 @@ impl GoshContract {
         Ok(serde_json::from_value::<T>(result)?)
     }
 
+    /// Same as `read_state`, but on the account state loaded beforehand
+    pub async fn read_state_with_boc<T>(
+        &self,
+        context: &EverClient,
+        account_boc: &str,
+        function_name: &str,
+        args: Option<serde_json::Value>,
+    ) -> anyhow::Result<T>
+    where
+        T: de::DeserializeOwned,
+    {
+        let result = run_local_with_boc(context, self, account_boc, function_name, args).await?;
+        Ok(serde_json::from_value::<T>(result)?)
+    }
+
     pub async fn get_version(&self, context: &EverClient) -> anyhow::Result<String> {
         let result: GetVersionResult = self.read_state(context, "getVersion", None).await?;
         tracing::trace!("get_version result: {:?}", result);
@@ -180,6 +230,98 @@ impl GoshContract {
     }
 }
 
+/// Contract with the ABI of `C`, only functions declared in that ABI can be
+/// called through it, e.g. `repository::GetHEAD` on
+/// `TypedContract<repository::Contract>`
+pub struct TypedContract<C> {
+    contract: GoshContract,
+    abi: PhantomData<fn() -> C>,
+}
+
+impl<C> Clone for TypedContract<C> {
+    fn clone(&self) -> Self {
+        Self {
+            contract: self.contract.clone(),
+            abi: PhantomData,
+        }
+    }
+}
+
+impl<C> std::fmt::Debug for TypedContract<C> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.contract.fmt(f)
+    }
+}
+
+impl<C: AbiContract> TypedContract<C> {
+    pub fn new<T>(address: T) -> Self
+    where
+        T: Into<BlockchainContractAddress>,
+    {
+        Self {
+            contract: GoshContract::new(address, (C::FILE, C::ABI)),
+            abi: PhantomData,
+        }
+    }
+
+    pub fn address(&self) -> &BlockchainContractAddress {
+        &self.contract.address
+    }
+
+    pub fn contract(&self) -> &GoshContract {
+        &self.contract
+    }
+
+    /// Functions without inputs are called without arguments
+    fn function_args<F>(function: F) -> anyhow::Result<Option<serde_json::Value>>
+    where
+        F: AbiFunction<Contract = C>,
+    {
+        let args = serde_json::to_value(function)?;
+        let is_empty = args.as_object().is_some_and(|args| args.is_empty());
+        Ok(if is_empty { None } else { Some(args) })
+    }
+
+    /// `run_static` with typed arguments and result from `abi::bindings`
+    pub async fn run_static_fn<F>(
+        &self,
+        context: &EverClient,
+        function: F,
+    ) -> anyhow::Result<F::Output>
+    where
+        F: AbiFunction<Contract = C>,
+    {
+        let args = Self::function_args(function)?;
+        self.contract.run_static(context, F::NAME, args).await
+    }
+
+    /// `run_local` with typed arguments and result from `abi::bindings`
+    pub async fn run_local_fn<F>(
+        &self,
+        context: &EverClient,
+        function: F,
+    ) -> anyhow::Result<F::Output>
+    where
+        F: AbiFunction<Contract = C>,
+    {
+        let args = Self::function_args(function)?;
+        self.contract.run_local(context, F::NAME, args).await
+    }
+
+    /// `read_state` with typed arguments and result from `abi::bindings`
+    pub async fn read_state_fn<F>(
+        &self,
+        context: &EverClient,
+        function: F,
+    ) -> anyhow::Result<F::Output>
+    where
+        F: AbiFunction<Contract = C>,
+    {
+        let args = Self::function_args(function)?;
+        self.contract.read_state(context, F::NAME, args).await
+    }
+}
+
 impl ContractInfo for GoshContract {
     fn get_abi(&self) -> &ton_client::abi::Abi {
         &self.abi
@@ -190,6 +332,9 @@ impl ContractInfo for GoshContract {
     fn get_keys(&self) -> &Option<ton_client::crypto::KeyPair> {
         &self.keys
     }
+    fn get_kind(&self) -> Option<ContractKind> {
+        self.kind()
+    }
 }
 
 #[async_trait]
@@ -211,15 +356,16 @@ impl ContractRead for GoshContract {
     }
 
     async fn load_account(&self, client: &EverClient) -> anyhow::Result<Option<ContractStatus>> {
-        let result = get_account_data(client, self).await?;
-
-        if result.is_null() {
-            Ok(None)
-        } else {
-            let result: ContractStatus =
-                serde_json::from_value(result).map_err(|e| anyhow::Error::from(e))?;
-            Ok(Some(result))
-        }
+        let boc = match account_boc(client, self, Freshness::Current).await? {
+            Some(boc) => boc,
+            None => return Ok(None),
+        };
+        let ResultOfParse { parsed } = parse_account(Arc::clone(client), ParamsOfParse { boc })
+            .await
+            .map_err(|e| anyhow::format_err!("Failed to parse account {}: {e}", self.address))?;
+        let result: ContractStatus =
+            serde_json::from_value(parsed).map_err(|e| anyhow::Error::from(e))?;
+        Ok(Some(result))
     }
 }
 
@@ -227,7 +373,8 @@ impl ContractRead for GoshContract {
 mod tests {
     use super::*;
     use crate::{
-        blockchain::{BlockchainContractAddress, GetAddrBranchResult},
+        abi::bindings::repository,
+        blockchain::{BlockchainContractAddress, BranchRef},
         config::Config,
         git_helper::ever_client::create_client,
     };
@@ -270,21 +417,39 @@ mod tests {
         }
     }
 
+    #[test]
+    fn ensure_typed_args_match_contract_abi() {
+        use crate::abi as gosh_abi;
+
+        type Repository = TypedContract<repository::Contract>;
+        assert_eq!(gosh_abi::REPO.0, "repository.abi.json");
+        let contract = Repository::new(BlockchainContractAddress::new("0:0"));
+        assert_eq!(contract.contract().pretty_name, gosh_abi::REPO.0);
+        let args = Repository::function_args(repository::GetHEAD {}).unwrap();
+        assert_eq!(args, None);
+        let args = Repository::function_args(repository::GetCommitAddr {
+            name_commit: "abc".to_owned(),
+        })
+        .unwrap();
+        assert_eq!(args, Some(json!({ "nameCommit": "abc" })));
+    }
+
     #[tokio::test]
     async fn test_dummy_contract() {
         let contract = TestDummyContract {};
         let client = Arc::new(create_client(&Config::default(), "localhost").unwrap());
-        let result: GetAddrBranchResult = contract
-            .read_state(&client, "getAddrBranch", None)
+        let result: repository::GetAddrBranchOutput = contract
+            .read_state(&client, repository::GetAddrBranch::NAME, None)
             .await
             .unwrap();
+        let branch = BranchRef::from(result.value0);
 
-        assert_eq!(result.branch.branch_name, "branch_name");
+        assert_eq!(branch.branch_name, "branch_name");
         assert_eq!(
-            result.branch.commit_address,
+            branch.commit_address,
             BlockchainContractAddress::new(format!("0:{:64}", 0))
         );
-        assert_eq!(result.branch.version, "commit_version");
+        assert_eq!(branch.version, "commit_version");
 
         let ContractStatus { status, balance } =
             contract.load_account(&client).await.unwrap().unwrap();
@@ -1,12 +1,13 @@
 use crate::blockchain::blockchain_contract_address::FormatShort;
-use crate::blockchain::{
-    BlockchainContractAddress, BlockchainService, MAX_ACCOUNTS_ADDRESSES_PER_QUERY,
-};
+use crate::blockchain::subscription::Subscription;
+use crate::blockchain::{concurrency, BlockchainContractAddress, BlockchainService};
 use std::collections::HashSet;
+use std::time::{Duration, Instant};
 use tokio::task::JoinSet;
 use tracing::Instrument;
 
-const MAX_RETRIES_FOR_DIFFS_TO_APPEAR: i32 = 20; // x 3sec
+const POLL_INTERVAL: Duration = Duration::from_secs(3);
+const MAX_RETRIES_FOR_DIFFS_TO_APPEAR: u32 = 20; // x 3sec
 
 #[instrument(level = "info", skip_all)]
 pub async fn wait_contracts_deployed<B>(
@@ -18,15 +19,18 @@ where
 {
     let mut deployment_results: JoinSet<anyhow::Result<Vec<BlockchainContractAddress>>> =
         JoinSet::new();
-    for chunk in addresses.chunks(MAX_ACCOUNTS_ADDRESSES_PER_QUERY) {
+    for chunk in addresses.chunks(concurrency::state_query_batch_size()) {
         let mut waiting_for_addresses = Vec::from(chunk);
         let b = blockchain.clone();
         deployment_results.spawn(
             async move {
+                let deadline =
+                    Instant::now() + POLL_INTERVAL * (MAX_RETRIES_FOR_DIFFS_TO_APPEAR + 1);
+                let subscription = Subscription::accounts(b.client(), &waiting_for_addresses).await;
                 let mut iteration = 0;
                 while !waiting_for_addresses.is_empty() {
                     iteration += 1;
-                    if iteration > MAX_RETRIES_FOR_DIFFS_TO_APPEAR + 1 {
+                    if Instant::now() > deadline {
                         // anyhow::bail!(
                         tracing::trace!(
                             "Some contracts didn't appear in time: {}",
@@ -40,7 +44,7 @@ where
                                 HashSet::from_iter(found_addresses.iter().cloned());
                             waiting_for_addresses.retain(|e| !available.contains(e));
                             if !waiting_for_addresses.is_empty() {
-                                tokio::time::sleep(std::time::Duration::from_secs(3)).await;
+                                subscription.wait(POLL_INTERVAL, deadline).await;
                                 tracing::trace!(
                                     "Addresses {} are not ready yet. iteration {}",
                                     waiting_for_addresses.format_short(),
@@ -49,7 +53,7 @@ where
                             }
                         }
                         Err(ref e) => {
-                            tokio::time::sleep(std::time::Duration::from_secs(3)).await;
+                            tokio::time::sleep(POLL_INTERVAL).await;
                             tracing::trace!(
                                 "State request failed with: {}. iteration {}",
                                 e,
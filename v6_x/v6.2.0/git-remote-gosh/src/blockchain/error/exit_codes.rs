@@ -0,0 +1,598 @@
+//! Exit codes of GOSH contracts, mirrors
+//! `contracts/gosh/smv/modifiers/errors.sol` of contract versions 6.0.0 - 6.2.0.
+
+use crate::blockchain::ContractKind;
+
+/// `versionErrors` of the contracts the catalogue was taken from
+pub const ERRORS_VERSION: &str = "6.2.0";
+
+// Exit codes of the ever-solidity runtime
+pub const OUT_OF_GAS: i32 = 13;
+pub const INVALID_SIGNATURE: i32 = 40;
+pub const CONSTRUCTOR_CALLED_TWICE: i32 = 51;
+pub const REPLAY_PROTECTION: i32 = 52;
+pub const MESSAGE_EXPIRED: i32 = 57;
+pub const WRONG_FUNCTION_ID: i32 = 60;
+pub const EMPTY_OPTIONAL: i32 = 63;
+pub const NOT_INITIALIZED: i32 = 76;
+
+pub const ERR_NO_SALT: i32 = 200;
+pub const ERR_SENDER_NOT_DAO: i32 = 202;
+pub const ERR_ZERO_ROOT_KEY: i32 = 203;
+pub const ERR_LOW_VALUE: i32 = 204;
+pub const ERR_NOT_ROOT_REPO: i32 = 205;
+pub const ERR_ZERO_ROOT_GOSH: i32 = 206;
+pub const ERR_INVALID_SENDER: i32 = 207;
+pub const ERR_LOW_BALANCE: i32 = 208;
+pub const ERR_DOUBLE_MSG: i32 = 209;
+pub const ERR_SENDER_NO_ALLOWED: i32 = 210;
+pub const ERR_NO_DATA: i32 = 211;
+pub const ERR_NOT_OWNER: i32 = 212;
+pub const ERR_BRANCH_NOT_EXIST: i32 = 213;
+pub const ERR_NOT_EMPTY_BRANCH: i32 = 214;
+pub const ERR_BRANCH_EXIST: i32 = 215;
+pub const ERR_TOO_MANY_PARENTS: i32 = 216;
+pub const ERR_SECOND_CHANGE: i32 = 217;
+pub const ERR_NOT_LAST_CHECK: i32 = 218;
+pub const ERR_DONT_PASS_CHECK: i32 = 219;
+pub const ERR_WRONG_COMMIT_ADDR: i32 = 220;
+pub const ERR_NEED_PUBKEY: i32 = 221;
+pub const ERR_WRONG_NAME: i32 = 222;
+pub const NOT_ERR: i32 = 223;
+pub const ERR_WRONG_INDEX: i32 = 224;
+pub const ERR_WALLET_NOT_EXIST: i32 = 225;
+pub const ERR_WRONG_BRANCH: i32 = 226;
+pub const ERR_DIFF_ALREADY_USED: i32 = 227;
+pub const ERR_PROCCESS_IS_EXIST: i32 = 228;
+pub const ERR_PROCCESS_END: i32 = 229;
+pub const ERR_NO_NEED_ANSWER: i32 = 230;
+pub const ERR_WRONG_DATA: i32 = 231;
+pub const ERR_NOT_EMPTY_DATA: i32 = 232;
+pub const ERR_SNAPSHOT_NOT_READY: i32 = 233;
+pub const ERR_EMPTY_BRANCH: i32 = 234;
+pub const ERR_GOSH_UPDATE: i32 = 235;
+pub const ERR_OLD_CONTRACT: i32 = 236;
+pub const ERR_SYSTEM_CONTRACT_BAD_VERSION: i32 = 237;
+pub const ERR_BAD_COUNT_PARENTS: i32 = 238;
+pub const ERR_REPOSITORY_NOT_READY: i32 = 239;
+pub const ERR_PREV_NOT_EXIST: i32 = 240;
+pub const ERR_WRONG_DAO: i32 = 241;
+pub const ERR_TOMBSTONE: i32 = 242;
+pub const ERR_BAD_NUMBER_CUSTODIANS: i32 = 243;
+pub const ERR_NOTHING_TO_CONFIRM: i32 = 244;
+pub const ERR_ALREADY_CONFIRMED: i32 = 245;
+pub const ERR_WRONG_NUMBER_MEMBER: i32 = 246;
+pub const ERR_BAD_PARENT: i32 = 247;
+pub const ERR_TOO_LOW_BALANCE: i32 = 248;
+pub const ERR_FIRST_DAO: i32 = 249;
+pub const ERR_MESSAGE_EXPIRED: i32 = 250;
+pub const ERR_MESSAGE_WITH_HUGE_EXPIREAT: i32 = 251;
+pub const ERR_MESSAGE_IS_EXIST: i32 = 252;
+pub const ERR_TOO_MANY_DIFFS: i32 = 253;
+pub const ERR_CONTRACT_BAD_VERSION: i32 = 254;
+pub const ERR_NOT_ALONE: i32 = 255;
+pub const ERR_TASK_COMPLETED: i32 = 256;
+pub const ERR_TASK_NOT_COMPLETED: i32 = 257;
+pub const ERR_ASSIGN_NOT_EXIST: i32 = 258;
+pub const ERR_REVIEW_NOT_EXIST: i32 = 259;
+pub const ERR_MANAGER_NOT_EXIST: i32 = 260;
+pub const ERR_NEED_SMV: i32 = 261;
+pub const ERR_BRANCH_PROTECTED: i32 = 262;
+pub const ERR_WALLET_LIMITED: i32 = 263;
+pub const ERR_LOW_TOKEN_RESERVE: i32 = 264;
+pub const ERR_LOW_TOKEN: i32 = 265;
+pub const ERR_TOO_MANY_TAGS: i32 = 266;
+pub const ERR_NOT_READY: i32 = 267;
+pub const ERR_NOT_ALLOW_MINT: i32 = 268;
+pub const ERR_DIFFERENT_COUNT: i32 = 269;
+pub const ERR_TOO_MANY_VESTING_TIME: i32 = 270;
+pub const ERR_ZERO_GRANT: i32 = 271;
+pub const ERR_WRONG_LOCK: i32 = 272;
+pub const ERR_TOO_MANY_PROPOSALS: i32 = 273;
+pub const ERR_TOO_FEW_PROPOSALS: i32 = 274;
+pub const ERR_WRONG_UPGRADE_STATUS: i32 = 275;
+pub const ERR_WALLET_EXIST: i32 = 276;
+pub const ERR_PROGRAM_EXIST: i32 = 277;
+pub const ERR_PROGRAM_NOT_EXIST: i32 = 278;
+
+/// Meaning of an exit code
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ExitCode {
+    pub code: i32,
+    /// Name of the constant in the contracts
+    pub name: &'static str,
+    pub message: &'static str,
+}
+
+type Entry = (i32, &'static str, &'static str);
+
+static EXIT_CODES: &[Entry] = &[
+    (OUT_OF_GAS, "OUT_OF_GAS", "the transaction ran out of gas"),
+    (
+        INVALID_SIGNATURE,
+        "INVALID_SIGNATURE",
+        "the message is signed with a wrong key",
+    ),
+    (
+        CONSTRUCTOR_CALLED_TWICE,
+        "CONSTRUCTOR_CALLED_TWICE",
+        "the contract is already deployed",
+    ),
+    (
+        REPLAY_PROTECTION,
+        "REPLAY_PROTECTION",
+        "the message was already processed or its timestamp is too old",
+    ),
+    (
+        MESSAGE_EXPIRED,
+        "MESSAGE_EXPIRED",
+        "the message expired before it was processed",
+    ),
+    (
+        WRONG_FUNCTION_ID,
+        "WRONG_FUNCTION_ID",
+        "the contract has no such function, its version may differ from the ABI",
+    ),
+    (
+        EMPTY_OPTIONAL,
+        "EMPTY_OPTIONAL",
+        "the contract has no requested data",
+    ),
+    (
+        NOT_INITIALIZED,
+        "NOT_INITIALIZED",
+        "the contract is not deployed yet",
+    ),
+    (ERR_NO_SALT, "ERR_NO_SALT", "the contract code has no salt"),
+    (
+        ERR_SENDER_NOT_DAO,
+        "ERR_SENDER_NOT_DAO",
+        "only the DAO can call the function",
+    ),
+    (
+        ERR_ZERO_ROOT_KEY,
+        "ERR_ZERO_ROOT_KEY",
+        "the root public key is not set",
+    ),
+    (
+        ERR_LOW_VALUE,
+        "ERR_LOW_VALUE",
+        "the message carries too few tokens",
+    ),
+    (
+        ERR_NOT_ROOT_REPO,
+        "ERR_NOT_ROOT_REPO",
+        "the sender is not the root repository",
+    ),
+    (
+        ERR_ZERO_ROOT_GOSH,
+        "ERR_ZERO_ROOT_GOSH",
+        "the system contract is not set",
+    ),
+    (
+        ERR_INVALID_SENDER,
+        "ERR_INVALID_SENDER",
+        "the sender is not allowed to call the function",
+    ),
+    (
+        ERR_LOW_BALANCE,
+        "ERR_LOW_BALANCE",
+        "insufficient balance of the contract",
+    ),
+    (
+        ERR_DOUBLE_MSG,
+        "ERR_DOUBLE_MSG",
+        "the message was already sent",
+    ),
+    (
+        ERR_SENDER_NO_ALLOWED,
+        "ERR_SENDER_NO_ALLOWED",
+        "the sender is not allowed to call the function",
+    ),
+    (
+        ERR_NO_DATA,
+        "ERR_NO_DATA",
+        "the requested data doesn't exist",
+    ),
+    (
+        ERR_NOT_OWNER,
+        "ERR_NOT_OWNER",
+        "the message is not signed by the owner of the contract",
+    ),
+    (
+        ERR_BRANCH_NOT_EXIST,
+        "ERR_BRANCH_NOT_EXIST",
+        "the branch doesn't exist",
+    ),
+    (
+        ERR_NOT_EMPTY_BRANCH,
+        "ERR_NOT_EMPTY_BRANCH",
+        "the branch is not empty",
+    ),
+    (
+        ERR_BRANCH_EXIST,
+        "ERR_BRANCH_EXIST",
+        "the branch already exists",
+    ),
+    (
+        ERR_TOO_MANY_PARENTS,
+        "ERR_TOO_MANY_PARENTS",
+        "the commit has too many parents",
+    ),
+    (
+        ERR_SECOND_CHANGE,
+        "ERR_SECOND_CHANGE",
+        "the object is already changed by another commit",
+    ),
+    (
+        ERR_NOT_LAST_CHECK,
+        "ERR_NOT_LAST_CHECK",
+        "the check is not the last one",
+    ),
+    (
+        ERR_DONT_PASS_CHECK,
+        "ERR_DONT_PASS_CHECK",
+        "the commit didn't pass the check",
+    ),
+    (
+        ERR_WRONG_COMMIT_ADDR,
+        "ERR_WRONG_COMMIT_ADDR",
+        "the commit address doesn't match",
+    ),
+    (
+        ERR_NEED_PUBKEY,
+        "ERR_NEED_PUBKEY",
+        "the call must be signed with a public key",
+    ),
+    (ERR_WRONG_NAME, "ERR_WRONG_NAME", "the name is not valid"),
+    (NOT_ERR, "NOT_ERR", "not an error"),
+    (
+        ERR_WRONG_INDEX,
+        "ERR_WRONG_INDEX",
+        "the index is out of range",
+    ),
+    (
+        ERR_WALLET_NOT_EXIST,
+        "ERR_WALLET_NOT_EXIST",
+        "the wallet doesn't exist",
+    ),
+    (
+        ERR_WRONG_BRANCH,
+        "ERR_WRONG_BRANCH",
+        "the branch doesn't match",
+    ),
+    (
+        ERR_DIFF_ALREADY_USED,
+        "ERR_DIFF_ALREADY_USED",
+        "the diff is already applied",
+    ),
+    (
+        ERR_PROCCESS_IS_EXIST,
+        "ERR_PROCCESS_IS_EXIST",
+        "another process is already running",
+    ),
+    (
+        ERR_PROCCESS_END,
+        "ERR_PROCCESS_END",
+        "the process is already finished",
+    ),
+    (
+        ERR_NO_NEED_ANSWER,
+        "ERR_NO_NEED_ANSWER",
+        "no answer was expected",
+    ),
+    (ERR_WRONG_DATA, "ERR_WRONG_DATA", "the data is not valid"),
+    (
+        ERR_NOT_EMPTY_DATA,
+        "ERR_NOT_EMPTY_DATA",
+        "the data is already set",
+    ),
+    (
+        ERR_SNAPSHOT_NOT_READY,
+        "ERR_SNAPSHOT_NOT_READY",
+        "the snapshot is not ready",
+    ),
+    (ERR_EMPTY_BRANCH, "ERR_EMPTY_BRANCH", "the branch is empty"),
+    (
+        ERR_GOSH_UPDATE,
+        "ERR_GOSH_UPDATE",
+        "the contract is being upgraded",
+    ),
+    (
+        ERR_OLD_CONTRACT,
+        "ERR_OLD_CONTRACT",
+        "the contract is outdated",
+    ),
+    (
+        ERR_SYSTEM_CONTRACT_BAD_VERSION,
+        "ERR_SYSTEM_CONTRACT_BAD_VERSION",
+        "the system contract version doesn't match",
+    ),
+    (
+        ERR_BAD_COUNT_PARENTS,
+        "ERR_BAD_COUNT_PARENTS",
+        "the number of parents doesn't match",
+    ),
+    (
+        ERR_REPOSITORY_NOT_READY,
+        "ERR_REPOSITORY_NOT_READY",
+        "the repository is not ready",
+    ),
+    (
+        ERR_PREV_NOT_EXIST,
+        "ERR_PREV_NOT_EXIST",
+        "the previous version doesn't exist",
+    ),
+    (ERR_WRONG_DAO, "ERR_WRONG_DAO", "the DAO doesn't match"),
+    (ERR_TOMBSTONE, "ERR_TOMBSTONE", "the contract is deleted"),
+    (
+        ERR_BAD_NUMBER_CUSTODIANS,
+        "ERR_BAD_NUMBER_CUSTODIANS",
+        "the number of custodians is not valid",
+    ),
+    (
+        ERR_NOTHING_TO_CONFIRM,
+        "ERR_NOTHING_TO_CONFIRM",
+        "there is nothing to confirm",
+    ),
+    (
+        ERR_ALREADY_CONFIRMED,
+        "ERR_ALREADY_CONFIRMED",
+        "it is already confirmed",
+    ),
+    (
+        ERR_WRONG_NUMBER_MEMBER,
+        "ERR_WRONG_NUMBER_MEMBER",
+        "the number of DAO members is not valid",
+    ),
+    (ERR_BAD_PARENT, "ERR_BAD_PARENT", "the parent is not valid"),
+    (
+        ERR_TOO_LOW_BALANCE,
+        "ERR_TOO_LOW_BALANCE",
+        "insufficient balance of the contract",
+    ),
+    (
+        ERR_FIRST_DAO,
+        "ERR_FIRST_DAO",
+        "the DAO has no previous version",
+    ),
+    (
+        ERR_MESSAGE_EXPIRED,
+        "ERR_MESSAGE_EXPIRED",
+        "the message expired",
+    ),
+    (
+        ERR_MESSAGE_WITH_HUGE_EXPIREAT,
+        "ERR_MESSAGE_WITH_HUGE_EXPIREAT",
+        "the message expiration time is too far",
+    ),
+    (
+        ERR_MESSAGE_IS_EXIST,
+        "ERR_MESSAGE_IS_EXIST",
+        "the message was already processed",
+    ),
+    (
+        ERR_TOO_MANY_DIFFS,
+        "ERR_TOO_MANY_DIFFS",
+        "the commit has too many diffs",
+    ),
+    (
+        ERR_CONTRACT_BAD_VERSION,
+        "ERR_CONTRACT_BAD_VERSION",
+        "the contract version doesn't match",
+    ),
+    (ERR_NOT_ALONE, "ERR_NOT_ALONE", "the DAO has other members"),
+    (
+        ERR_TASK_COMPLETED,
+        "ERR_TASK_COMPLETED",
+        "the task is already completed",
+    ),
+    (
+        ERR_TASK_NOT_COMPLETED,
+        "ERR_TASK_NOT_COMPLETED",
+        "the task is not completed",
+    ),
+    (
+        ERR_ASSIGN_NOT_EXIST,
+        "ERR_ASSIGN_NOT_EXIST",
+        "the task assignee doesn't exist",
+    ),
+    (
+        ERR_REVIEW_NOT_EXIST,
+        "ERR_REVIEW_NOT_EXIST",
+        "the task reviewer doesn't exist",
+    ),
+    (
+        ERR_MANAGER_NOT_EXIST,
+        "ERR_MANAGER_NOT_EXIST",
+        "the task manager doesn't exist",
+    ),
+    (
+        ERR_NEED_SMV,
+        "ERR_NEED_SMV",
+        "the change must be accepted by a proposal",
+    ),
+    (
+        ERR_BRANCH_PROTECTED,
+        "ERR_BRANCH_PROTECTED",
+        "the branch is protected, it's changed by proposals only",
+    ),
+    (
+        ERR_WALLET_LIMITED,
+        "ERR_WALLET_LIMITED",
+        "the wallet is limited",
+    ),
+    (
+        ERR_LOW_TOKEN_RESERVE,
+        "ERR_LOW_TOKEN_RESERVE",
+        "insufficient DAO token reserve",
+    ),
+    (ERR_LOW_TOKEN, "ERR_LOW_TOKEN", "insufficient DAO tokens"),
+    (ERR_TOO_MANY_TAGS, "ERR_TOO_MANY_TAGS", "too many tags"),
+    (ERR_NOT_READY, "ERR_NOT_READY", "the contract is not ready"),
+    (
+        ERR_NOT_ALLOW_MINT,
+        "ERR_NOT_ALLOW_MINT",
+        "minting of DAO tokens is not allowed",
+    ),
+    (
+        ERR_DIFFERENT_COUNT,
+        "ERR_DIFFERENT_COUNT",
+        "the numbers of items don't match",
+    ),
+    (
+        ERR_TOO_MANY_VESTING_TIME,
+        "ERR_TOO_MANY_VESTING_TIME",
+        "the vesting time is too long",
+    ),
+    (ERR_ZERO_GRANT, "ERR_ZERO_GRANT", "the grant is zero"),
+    (
+        ERR_WRONG_LOCK,
+        "ERR_WRONG_LOCK",
+        "the lock of tokens is not valid",
+    ),
+    (
+        ERR_TOO_MANY_PROPOSALS,
+        "ERR_TOO_MANY_PROPOSALS",
+        "too many proposals",
+    ),
+    (
+        ERR_TOO_FEW_PROPOSALS,
+        "ERR_TOO_FEW_PROPOSALS",
+        "too few proposals",
+    ),
+    (
+        ERR_WRONG_UPGRADE_STATUS,
+        "ERR_WRONG_UPGRADE_STATUS",
+        "the wallet was not upgraded to this version",
+    ),
+    (
+        ERR_WALLET_EXIST,
+        "ERR_WALLET_EXIST",
+        "the wallet already exists",
+    ),
+    (
+        ERR_PROGRAM_EXIST,
+        "ERR_PROGRAM_EXIST",
+        "the program already exists",
+    ),
+    (
+        ERR_PROGRAM_NOT_EXIST,
+        "ERR_PROGRAM_NOT_EXIST",
+        "the program doesn't exist",
+    ),
+];
+
+// Messages that are more precise for the contract that raised the code
+static CONTRACT_MESSAGES: &[(ContractKind, i32, &str)] = &[
+    (
+        ContractKind::Wallet,
+        ERR_NOT_OWNER,
+        "the keys don't own the wallet, the user is not a DAO member",
+    ),
+    (
+        ContractKind::Wallet,
+        ERR_SENDER_NO_ALLOWED,
+        "the wallet doesn't belong to a DAO member",
+    ),
+    (
+        ContractKind::Wallet,
+        ERR_TOO_LOW_BALANCE,
+        "insufficient balance of the wallet",
+    ),
+    (
+        ContractKind::Wallet,
+        ERR_TOMBSTONE,
+        "the wallet is deleted, the user is not a DAO member anymore",
+    ),
+    (
+        ContractKind::Wallet,
+        ERR_WALLET_LIMITED,
+        "the wallet is limited, the DAO member can't push",
+    ),
+    (
+        ContractKind::Repo,
+        ERR_SENDER_NO_ALLOWED,
+        "the wallet doesn't belong to a member of the DAO of the repository",
+    ),
+    (
+        ContractKind::Repo,
+        ERR_TOO_LOW_BALANCE,
+        "insufficient balance of the repository",
+    ),
+    (
+        ContractKind::Commit,
+        ERR_PROCCESS_IS_EXIST,
+        "the commit is already being set to a branch",
+    ),
+    (
+        ContractKind::Snapshot,
+        ERR_SNAPSHOT_NOT_READY,
+        "the snapshot is not ready for diffs yet",
+    ),
+    (
+        ContractKind::Diff,
+        ERR_DIFF_ALREADY_USED,
+        "the diff is already applied to the snapshot",
+    ),
+];
+
+/// Describes the exit code raised by the contract of the `kind`,
+/// `None` for codes that are not in the catalogue
+pub fn describe(kind: Option<ContractKind>, code: i32) -> Option<ExitCode> {
+    let (code, name, message) = *EXIT_CODES.iter().find(|(known, ..)| *known == code)?;
+    let message = kind
+        .and_then(|kind| {
+            CONTRACT_MESSAGES
+                .iter()
+                .find(|(known_kind, known, _)| *known_kind == kind && *known == code)
+        })
+        .map(|(.., message)| *message)
+        .unwrap_or(message);
+    Some(ExitCode {
+        code,
+        name,
+        message,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ensure_exit_codes_are_described_per_contract() {
+        let protected = describe(Some(ContractKind::Repo), ERR_BRANCH_PROTECTED).unwrap();
+        assert_eq!(protected.name, "ERR_BRANCH_PROTECTED");
+
+        let not_member = describe(Some(ContractKind::Wallet), ERR_NOT_OWNER).unwrap();
+        let not_owner = describe(Some(ContractKind::Dao), ERR_NOT_OWNER).unwrap();
+        assert_eq!(not_member.name, not_owner.name);
+        assert_ne!(not_member.message, not_owner.message);
+        assert_eq!(describe(None, ERR_NOT_OWNER), Some(not_owner));
+
+        assert!(describe(Some(ContractKind::Wallet), 1000).is_none());
+
+        // every code is described once
+        let mut codes: Vec<i32> = EXIT_CODES.iter().map(|(code, ..)| *code).collect();
+        codes.sort();
+        codes.dedup();
+        assert_eq!(codes.len(), EXIT_CODES.len());
+    }
+
+    #[test]
+    fn ensure_catalogue_mirrors_contract_errors() {
+        let errors = include_str!("../../../../contracts/gosh/smv/modifiers/errors.sol");
+        let version = format!("string constant versionErrors = \"{ERRORS_VERSION}\";");
+        assert!(errors.contains(&version));
+
+        let constants = errors
+            .lines()
+            .filter_map(|line| line.trim().strip_prefix("uint16 constant "));
+        for constant in constants {
+            let (name, code) = constant.trim_end_matches(';').split_once(" = ").unwrap();
+            let described = describe(None, code.parse().unwrap());
+            assert_eq!(described.map(|reason| reason.name), Some(name), "{constant}");
+        }
+    }
+}
@@ -1,4 +1,9 @@
+use super::{BlockchainContractAddress, ContractKind};
 use std::{convert::From, error::Error, fmt};
+use ton_client::error::ClientError;
+
+pub mod exit_codes;
+pub use exit_codes::ExitCode;
 
 #[derive(Debug, Clone)]
 pub struct RunLocalError {
@@ -46,3 +51,67 @@ where
         RunLocalError::new(format!("Inner error: {}", e))
     }
 }
+
+/// Contract function failed with an exit code, e.g. a `require` of the
+/// contract. [`ContractError::is`] tells the failures apart:
+/// `e.is(exit_codes::ERR_BRANCH_PROTECTED)`
+#[derive(Debug, Clone)]
+pub struct ContractError {
+    /// `None` for contracts with an ABI that is not of GOSH
+    pub kind: Option<ContractKind>,
+    pub address: BlockchainContractAddress,
+    pub function: String,
+    pub code: i32,
+    /// `None` for codes that are not in the catalogue
+    pub reason: Option<ExitCode>,
+}
+
+impl ContractError {
+    pub fn new(
+        kind: Option<ContractKind>,
+        address: &BlockchainContractAddress,
+        function: &str,
+        code: i32,
+    ) -> Self {
+        Self {
+            kind,
+            address: address.clone(),
+            function: function.to_owned(),
+            code,
+            reason: exit_codes::describe(kind, code),
+        }
+    }
+
+    /// Decodes the exit code of the SDK error, `None` when the error is
+    /// not raised by the contract code
+    pub fn from_client_error(
+        kind: Option<ContractKind>,
+        address: &BlockchainContractAddress,
+        function: &str,
+        error: &ClientError,
+    ) -> Option<Self> {
+        let code = error.data["exit_code"].as_i64()?;
+        Some(Self::new(kind, address, function, code as i32))
+    }
+
+    pub fn is(&self, code: i32) -> bool {
+        self.code == code
+    }
+}
+
+impl fmt::Display for ContractError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.kind {
+            Some(kind) => write!(f, "{kind:?} {}", self.address)?,
+            None => write!(f, "Contract {}", self.address)?,
+        }
+        let function = &self.function;
+        write!(f, " failed in `{function}` with exit code {}", self.code)?;
+        match self.reason {
+            Some(reason) => write!(f, " ({}): {}", reason.name, reason.message),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Error for ContractError {}
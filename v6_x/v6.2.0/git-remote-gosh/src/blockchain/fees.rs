@@ -0,0 +1,44 @@
+//! Fees of the messages processed with `call`. Messages sent with
+//! `send_message` are only counted, their cost comes from the wallet balances.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+
+static CALL_FEES: AtomicU64 = AtomicU64::new(0);
+static CALLS: AtomicU64 = AtomicU64::new(0);
+static MESSAGES_SENT: AtomicU64 = AtomicU64::new(0);
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FeeCounters {
+    /// Sum of `total_fees` of the processed calls, nanotokens
+    pub call_fees: u64,
+    pub calls: u64,
+    pub messages_sent: u64,
+}
+
+impl FeeCounters {
+    pub fn current() -> Self {
+        Self {
+            call_fees: CALL_FEES.load(Ordering::SeqCst),
+            calls: CALLS.load(Ordering::SeqCst),
+            messages_sent: MESSAGES_SENT.load(Ordering::SeqCst),
+        }
+    }
+
+    /// Counters gathered since the `earlier` snapshot
+    pub fn since(&self, earlier: &FeeCounters) -> FeeCounters {
+        FeeCounters {
+            call_fees: self.call_fees.saturating_sub(earlier.call_fees),
+            calls: self.calls.saturating_sub(earlier.calls),
+            messages_sent: self.messages_sent.saturating_sub(earlier.messages_sent),
+        }
+    }
+}
+
+pub(super) fn record_call(total_fees: u64) {
+    CALL_FEES.fetch_add(total_fees, Ordering::SeqCst);
+    CALLS.fetch_add(1, Ordering::SeqCst);
+}
+
+pub(super) fn record_message_sent() {
+    MESSAGES_SENT.fetch_add(1, Ordering::SeqCst);
+}
@@ -8,13 +8,21 @@ use tracing_futures::Instrument;
 
 use std::sync::Arc;
 
+pub mod account_cache;
+pub mod address;
 pub mod branch;
 mod call;
+pub mod concurrency;
+pub mod consistency;
+pub mod fees;
 pub mod contract;
-mod error;
-use error::RunLocalError;
+pub mod error;
+use error::{ContractError, RunLocalError};
 pub mod service;
+pub mod subscription;
 pub use service::*;
+#[cfg(test)]
+pub mod simulator;
 
 use ton_client::{
     abi::{
@@ -22,10 +30,9 @@ use ton_client::{
         ParamsOfEncodeMessage, ResultOfEncodeInitialData, Signer,
     },
     boc::{
-        cache_set, get_boc_hash, BocCacheType, ParamsOfBocCacheSet, ParamsOfGetBocHash,
-        ResultOfBocCacheSet, ResultOfGetBocHash,
+        get_boc_hash, ParamsOfGetBocHash, ResultOfGetBocHash,
     },
-    net::{query_collection, ParamsOfQuery, ParamsOfQueryCollection},
+    net::{ParamsOfQuery, ParamsOfQueryCollection},
     processing::ProcessingEvent,
     tvm::{run_tvm, ParamsOfRunTvm},
     ClientContext,
@@ -44,19 +51,21 @@ pub use crate::{
     abi as gosh_abi,
     config::{self, UserWalletConfig},
 };
+use crate::abi::bindings::{self, repository, systemcontract};
+use crate::bundle;
 pub use commit::GoshCommit;
-use once_cell::sync::Lazy;
 use serde_number::Number;
+pub use serde_number::NumberU64;
 pub use snapshot::Snapshot;
 use std::collections::HashMap;
 
-use tokio::sync::RwLock;
 
 use ton_client::boc::{encode_state_init, ParamsOfEncodeStateInit, ResultOfEncodeStateInit};
 pub use tree::Tree;
 pub use tvm_hash::tvm_hash;
 
-use self::contract::{ContractRead, ContractStatic, GoshContract};
+use self::account_cache::Freshness;
+use self::contract::{GoshContract, TypedContract};
 
 pub const ZERO_SHA: &str = "0000000000000000000000000000000000000000";
 pub const EMPTY_BLOB_SHA1: &str = "e69de29bb2d1d6434b8b29ae775ad8c2e48c5391";
@@ -65,10 +74,6 @@ pub const EMPTY_BLOB_SHA256: &str =
 
 pub const MAX_ACCOUNTS_ADDRESSES_PER_QUERY: usize = 50;
 
-static PINNED_CONTRACT_BOCREFS: Lazy<
-    Arc<RwLock<HashMap<BlockchainContractAddress, (String, EverClient)>>>,
-> = Lazy::new(|| Arc::new(RwLock::new(HashMap::new())));
-
 #[repr(u8)]
 pub enum GoshBlobBitFlags {
     Binary = 1,
@@ -77,6 +82,7 @@ pub enum GoshBlobBitFlags {
 }
 
 #[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum ContractKind {
     Dao,
     Wallet,
@@ -100,12 +106,7 @@ pub struct GoshBlob {
     pub flags: Number,
 }
 
-#[derive(Deserialize, Debug)]
-pub struct AccountBoc {
-    boc: String,
-}
-
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct AddrVersion {
     #[serde(rename = "addr")]
     pub address: BlockchainContractAddress,
@@ -132,36 +133,6 @@ struct SendMessageResult {
     sending_endpoints: Vec<String>,
 }
 
-#[derive(Deserialize, Debug)]
-pub struct GetNameCommitResult {
-    #[serde(rename = "value0")]
-    pub name: String,
-}
-
-#[derive(Deserialize, Debug)]
-pub struct GetNameBranchResult {
-    #[serde(rename = "value0")]
-    pub name: String,
-}
-
-#[derive(Deserialize, Debug)]
-struct GetRepoAddrResult {
-    #[serde(rename = "value0")]
-    pub address: BlockchainContractAddress,
-}
-
-#[derive(Deserialize, Debug)]
-struct GetCommitAddrResult {
-    #[serde(rename = "value0")]
-    pub address: BlockchainContractAddress,
-}
-
-#[derive(Deserialize, Debug)]
-struct GetBoolResult {
-    #[serde(rename = "value0")]
-    pub is_ok: bool,
-}
-
 #[derive(Deserialize, Debug, Clone)]
 pub struct BranchRef {
     #[serde(rename = "branchname")]
@@ -174,40 +145,24 @@ pub struct BranchRef {
     pub version: String,
 }
 
-#[derive(Deserialize, Debug)]
-pub struct GetAllAddressResult {
-    #[serde(rename = "value0")]
-    pub branch_ref: Vec<BranchRef>,
-}
-
-#[derive(Deserialize, Debug)]
-pub struct GetContractCodeResult {
-    #[serde(rename = "value0")]
-    pub code: String,
-}
-
-#[derive(Deserialize, Debug)]
-pub struct Account {
-    #[serde(rename = "id")]
-    pub address: String,
-}
-
-#[derive(Deserialize, Clone)]
-pub struct GetTagContentResult {
-    #[serde(rename = "value0")]
-    pub content: String,
-}
-
-#[derive(Deserialize, Debug, Clone)]
-struct GetAddrBranchResult {
-    #[serde(rename = "value0")]
-    pub branch: BranchRef,
+impl From<repository::GetAllAddressOutputValue0> for BranchRef {
+    fn from(branch: repository::GetAllAddressOutputValue0) -> Self {
+        Self {
+            branch_name: branch.branchname,
+            commit_address: branch.commitaddr,
+            version: branch.commitversion,
+        }
+    }
 }
 
-#[derive(Deserialize, Debug)]
-struct GetHeadResult {
-    #[serde(rename = "value0")]
-    pub head: String,
+impl From<repository::GetAddrBranchOutputValue0> for BranchRef {
+    fn from(branch: repository::GetAddrBranchOutputValue0) -> Self {
+        Self {
+            branch_name: branch.branchname,
+            commit_address: branch.commitaddr,
+            version: branch.commitversion,
+        }
+    }
 }
 
 #[derive(Deserialize, Debug)]
@@ -218,6 +173,12 @@ struct GetVersionResult {
     pub version: String,
 }
 
+#[derive(Deserialize, Debug)]
+pub struct Account {
+    #[serde(rename = "id")]
+    pub address: String,
+}
+
 pub type EverClient = Arc<ClientContext>;
 
 #[derive(Builder)]
@@ -251,7 +212,9 @@ async fn check_contracts_deployed(
     }
     tracing::trace!("internal get_contracts_blocks start");
     let mut accounts_bocs = vec![];
-    for chunk in contracts_addresses.chunks(MAX_ACCOUNTS_ADDRESSES_PER_QUERY) {
+    let batch_size = concurrency::state_query_batch_size();
+    tracing::trace!("check_contracts_deployed: batch_size={batch_size}");
+    for chunk in contracts_addresses.chunks(batch_size) {
         let addresses: &[String] = &chunk
             .iter()
             .map(|e| -> String { <&BlockchainContractAddress as Into<String>>::into(e) })
@@ -263,31 +226,36 @@ async fn check_contracts_deployed(
         });
         // This log is too big and is printed too often
         // tracing::trace!("Filter: {}", filter.to_string());
-        let query_result: Vec<serde_json::Value> = query_collection(
-            Arc::clone(context),
+        let start = std::time::Instant::now();
+        let query_result = bundle::query_accounts(
+            context,
             ParamsOfQueryCollection {
                 collection: "accounts".to_owned(),
                 filter: Some(filter),
                 result: "id".to_owned(),
-                limit: Some(contracts_addresses.len() as u32),
+                limit: Some(chunk.len() as u32),
                 order: None,
             },
         )
         .instrument(info_span!("get_contracts_blocks sdk::query_collection").or_current())
-        .await
-        .map(|r| r.result)?;
-        if query_result.len() != contracts_addresses.len() {
+        .await;
+        concurrency::observe_state_query(concurrency::Signal::from_result(
+            &query_result,
+            start.elapsed(),
+        ));
+        let query_result: Vec<serde_json::Value> = query_result?;
+        if query_result.len() != chunk.len() {
             if !allow_incomplete_results {
                 anyhow::bail!(
                     "Some accounts are missing. Expecting {} boc results while have {}",
-                    contracts_addresses.len(),
+                    chunk.len(),
                     query_result.len()
                 );
             } else {
                 tracing::trace!(
                     "Got incomplete result: {} out of {}",
                     query_result.len(),
-                    contracts_addresses.len()
+                    chunk.len()
                 );
             }
         }
@@ -304,6 +272,46 @@ async fn check_contracts_deployed(
     return Ok(accounts_bocs);
 }
 
+/// Loads states of the accounts in batches. Accounts that don't exist
+/// are missing in the result
+#[instrument(level = "info", skip_all)]
+pub async fn load_accounts_bocs(
+    context: &EverClient,
+    addresses: &[BlockchainContractAddress],
+) -> anyhow::Result<HashMap<BlockchainContractAddress, String>> {
+    tracing::trace!("load_accounts_bocs: addresses={}", addresses.len());
+    let mut bocs = HashMap::new();
+    for chunk in addresses.chunks(concurrency::state_query_batch_size()) {
+        let ids: Vec<String> = chunk.iter().map(String::from).collect();
+        let start = std::time::Instant::now();
+        let query_result = bundle::query_accounts(
+            context,
+            ParamsOfQueryCollection {
+                collection: "accounts".to_owned(),
+                filter: Some(serde_json::json!({ "id": { "in": ids } })),
+                result: "id boc last_trans_lt".to_owned(),
+                limit: Some(chunk.len() as u32),
+                order: None,
+            },
+        )
+        .instrument(info_span!("load_accounts_bocs sdk::query_collection").or_current())
+        .await;
+        concurrency::observe_state_query(concurrency::Signal::from_result(
+            &query_result,
+            start.elapsed(),
+        ));
+        for account in query_result? {
+            if let (Some(id), Some(boc)) = (account["id"].as_str(), account["boc"].as_str()) {
+                let address = BlockchainContractAddress::new(id);
+                let last_trans_lt = account_cache::lt_of(&account);
+                account_cache::insert(context, &address, boc, last_trans_lt, false);
+                bocs.insert(address, boc.to_owned());
+            }
+        }
+    }
+    Ok(bocs)
+}
+
 #[instrument(level = "info", skip_all)]
 async fn run_local(
     context: &EverClient,
@@ -313,37 +321,39 @@ async fn run_local(
 ) -> anyhow::Result<serde_json::Value> {
     tracing::trace!("internal run_local start");
     tracing::trace!("read_state: function_name={function_name}, args={args:?}");
-    let filter = Some(serde_json::json!({
-        "id": { "eq": contract.address }
-    }));
-    let query = query_collection(
-        Arc::clone(context),
-        ParamsOfQueryCollection {
-            collection: "accounts".to_owned(),
-            filter,
-            result: "boc".to_owned(),
-            limit: Some(1),
-            order: None,
-        },
-    )
-    .instrument(info_span!("run_local sdk::query_collection").or_current())
-    .await
-    .map(|r| r.result)?;
+    let account_boc = account_boc(context, contract, Freshness::Current, function_name).await?;
+    run_local_with_boc(context, contract, &account_boc, function_name, args).await
+}
 
-    if query.is_empty() {
-        anyhow::bail!(
-            "account with address {} not found. Was trying to call {}",
-            contract.address,
-            function_name,
-        );
-    }
-    let account_boc = &query[0]["boc"].as_str();
-    if account_boc.is_none() {
-        anyhow::bail!(
-            "account with address {} does not contain boc",
-            contract.address,
-        );
-    }
+/// State of the account from the cache, fails when the account doesn't exist
+async fn account_boc(
+    context: &EverClient,
+    contract: &GoshContract,
+    freshness: Freshness,
+    function_name: &str,
+) -> anyhow::Result<String> {
+    account_cache::account_boc(context, contract, freshness)
+        .await?
+        .ok_or_else(|| {
+            anyhow::format_err!(
+                "account with address {} not found. Was trying to call {}",
+                contract.address,
+                function_name,
+            )
+        })
+}
+
+/// Runs a getter on the account state loaded beforehand,
+/// e.g. with [`load_accounts_bocs`]
+#[instrument(level = "info", skip_all)]
+async fn run_local_with_boc(
+    context: &EverClient,
+    contract: &GoshContract,
+    account_boc: &str,
+    function_name: &str,
+    args: Option<serde_json::Value>,
+) -> anyhow::Result<serde_json::Value> {
+    tracing::trace!("run_local_with_boc: function_name={function_name}, args={args:?}");
     let call_set = match args {
         Some(value) => CallSet::some_with_function_and_input(function_name, value),
         None => CallSet::some_with_function(function_name),
@@ -369,7 +379,7 @@ async fn run_local(
         Arc::clone(context),
         ParamsOfRunTvm {
             message: encoded.message,
-            account: account_boc.unwrap().to_string(),
+            account: account_boc.to_string(),
             abi: Some(contract.abi.clone()),
             boc_cache: None,
             execution_options: None,
@@ -380,7 +390,17 @@ async fn run_local(
     .await
     .map(|r| r.decoded.unwrap())
     .map(|r| r.output.unwrap())
-    .map_err(|e| anyhow::format_err!("run_local failed: {e}"))?;
+    .map_err(|e| {
+        match ContractError::from_client_error(
+            contract.kind(),
+            &contract.address,
+            function_name,
+            &e,
+        ) {
+            Some(contract_error) => anyhow::Error::from(contract_error),
+            None => anyhow::format_err!("run_local failed: {e}"),
+        }
+    })?;
 
     Ok(result)
 }
@@ -392,147 +412,9 @@ async fn run_static(
     function_name: &str,
     args: Option<serde_json::Value>,
 ) -> anyhow::Result<serde_json::Value> {
-    // Read lock
     tracing::trace!("run_static: function_name={function_name}, args={args:?}");
-    let boc_ref = {
-        PINNED_CONTRACT_BOCREFS
-            .read()
-            .await
-            .get(&contract.address)
-            .map(|(r, c)| (r.to_owned(), Arc::clone(c)))
-    };
-    let pin = contract.address.to_string();
-    let (account, boc_cache, client) = if let Some(boc_ref) = boc_ref {
-        let (boc_ref, client) = boc_ref;
-        tracing::trace!(
-            "run_static: use cached boc ref: {} -> {}",
-            &contract.address,
-            boc_ref
-        );
-        (boc_ref, Some(BocCacheType::Pinned { pin: pin }), client)
-    } else {
-        tracing::trace!("run_static: load account' boc");
-        let filter = Some(serde_json::json!({
-            "id": { "eq": contract.address }
-        }));
-        let query = query_collection(
-            Arc::clone(context),
-            ParamsOfQueryCollection {
-                collection: "accounts".to_owned(),
-                filter,
-                result: "boc".to_owned(),
-                limit: Some(1),
-                order: None,
-            },
-        )
-        .instrument(info_span!("run_static sdk::query_collection").or_current())
-        .await
-        .map(|r| r.result)?;
-
-        if query.is_empty() {
-            anyhow::bail!(
-                "account with address {} not found. Was trying to call {}",
-                contract.address,
-                function_name,
-            );
-        }
-        let AccountBoc { boc, .. } = serde_json::from_value(query[0].clone())?;
-        tracing::trace!("Save acc to cache");
-        let ResultOfBocCacheSet { boc_ref } = cache_set(
-            Arc::clone(context),
-            ParamsOfBocCacheSet {
-                boc,
-                cache_type: BocCacheType::Pinned { pin: pin },
-            },
-        )
-        .await?;
-        // write lock
-        {
-            let mut refs = PINNED_CONTRACT_BOCREFS.write().await;
-            refs.insert(
-                contract.address.clone(),
-                (boc_ref.clone(), Arc::clone(context)),
-            );
-        }
-        (boc_ref, None, Arc::clone(context))
-    };
-    // ---------
-    let call_set = match args {
-        Some(value) => CallSet::some_with_function_and_input(function_name, value),
-        None => CallSet::some_with_function(function_name),
-    };
-
-    let encoded = encode_message(
-        Arc::clone(&client),
-        ParamsOfEncodeMessage {
-            abi: contract.abi.clone(),
-            address: Some(String::from(contract.address.clone())),
-            call_set,
-            signer: Signer::None,
-            deploy_set: None,
-            processing_try_index: None,
-            signature_id: None,
-        },
-    )
-    .instrument(info_span!("run_static sdk::encode_message").or_current())
-    .await
-    .map_err(|e| Box::new(RunLocalError::from(&e)))?;
-    // ---------
-    let result = run_tvm(
-        Arc::clone(&client),
-        ParamsOfRunTvm {
-            message: encoded.message,
-            account: account.clone(),
-            abi: Some(contract.abi.clone()),
-            boc_cache,
-            execution_options: None,
-            return_updated_account: None,
-        },
-    )
-    .instrument(info_span!("run_static sdk::run_tvm").or_current())
-    .await
-    .map(|r| r.decoded.unwrap())
-    .map(|r| r.output.unwrap())
-    .map_err(|e| anyhow::format_err!("run_static failed: {e}"));
-    if result.is_err() {
-        tracing::trace!("run_static error: {result:?}");
-    }
-    Ok(result?)
-}
-
-#[instrument(level = "debug", skip(context))]
-pub async fn get_account_data(
-    context: &EverClient,
-    contract: &GoshContract,
-) -> anyhow::Result<serde_json::Value> {
-    let query = r#"query($address: String!){
-        blockchain {
-          account(address: $address) {
-            info {
-              acc_type balance
-            }
-          }
-        }
-    }"#
-    .to_owned();
-
-    let result = ton_client::net::query(
-        Arc::clone(context),
-        ParamsOfQuery {
-            query: query.clone(),
-            variables: Some(serde_json::json!({
-                "address": contract.address,
-            })),
-            ..Default::default()
-        },
-    )
-    .await
-    .map(|r| r.result)
-    .map_err(|e| anyhow::format_err!("query error: {e}"))?;
-
-    let extracted_data = &result["data"]["blockchain"]["account"]["info"];
-
-    Ok(extracted_data.clone())
+    let account_boc = account_boc(context, contract, Freshness::Any, function_name).await?;
+    run_local_with_boc(context, contract, &account_boc, function_name, args).await
 }
 
 fn processing_event_to_string(pe: ProcessingEvent) -> String {
@@ -615,27 +497,30 @@ pub async fn get_repo_address(
     repo: &str,
 ) -> anyhow::Result<BlockchainContractAddress> {
     tracing::trace!("get_repo_address: gosh_root_addr={gosh_root_addr}, dao={dao}, repo={repo}");
-    let contract = GoshContract::new(gosh_root_addr, gosh_abi::GOSH);
+    let contract = TypedContract::<systemcontract::Contract>::new(gosh_root_addr);
 
-    let args = serde_json::json!({ "dao": dao, "name": repo });
-    let result: GetRepoAddrResult = contract
-        .read_state(context, "getAddrRepository", Some(args))
-        .await?;
+    let args = systemcontract::GetAddrRepository {
+        name: repo.to_owned(),
+        dao: dao.to_owned(),
+    };
+    let result = contract.read_state_fn(context, args).await?;
     tracing::trace!("get_repo_address result: {:?}", result);
-    Ok(BlockchainContractAddress::new(result.address))
+    Ok(result.value0)
 }
 
 #[instrument(level = "info", skip_all)]
 pub async fn branch_list(
     context: &EverClient,
     repo_addr: &BlockchainContractAddress,
-) -> anyhow::Result<GetAllAddressResult> {
+) -> anyhow::Result<Vec<BranchRef>> {
     tracing::trace!("branch_list: repo_addr={repo_addr}");
-    let contract = GoshContract::new(repo_addr, gosh_abi::REPO);
+    let contract = TypedContract::<repository::Contract>::new(repo_addr);
 
-    let result: GetAllAddressResult = contract.read_state(context, "getAllAddress", None).await?;
+    let result = contract
+        .read_state_fn(context, repository::GetAllAddress {})
+        .await?;
     tracing::trace!("branch_list result: {:?}", result);
-    Ok(result)
+    Ok(result.value0.into_iter().map(BranchRef::from).collect())
 }
 
 #[instrument(level = "trace", skip_all)]
@@ -643,20 +528,28 @@ pub async fn get_contract_code(
     context: &EverClient,
     repo_addr: &BlockchainContractAddress,
     kind: ContractKind,
-) -> anyhow::Result<GetContractCodeResult> {
+) -> anyhow::Result<String> {
     tracing::debug!("get_contract_code: repo_addr={repo_addr}");
-    let contract = GoshContract::new(repo_addr, gosh_abi::REPO);
-
-    let fn_name = match kind {
-        ContractKind::Commit => "getCommitCode",
-        ContractKind::Tag => "getTagCode",
+    let contract = TypedContract::<repository::Contract>::new(repo_addr);
+
+    let code = match kind {
+        ContractKind::Commit => {
+            contract
+                .read_state_fn(context, repository::GetCommitCode {})
+                .await?
+                .value0
+        }
+        ContractKind::Tag => {
+            contract
+                .read_state_fn(context, repository::GetTagCode {})
+                .await?
+                .value0
+        }
         _ => unimplemented!(),
     };
+    tracing::debug!("get_contract_code result: {code:?}");
 
-    let result: GetContractCodeResult = contract.read_state(context, fn_name, None).await?;
-    tracing::debug!("{fn_name} result: {result:?}");
-
-    Ok(result)
+    Ok(code)
 }
 
 #[instrument(level = "trace", skip_all)]
@@ -666,8 +559,7 @@ pub async fn tag_list(
 ) -> anyhow::Result<Vec<String>> {
     tracing::debug!("tag_list: repo_addr={repo_addr}");
 
-    let GetContractCodeResult { code } =
-        get_contract_code(context, repo_addr, ContractKind::Tag).await?;
+    let code = get_contract_code(context, repo_addr, ContractKind::Tag).await?;
 
     let hash = calculate_boc_hash(context, &code).await?;
     let query = r#"query($code_hash: String!) {
@@ -679,8 +571,9 @@ pub async fn tag_list(
     }"#
     .to_string();
 
-    let result = ton_client::net::query(
-        Arc::clone(&context),
+    let raw_accounts = bundle::query_accounts_by_code_hash(
+        context,
+        &hash,
         ParamsOfQuery {
             query,
             variables: Some(serde_json::json!({
@@ -689,20 +582,19 @@ pub async fn tag_list(
             ..Default::default()
         },
     )
-    .await
-    .map(|r| r.result)
-    .map_err(|e| anyhow::format_err!("query error: {e}"))?;
+    .await?;
 
-    let raw_accounts = result["data"]["accounts"].clone();
     let accounts: Vec<Account> = serde_json::from_value(raw_accounts)?;
 
     let mut result: Vec<String> = vec![];
 
     for account in accounts {
         let address = BlockchainContractAddress::new(account.address);
-        let tag_contract = GoshContract::new(address, gosh_abi::TAG);
-        let GetTagContentResult { content } =
-            tag_contract.read_state(context, "getContent", None).await?;
+        let tag_contract = TypedContract::<bindings::tag::Contract>::new(address);
+        let content = tag_contract
+            .read_state_fn(context, bindings::tag::GetContent {})
+            .await?
+            .value0;
         let mut iter = content.split('\n');
         let first = iter.next().unwrap();
         let item = if first.starts_with("tag") {
@@ -732,14 +624,7 @@ pub async fn get_commit_address(
     sha: &str,
 ) -> anyhow::Result<BlockchainContractAddress> {
     tracing::trace!("get_commit_address: repo_contract={repo_contract:?}, sha={sha}");
-    let result: GetCommitAddrResult = repo_contract
-        .static_method(
-            context,
-            "getCommitAddr",
-            gosh_abi::get_commit_addr_args(sha),
-        )
-        .await?;
-    Ok(result.address)
+    address::commit_address(context, repo_contract, sha).await
 }
 
 #[instrument(level = "info", skip_all)]
@@ -756,10 +641,12 @@ pub async fn get_head(
     context: &EverClient,
     address: &BlockchainContractAddress,
 ) -> anyhow::Result<String> {
-    let contract = GoshContract::new(address, gosh_abi::REPO);
-    let result: GetHeadResult = contract.read_state(context, "getHEAD", None).await?;
+    let contract = TypedContract::<repository::Contract>::new(address);
+    let result = contract
+        .read_state_fn(context, repository::GetHEAD {})
+        .await?;
     tracing::trace!("get_head result: {:?}", result);
-    Ok(result.head)
+    Ok(result.value0)
 }
 
 #[instrument(level = "trace", skip_all)]
@@ -913,16 +800,16 @@ pub mod tests {
             .unwrap();
         let address = "0:4de6a95c7dbfebef9bad6ef7f34f6a31f62953f989a169e81ef71493332ac4a6";
         let address = BlockchainContractAddress::new(address);
-        let contract = GoshContract::new(address, gosh_abi::REPO);
-        let list: GetAllAddressResult = contract
-            .run_static(&te.client, "getAllAddress", None)
+        let contract = TypedContract::<repository::Contract>::new(address);
+        let list = contract
+            .run_static_fn(&te.client, repository::GetAllAddress {})
             .await
             .unwrap();
 
         eprintln!("{:#?}", list);
 
-        let head: GetHeadResult = contract
-            .run_static(&te.client, "getHEAD", None)
+        let head = contract
+            .run_static_fn(&te.client, repository::GetHEAD {})
             .await
             .unwrap();
 
@@ -15,6 +15,12 @@ pub struct Number(u8);
 #[serde(transparent)]
 pub struct NumberU64(u64);
 
+impl From<u64> for NumberU64 {
+    fn from(value: u64) -> Self {
+        NumberU64(value)
+    }
+}
+
 impl Into<u64> for NumberU64 {
     fn into(self) -> u64 {
         self.0
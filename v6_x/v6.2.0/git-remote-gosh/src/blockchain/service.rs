@@ -1,15 +1,14 @@
 use super::{
     branch::{DeleteBranch, DeployBranch},
     commit::save::BlockchainCommitPusher,
-    contract::ContractRead,
+    contract::TypedContract,
     snapshot::save::{DeleteSnapshot, DeployDiff, DeployNewSnapshot},
     tag::save::Tagging,
     tree::DeployTree,
     user_wallet::BlockchainUserWalletService,
-    BlockchainContractAddress, EverClient, Everscale, GetAddrBranchResult, GetBoolResult,
-    GoshCommit, GoshContract,
+    BlockchainContractAddress, BranchRef, EverClient, Everscale, GoshCommit, GoshContract,
 };
-use crate::abi as gosh_abi;
+use crate::abi::bindings::repository;
 use async_trait::async_trait;
 
 use crate::blockchain::check_contracts_deployed;
@@ -81,14 +80,14 @@ impl BlockchainBranchesService for Everscale {
         branch_name: &str,
     ) -> anyhow::Result<bool> {
         tracing::trace!("is_branch_protected: repository_address={repository_address}, branch_name={branch_name}");
-        let contract = GoshContract::new(repository_address, gosh_abi::REPO);
+        let contract = TypedContract::<repository::Contract>::new(repository_address);
 
-        let params = serde_json::json!({ "branch": branch_name });
-        let result: GetBoolResult = contract
-            .read_state(self.client(), "isBranchProtected", Some(params))
-            .await?;
+        let args = repository::IsBranchProtected {
+            branch: branch_name.to_owned(),
+        };
+        let result = contract.read_state_fn(self.client(), args).await?;
         tracing::trace!("is_branch_protected result: {:?}", result);
-        Ok(result.is_ok)
+        Ok(result.value0)
     }
 
     #[instrument(level = "info", skip_all)]
@@ -98,16 +97,17 @@ impl BlockchainBranchesService for Everscale {
         rev: &str,
     ) -> anyhow::Result<Option<(BlockchainContractAddress, String)>> {
         tracing::trace!("remote_rev_parse: repository_address={repository_address}, rev={rev}");
-        let contract = GoshContract::new(repository_address, gosh_abi::REPO);
-        let args = serde_json::json!({ "name": rev });
-        let result: GetAddrBranchResult = contract
-            .read_state(self.client(), "getAddrBranch", Some(args))
-            .await?;
+        let contract = TypedContract::<repository::Contract>::new(repository_address);
+        let args = repository::GetAddrBranch {
+            name: rev.to_owned(),
+        };
+        let result = contract.read_state_fn(self.client(), args).await?;
         tracing::trace!("remote_rev_parse result: {:?}", result);
-        if result.branch.branch_name.is_empty() {
+        let branch = BranchRef::from(result.value0);
+        if branch.branch_name.is_empty() {
             Ok(None)
         } else {
-            Ok(Some((result.branch.commit_address, result.branch.version)))
+            Ok(Some((branch.commit_address, branch.version)))
         }
     }
 }
@@ -0,0 +1,865 @@
+//! In-process model of a GOSH repository implementing `BlockchainService`,
+//! so pushes run in tests without a network. Clones share the state.
+
+use super::{
+    branch::{DeleteBranch, DeployBranch},
+    commit::save::BlockchainCommitPusher,
+    snapshot::save::{DeleteSnapshot, DeployDiff, DeployNewSnapshot, Diff},
+    tag::save::Tagging,
+    tree::{load::TreeComponent, DeployTree},
+    tvm_hash,
+    user_wallet::{BlockchainUserWalletService, UserWallet, UserWalletMirrors},
+    AddrVersion, BlockchainBranchesService, BlockchainCommitService, BlockchainContractAddress,
+    BlockchainReadContractState, BlockchainService, EverClient, GoshCommit, GoshContract,
+    ZERO_SHA,
+};
+use crate::{
+    abi as gosh_abi,
+    config::{Config, UserWalletConfig},
+    database::GoshDB,
+    git_helper::supported_contract_version,
+    utilities::Remote,
+};
+use async_trait::async_trait;
+use git_hash::ObjectId;
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+use ton_client::{
+    utils::{compress_zstd, decompress_zstd},
+    ClientConfig, ClientContext,
+};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SimCommit {
+    pub commit_id: String,
+    pub tree_sha: String,
+    pub raw_commit: String,
+    pub parents: Vec<AddrVersion>,
+    pub upgrade: bool,
+    pub is_correct: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SimSnapshot {
+    pub file_path: String,
+    pub commit_id: String,
+    /// hex encoded zstd compressed content, empty when it is stored in IPFS
+    pub content: String,
+    pub ipfs: Option<String>,
+}
+
+impl SimSnapshot {
+    pub fn decompressed(&self) -> anyhow::Result<Vec<u8>> {
+        if self.content.is_empty() {
+            return Ok(vec![]);
+        }
+        Ok(decompress_zstd(&hex::decode(&self.content)?)?)
+    }
+}
+
+/// Message emitted by a contract, e.g. `allCorrect` by a commit
+#[derive(Debug, Clone, PartialEq)]
+pub struct SimEvent {
+    pub address: BlockchainContractAddress,
+    pub name: String,
+}
+
+#[derive(Debug, Default)]
+struct SimState {
+    branches: HashMap<String, String>,
+    protected_branches: HashSet<String>,
+    commits: HashMap<BlockchainContractAddress, SimCommit>,
+    trees: HashMap<String, HashMap<String, TreeComponent>>,
+    tree_addresses: HashSet<BlockchainContractAddress>,
+    snapshots: HashMap<BlockchainContractAddress, SimSnapshot>,
+    // commit id -> diffs waiting for `setCommit`
+    pending_diffs: HashMap<String, Vec<Diff>>,
+    tags: HashMap<String, (String, String)>,
+    events: Vec<SimEvent>,
+}
+
+/// Commits become correct and their diffs are applied to snapshots only
+/// when `setCommit` is accepted, like in the contracts
+#[derive(Clone)]
+pub struct Simulator {
+    repo_name: String,
+    client: EverClient,
+    root_contract: GoshContract,
+    repo_contract: GoshContract,
+    wallet_config: Option<UserWalletConfig>,
+    state: Arc<Mutex<SimState>>,
+}
+
+impl std::fmt::Debug for Simulator {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Simulator")
+            .field("repo_name", &self.repo_name)
+            .finish()
+    }
+}
+
+fn derive_address(kind: &str, name: &str) -> BlockchainContractAddress {
+    BlockchainContractAddress::new(format!("0:{}", sha256::digest(format!("{kind}/{name}"))))
+}
+
+impl Simulator {
+    /// Repository with the `main` branch pointing to the zero commit,
+    /// like a freshly deployed one
+    pub fn new(repo_name: &str) -> anyhow::Result<Self> {
+        let client = Arc::new(ClientContext::new(ClientConfig::default())?);
+        let mut state = SimState::default();
+        state
+            .branches
+            .insert("main".to_owned(), ZERO_SHA.to_owned());
+        Ok(Self {
+            repo_name: repo_name.to_owned(),
+            client,
+            root_contract: GoshContract::new(derive_address("root", ""), gosh_abi::GOSH),
+            repo_contract: GoshContract::new(derive_address("repo", repo_name), gosh_abi::REPO),
+            wallet_config: None,
+            state: Arc::new(Mutex::new(state)),
+        })
+    }
+
+    pub fn commit_address(&self, commit_id: &str) -> BlockchainContractAddress {
+        derive_address(&format!("{}/commit", self.repo_name), commit_id)
+    }
+
+    pub fn snapshot_address(&self, commit_id: &str, file_path: &str) -> BlockchainContractAddress {
+        derive_address(
+            &format!("{}/snapshot", self.repo_name),
+            &format!("{commit_id}/{file_path}"),
+        )
+    }
+
+    pub fn protect_branch(&self, branch_name: &str) {
+        let mut state = self.state.lock().unwrap();
+        state.protected_branches.insert(branch_name.to_owned());
+    }
+
+    /// Commit id of the branch head
+    pub fn branch_head(&self, branch_name: &str) -> Option<String> {
+        let state = self.state.lock().unwrap();
+        state.branches.get(branch_name).cloned()
+    }
+
+    pub fn snapshot(&self, address: &BlockchainContractAddress) -> Option<SimSnapshot> {
+        let state = self.state.lock().unwrap();
+        state.snapshots.get(address).cloned()
+    }
+
+    pub fn tag(&self, tag_name: &str) -> Option<(String, String)> {
+        let state = self.state.lock().unwrap();
+        state.tags.get(tag_name).cloned()
+    }
+
+    pub fn events(&self) -> Vec<SimEvent> {
+        self.state.lock().unwrap().events.clone()
+    }
+
+    fn check_repo(&self, repo_name: &str) -> anyhow::Result<()> {
+        if repo_name != self.repo_name {
+            anyhow::bail!("Unknown repository {repo_name}");
+        }
+        Ok(())
+    }
+
+    /// Content of the snapshot after the diff or an error if it doesn't
+    /// match the hash from the diff
+    async fn apply_diff(&self, snapshot: &SimSnapshot, diff: &Diff) -> anyhow::Result<SimSnapshot> {
+        let mut updated = snapshot.clone();
+        updated.commit_id = diff.commit_id.clone();
+        if let Some(ipfs) = &diff.ipfs {
+            updated.content = String::new();
+            updated.ipfs = Some(ipfs.clone());
+            return Ok(updated);
+        }
+        let patch = diff
+            .patch
+            .as_ref()
+            .ok_or_else(|| anyhow::format_err!("Diff has neither patch nor ipfs"))?;
+        let content = if diff.remove_ipfs {
+            decompress_zstd(&hex::decode(patch)?)?
+        } else {
+            let patch = decompress_zstd(&hex::decode(patch)?)?;
+            let patch = diffy::Patch::from_bytes(&patch)?;
+            diffy::apply_bytes(&snapshot.decompressed()?, &patch)?
+        };
+        let sha256 = format!("0x{}", tvm_hash(&self.client, &content).await?);
+        if sha256 != diff.sha256 {
+            anyhow::bail!(
+                "Content hash mismatch: expected {}, got {sha256}",
+                diff.sha256
+            );
+        }
+        updated.content = hex::encode(compress_zstd(&content, None)?);
+        updated.ipfs = None;
+        Ok(updated)
+    }
+
+    /// Snapshots changed by the diffs, in the state after all of them
+    async fn apply_diffs(
+        &self,
+        diffs: &[Diff],
+    ) -> anyhow::Result<HashMap<BlockchainContractAddress, SimSnapshot>> {
+        let mut updated: HashMap<BlockchainContractAddress, SimSnapshot> = HashMap::new();
+        for diff in diffs {
+            let snapshot = match updated.get(&diff.snapshot_addr) {
+                Some(snapshot) => snapshot.clone(),
+                None => self.snapshot(&diff.snapshot_addr).ok_or_else(|| {
+                    anyhow::format_err!("Snapshot {} is not deployed", diff.snapshot_addr)
+                })?,
+            };
+            let snapshot = self.apply_diff(&snapshot, diff).await?;
+            updated.insert(diff.snapshot_addr.clone(), snapshot);
+        }
+        Ok(updated)
+    }
+
+    /// Result of `setCommit` on the commit contract. `changed_files` is
+    /// `None` when some diff of the commit doesn't apply
+    fn validate_commit(
+        state: &SimState,
+        commit: &SimCommit,
+        branch: &str,
+        changed_files: Option<usize>,
+        number_of_files_changed: u32,
+    ) -> &'static str {
+        if !state.trees.contains_key(&commit.tree_sha) {
+            return "cancelCommit";
+        }
+        if changed_files != Some(number_of_files_changed as usize) {
+            return "cancelCommit";
+        }
+        if commit.upgrade {
+            return "treeAccept";
+        }
+        let head = match state.branches.get(branch) {
+            Some(head) => head,
+            None => return "cancelCommit",
+        };
+        let based_on_head = (head == ZERO_SHA && commit.parents.is_empty())
+            || commit.parents.iter().any(|parent| {
+                state
+                    .commits
+                    .get(&parent.address)
+                    .map(|parent| &parent.commit_id == head)
+                    .unwrap_or(false)
+            });
+        if !based_on_head {
+            return "NotCorrectRepo";
+        }
+        "allCorrect"
+    }
+}
+
+#[async_trait]
+impl DeployBranch for Simulator {
+    async fn deploy_branch(
+        &self,
+        _wallet: &UserWallet,
+        repo_name: String,
+        new_name: String,
+        from_commit: String,
+    ) -> anyhow::Result<()> {
+        tracing::trace!("simulator deploy_branch: new_name={new_name}, from_commit={from_commit}");
+        self.check_repo(&repo_name)?;
+        let mut state = self.state.lock().unwrap();
+        if state.branches.contains_key(&new_name) {
+            anyhow::bail!("Branch {new_name} already exists");
+        }
+        let commit_address = self.commit_address(&from_commit);
+        if from_commit != ZERO_SHA && !state.commits.contains_key(&commit_address) {
+            anyhow::bail!("Commit {from_commit} is not deployed");
+        }
+        state.branches.insert(new_name, from_commit);
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl DeleteBranch for Simulator {
+    async fn delete_branch(
+        &self,
+        _wallet: &UserWallet,
+        repo_name: String,
+        branch_name: String,
+    ) -> anyhow::Result<()> {
+        tracing::trace!("simulator delete_branch: branch_name={branch_name}");
+        self.check_repo(&repo_name)?;
+        let mut state = self.state.lock().unwrap();
+        if state.protected_branches.contains(&branch_name) {
+            anyhow::bail!("Branch {branch_name} is protected");
+        }
+        state
+            .branches
+            .remove(&branch_name)
+            .ok_or_else(|| anyhow::format_err!("Branch {branch_name} doesn't exist"))?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl DeployTree for Simulator {
+    async fn deploy_tree(
+        &self,
+        _wallet: &UserWallet,
+        sha: &str,
+        tree_address: &str,
+        repo_name: &str,
+        nodes: &mut HashMap<String, TreeComponent>,
+        sha_inner_hash: &str,
+    ) -> anyhow::Result<()> {
+        tracing::trace!("simulator deploy_tree: sha={sha}, tree_address={tree_address}");
+        self.check_repo(repo_name)?;
+        let mut state = self.state.lock().unwrap();
+        // tree is deployed by chunks of nodes
+        state
+            .trees
+            .entry(sha_inner_hash.to_owned())
+            .or_default()
+            .extend(nodes.drain());
+        state
+            .tree_addresses
+            .insert(BlockchainContractAddress::new(tree_address));
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl DeployDiff for Simulator {
+    async fn deploy_diff(
+        &self,
+        _wallet: &UserWallet,
+        repo_name: String,
+        branch_name: String,
+        commit_id: String,
+        diff: Diff,
+        index1: u32,
+        index2: u32,
+        last: bool,
+    ) -> anyhow::Result<()> {
+        tracing::trace!(
+            "simulator deploy_diff: commit_id={commit_id}, index1={index1}, index2={index2}, last={last}"
+        );
+        self.check_repo(&repo_name)?;
+        let mut state = self.state.lock().unwrap();
+        if !state.snapshots.contains_key(&diff.snapshot_addr) {
+            anyhow::bail!("Snapshot {} is not deployed", diff.snapshot_addr);
+        }
+        // diffs are delivered even when they are wrong, they are checked
+        // and applied on `setCommit`
+        state.pending_diffs.entry(commit_id).or_default().push(diff);
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl DeployNewSnapshot for Simulator {
+    async fn deploy_new_snapshot(
+        &self,
+        _wallet: &UserWallet,
+        repo_address: BlockchainContractAddress,
+        commit_id: String,
+        file_path: String,
+        content: String,
+        ipfs: Option<String>,
+    ) -> anyhow::Result<()> {
+        tracing::trace!(
+            "simulator deploy_new_snapshot: commit_id={commit_id}, file_path={file_path}"
+        );
+        if repo_address != self.repo_contract.address {
+            anyhow::bail!("Unknown repository {repo_address}");
+        }
+        let address = self.snapshot_address(&commit_id, &file_path);
+        let mut state = self.state.lock().unwrap();
+        // deploy to an existing address has no effect
+        state.snapshots.entry(address).or_insert(SimSnapshot {
+            file_path,
+            commit_id,
+            content,
+            ipfs,
+        });
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl DeleteSnapshot for Simulator {
+    async fn delete_snapshot(
+        &self,
+        _wallet: &UserWallet,
+        snapshot_address: BlockchainContractAddress,
+    ) -> anyhow::Result<()> {
+        tracing::trace!("simulator delete_snapshot: snapshot_address={snapshot_address}");
+        let mut state = self.state.lock().unwrap();
+        state
+            .snapshots
+            .remove(&snapshot_address)
+            .ok_or_else(|| anyhow::format_err!("Snapshot {snapshot_address} is not deployed"))?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Tagging for Simulator {
+    async fn deploy_tag(
+        &self,
+        _wallet: &UserWallet,
+        repo_name: String,
+        tag_name: String,
+        commit_id: String,
+        content: String,
+        commit_address: BlockchainContractAddress,
+    ) -> anyhow::Result<()> {
+        tracing::trace!("simulator deploy_tag: tag_name={tag_name}, commit_id={commit_id}");
+        self.check_repo(&repo_name)?;
+        let mut state = self.state.lock().unwrap();
+        if !state.commits.contains_key(&commit_address) {
+            anyhow::bail!("Commit {commit_id} is not deployed");
+        }
+        state.tags.insert(tag_name, (commit_id, content));
+        Ok(())
+    }
+
+    async fn delete_tag(
+        &self,
+        _wallet: &UserWallet,
+        repo_name: String,
+        tag_name: String,
+    ) -> anyhow::Result<()> {
+        tracing::trace!("simulator delete_tag: tag_name={tag_name}");
+        self.check_repo(&repo_name)?;
+        let mut state = self.state.lock().unwrap();
+        state
+            .tags
+            .remove(&tag_name)
+            .ok_or_else(|| anyhow::format_err!("Tag {tag_name} doesn't exist"))?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl BlockchainCommitPusher for Simulator {
+    async fn push_commit(
+        &self,
+        commit_address: &str,
+        remote: &Remote,
+        _dao_addr: &BlockchainContractAddress,
+        database: Arc<GoshDB>,
+    ) -> anyhow::Result<()> {
+        tracing::trace!("simulator push_commit: commit_address={commit_address}");
+        self.check_repo(&remote.repo)?;
+        let commit = database.get_commit(commit_address)?;
+        let expected_address = self.commit_address(&commit.commit_id);
+        if String::from(&expected_address) != commit_address {
+            anyhow::bail!(
+                "Commit {} is expected at {expected_address}, not at {commit_address}",
+                commit.commit_id
+            );
+        }
+        let mut state = self.state.lock().unwrap();
+        // deploy to an existing address has no effect
+        state.commits.entry(expected_address).or_insert(SimCommit {
+            commit_id: commit.commit_id,
+            tree_sha: commit.tree_sha,
+            raw_commit: commit.raw_commit,
+            parents: commit.parents,
+            upgrade: commit.upgrade_commit,
+            is_correct: false,
+        });
+        Ok(())
+    }
+
+    async fn notify_commit(
+        &self,
+        commit_id: &ObjectId,
+        branch: &str,
+        number_of_files_changed: u32,
+        number_of_commits: u64,
+        remote: &Remote,
+        _dao_addr: &BlockchainContractAddress,
+        is_upgrade: bool,
+        _config: &Config,
+    ) -> anyhow::Result<()> {
+        tracing::trace!(
+            "simulator notify_commit: commit_id={commit_id}, branch={branch}, number_of_files_changed={number_of_files_changed}, number_of_commits={number_of_commits}"
+        );
+        self.check_repo(&remote.repo)?;
+        let address = self.commit_address(&commit_id.to_string());
+        let (commit, diffs) = {
+            let mut state = self.state.lock().unwrap();
+            let commit = match state.commits.get(&address) {
+                Some(commit) => commit.clone(),
+                // nobody answers `setCommit` of a missing commit
+                None => anyhow::bail!("Time is up. Fix and retry"),
+            };
+            let diffs = state
+                .pending_diffs
+                .remove(&commit.commit_id)
+                .unwrap_or_default();
+            (commit, diffs)
+        };
+        let updated = match self.apply_diffs(&diffs).await {
+            Ok(updated) => Some(updated),
+            Err(e) => {
+                tracing::trace!("simulator: diffs of {} are rejected: {e}", commit.commit_id);
+                None
+            }
+        };
+        let mut state = self.state.lock().unwrap();
+        let changed_files = updated.as_ref().map(|updated| updated.len());
+        let mut event =
+            Self::validate_commit(&state, &commit, branch, changed_files, number_of_files_changed);
+        if event == "treeAccept" && !is_upgrade {
+            event = "cancelCommit";
+        }
+        state.events.push(SimEvent {
+            address: address.clone(),
+            name: event.to_owned(),
+        });
+        if let "allCorrect" | "treeAccept" = event {
+            state.snapshots.extend(updated.unwrap_or_default());
+            state.branches.insert(branch.to_owned(), commit.commit_id);
+            if let Some(commit) = state.commits.get_mut(&address) {
+                commit.is_correct = true;
+            }
+            return Ok(());
+        }
+        // the commit can be set again after the missing parts arrive, diffs
+        // delivered in the meantime go after the kept ones
+        let pending = state.pending_diffs.entry(commit.commit_id).or_default();
+        let delivered = std::mem::replace(pending, diffs);
+        pending.extend(delivered);
+        match event {
+            "NotCorrectRepo" => anyhow::bail!("Push failed. Fetch first"),
+            _ => anyhow::bail!("Push failed. Fix and retry"),
+        }
+    }
+}
+
+#[async_trait]
+impl BlockchainUserWalletService for Simulator {
+    fn wallet_config(&self) -> &Option<UserWalletConfig> {
+        &self.wallet_config
+    }
+
+    async fn user_wallet(
+        &self,
+        _dao_address: &BlockchainContractAddress,
+        _remote_network: &str,
+    ) -> anyhow::Result<UserWallet> {
+        // messages are not signed by the simulator
+        Ok(Arc::new(UserWalletMirrors::new()))
+    }
+}
+
+#[async_trait]
+impl BlockchainBranchesService for Simulator {
+    async fn is_branch_protected(
+        &self,
+        _repository_address: &BlockchainContractAddress,
+        branch_name: &str,
+    ) -> anyhow::Result<bool> {
+        let state = self.state.lock().unwrap();
+        Ok(state.protected_branches.contains(branch_name))
+    }
+
+    async fn remote_rev_parse(
+        &self,
+        repository_address: &BlockchainContractAddress,
+        rev: &str,
+    ) -> anyhow::Result<Option<(BlockchainContractAddress, String)>> {
+        if repository_address != &self.repo_contract.address {
+            return Ok(None);
+        }
+        Ok(self.branch_head(rev).map(|commit_id| {
+            (
+                self.commit_address(&commit_id),
+                supported_contract_version(),
+            )
+        }))
+    }
+}
+
+#[async_trait]
+impl BlockchainCommitService for Simulator {
+    async fn get_commit_by_addr(
+        &self,
+        address: &BlockchainContractAddress,
+    ) -> anyhow::Result<Option<GoshCommit>> {
+        let commit = match self.state.lock().unwrap().commits.get(address) {
+            Some(commit) => commit.clone(),
+            None => return Ok(None),
+        };
+        let commit = serde_json::from_value(serde_json::json!({
+            "time": "0",
+            "repo": self.repo_contract.address,
+            "sha": commit.commit_id,
+            "parents": commit.parents,
+            "content": commit.raw_commit,
+            "initupgrade": commit.upgrade,
+            "isCorrectCommit": commit.is_correct,
+            "isPinned": false,
+        }))?;
+        Ok(Some(commit))
+    }
+}
+
+#[async_trait]
+impl BlockchainReadContractState for Simulator {
+    async fn check_contracts_state(
+        &self,
+        addresses: &[BlockchainContractAddress],
+        allow_incomplete_results: bool,
+    ) -> anyhow::Result<Vec<BlockchainContractAddress>> {
+        let state = self.state.lock().unwrap();
+        let deployed: Vec<BlockchainContractAddress> = addresses
+            .iter()
+            .filter(|address| {
+                state.commits.contains_key(address)
+                    || state.snapshots.contains_key(address)
+                    || state.tree_addresses.contains(address)
+            })
+            .cloned()
+            .collect();
+        if deployed.len() != addresses.len() && !allow_incomplete_results {
+            anyhow::bail!(
+                "Some accounts are missing. Expecting {} boc results while have {}",
+                addresses.len(),
+                deployed.len()
+            );
+        }
+        Ok(deployed)
+    }
+}
+
+impl BlockchainService for Simulator {
+    fn client(&self) -> &EverClient {
+        &self.client
+    }
+
+    fn root_contract(&self) -> &GoshContract {
+        &self.root_contract
+    }
+
+    fn repo_contract(&self) -> &GoshContract {
+        &self.repo_contract
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::git_helper::push::parallel_snapshot_upload_support::ParallelCommit;
+    use std::str::FromStr;
+
+    const REPO: &str = "repo";
+
+    fn remote() -> Remote {
+        Remote {
+            scheme: "gosh".to_owned(),
+            network: "localhost".to_owned(),
+            account: String::new(),
+            dao: "dao".to_owned(),
+            repo: REPO.to_owned(),
+            gosh: BlockchainContractAddress::new("0:0"),
+        }
+    }
+
+    fn compressed(content: &[u8]) -> String {
+        hex::encode(compress_zstd(content, None).unwrap())
+    }
+
+    struct Push<'a> {
+        simulator: &'a Simulator,
+        database: Arc<GoshDB>,
+        wallet: UserWallet,
+    }
+
+    impl<'a> Push<'a> {
+        async fn commit(
+            &self,
+            commit_id: &str,
+            parents: &[&str],
+            tree_sha: &str,
+        ) -> anyhow::Result<()> {
+            let parents = parents
+                .iter()
+                .map(|parent| AddrVersion {
+                    address: self.simulator.commit_address(parent),
+                    version: supported_contract_version(),
+                })
+                .collect();
+            let address = String::from(&self.simulator.commit_address(commit_id));
+            let commit = ParallelCommit::new(
+                ObjectId::from_str(commit_id)?,
+                tree_sha.to_owned(),
+                format!("tree {tree_sha}"),
+                parents,
+                false,
+            );
+            self.database.put_commit(commit, address.clone())?;
+            self.simulator
+                .deploy_tree(
+                    &self.wallet,
+                    tree_sha,
+                    &format!("0:{tree_sha}"),
+                    REPO,
+                    &mut HashMap::new(),
+                    tree_sha,
+                )
+                .await?;
+            self.simulator
+                .push_commit(
+                    &address,
+                    &remote(),
+                    &BlockchainContractAddress::new("0:0"),
+                    self.database.clone(),
+                )
+                .await
+        }
+
+        async fn diff(
+            &self,
+            commit_id: &str,
+            snapshot_address: &BlockchainContractAddress,
+            from: &str,
+            to: &str,
+        ) -> anyhow::Result<()> {
+            let patch = diffy::create_patch(from, to).to_bytes();
+            let sha256 = format!(
+                "0x{}",
+                tvm_hash(self.simulator.client(), to.as_bytes()).await?
+            );
+            let diff = Diff {
+                snapshot_addr: snapshot_address.clone(),
+                snapshot_file_path: "README".to_owned(),
+                commit_id: commit_id.to_owned(),
+                patch: Some(compressed(&patch)),
+                ipfs: None,
+                remove_ipfs: false,
+                sha1: String::new(),
+                sha256,
+            };
+            self.simulator
+                .deploy_diff(
+                    &self.wallet,
+                    REPO.to_owned(),
+                    "main".to_owned(),
+                    commit_id.to_owned(),
+                    diff,
+                    0,
+                    0,
+                    true,
+                )
+                .await
+        }
+
+        async fn set_commit(&self, commit_id: &str, changed_files: u32) -> anyhow::Result<()> {
+            self.simulator
+                .notify_commit(
+                    &ObjectId::from_str(commit_id)?,
+                    "main",
+                    changed_files,
+                    1,
+                    &remote(),
+                    &BlockchainContractAddress::new("0:0"),
+                    false,
+                    &Config::default(),
+                )
+                .await
+        }
+    }
+
+    #[tokio::test]
+    async fn ensure_push_and_clone_roundtrip() {
+        let git_dir =
+            std::env::temp_dir().join(format!("simulator_roundtrip_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&git_dir);
+        std::fs::create_dir_all(&git_dir).unwrap();
+        let simulator = Simulator::new(REPO).unwrap();
+        let push = Push {
+            simulator: &simulator,
+            database: Arc::new(GoshDB::open_in(git_dir.to_str().unwrap()).unwrap()),
+            wallet: Arc::new(UserWalletMirrors::new()),
+        };
+        let first = "1111111111111111111111111111111111111111";
+        let second = "2222222222222222222222222222222222222222";
+        let stale = "3333333333333333333333333333333333333333";
+
+        // the first commit brings the snapshot
+        push.commit(first, &[], "a1").await.unwrap();
+        simulator
+            .deploy_new_snapshot(
+                &push.wallet,
+                simulator.repo_contract().address.clone(),
+                first.to_owned(),
+                "README".to_owned(),
+                compressed(b"hello\n"),
+                None,
+            )
+            .await
+            .unwrap();
+        push.set_commit(first, 0).await.unwrap();
+
+        // the second one changes it
+        let snapshot_address = simulator.snapshot_address(first, "README");
+        push.commit(second, &[first], "b2").await.unwrap();
+        push.diff(second, &snapshot_address, "hello\n", "hello\nworld\n")
+            .await
+            .unwrap();
+        // the diff is not applied until the commit is accepted
+        assert!(push.set_commit(second, 2).await.is_err());
+        assert_eq!(simulator.branch_head("main").unwrap(), first);
+        let snapshot = simulator.snapshot(&snapshot_address).unwrap();
+        assert_eq!(snapshot.decompressed().unwrap(), b"hello\n");
+        push.set_commit(second, 1).await.unwrap();
+
+        // commit based on the old head is refused
+        push.commit(stale, &[first], "c3").await.unwrap();
+        let error = push.set_commit(stale, 0).await.unwrap_err();
+        assert_eq!(error.to_string(), "Push failed. Fetch first");
+
+        // clone
+        let (head_address, _) = simulator
+            .remote_rev_parse(&simulator.repo_contract().address, "main")
+            .await
+            .unwrap()
+            .unwrap();
+        let head = simulator
+            .get_commit_by_addr(&head_address)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(head.sha, second);
+        assert!(head.is_correct_commit);
+        assert_eq!(head.parents[0].address, simulator.commit_address(first));
+        let snapshot = simulator.snapshot(&snapshot_address).unwrap();
+        assert_eq!(snapshot.decompressed().unwrap(), b"hello\nworld\n");
+        let events: Vec<String> = simulator.events().into_iter().map(|e| e.name).collect();
+        assert_eq!(
+            events,
+            ["allCorrect", "cancelCommit", "allCorrect", "NotCorrectRepo"]
+        );
+
+        // a diff that doesn't match its hash cancels the commit
+        let broken = "4444444444444444444444444444444444444444";
+        push.commit(broken, &[second], "d4").await.unwrap();
+        push.diff(broken, &snapshot_address, "hello\n", "bye\n")
+            .await
+            .unwrap();
+        let error = push.set_commit(broken, 1).await.unwrap_err();
+        assert_eq!(error.to_string(), "Push failed. Fix and retry");
+        let snapshot = simulator.snapshot(&snapshot_address).unwrap();
+        assert_eq!(snapshot.decompressed().unwrap(), b"hello\nworld\n");
+
+        simulator.protect_branch("main");
+        assert!(
+            simulator
+                .delete_branch(&push.wallet, REPO.to_owned(), "main".to_owned())
+                .await
+                .is_err()
+        );
+    }
+}
@@ -1,7 +1,7 @@
 use std::iter::Iterator;
 
 // TODO: leave only one struct Diff
-#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct Diff {
     #[serde(rename = "snap")]
     snapshot_contract_address: String,
@@ -30,6 +30,14 @@ impl Diff {
         f(Some(&patch))
     }
 
+    /// Size of the compressed patch stored in the contract
+    pub fn patch_size(&self) -> usize {
+        self.patch
+            .as_ref()
+            .map(|patch| patch.len() / 2)
+            .unwrap_or(0)
+    }
+
     pub fn get_patch_data(&self) -> Option<Vec<u8>> {
         let data: String = match &self.patch {
             None => return None,
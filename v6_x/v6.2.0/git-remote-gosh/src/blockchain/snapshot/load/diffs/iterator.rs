@@ -4,13 +4,40 @@ use crate::blockchain::{
     get_commit_address, snapshot::diffs::Diff, BlockchainContractAddress, EverClient, GoshContract,
     Snapshot,
 };
+use crate::bundle;
+use crate::cache::object_cache::{self, object_cache};
+use std::future::Future;
 use std::iter::Iterator;
 use std::sync::Arc;
 use either::Either;
 use ton_client::abi::{decode_message_body, Abi, ParamsOfDecodeMessageBody};
 use ton_client::net::ParamsOfQuery;
+use tokio::sync::mpsc;
+
+const GOSH_DIFF_PAGE_SIZE: &str = "GOSH_DIFF_PAGE_SIZE";
+const DEFAULT_DIFF_PAGE_SIZE: u32 = 50;
+const GOSH_DIFF_PREFETCH_PAGES: &str = "GOSH_DIFF_PREFETCH_PAGES";
+const DEFAULT_DIFF_PREFETCH_PAGES: usize = 4;
+
+fn get_diff_page_size() -> u32 {
+    std::env::var(GOSH_DIFF_PAGE_SIZE)
+        .ok()
+        .and_then(|value| value.parse::<u32>().ok())
+        .filter(|value| *value > 0)
+        .unwrap_or(DEFAULT_DIFF_PAGE_SIZE)
+}
+
+fn get_diff_prefetch_pages() -> usize {
+    std::env::var(GOSH_DIFF_PREFETCH_PAGES)
+        .ok()
+        .and_then(|value| value.parse::<usize>().ok())
+        .filter(|value| *value > 0)
+        .unwrap_or(DEFAULT_DIFF_PREFETCH_PAGES)
+}
+
+type LoadedPage = anyhow::Result<(Vec<DiffMessage>, Option<String>)>;
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct DiffMessage {
     pub diff: Diff,
     pub created_at: u64,
@@ -31,6 +58,8 @@ pub struct DiffMessagesIterator {
     next: Option<NextChunk>,
     branch: String,
     from_end_to_start: bool,
+    // pages of the current snapshot loaded ahead of the consumer
+    prefetched_pages: Option<mpsc::Receiver<LoadedPage>>,
 }
 
 #[derive(Debug, Clone)]
@@ -97,6 +126,7 @@ impl DiffMessagesIterator {
             next: Some(NextChunk::MessagesPage(snapshot_address.into(), None)),
             branch,
             from_end_to_start,
+            prefetched_pages: None,
         }
     }
 
@@ -125,10 +155,15 @@ impl DiffMessagesIterator {
             )),
             None => {
                 // find last commit
-                let Snapshot {
-                    original_commit, ..
-                } = Snapshot::load(client, address).await?;
-                let file_path = Snapshot::get_file_path(client, address).await?;
+                let (
+                    Snapshot {
+                        original_commit, ..
+                    },
+                    file_path,
+                ) = tokio::try_join!(
+                    Snapshot::load(client, address),
+                    Snapshot::get_file_path(client, address)
+                )?;
                 let commit_addr =
                     get_commit_address(client, repo_contract, &original_commit).await?;
                 let commit_data = get_commit_by_addr(client, &commit_addr)
@@ -143,7 +178,16 @@ impl DiffMessagesIterator {
                         .await?;
                 let snapshot_contract =
                     GoshContract::new(original_snapshot.clone(), crate::abi::SNAPSHOT);
-                let snapshot_is_active = snapshot_contract.is_active(client).await?;
+                // generate filter
+                let (snapshot_is_active, created_at) = tokio::try_join!(
+                    snapshot_contract.is_active(client),
+                    crate::blockchain::commit::get_set_commit_created_at_time(
+                        client,
+                        repo_contract,
+                        &original_commit,
+                        branch,
+                    )
+                )?;
                 tracing::trace!(
                     "snap={original_snapshot} is {}",
                     if snapshot_is_active {
@@ -159,18 +203,6 @@ impl DiffMessagesIterator {
                     branch,
                     original_snapshot
                 );
-                // generate filter
-                let created_at: u64 = // if snapshot_is_active {
-                    crate::blockchain::commit::get_set_commit_created_at_time(
-                        client,
-                        repo_contract,
-                        &original_commit,
-                        branch,
-                    )
-                    .await?;
-                // } else {
-                //     0u64
-                // };
                 Some(NextChunk::JumpToAnotherBranchSnapshot(
                     original_snapshot,
                     created_at,
@@ -197,14 +229,12 @@ impl DiffMessagesIterator {
                 // Now we will be loading page by page till
                 // we find a message with the expected commit
                 // Fail if not found: it must be there
-                let mut cursor = None;
+                self.prefetched_pages = Some(self.load_pages_ahead(client, address, None));
                 let mut index = None;
                 let mut next_page_info = None;
                 while index.is_none() {
                     tracing::info!("loading messages");
-                    let (buffer, page) =
-                        load_messages_to(client, &address, &cursor, None, self.from_end_to_start)
-                            .await?;
+                    let (buffer, cursor) = recv_page(&mut self.prefetched_pages).await?;
                     for (i, item) in buffer.iter().enumerate() {
                         if &item.created_at <= ignore_commits_created_after {
                             index = Some(i);
@@ -214,9 +244,7 @@ impl DiffMessagesIterator {
                     self.buffer = buffer;
                     if index.is_none() {
                         tracing::info!("Expected commit was not found");
-                        if page.cursor.is_some() {
-                            cursor = page.cursor;
-                        } else {
+                        if cursor.is_none() {
                             // Do not panic but stop search, because this commit can be found in the other branch
                             tracing::info!("snap={address}: We reached the end of the messages queue to a snapshot and were not able to find original commit there.");
                             tracing::info!(
@@ -225,14 +253,19 @@ impl DiffMessagesIterator {
                                 self.buffer_cursor
                             );
                             self.buffer_cursor = 0;
+                            self.prefetched_pages = None;
                             return Ok(LoadStatus::StopSearch);
                         }
                     } else {
                         tracing::info!("Commit found at {}", index.unwrap());
-                        next_page_info = page.cursor;
+                        next_page_info = cursor;
                     }
                 }
                 self.buffer_cursor = index.unwrap();
+                // the loader keeps going with the following pages of the snapshot
+                if next_page_info.is_none() {
+                    self.prefetched_pages = None;
+                }
                 DiffMessagesIterator::into_next_page(
                     client,
                     &address,
@@ -243,17 +276,22 @@ impl DiffMessagesIterator {
                 .await?
             }
             Some(NextChunk::MessagesPage(address, cursor)) => {
-                let (buffer, page) =
-                    load_messages_to(client, &address, cursor, None, self.from_end_to_start)
-                        .await?;
+                if self.prefetched_pages.is_none() {
+                    self.prefetched_pages =
+                        Some(self.load_pages_ahead(client, address, cursor.clone()));
+                }
+                let (buffer, cursor) = recv_page(&mut self.prefetched_pages).await?;
                 self.buffer = buffer;
                 self.buffer_cursor = 0;
-                match page.cursor {
+                match cursor {
                     Some(cursor) => Some(NextChunk::MessagesPage(
                         address.clone(),
                         Some(cursor),
                     )),
-                    None => None
+                    None => {
+                        self.prefetched_pages = None;
+                        None
+                    }
                 }
                 // DiffMessagesIterator::into_next_page(
                 //     client,
@@ -268,6 +306,31 @@ impl DiffMessagesIterator {
         Ok(LoadStatus::Success)
     }
 
+    /// Starts loading pages of the snapshot from `cursor` ahead of the consumer
+    fn load_pages_ahead(
+        &self,
+        client: &EverClient,
+        address: &BlockchainContractAddress,
+        cursor: Option<String>,
+    ) -> mpsc::Receiver<LoadedPage> {
+        let client = Arc::clone(client);
+        let address = address.clone();
+        let from_end_to_start = self.from_end_to_start;
+        spawn_page_loader(
+            move |cursor| {
+                let client = Arc::clone(&client);
+                let address = address.clone();
+                async move {
+                    load_messages_to(&client, &address, &cursor, None, from_end_to_start)
+                        .await
+                        .map(|(buffer, page)| (buffer, page.cursor))
+                }
+            },
+            cursor,
+            get_diff_prefetch_pages(),
+        )
+    }
+
     #[instrument(level = "info", skip_all)]
     fn is_buffer_ready(&self) -> bool {
         self.buffer_cursor < self.buffer.len()
@@ -285,6 +348,48 @@ impl DiffMessagesIterator {
     }
 }
 
+/// Loads pages one after another starting from `cursor` in a separate task.
+/// Up to `depth` pages are kept ahead of the consumer, the task stops after
+/// the last page, on the first error or when the receiver is dropped
+fn spawn_page_loader<F, Fut>(
+    load_page: F,
+    cursor: Option<String>,
+    depth: usize,
+) -> mpsc::Receiver<LoadedPage>
+where
+    F: Fn(Option<String>) -> Fut + Send + 'static,
+    Fut: Future<Output = LoadedPage> + Send + 'static,
+{
+    let (sender, receiver) = mpsc::channel(depth);
+    tokio::spawn(async move {
+        let mut cursor = cursor;
+        loop {
+            let page = load_page(cursor).await;
+            let next_cursor = match &page {
+                Ok((_, next_cursor)) => next_cursor.clone(),
+                Err(_) => None,
+            };
+            if sender.send(page).await.is_err() {
+                break;
+            }
+            match next_cursor {
+                Some(next_cursor) => cursor = Some(next_cursor),
+                None => break,
+            }
+        }
+    });
+    receiver
+}
+
+async fn recv_page(pages: &mut Option<mpsc::Receiver<LoadedPage>>) -> LoadedPage {
+    pages
+        .as_mut()
+        .ok_or_else(|| anyhow::format_err!("Diff messages are not being loaded"))?
+        .recv()
+        .await
+        .ok_or_else(|| anyhow::format_err!("Loading of diff messages was interrupted"))?
+}
+
 #[instrument(level = "info", skip_all)]
 pub async fn load_messages_to(
     context: &EverClient,
@@ -294,11 +399,18 @@ pub async fn load_messages_to(
     from_end_to_start: bool,
 ) -> anyhow::Result<(Vec<DiffMessage>, PageIterator)> {
     tracing::trace!("load_messages_to: address={address}, cursor={cursor:?}, from_end_to_start={from_end_to_start}");
+    let page_size = get_diff_page_size();
+    let cache_key = object_cache::diff_page_key(address, cursor, from_end_to_start, page_size);
+    if stop_on.is_none() {
+        if let Some((messages, cursor)) = object_cache().get_serialized(&cache_key) {
+            return Ok((messages, PageIterator { cursor }));
+        }
+    }
     let query = if from_end_to_start {
-        r#"query($addr: String!, $before: String){
+        r#"query($addr: String!, $before: String, $limit: Int){
       blockchain {
         account(address: $addr) {
-          messages(msg_type: [IntIn], before: $before, last: 50) {
+          messages(msg_type: [IntIn], before: $before, last: $limit) {
             edges {
               node { id body created_at created_lt status bounced }
             }
@@ -308,10 +420,10 @@ pub async fn load_messages_to(
       }
     }"#
     } else {
-        r#"query($addr: String!, $after: String){
+        r#"query($addr: String!, $after: String, $limit: Int){
       blockchain {
         account(address: $addr) {
-          messages(msg_type: [IntIn], after: $after, first: 50) {
+          messages(msg_type: [IntIn], after: $after, first: $limit) {
             edges {
               node { id body created_at created_lt status bounced }
             }
@@ -328,31 +440,47 @@ pub async fn load_messages_to(
         None => "",
     };
 
-    let result = ton_client::net::query(
-        Arc::clone(context),
+    let nodes = bundle::query_messages(
+        context,
+        bundle::MessagesPage {
+            address,
+            cursor: cursor.as_deref(),
+            limit: Some(page_size),
+            from_end: from_end_to_start,
+        },
         ParamsOfQuery {
             query,
             variables: if from_end_to_start {
                 Some(serde_json::json!({
                     "addr": address,
-                    "before": limit
+                    "before": limit,
+                    "limit": page_size
                 }))
             } else {
                 Some(serde_json::json!({
                     "addr": address,
-                    "after": limit
+                    "after": limit,
+                    "limit": page_size
                 }))
             },
             ..Default::default()
         },
     )
-    .await
-    .map(|r| r.result)
-    .map_err(|e| anyhow::format_err!("query error: {e}"))?;
+    .await?;
 
     let mut messages: Vec<DiffMessage> = Vec::new();
-    let nodes = &result["data"]["blockchain"]["account"]["messages"];
-    let edges: Messages = serde_json::from_value(nodes.clone())?;
+    let edges: Messages = serde_json::from_value(nodes)?;
+    // Older messages never change, so a page that ends at the cursor or is
+    // followed by other messages stays the same unless some of its messages
+    // are not finalized yet
+    let is_bounded_page = if from_end_to_start {
+        cursor.is_some()
+    } else {
+        edges.page_info.has_next_page
+    };
+    let is_complete_page = stop_on.is_none()
+        && is_bounded_page
+        && edges.edges.iter().all(|elem| elem.message.status == 5);
     let mut subsequent_page_info = if from_end_to_start {
         if edges.page_info.has_previous_page {
             Some(edges.page_info.start_cursor)
@@ -417,6 +545,9 @@ pub async fn load_messages_to(
     //     0 => None,
     //     n => Some(messages[n - 1].created_at),
     // };
+    if is_complete_page {
+        object_cache().put_serialized(&cache_key, &(&messages, &subsequent_page_info));
+    }
     let page = PageIterator {
         cursor: subsequent_page_info,
         // stop_on: oldest_timestamp,
@@ -430,6 +561,10 @@ pub async fn load_constructor(
     address: &BlockchainContractAddress,
 ) -> anyhow::Result<(Vec<u8>, Option<String>)> {
     tracing::trace!("load_constructor of: address={address}");
+    let cache_key = object_cache::constructor_key(address);
+    if let Some(constructor_data) = object_cache().get_serialized(&cache_key) {
+        return Ok(constructor_data);
+    }
     let query = r#"query($addr: String!, $after: String){
       blockchain {
         account(address: $addr) {
@@ -446,8 +581,14 @@ pub async fn load_constructor(
 
     let after = "";
 
-    let result = ton_client::net::query(
-        Arc::clone(context),
+    let nodes = bundle::query_messages(
+        context,
+        bundle::MessagesPage {
+            address,
+            cursor: None,
+            limit: Some(50),
+            from_end: false,
+        },
         ParamsOfQuery {
             query,
             variables: Some(serde_json::json!({
@@ -457,12 +598,9 @@ pub async fn load_constructor(
             ..Default::default()
         },
     )
-    .await
-    .map(|r| r.result)
-    .map_err(|e| anyhow::format_err!("query error: {e}"))?;
+    .await?;
 
-    let nodes = &result["data"]["blockchain"]["account"]["messages"];
-    let edges: Messages = serde_json::from_value(nodes.clone())?;
+    let edges: Messages = serde_json::from_value(nodes)?;
 
     let mut first_diff = false;
     tracing::trace!("Loaded {} message(s) to {}", edges.edges.len(), address);
@@ -505,7 +643,9 @@ pub async fn load_constructor(
                     None => panic!("Broken diff detected: neither ipfs nor patch exists"),
                 })?;
 
-            return Ok((blob_data, diff.ipfs));
+            let constructor_data = (blob_data, diff.ipfs);
+            object_cache().put_serialized(&cache_key, &constructor_data);
+            return Ok(constructor_data);
         } else if decoded.name == "constructor" {
             tracing::trace!("constructor for address={address} was found");
             let value = decoded.value.unwrap();
@@ -525,7 +665,9 @@ pub async fn load_constructor(
             let decoded_data: Vec<u8> =
                 ton_client::utils::decompress_zstd(&data).expect("Must be correct archive");
 
-            return Ok((decoded_data, ipfs));
+            let constructor_data = (decoded_data, ipfs);
+            object_cache().put_serialized(&cache_key, &constructor_data);
+            return Ok(constructor_data);
         }
     }
 
@@ -534,3 +676,47 @@ pub async fn load_constructor(
         address
     ))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn ensure_pages_are_loaded_ahead_in_order() {
+        let (started, mut started_pages) = mpsc::unbounded_channel();
+        let mut pages = spawn_page_loader(
+            move |cursor: Option<String>| {
+                started.send(cursor.clone()).unwrap();
+                async move {
+                    let page: usize = cursor.map(|c| c.parse().unwrap()).unwrap_or(0);
+                    let next_cursor = if page < 5 {
+                        Some((page + 1).to_string())
+                    } else {
+                        None
+                    };
+                    let page: LoadedPage = Ok((vec![], next_cursor));
+                    page
+                }
+            },
+            None,
+            2,
+        );
+
+        // two pages wait in the channel and the third one waits for a free slot
+        for _ in 0..3 {
+            started_pages.recv().await.unwrap();
+        }
+        assert!(started_pages.try_recv().is_err());
+        let first = pages.recv().await.unwrap().unwrap();
+        assert_eq!(first.1.as_deref(), Some("1"));
+        assert_eq!(started_pages.recv().await.unwrap().as_deref(), Some("3"));
+
+        let mut cursors = vec![first.1];
+        while let Some(page) = pages.recv().await {
+            cursors.push(page.unwrap().1);
+        }
+        assert_eq!(cursors.len(), 6);
+        assert_eq!(cursors[0].as_deref(), Some("1"));
+        assert_eq!(cursors[5], None);
+    }
+}
@@ -1,6 +1,7 @@
 use crate::blockchain::blockchain_contract_address::FormatShort;
-use crate::blockchain::contract::GoshContract;
-use crate::blockchain::{gosh_abi, BlockchainContractAddress, BlockchainService};
+use crate::abi::bindings::diff;
+use crate::blockchain::contract::TypedContract;
+use crate::blockchain::{BlockchainContractAddress, BlockchainService};
 use std::collections::HashSet;
 use tokio::task::JoinSet;
 use tracing::Instrument;
@@ -8,12 +9,6 @@ use tracing::Instrument;
 const MAX_RETRIES_FOR_DIFF_READINESS: i32 = 20;
 const CHUNK_SIZE: usize = 50;
 
-#[derive(Deserialize, Debug, Clone)]
-struct GetStatusResult {
-    #[serde(rename = "value0")]
-    pub correct: bool,
-}
-
 #[instrument(level = "info", skip_all)]
 pub async fn wait_diffs_until_ready<B>(
     blockchain: &B,
@@ -42,13 +37,14 @@ where
                     }
                     let mut not_ready = Vec::<BlockchainContractAddress>::new();
                     for diff_addr in chunk_clone.clone() {
-                        let diff_contract = GoshContract::new(&diff_addr, gosh_abi::DIFF);
-                        let diff_status: anyhow::Result<GetStatusResult> =
-                            diff_contract.run_local(b.client(), "getStatus", None).await;
+                        let diff_contract = TypedContract::<diff::Contract>::new(&diff_addr);
+                        let diff_status = diff_contract
+                            .run_local_fn(b.client(), diff::GetStatus {})
+                            .await;
                         tracing::trace!("get status of {}: {:?}", diff_addr, diff_status);
                         match diff_status {
                             Ok(status) => {
-                                if !status.correct {
+                                if !status.value0 {
                                     // TODO: should wait and ask again
                                     not_ready.push(diff_addr.clone());
                                     tracing::trace!("Diff not ready yet: {}", diff_addr);
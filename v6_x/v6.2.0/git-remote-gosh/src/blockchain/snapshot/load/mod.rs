@@ -1,8 +1,5 @@
 #![allow(unused_variables)]
-use crate::{
-    abi as gosh_abi,
-    blockchain::{contract::ContractRead, EverClient, GoshContract},
-};
+use crate::blockchain::{EverClient, GoshContract};
 
 use crate::blockchain::BlockchainContractAddress;
 use data_contract_macro_derive::DataContract;
@@ -14,6 +11,7 @@ pub mod diffs;
 #[derive(Deserialize, DataContract)]
 #[abi = "snapshot.abi.json"]
 #[abi_data_fn = "getSnapshot"]
+#[abi_getter(method = load_file_path, function = "getName", output = GetSnapshotFilePath)]
 pub struct Snapshot {
     #[serde(rename = "temporaryCommit")]
     pub next_commit: String,
@@ -82,13 +80,7 @@ impl fmt::Debug for Snapshot {
 }
 
 #[derive(Deserialize, Debug)]
-pub struct GetSnapshotAddrResult {
-    #[serde(rename = "value0")]
-    pub address: BlockchainContractAddress,
-}
-
-#[derive(Deserialize, Debug)]
-struct GetSnapshotFilePath {
+pub struct GetSnapshotFilePath {
     #[serde(rename = "value0")]
     pub file_path: String,
 }
@@ -102,14 +94,8 @@ impl Snapshot {
         file_path: &str,
     ) -> anyhow::Result<BlockchainContractAddress> {
         tracing::trace!("calculate_address: commit_sha={commit_sha}, repo_contract.address={}, file_path={file_path}", repo_contract.address);
-        let params = serde_json::json!({
-            "commitsha": commit_sha,
-            "name": file_path
-        });
-        let result: GetSnapshotAddrResult = repo_contract
-            .run_static(context, "getSnapshotAddr", Some(params))
-            .await?;
-        Ok(result.address)
+        crate::blockchain::address::snapshot_address(context, repo_contract, commit_sha, file_path)
+            .await
     }
 
     #[instrument(level = "info", skip_all)]
@@ -118,9 +104,8 @@ impl Snapshot {
         address: &BlockchainContractAddress,
     ) -> anyhow::Result<String> {
         tracing::trace!("get_file_path: address={address}");
-        let snapshot = GoshContract::new(address, gosh_abi::SNAPSHOT);
-        let result: GetSnapshotFilePath = snapshot.read_state(context, "getName", None).await?;
-        tracing::trace!("received file path `{result:?}` for snapshot {snapshot:?}",);
+        let result = Self::load_file_path(context, address).await?;
+        tracing::trace!("received file path `{result:?}` for snapshot {address}");
         Ok(result.file_path)
     }
 }
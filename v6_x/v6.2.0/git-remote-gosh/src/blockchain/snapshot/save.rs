@@ -9,26 +9,6 @@ use crate::{
     blockchain::{BlockchainContractAddress, Everscale},
 };
 
-#[derive(Deserialize, Debug)]
-pub struct GetDiffAddrResult {
-    #[serde(rename = "value0")]
-    pub address: BlockchainContractAddress,
-}
-
-#[derive(Deserialize, Debug)]
-pub struct GetDiffResultResult {
-    #[serde(rename = "value0")]
-    pub hex_encoded_compressed_content: Option<String>,
-}
-
-#[derive(Deserialize, Debug)]
-pub struct GetVersionResult {
-    #[serde(rename = "value0")]
-    pub contract_name: String,
-    #[serde(rename = "value1")]
-    pub version: String,
-}
-
 // TODO: leave only one struct Diff
 #[derive(Serialize, Debug)]
 pub struct Diff {
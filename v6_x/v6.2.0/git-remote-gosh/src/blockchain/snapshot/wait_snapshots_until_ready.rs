@@ -1,10 +1,13 @@
 use crate::blockchain::blockchain_contract_address::FormatShort;
+use crate::blockchain::subscription::Subscription;
 use crate::blockchain::{BlockchainContractAddress, BlockchainService, Snapshot};
 use std::collections::HashSet;
+use std::time::{Duration, Instant};
 use tokio::task::JoinSet;
 use tracing::Instrument;
 
-const MAX_RETRIES_FOR_SNAP_READINESS: i32 = 20;
+const POLL_INTERVAL: Duration = Duration::from_secs(3);
+const MAX_RETRIES_FOR_SNAP_READINESS: u32 = 20;
 const CHUNK_SIZE: usize = 50;
 
 #[instrument(level = "info", skip_all)]
@@ -23,10 +26,12 @@ where
         let mut chunk_clone = chunk.to_vec();
         current_status.spawn(
             async move {
+                let deadline = Instant::now() + POLL_INTERVAL * MAX_RETRIES_FOR_SNAP_READINESS;
+                let subscription = Subscription::accounts(b.client(), &chunk_clone).await;
                 let mut iteration = 0;
                 while !chunk_clone.is_empty() {
                     iteration += 1;
-                    if iteration > MAX_RETRIES_FOR_SNAP_READINESS {
+                    if Instant::now() > deadline {
                         tracing::trace!(
                             "Some contracts didn't appear in time: {}",
                             chunk_clone.format_short()
@@ -61,7 +66,9 @@ where
                         );
                     }
                     chunk_clone = not_ready.to_vec();
-                    tokio::time::sleep(std::time::Duration::from_secs(3)).await;
+                    if !chunk_clone.is_empty() {
+                        subscription.wait(POLL_INTERVAL, deadline).await;
+                    }
                 }
                 Ok(vec![])
             }
@@ -0,0 +1,172 @@
+//! Wakes the waits for contracts and messages on account updates instead of
+//! polling. The state is still queried every `FALLBACK_POLL_INTERVAL` as
+//! subscriptions may drop events.
+
+use super::{BlockchainContractAddress, EverClient};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::Notify;
+use ton_client::{
+    error::ClientResult,
+    net::{
+        subscribe_collection, unsubscribe, ParamsOfSubscribeCollection,
+        ResultOfSubscribeCollection, ResultOfSubscription,
+    },
+};
+
+const GOSH_DISABLE_SUBSCRIPTIONS: &str = "GOSH_DISABLE_SUBSCRIPTIONS";
+// interval of queries while the subscription is active
+const FALLBACK_POLL_INTERVAL: Duration = Duration::from_secs(30);
+// internal messages in `msg_type` of the messages collection
+const INTERNAL_MESSAGE: u8 = 0;
+
+fn subscriptions_disabled() -> bool {
+    std::env::var(GOSH_DISABLE_SUBSCRIPTIONS)
+        .map(|value| value == "1" || value.eq_ignore_ascii_case("true"))
+        .unwrap_or(false)
+}
+
+/// Wakes a waiting task on updates of the subscribed collection
+pub struct Subscription {
+    context: EverClient,
+    handle: Option<ResultOfSubscribeCollection>,
+    events: Arc<Notify>,
+}
+
+impl Subscription {
+    async fn subscribe(context: &EverClient, collection: &str, filter: serde_json::Value) -> Self {
+        let events = Arc::new(Notify::new());
+        let handle = if subscriptions_disabled() {
+            None
+        } else {
+            let notify = Arc::clone(&events);
+            let params = ParamsOfSubscribeCollection {
+                collection: collection.to_owned(),
+                filter: Some(filter),
+                result: "id".to_owned(),
+            };
+            let callback = move |result: ClientResult<ResultOfSubscription>| {
+                if let Err(e) = result {
+                    // the state is queried anyway, an error only wakes it earlier
+                    tracing::trace!("Subscription error: {e}");
+                }
+                notify.notify_one();
+                async {}
+            };
+            match subscribe_collection(Arc::clone(context), params, callback).await {
+                Ok(handle) => Some(handle),
+                Err(e) => {
+                    tracing::trace!("Failed to subscribe to {collection}, polling instead: {e}");
+                    None
+                }
+            }
+        };
+        Self {
+            context: Arc::clone(context),
+            handle,
+            events,
+        }
+    }
+
+    /// Updates of the accounts, including their deploy
+    #[instrument(level = "trace", skip_all)]
+    pub async fn accounts(context: &EverClient, addresses: &[BlockchainContractAddress]) -> Self {
+        let ids: Vec<String> = addresses.iter().map(String::from).collect();
+        let filter = serde_json::json!({ "id": { "in": ids } });
+        Self::subscribe(context, "accounts", filter).await
+    }
+
+    /// Internal messages sent to the account
+    #[instrument(level = "trace", skip_all)]
+    pub async fn messages(context: &EverClient, address: &BlockchainContractAddress) -> Self {
+        let filter = serde_json::json!({
+            "dst": { "eq": address },
+            "msg_type": { "eq": INTERNAL_MESSAGE },
+        });
+        Self::subscribe(context, "messages", filter).await
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.handle.is_some()
+    }
+
+    /// Waits for an update, or for `poll_interval` when there is no
+    /// subscription, but not past `deadline`. Updates that came since the
+    /// last wait end it at once
+    pub async fn wait(&self, poll_interval: Duration, deadline: Instant) {
+        let interval = if self.is_active() {
+            FALLBACK_POLL_INTERVAL
+        } else {
+            poll_interval
+        };
+        let interval = interval.min(deadline.saturating_duration_since(Instant::now()));
+        if tokio::time::timeout(interval, self.events.notified())
+            .await
+            .is_err()
+            && self.is_active()
+        {
+            tracing::trace!("No updates in {}ms, polling", interval.as_millis());
+        }
+    }
+}
+
+impl Drop for Subscription {
+    fn drop(&mut self) {
+        if let Some(handle) = self.handle.take() {
+            let context = Arc::clone(&self.context);
+            tokio::spawn(async move {
+                if let Err(e) = unsubscribe(context, handle).await {
+                    tracing::trace!("Failed to unsubscribe: {e}");
+                }
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ton_client::{ClientConfig, ClientContext};
+
+    #[tokio::test]
+    async fn ensure_updates_end_the_wait() {
+        let context = Arc::new(ClientContext::new(ClientConfig::default()).unwrap());
+        let subscription = Subscription {
+            context,
+            handle: None,
+            events: Arc::new(Notify::new()),
+        };
+        let far = Instant::now() + Duration::from_secs(600);
+        let started = Instant::now();
+        subscription.wait(Duration::from_millis(50), far).await;
+        assert!(started.elapsed() >= Duration::from_millis(50));
+
+        // an update that came before the wait isn't lost
+        subscription.events.notify_one();
+        let started = Instant::now();
+        subscription.wait(Duration::from_secs(60), far).await;
+        assert!(started.elapsed() < Duration::from_secs(60));
+    }
+
+    #[tokio::test]
+    async fn ensure_waits_stop_at_the_deadline() {
+        let context = Arc::new(ClientContext::new(ClientConfig::default()).unwrap());
+        let subscription = Subscription {
+            context,
+            handle: None,
+            events: Arc::new(Notify::new()),
+        };
+        let started = Instant::now();
+        subscription
+            .wait(Duration::from_secs(60), started + Duration::from_millis(50))
+            .await;
+        assert!(started.elapsed() < Duration::from_secs(60));
+
+        // a passed deadline doesn't wait at all
+        let started = Instant::now();
+        subscription
+            .wait(Duration::from_secs(60), started - Duration::from_secs(1))
+            .await;
+        assert!(started.elapsed() < Duration::from_secs(60));
+    }
+}
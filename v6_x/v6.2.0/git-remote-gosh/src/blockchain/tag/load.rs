@@ -1,12 +1,5 @@
-use crate::blockchain::{
-    contract::ContractRead, contract::GoshContract, BlockchainContractAddress, EverClient,
-};
-
-#[derive(Deserialize, Debug)]
-pub struct GetTagContentResult {
-    #[serde(rename = "value0")]
-    pub content: String,
-}
+use crate::abi::bindings::tag;
+use crate::blockchain::{contract::TypedContract, BlockchainContractAddress, EverClient};
 
 #[derive(Debug, Clone)]
 pub struct Lightweight {
@@ -33,10 +26,11 @@ pub async fn get_content(
     context: &EverClient,
     address: &BlockchainContractAddress,
 ) -> anyhow::Result<TagObject> {
-    let tag_contract = GoshContract::new(address, crate::abi::TAG);
-    let GetTagContentResult { content } = tag_contract
-        .read_state(&context, "getContent", None)
-        .await?;
+    let tag_contract = TypedContract::<tag::Contract>::new(address);
+    let content = tag_contract
+        .read_state_fn(context, tag::GetContent {})
+        .await?
+        .value0;
 
     let mut iter = content.splitn(2, '\n');
     let head = iter.next().unwrap();
@@ -4,9 +4,8 @@ use async_trait::async_trait;
 
 pub use crate::abi as gosh_abi;
 use crate::blockchain::{
-    calculate_contract_address, call::BlockchainCall, contract::ContractInfo,
-    user_wallet::UserWallet, BlockchainContractAddress, Everscale, GetContractCodeResult,
-    GoshContract,
+    call::BlockchainCall, contract::ContractInfo, user_wallet::UserWallet,
+    BlockchainContractAddress, Everscale, GoshContract,
 };
 
 #[derive(Serialize, Debug)]
@@ -64,18 +63,10 @@ impl Tagging for Everscale {
         commit_address: BlockchainContractAddress,
     ) -> anyhow::Result<()> {
         let client = Arc::clone(&self.ever_client);
-        let GetContractCodeResult { code } = crate::blockchain::get_contract_code(
+        let address = crate::blockchain::address::tag_address(
             &client,
-            &self.repo_contract.get_address(),
-            crate::blockchain::ContractKind::Tag,
-        )
-        .await?;
-
-        let address = calculate_contract_address(
-            &client,
-            crate::blockchain::ContractKind::Tag,
-            &code,
-            Some(serde_json::json!({ "_nametag": tag_name })),
+            self.repo_contract.get_address(),
+            &tag_name,
         )
         .await?;
 
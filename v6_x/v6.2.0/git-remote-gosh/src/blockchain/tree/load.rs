@@ -1,4 +1,5 @@
-use crate::blockchain::{gosh_abi, run_local, BlockchainContractAddress, BlockchainService, EverClient, GoshContract, Snapshot, GoshBlobBitFlags};
+use crate::abi::bindings::{self, goshwallet};
+use crate::blockchain::{account_cache, contract::TypedContract, BlockchainContractAddress, BlockchainService, EverClient, GoshContract, Snapshot, GoshBlobBitFlags, GoshCommit};
 use ::git_object;
 use data_contract_macro_derive::DataContract;
 use git_object::tree::EntryMode;
@@ -6,7 +7,7 @@ use std::collections::{HashMap, VecDeque};
 use git_object::tree;
 
 // TODO: the same as TreeNode leave only one
-#[derive(Serialize, Debug, Clone, Deserialize)]
+#[derive(Serialize, Debug, Clone, PartialEq, Deserialize)]
 pub struct TreeComponent {
     pub flags: String,
     pub mode: String,
@@ -22,21 +23,7 @@ pub struct TreeComponent {
     pub commit: String,
 }
 
-#[derive(Deserialize, Debug)]
-struct GetDetailsResult {
-    #[serde(rename = "value0")]
-    pub is_ready: bool,
-    #[serde(rename = "value1")]
-    objects: HashMap<String, TreeComponent>,
-    #[serde(rename = "value2")]
-    _sha_tree_local: String,
-    #[serde(rename = "value3")]
-    _sha_tree: String,
-    #[serde(rename = "value4")]
-    _pubaddr: BlockchainContractAddress,
-}
-
-#[derive(Deserialize, Debug, DataContract)]
+#[derive(Deserialize, Debug, PartialEq, DataContract)]
 #[abi = "tree.abi.json"]
 #[abi_data_fn = "gettree"]
 pub struct Tree {
@@ -50,31 +37,30 @@ pub struct GetTreeResult {
     pub address: BlockchainContractAddress,
 }
 
-#[derive(Deserialize)]
-pub struct CalculateHashResult {
-    #[serde(rename = "value0")]
-    hash: String,
-}
-
 impl Tree {
     pub async fn calculate_address(
         context: &EverClient,
         repo_contract: &GoshContract,
         sha_inner_tree: &str,
     ) -> anyhow::Result<BlockchainContractAddress> {
-        let params = serde_json::json!({ "shainnertree": sha_inner_tree });
-        let result: GetTreeResult = repo_contract
-            .run_static(context, "getTreeAddr", Some(params))
-            .await?;
-        Ok(result.address)
+        crate::blockchain::address::tree_address(context, repo_contract, sha_inner_tree).await
     }
 
     pub async fn get_address_from_commit(
         context: &EverClient,
         commit_address: &BlockchainContractAddress,
     ) -> anyhow::Result<BlockchainContractAddress> {
-        let commit_contract = GoshContract::new(commit_address, gosh_abi::COMMIT);
-        let result: GetTreeResult = commit_contract.run_local(context, "gettree", None).await?;
+        let result = GoshCommit::load_tree_address(context, commit_address).await?;
+        Ok(result.address)
+    }
+
+    pub async fn get_address_from_commit_with_boc(
+        context: &EverClient,
+        commit_address: &BlockchainContractAddress,
+        commit_boc: &str,
+    ) -> anyhow::Result<BlockchainContractAddress> {
+        let result =
+            GoshCommit::load_tree_address_with_boc(context, commit_address, commit_boc).await?;
         Ok(result.address)
     }
 
@@ -83,11 +69,15 @@ impl Tree {
         wallet_contract: &GoshContract,
         tree: &HashMap<String, TreeComponent>,
     ) -> anyhow::Result<String> {
-        let params = serde_json::json!({ "_tree": tree });
-        let result: CalculateHashResult = wallet_contract
-            .run_static(context, "calculateInnerTreeHash", Some(params))
-            .await?;
-        Ok(result.hash)
+        let wallet_contract = TypedContract::<goshwallet::Contract>::new(&wallet_contract.address);
+        let args = goshwallet::CalculateInnerTreeHash {
+            _tree: tree
+                .iter()
+                .map(|(key, component)| (key.to_owned(), component.clone().into()))
+                .collect(),
+        };
+        let result = wallet_contract.run_static_fn(context, args).await?;
+        Ok(result.value0)
     }
 }
 
@@ -102,6 +92,21 @@ pub fn type_obj_to_entry_mod(type_obj: &str) -> git_object::tree::EntryMode {
     }
 }
 
+impl From<TreeComponent> for goshwallet::CalculateInnerTreeHash_Tree {
+    fn from(component: TreeComponent) -> Self {
+        Self {
+            flags: component.flags,
+            mode: component.mode,
+            type_obj: component.type_obj,
+            name: component.name,
+            gitsha: component.git_sha,
+            tvmshatree: component.tvm_sha_tree,
+            tvmshafile: component.tvm_sha_file,
+            commit: component.commit,
+        }
+    }
+}
+
 impl Into<git_object::tree::Entry> for TreeComponent {
     fn into(self) -> git_object::tree::Entry {
         let mode = type_obj_to_entry_mod(self.type_obj.as_str());
@@ -134,17 +139,21 @@ where
     B: BlockchainService + 'static,
 {
     tracing::trace!("Check whether tree is ready: {address}");
-    let tree_contract = GoshContract::new(address, gosh_abi::TREE);
-    let value = run_local(blockchain.client(), &tree_contract, "getDetails", None).await?;
-    let res: GetDetailsResult = serde_json::from_value(value)?;
+    let tree_contract = TypedContract::<bindings::tree::Contract>::new(address);
+    // the tree is cached once it's loaded, but it's filled after the deploy
+    account_cache::invalidate(address);
+    let res = tree_contract
+        .run_local_fn(blockchain.client(), bindings::tree::GetDetails {})
+        .await?;
+    let (is_ready, objects) = (res.value0, res.value1);
 
     tracing::trace!(
         "tree {}: ready={}, objects={}",
         address,
-        res.is_ready,
-        res.objects.len()
+        is_ready,
+        objects.len()
     );
-    Ok((res.is_ready, res.objects.len()))
+    Ok((is_ready, objects.len()))
 }
 
 #[derive(Debug)]
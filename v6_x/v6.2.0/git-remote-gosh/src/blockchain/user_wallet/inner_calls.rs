@@ -1,49 +1,23 @@
 use crate::abi;
+use crate::abi::bindings::{goshdao, goshwallet, systemcontract};
 use crate::blockchain::call::BlockchainCall;
 use crate::blockchain::{
-    contract::{ContractInfo, ContractRead},
-    serde_number::NumberU64,
-    BlockchainContractAddress, BlockchainService, GoshContract,
+    contract::TypedContract, BlockchainContractAddress, BlockchainService, GoshContract,
 };
 use crate::config::UserWalletConfig;
 use ton_client::crypto::KeyPair;
 
-#[derive(Deserialize, Debug)]
-struct GetConfigResult {
-    #[serde(rename = "value0")]
-    pub max_number_of_mirror_wallets: NumberU64,
-}
-
-#[derive(Deserialize, Debug)]
-struct GetProfileAddrResult {
-    #[serde(rename = "value0")]
-    pub address: BlockchainContractAddress,
-}
-
-#[derive(Deserialize, Debug)]
-struct GetAddrWalletResult {
-    #[serde(rename = "value0")]
-    pub address: BlockchainContractAddress,
-}
-
-#[derive(Deserialize, Debug)]
-struct GetWalletMirrorsCountResult {
-    #[serde(rename = "value0")]
-    pub number_of_wallets: NumberU64,
-}
-
 #[instrument(level = "info", skip_all)]
-pub(super) async fn get_number_of_user_wallet_mirrors_deployed<B, C>(
+pub(super) async fn get_number_of_user_wallet_mirrors_deployed<B>(
     blockchain: &B,
-    wallet: &C,
+    wallet: &GoshContract,
 ) -> anyhow::Result<u64>
 where
     B: BlockchainService + BlockchainCall,
-    C: ContractRead + ContractInfo + Sync,
 {
     tracing::trace!("get_number_of_user_wallet_mirrors_deployed: wallet={wallet:?}");
-    let result: GetWalletMirrorsCountResult = wallet
-        .read_state(blockchain.client(), "getWalletsCount", None)
+    let result = TypedContract::<goshwallet::Contract>::new(&wallet.address)
+        .read_state_fn(blockchain.client(), goshwallet::GetWalletsCount {})
         .await
         .map_err(|e| {
             tracing::trace!("get_number_of_user_wallet_mirrors_deployed error: {}", e);
@@ -53,7 +27,8 @@ where
         "get_number_of_user_wallet_mirrors_deployed result: {:?}",
         result
     );
-    Ok(Into::<u64>::into(result.number_of_wallets) - 1)
+    let number_of_wallets: u64 = result.value0.parse()?;
+    Ok(number_of_wallets - 1)
 }
 
 #[instrument(level = "info", skip_all)]
@@ -74,31 +49,31 @@ pub(super) async fn get_user_wallet(
         .clone()
         .ok_or_else(|| anyhow::anyhow!("Wallet config expected"))?;
 
-    let result: GetProfileAddrResult = gosh_root
-        .run_static(
+    let gosh_root = TypedContract::<systemcontract::Contract>::new(&gosh_root.address);
+    let result = gosh_root
+        .run_static_fn(
             blockchain.client(),
-            "getProfileAddr",
-            Some(serde_json::json!({ "name": profile })),
+            systemcontract::GetProfileAddr { name: profile },
         )
         .await
         .map_err(|e| {
             tracing::trace!("getProfileAddr error: {}", e);
             e
         })?;
-    let dao_contract = GoshContract::new(dao_address, abi::DAO);
+    let dao_contract = TypedContract::<goshdao::Contract>::new(dao_address);
 
-    let params = serde_json::json!({
-        "pubaddr": result.address,
-        "index": user_wallet_index
-    });
-    let result: GetAddrWalletResult = dao_contract
-        .run_static(blockchain.client(), "getAddrWallet", Some(params))
+    let args = goshdao::GetAddrWallet {
+        pubaddr: result.value0,
+        index: user_wallet_index.to_string(),
+    };
+    let result = dao_contract
+        .run_static_fn(blockchain.client(), args)
         .await
         .map_err(|e| {
             tracing::trace!("getAddrWallet error: {}", e);
             e
         })?;
-    let user_wallet_address = result.address;
+    let user_wallet_address = result.value0;
     tracing::trace!("user_wallet address: {:?}", user_wallet_address);
     let secrets = KeyPair::new(pubkey.into(), secret.into());
 
@@ -106,23 +81,23 @@ pub(super) async fn get_user_wallet(
     Ok(contract)
 }
 
+/// The limit of wallet mirrors is a config of the DAO
 #[instrument(level = "info", skip_all)]
-pub(super) async fn get_user_wallet_config_max_number_of_mirrors<B, C>(
+pub(super) async fn get_user_wallet_config_max_number_of_mirrors<B>(
     blockchain: &B,
-    user_wallet_contract: &C,
+    dao_address: &BlockchainContractAddress,
 ) -> anyhow::Result<u64>
 where
     B: BlockchainService + BlockchainCall,
-    C: ContractRead + ContractInfo + Sync,
 {
-    tracing::trace!("get_user_wallet_config_max_number_of_mirrors: user_wallet_contract={user_wallet_contract:?}");
-    let result: GetConfigResult = user_wallet_contract
-        .read_state(blockchain.client(), "getConfig", None)
+    tracing::trace!("get_user_wallet_config_max_number_of_mirrors: dao_address={dao_address}");
+    let result = TypedContract::<goshdao::Contract>::new(dao_address)
+        .read_state_fn(blockchain.client(), goshdao::GetConfig {})
         .await?;
     tracing::trace!(
         "get_user_wallet_config_max_number_of_mirrors result: {:?}",
         result
     );
-    let number = result.max_number_of_mirror_wallets.into();
+    let number = result.value0.parse()?;
     Ok(number)
 }
@@ -1,6 +1,7 @@
 use crate::blockchain::call::BlockchainCall;
 use crate::blockchain::user_wallet::inner_calls;
-use crate::blockchain::{BlockchainContractAddress, BlockchainService, GoshContract};
+use crate::blockchain::contract::ContractRead;
+use crate::blockchain::{BlockchainContractAddress, BlockchainService, EverClient, GoshContract};
 
 use std::ops::Deref;
 use std::sync::atomic::{AtomicU64, Ordering};
@@ -223,9 +224,11 @@ impl UserWalletMirrors {
                 match max_number_of_wallets {
                     Some(w) => w,
                     None => {
-                        let n =
-                            get_user_wallet_config_max_number_of_mirrors(blockchain, &zero_wallet)
-                                .await?;
+                        let n = get_user_wallet_config_max_number_of_mirrors(
+                            blockchain,
+                            &inner_state.dao_address,
+                        )
+                        .await?;
                         let mut inner_state = self.inner.write().await;
                         let w: TWalletMirrorIndex = (n + 1) as TWalletMirrorIndex;
                         inner_state.max_number_of_wallets = Some(w);
@@ -252,6 +255,28 @@ impl UserWalletMirrors {
         Ok(contract)
     }
 
+    /// Balances of the zero wallet and all initialized mirrors in nanotokens
+    pub async fn balances(
+        &self,
+        client: &EverClient,
+    ) -> anyhow::Result<Vec<(TWalletMirrorIndex, BlockchainContractAddress, u64)>> {
+        let inner_state = { self.inner.read().await.clone() };
+        let mut balances = vec![];
+        for (index, wallet) in inner_state.wallets() {
+            if let Wallet::Contract(contract) = wallet {
+                let balance = contract
+                    .load_account(client)
+                    .await?
+                    .map(|status| status.balance())
+                    .unwrap_or(0);
+                tracing::trace!("wallet #{index} {}: balance={balance}", contract.address);
+                balances.push((*index, contract.address.clone(), balance));
+            }
+        }
+        balances.sort_by_key(|(index, _, _)| *index);
+        Ok(balances)
+    }
+
     pub async fn take_one(&self) -> anyhow::Result<UserWalletContractRef> {
         let permit = self.semaphore.acquire().await?;
         let inner_state = { self.inner.read().await.clone() };
@@ -0,0 +1,348 @@
+//! Bundle file: the header line, zstd compressed entries, the msgpack index
+//! of the entries and the trailer with the offset, length and sha256 of the
+//! index. Entries are read and verified only when they are requested.
+
+use super::BundleError;
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+const BUNDLE_HEADER: &[u8] = b"# gosh bundle v2\n";
+const CHECKSUM_LEN: usize = 64;
+const TRAILER_LEN: usize = 8 + 8 + CHECKSUM_LEN;
+const COMPRESSION_LEVEL: i32 = 3;
+// entries are decompressed up to this size, larger ones are treated as
+// corrupted
+const MAX_ENTRY_SIZE: u64 = 1 << 30;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Entry {
+    offset: u64,
+    len: u64,
+    sha256: String,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Index {
+    url: String,
+    entries: BTreeMap<String, Entry>,
+}
+
+/// Reads the entry, it must lie between the header and `end`
+fn read_entry(
+    file: &mut File,
+    display: &str,
+    key: &str,
+    entry: &Entry,
+    end: u64,
+) -> anyhow::Result<Vec<u8>> {
+    let corrupted = || BundleError::CorruptedEntry {
+        bundle: display.to_owned(),
+        key: key.to_owned(),
+    };
+    let in_range = entry.offset >= BUNDLE_HEADER.len() as u64
+        && entry
+            .offset
+            .checked_add(entry.len)
+            .is_some_and(|entry_end| entry_end <= end);
+    if !in_range {
+        return Err(corrupted().into());
+    }
+    let mut compressed = vec![0; entry.len as usize];
+    file.seek(SeekFrom::Start(entry.offset))?;
+    file.read_exact(&mut compressed)?;
+    if sha256::digest(compressed.as_slice()) != entry.sha256 {
+        return Err(corrupted().into());
+    }
+    let mut data = vec![];
+    zstd::stream::read::Decoder::new(compressed.as_slice())?
+        .take(MAX_ENTRY_SIZE + 1)
+        .read_to_end(&mut data)?;
+    if data.len() as u64 > MAX_ENTRY_SIZE {
+        return Err(corrupted().into());
+    }
+    Ok(data)
+}
+
+struct WriterState {
+    file: BufWriter<File>,
+    offset: u64,
+    index: Index,
+}
+
+/// Bundle that is being exported. Entries are written to `<path>.partial`,
+/// which is renamed to `path` when the bundle is finished and removed when
+/// it is dropped unfinished
+pub struct BundleWriter {
+    path: PathBuf,
+    partial_path: PathBuf,
+    state: Mutex<Option<WriterState>>,
+}
+
+impl BundleWriter {
+    pub fn create(path: impl AsRef<Path>, url: &str) -> anyhow::Result<Self> {
+        let path = path.as_ref().to_owned();
+        let mut partial_path = path.clone().into_os_string();
+        partial_path.push(".partial");
+        let partial_path = PathBuf::from(partial_path);
+        let mut file = BufWriter::new(File::create(&partial_path)?);
+        file.write_all(BUNDLE_HEADER)?;
+        let index = Index {
+            url: url.to_owned(),
+            ..Default::default()
+        };
+        Ok(Self {
+            path,
+            partial_path,
+            state: Mutex::new(Some(WriterState {
+                file,
+                offset: BUNDLE_HEADER.len() as u64,
+                index,
+            })),
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Adds the entry, entries that are already written are kept
+    pub fn put(&self, key: &str, data: &[u8]) -> anyhow::Result<()> {
+        {
+            let state = self.state.lock().unwrap();
+            if state
+                .as_ref()
+                .is_some_and(|state| state.index.entries.contains_key(key))
+            {
+                return Ok(());
+            }
+        }
+        // compressed without the lock, so other entries are written meanwhile
+        let compressed = zstd::encode_all(data, COMPRESSION_LEVEL)?;
+        let mut state = self.state.lock().unwrap();
+        let state = state.as_mut().ok_or_else(|| {
+            anyhow::format_err!("Bundle {} is already written", self.path.display())
+        })?;
+        if state.index.entries.contains_key(key) {
+            return Ok(());
+        }
+        state.file.write_all(&compressed)?;
+        let entry = Entry {
+            offset: state.offset,
+            len: compressed.len() as u64,
+            sha256: sha256::digest(compressed.as_slice()),
+        };
+        state.offset += entry.len;
+        state.index.entries.insert(key.to_owned(), entry);
+        Ok(())
+    }
+
+    /// Reads back an entry that was written before
+    pub fn get(&self, key: &str) -> anyhow::Result<Option<Vec<u8>>> {
+        let mut state = self.state.lock().unwrap();
+        let state = match state.as_mut() {
+            Some(state) => state,
+            None => return Ok(None),
+        };
+        let entry = match state.index.entries.get(key) {
+            Some(entry) => entry.clone(),
+            None => return Ok(None),
+        };
+        state.file.flush()?;
+        let mut file = File::open(&self.partial_path)?;
+        let display = self.partial_path.display().to_string();
+        read_entry(&mut file, &display, key, &entry, state.offset).map(Some)
+    }
+
+    /// Writes the index and moves the bundle into place. Returns the number
+    /// of entries
+    pub fn finish(&self) -> anyhow::Result<usize> {
+        let WriterState {
+            mut file,
+            offset,
+            index,
+        } = self.state.lock().unwrap().take().ok_or_else(|| {
+            anyhow::format_err!("Bundle {} is already written", self.path.display())
+        })?;
+        let index_data = rmp_serde::to_vec(&index)?;
+        file.write_all(&index_data)?;
+        file.write_all(&offset.to_le_bytes())?;
+        file.write_all(&(index_data.len() as u64).to_le_bytes())?;
+        file.write_all(sha256::digest(index_data.as_slice()).as_bytes())?;
+        file.into_inner()?.sync_all()?;
+        std::fs::rename(&self.partial_path, &self.path)?;
+        Ok(index.entries.len())
+    }
+}
+
+impl Drop for BundleWriter {
+    fn drop(&mut self) {
+        if self.state.get_mut().unwrap().take().is_some() {
+            if let Err(e) = std::fs::remove_file(&self.partial_path) {
+                tracing::trace!("Failed to remove {}: {e}", self.partial_path.display());
+            }
+        }
+    }
+}
+
+/// Bundle that fetches are served from
+pub struct BundleReader {
+    display: String,
+    index: Index,
+    index_offset: u64,
+    file: Mutex<File>,
+}
+
+impl BundleReader {
+    pub fn open(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let display = path.as_ref().display().to_string();
+        let unknown_format = || BundleError::UnknownFormat(display.clone());
+        let mut file = File::open(path)?;
+        let mut header = vec![0; BUNDLE_HEADER.len()];
+        let len = file.metadata()?.len();
+        if len < (BUNDLE_HEADER.len() + TRAILER_LEN) as u64 {
+            return Err(unknown_format().into());
+        }
+        file.read_exact(&mut header)?;
+        if header != BUNDLE_HEADER {
+            return Err(unknown_format().into());
+        }
+        let mut trailer = [0; TRAILER_LEN];
+        file.seek(SeekFrom::Start(len - TRAILER_LEN as u64))?;
+        file.read_exact(&mut trailer)?;
+        let index_offset = u64::from_le_bytes(trailer[..8].try_into()?);
+        let index_len = u64::from_le_bytes(trailer[8..16].try_into()?);
+        if index_offset.checked_add(index_len) != Some(len - TRAILER_LEN as u64) {
+            return Err(unknown_format().into());
+        }
+        let mut index_data = vec![0; index_len as usize];
+        file.seek(SeekFrom::Start(index_offset))?;
+        file.read_exact(&mut index_data)?;
+        if sha256::digest(index_data.as_slice()).as_bytes() != &trailer[16..] {
+            return Err(BundleError::ChecksumMismatch(display).into());
+        }
+        let index: Index = rmp_serde::from_slice(&index_data)?;
+        Ok(Self {
+            display,
+            index,
+            index_offset,
+            file: Mutex::new(file),
+        })
+    }
+
+    pub fn url(&self) -> &str {
+        &self.index.url
+    }
+
+    pub fn entry_count(&self) -> usize {
+        self.index.entries.len()
+    }
+
+    pub fn get(&self, key: &str) -> anyhow::Result<Option<Vec<u8>>> {
+        let entry = match self.index.entries.get(key) {
+            Some(entry) => entry,
+            None => return Ok(None),
+        };
+        let mut file = self.file.lock().unwrap();
+        read_entry(&mut file, &self.display, key, entry, self.index_offset).map(Some)
+    }
+}
+
+impl std::fmt::Display for BundleReader {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.display)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ensure_entries_are_verified() {
+        let path = std::env::temp_dir().join(format!("gosh_bundle_test_{}", std::process::id()));
+        let writer = BundleWriter::create(&path, "gosh://0:0/dao/repo").unwrap();
+        writer.put("account/0:1", b"te6ccg").unwrap();
+        writer.put("ipfs/cid", b"blob").unwrap();
+        writer.put("ipfs/cid", b"other blob").unwrap();
+        assert_eq!(writer.get("ipfs/cid").unwrap().unwrap(), b"blob");
+        assert_eq!(writer.finish().unwrap(), 2);
+
+        let reader = BundleReader::open(&path).unwrap();
+        assert_eq!(reader.url(), "gosh://0:0/dao/repo");
+        assert_eq!(reader.get("ipfs/cid").unwrap().unwrap(), b"blob");
+        assert!(reader.get("ipfs/other").unwrap().is_none());
+
+        // damaged entries are detected when they are read
+        let mut data = std::fs::read(&path).unwrap();
+        data[BUNDLE_HEADER.len()] ^= 1;
+        std::fs::write(&path, &data).unwrap();
+        let reader = BundleReader::open(&path).unwrap();
+        let error = reader.get("account/0:1").unwrap_err();
+        assert!(matches!(
+            error.downcast_ref::<BundleError>(),
+            Some(BundleError::CorruptedEntry { .. })
+        ));
+        assert_eq!(reader.get("ipfs/cid").unwrap().unwrap(), b"blob");
+
+        // and the index when the bundle is opened
+        let last = data.len() - TRAILER_LEN - 1;
+        data[last] ^= 1;
+        std::fs::write(&path, data).unwrap();
+        let error = BundleReader::open(&path).err().unwrap();
+        assert!(matches!(
+            error.downcast_ref::<BundleError>(),
+            Some(BundleError::ChecksumMismatch(_))
+        ));
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn ensure_entries_out_of_the_bundle_are_rejected() {
+        let path =
+            std::env::temp_dir().join(format!("gosh_bundle_range_{}", std::process::id()));
+        let writer = BundleWriter::create(&path, "gosh://0:0/dao/repo").unwrap();
+        writer.put("ipfs/cid", b"blob").unwrap();
+        writer.finish().unwrap();
+        let reader = BundleReader::open(&path).unwrap();
+        let mut entry = reader.index.entries["ipfs/cid"].clone();
+        let mut file = File::open(&path).unwrap();
+        assert_eq!(
+            read_entry(&mut file, "bundle", "ipfs/cid", &entry, reader.index_offset).unwrap(),
+            b"blob"
+        );
+
+        // entries must not overlap the index or the header
+        for (offset, len) in [
+            (entry.offset, reader.index_offset),
+            (u64::MAX, 2),
+            (0, entry.len),
+        ] {
+            entry.offset = offset;
+            entry.len = len;
+            let error =
+                read_entry(&mut file, "bundle", "ipfs/cid", &entry, reader.index_offset)
+                    .unwrap_err();
+            assert!(matches!(
+                error.downcast_ref::<BundleError>(),
+                Some(BundleError::CorruptedEntry { .. })
+            ));
+        }
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn ensure_unfinished_bundle_is_removed() {
+        let path =
+            std::env::temp_dir().join(format!("gosh_bundle_unfinished_{}", std::process::id()));
+        let writer = BundleWriter::create(&path, "gosh://0:0/dao/repo").unwrap();
+        writer.put("ipfs/cid", b"blob").unwrap();
+        let partial_path = writer.partial_path.clone();
+        assert!(partial_path.exists());
+        drop(writer);
+        assert!(!partial_path.exists());
+        assert!(!path.exists());
+    }
+}
@@ -0,0 +1,252 @@
+use super::BundleError;
+use crate::{
+    blockchain::{
+        branch::{DeleteBranch, DeployBranch},
+        commit::save::BlockchainCommitPusher,
+        snapshot::save::{DeleteSnapshot, DeployDiff, DeployNewSnapshot, Diff},
+        tag::save::Tagging,
+        tree::{load::TreeComponent, DeployTree},
+        user_wallet::{BlockchainUserWalletService, UserWallet},
+        BlockchainBranchesService, BlockchainCommitService, BlockchainContractAddress,
+        BlockchainReadContractState, BlockchainService, EverClient, Everscale, GoshCommit,
+        GoshContract,
+    },
+    config::{Config, UserWalletConfig},
+    database::GoshDB,
+    utilities::Remote,
+};
+use async_trait::async_trait;
+use git_hash::ObjectId;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/// `BlockchainService` of a bundle. Reads go through the client the bundle
+/// is attached to, deployments are refused: bundles are read-only
+#[derive(Clone)]
+pub struct BundleBlockchain {
+    inner: Everscale,
+}
+
+impl BundleBlockchain {
+    /// `inner` must be built over a client the bundle is attached to
+    pub fn new(inner: Everscale) -> Self {
+        Self { inner }
+    }
+}
+
+impl std::fmt::Debug for BundleBlockchain {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("BundleBlockchain").finish()
+    }
+}
+
+fn read_only<T>() -> anyhow::Result<T> {
+    Err(BundleError::ReadOnly("Push").into())
+}
+
+#[async_trait]
+impl DeployBranch for BundleBlockchain {
+    async fn deploy_branch(
+        &self,
+        _wallet: &UserWallet,
+        _repo_name: String,
+        _new_name: String,
+        _from_commit: String,
+    ) -> anyhow::Result<()> {
+        read_only()
+    }
+}
+
+#[async_trait]
+impl DeleteBranch for BundleBlockchain {
+    async fn delete_branch(
+        &self,
+        _wallet: &UserWallet,
+        _repo_name: String,
+        _branch_name: String,
+    ) -> anyhow::Result<()> {
+        read_only()
+    }
+}
+
+#[async_trait]
+impl DeployTree for BundleBlockchain {
+    async fn deploy_tree(
+        &self,
+        _wallet: &UserWallet,
+        _sha: &str,
+        _tree_address: &str,
+        _repo_name: &str,
+        _nodes: &mut HashMap<String, TreeComponent>,
+        _sha_inner_hash: &str,
+    ) -> anyhow::Result<()> {
+        read_only()
+    }
+}
+
+#[async_trait]
+impl DeployDiff for BundleBlockchain {
+    async fn deploy_diff(
+        &self,
+        _wallet: &UserWallet,
+        _repo_name: String,
+        _branch_name: String,
+        _commit_id: String,
+        _diff: Diff,
+        _index1: u32,
+        _index2: u32,
+        _last: bool,
+    ) -> anyhow::Result<()> {
+        read_only()
+    }
+}
+
+#[async_trait]
+impl DeployNewSnapshot for BundleBlockchain {
+    async fn deploy_new_snapshot(
+        &self,
+        _wallet: &UserWallet,
+        _repo_address: BlockchainContractAddress,
+        _commit_id: String,
+        _file_path: String,
+        _content: String,
+        _ipfs: Option<String>,
+    ) -> anyhow::Result<()> {
+        read_only()
+    }
+}
+
+#[async_trait]
+impl DeleteSnapshot for BundleBlockchain {
+    async fn delete_snapshot(
+        &self,
+        _wallet: &UserWallet,
+        _snapshot_address: BlockchainContractAddress,
+    ) -> anyhow::Result<()> {
+        read_only()
+    }
+}
+
+#[async_trait]
+impl Tagging for BundleBlockchain {
+    async fn deploy_tag(
+        &self,
+        _wallet: &UserWallet,
+        _repo_name: String,
+        _tag_name: String,
+        _commit_id: String,
+        _content: String,
+        _commit_address: BlockchainContractAddress,
+    ) -> anyhow::Result<()> {
+        read_only()
+    }
+
+    async fn delete_tag(
+        &self,
+        _wallet: &UserWallet,
+        _repo_name: String,
+        _tag_name: String,
+    ) -> anyhow::Result<()> {
+        read_only()
+    }
+}
+
+#[async_trait]
+impl BlockchainCommitPusher for BundleBlockchain {
+    async fn push_commit(
+        &self,
+        _commit_address: &str,
+        _remote: &Remote,
+        _dao_addr: &BlockchainContractAddress,
+        _database: Arc<GoshDB>,
+    ) -> anyhow::Result<()> {
+        read_only()
+    }
+
+    async fn notify_commit(
+        &self,
+        _commit_id: &ObjectId,
+        _branch: &str,
+        _number_of_files_changed: u32,
+        _number_of_commits: u64,
+        _remote: &Remote,
+        _dao_addr: &BlockchainContractAddress,
+        _is_upgrade: bool,
+        _config: &Config,
+    ) -> anyhow::Result<()> {
+        read_only()
+    }
+}
+
+#[async_trait]
+impl BlockchainUserWalletService for BundleBlockchain {
+    fn wallet_config(&self) -> &Option<UserWalletConfig> {
+        self.inner.wallet_config()
+    }
+
+    async fn user_wallet(
+        &self,
+        _dao_address: &BlockchainContractAddress,
+        _remote_network: &str,
+    ) -> anyhow::Result<UserWallet> {
+        read_only()
+    }
+}
+
+#[async_trait]
+impl BlockchainBranchesService for BundleBlockchain {
+    async fn is_branch_protected(
+        &self,
+        repository_address: &BlockchainContractAddress,
+        branch_name: &str,
+    ) -> anyhow::Result<bool> {
+        self.inner
+            .is_branch_protected(repository_address, branch_name)
+            .await
+    }
+
+    async fn remote_rev_parse(
+        &self,
+        repository_address: &BlockchainContractAddress,
+        rev: &str,
+    ) -> anyhow::Result<Option<(BlockchainContractAddress, String)>> {
+        self.inner.remote_rev_parse(repository_address, rev).await
+    }
+}
+
+#[async_trait]
+impl BlockchainCommitService for BundleBlockchain {
+    async fn get_commit_by_addr(
+        &self,
+        address: &BlockchainContractAddress,
+    ) -> anyhow::Result<Option<GoshCommit>> {
+        self.inner.get_commit_by_addr(address).await
+    }
+}
+
+#[async_trait]
+impl BlockchainReadContractState for BundleBlockchain {
+    async fn check_contracts_state(
+        &self,
+        addresses: &[BlockchainContractAddress],
+        allow_incomplete_results: bool,
+    ) -> anyhow::Result<Vec<BlockchainContractAddress>> {
+        self.inner
+            .check_contracts_state(addresses, allow_incomplete_results)
+            .await
+    }
+}
+
+impl BlockchainService for BundleBlockchain {
+    fn client(&self) -> &EverClient {
+        self.inner.client()
+    }
+
+    fn root_contract(&self) -> &GoshContract {
+        self.inner.root_contract()
+    }
+
+    fn repo_contract(&self) -> &GoshContract {
+        self.inner.repo_contract()
+    }
+}
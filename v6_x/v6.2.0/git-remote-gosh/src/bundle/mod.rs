@@ -0,0 +1,504 @@
+//! Bundle: a single file with everything `fetch` reads from the network for
+//! a repository, keyed by what the entries describe rather than by queries.
+//! `export_bundle` records the reads of a fetch of all refs, and with
+//! `GOSH_BUNDLE` set they are served from the bundle, so `git clone` works
+//! offline. The bundle is attached to the client of the blockchain service,
+//! other clients (e.g. ones comparing endpoints) never use it.
+
+mod archive;
+mod blockchain;
+mod storage;
+
+pub use archive::{BundleReader, BundleWriter};
+pub use blockchain::BundleBlockchain;
+pub use storage::BundleStorage;
+
+use crate::blockchain::{BlockchainContractAddress, EverClient};
+use crate::git_helper::endpoint_health;
+use once_cell::sync::Lazy;
+use std::collections::HashMap;
+use std::future::Future;
+use std::path::Path;
+use std::sync::{Arc, Mutex, Weak};
+use ton_client::{
+    net::{query_collection, ParamsOfQuery, ParamsOfQueryCollection},
+    ClientContext,
+};
+
+const GOSH_BUNDLE: &str = "GOSH_BUNDLE";
+const HISTORY_PAGE_SIZE: u32 = 50;
+// page size of `blockchain.account.messages` when `first`/`last` is omitted
+const DEFAULT_MESSAGES_PAGE_SIZE: u32 = 50;
+
+const HISTORY_QUERY: &str = r#"query($addr: String!, $after: String, $limit: Int){
+  blockchain {
+    account(address: $addr) {
+      messages(msg_type: [IntIn], after: $after, first: $limit) {
+        edges {
+          node { id src body created_at created_lt status bounced }
+        }
+        pageInfo { hasNextPage endCursor }
+      }
+    }
+  }
+}"#;
+
+// Bundles by the address of their client. The weak handle keeps the address
+// from being reused by another client while the entry exists
+static ATTACHED: Lazy<Mutex<HashMap<usize, (Weak<ClientContext>, Bundle)>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// Path to the bundle the helper should serve fetches from
+pub fn get_bundle_path() -> Option<String> {
+    std::env::var(GOSH_BUNDLE)
+        .ok()
+        .filter(|path| !path.is_empty())
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum BundleError {
+    #[error("{0} is not a gosh bundle")]
+    UnknownFormat(String),
+    #[error("Bundle {0} is corrupted: checksum mismatch")]
+    ChecksumMismatch(String),
+    #[error("Bundle {bundle} is corrupted: damaged entry {key}")]
+    CorruptedEntry { bundle: String, key: String },
+    #[error("Bundle was exported from {exported}, it can't serve {requested}")]
+    WrongRepository { exported: String, requested: String },
+    #[error("Bundle doesn't contain {0}, export it again")]
+    Missing(String),
+    #[error("Cursor {0} doesn't belong to the bundle")]
+    UnknownCursor(String),
+    #[error("{0} is not supported while fetching from a bundle")]
+    ReadOnly(&'static str),
+}
+
+/// Bundle that reads of a client are recorded to or served from
+#[derive(Clone)]
+pub enum Bundle {
+    Recording(Arc<BundleWriter>),
+    Replaying(Arc<BundleReader>),
+}
+
+impl std::fmt::Debug for Bundle {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Bundle::Recording(writer) => f
+                .debug_tuple("Recording")
+                .field(&writer.path())
+                .finish(),
+            Bundle::Replaying(reader) => f
+                .debug_tuple("Replaying")
+                .field(&reader.to_string())
+                .finish(),
+        }
+    }
+}
+
+impl Bundle {
+    /// Starts the export of `url` to the bundle at `path`
+    pub fn create(path: impl AsRef<Path>, url: &str) -> anyhow::Result<Self> {
+        Ok(Bundle::Recording(Arc::new(BundleWriter::create(path, url)?)))
+    }
+
+    /// Opens the bundle, it must be exported from `url`
+    pub fn open(path: impl AsRef<Path>, url: &str) -> anyhow::Result<Self> {
+        let reader = BundleReader::open(path)?;
+        if reader.url() != url {
+            return Err(BundleError::WrongRepository {
+                exported: reader.url().to_owned(),
+                requested: url.to_owned(),
+            }
+            .into());
+        }
+        tracing::trace!("Bundle {reader}: {} entries", reader.entry_count());
+        Ok(Bundle::Replaying(Arc::new(reader)))
+    }
+
+    pub fn is_replaying(&self) -> bool {
+        matches!(self, Bundle::Replaying(_))
+    }
+
+    /// Reads of `client` are recorded to or served from the bundle
+    /// afterwards
+    pub fn attach(&self, client: &EverClient) {
+        let mut attached = ATTACHED.lock().unwrap();
+        attached.retain(|_, (attached_client, _)| attached_client.strong_count() > 0);
+        attached.insert(client_key(client), (Arc::downgrade(client), self.clone()));
+    }
+
+    /// Finishes the export, returns the number of entries
+    pub fn finish(&self) -> anyhow::Result<usize> {
+        match self {
+            Bundle::Recording(writer) => writer.finish(),
+            Bundle::Replaying(_) => anyhow::bail!("Bundle is not recorded"),
+        }
+    }
+
+    fn get(&self, key: &str) -> anyhow::Result<Option<Vec<u8>>> {
+        match self {
+            Bundle::Recording(writer) => writer.get(key),
+            Bundle::Replaying(reader) => reader.get(key),
+        }
+    }
+
+    /// Entry of the bundle, `load` gives it while the bundle is recorded
+    async fn entry<F, Fut>(&self, key: &str, load: F) -> anyhow::Result<Vec<u8>>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = anyhow::Result<Vec<u8>>>,
+    {
+        if let Some(data) = self.get(key)? {
+            return Ok(data);
+        }
+        match self {
+            Bundle::Recording(writer) => {
+                let data = load().await?;
+                writer.put(key, &data)?;
+                Ok(data)
+            }
+            Bundle::Replaying(_) => Err(BundleError::Missing(key.to_owned()).into()),
+        }
+    }
+}
+
+fn client_key(client: &EverClient) -> usize {
+    Arc::as_ptr(client) as usize
+}
+
+fn attached(client: &EverClient) -> Option<Bundle> {
+    ATTACHED
+        .lock()
+        .unwrap()
+        .get(&client_key(client))
+        .map(|(_, bundle)| bundle.clone())
+}
+
+fn account_key(address: &str) -> String {
+    format!("account/{address}")
+}
+
+fn messages_key(address: &BlockchainContractAddress) -> String {
+    format!("messages/{address}")
+}
+
+fn code_hash_key(code_hash: &str) -> String {
+    format!("code_hash/{code_hash}")
+}
+
+fn ipfs_key(cid: &str) -> String {
+    format!("ipfs/{cid}")
+}
+
+async fn run_query(
+    context: &EverClient,
+    params: ParamsOfQuery,
+) -> anyhow::Result<serde_json::Value> {
+    let result = ton_client::net::query(Arc::clone(context), params).await;
+    endpoint_health::tracked(context, result)
+        .await
+        .map(|r| r.result)
+        .map_err(|e| anyhow::format_err!("query error: {e}"))
+}
+
+/// Addresses of the accounts selected by `{"id": {"eq": ..}}` or
+/// `{"id": {"in": [..]}}`
+fn filter_ids(filter: &Option<serde_json::Value>) -> Vec<String> {
+    let id = match filter.as_ref().map(|filter| &filter["id"]) {
+        Some(id) => id,
+        None => return vec![],
+    };
+    if let Some(address) = id["eq"].as_str() {
+        return vec![address.to_owned()];
+    }
+    id["in"]
+        .as_array()
+        .map(|ids| {
+            ids.iter()
+                .filter_map(|id| id.as_str().map(str::to_owned))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+async fn load_accounts(
+    context: &EverClient,
+    params: ParamsOfQueryCollection,
+) -> anyhow::Result<Vec<serde_json::Value>> {
+    let result = query_collection(Arc::clone(context), params).await;
+    Ok(endpoint_health::tracked(context, result).await?.result)
+}
+
+/// `query_collection` of accounts by their addresses that is recorded to or
+/// served from the bundle. Results served from the bundle contain only `id`
+/// and `boc` of the accounts
+pub async fn query_accounts(
+    context: &EverClient,
+    mut params: ParamsOfQueryCollection,
+) -> anyhow::Result<Vec<serde_json::Value>> {
+    match attached(context) {
+        None => Ok(load_accounts(context, params).await?),
+        Some(Bundle::Replaying(reader)) => {
+            let mut accounts = vec![];
+            for id in filter_ids(&params.filter) {
+                if let Some(boc) = reader.get(&account_key(&id))? {
+                    let boc = String::from_utf8(boc)?;
+                    accounts.push(serde_json::json!({ "id": id, "boc": boc }));
+                }
+            }
+            Ok(accounts)
+        }
+        Some(Bundle::Recording(writer)) => {
+            for field in ["id", "boc"] {
+                if !params.result.split_whitespace().any(|name| name == field) {
+                    params.result = format!("{} {field}", params.result);
+                }
+            }
+            let result = load_accounts(context, params).await?;
+            for account in result.iter() {
+                if let (Some(id), Some(boc)) = (account["id"].as_str(), account["boc"].as_str()) {
+                    writer.put(&account_key(id), boc.as_bytes())?;
+                }
+            }
+            Ok(result)
+        }
+    }
+}
+
+/// Page of the inbound messages of an account
+#[derive(Debug)]
+pub struct MessagesPage<'a> {
+    pub address: &'a BlockchainContractAddress,
+    /// `after` cursor, or `before` when the messages are read from the end
+    pub cursor: Option<&'a str>,
+    pub limit: Option<u32>,
+    pub from_end: bool,
+}
+
+/// `blockchain.account.messages` (`edges` and `pageInfo`) of the page
+/// loaded by `params`. With a bundle the page is cut from the complete
+/// history of the account, and cursors are positions in the history
+pub async fn query_messages(
+    context: &EverClient,
+    page: MessagesPage<'_>,
+    params: ParamsOfQuery,
+) -> anyhow::Result<serde_json::Value> {
+    let bundle = match attached(context) {
+        Some(bundle) => bundle,
+        None => {
+            let result = run_query(context, params).await?;
+            return Ok(result["data"]["blockchain"]["account"]["messages"].clone());
+        }
+    };
+    let history = bundle
+        .entry(&messages_key(page.address), || async {
+            Ok(serde_json::to_vec(&load_history(context, page.address).await?)?)
+        })
+        .await?;
+    let history: Vec<serde_json::Value> = serde_json::from_slice(&history)?;
+    messages_page(&history, &page)
+}
+
+async fn load_history(
+    context: &EverClient,
+    address: &BlockchainContractAddress,
+) -> anyhow::Result<Vec<serde_json::Value>> {
+    tracing::trace!("load_history: address={address}");
+    let mut history = vec![];
+    let mut cursor = String::new();
+    loop {
+        let result = run_query(
+            context,
+            ParamsOfQuery {
+                query: HISTORY_QUERY.to_owned(),
+                variables: Some(serde_json::json!({
+                    "addr": address,
+                    "after": cursor,
+                    "limit": HISTORY_PAGE_SIZE,
+                })),
+                ..Default::default()
+            },
+        )
+        .await?;
+        let messages = &result["data"]["blockchain"]["account"]["messages"];
+        if let Some(edges) = messages["edges"].as_array() {
+            history.extend(edges.iter().map(|edge| edge["node"].clone()));
+        }
+        if messages["pageInfo"]["hasNextPage"].as_bool() != Some(true) {
+            break;
+        }
+        cursor = messages["pageInfo"]["endCursor"]
+            .as_str()
+            .ok_or_else(|| anyhow::format_err!("Page of messages to {address} has no cursor"))?
+            .to_owned();
+    }
+    Ok(history)
+}
+
+fn messages_page(
+    history: &[serde_json::Value],
+    page: &MessagesPage,
+) -> anyhow::Result<serde_json::Value> {
+    let position = match page.cursor.filter(|cursor| !cursor.is_empty()) {
+        Some(cursor) => Some(
+            cursor
+                .parse::<usize>()
+                .map_err(|_| BundleError::UnknownCursor(cursor.to_owned()))?
+                .min(history.len()),
+        ),
+        None => None,
+    };
+    let limit = page.limit.unwrap_or(DEFAULT_MESSAGES_PAGE_SIZE) as usize;
+    let (start, end) = if page.from_end {
+        let end = position.unwrap_or(history.len());
+        (end.saturating_sub(limit), end)
+    } else {
+        let start = position.unwrap_or(0);
+        (start, (start + limit).min(history.len()))
+    };
+    let edges: Vec<serde_json::Value> = history[start..end]
+        .iter()
+        .map(|node| serde_json::json!({ "node": node }))
+        .collect();
+    Ok(serde_json::json!({
+        "edges": edges,
+        "pageInfo": {
+            "hasPreviousPage": start > 0,
+            "startCursor": start.to_string(),
+            "hasNextPage": end < history.len(),
+            "endCursor": end.to_string(),
+        },
+    }))
+}
+
+/// `accounts` selected by their code hash with `params`, recorded to or
+/// served from the bundle
+pub async fn query_accounts_by_code_hash(
+    context: &EverClient,
+    code_hash: &str,
+    params: ParamsOfQuery,
+) -> anyhow::Result<serde_json::Value> {
+    let bundle = match attached(context) {
+        Some(bundle) => bundle,
+        None => return Ok(run_query(context, params).await?["data"]["accounts"].clone()),
+    };
+    let accounts = bundle
+        .entry(&code_hash_key(code_hash), || async {
+            let result = run_query(context, params).await?;
+            Ok(serde_json::to_vec(&result["data"]["accounts"])?)
+        })
+        .await?;
+    Ok(serde_json::from_slice(&accounts)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ton_client::ClientConfig;
+
+    fn client() -> EverClient {
+        Arc::new(ClientContext::new(ClientConfig::default()).unwrap())
+    }
+
+    #[test]
+    fn ensure_bundle_is_served_only_to_its_client() {
+        let path =
+            std::env::temp_dir().join(format!("gosh_bundle_attach_{}", std::process::id()));
+        let bundle = Bundle::create(&path, "gosh://0:0/dao/repo").unwrap();
+        let attached_client = client();
+        let other_client = client();
+        bundle.attach(&attached_client);
+        assert!(attached(&attached_client).is_some());
+        assert!(attached(&other_client).is_none());
+
+        // a new client never takes the address of the dropped one
+        let key = client_key(&attached_client);
+        drop(attached_client);
+        let new_client = client();
+        assert_ne!(client_key(&new_client), key);
+        assert!(attached(&new_client).is_none());
+    }
+
+    fn history(len: usize) -> Vec<serde_json::Value> {
+        (0..len)
+            .map(|i| serde_json::json!({ "id": i.to_string() }))
+            .collect()
+    }
+
+    fn ids(page: &serde_json::Value) -> Vec<String> {
+        page["edges"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|edge| edge["node"]["id"].as_str().unwrap().to_owned())
+            .collect()
+    }
+
+    fn read_all(history: &[serde_json::Value], limit: u32, from_end: bool) -> Vec<String> {
+        let address = BlockchainContractAddress::new("0:1");
+        let mut cursor = String::new();
+        let mut result = vec![];
+        loop {
+            let page = messages_page(
+                history,
+                &MessagesPage {
+                    address: &address,
+                    cursor: Some(&cursor),
+                    limit: Some(limit),
+                    from_end,
+                },
+            )
+            .unwrap();
+            let mut page_ids = ids(&page);
+            let page_info = &page["pageInfo"];
+            let (has_more, next) = if from_end {
+                page_ids.reverse();
+                (page_info["hasPreviousPage"].as_bool().unwrap(), "startCursor")
+            } else {
+                (page_info["hasNextPage"].as_bool().unwrap(), "endCursor")
+            };
+            result.extend(page_ids);
+            if !has_more {
+                return result;
+            }
+            cursor = page_info[next].as_str().unwrap().to_owned();
+        }
+    }
+
+    #[test]
+    fn ensure_pages_do_not_depend_on_page_size() {
+        let history = history(7);
+        let forward: Vec<String> = (0..7).map(|i| i.to_string()).collect();
+        let backward: Vec<String> = forward.iter().rev().cloned().collect();
+        for limit in [1, 3, 7, 50] {
+            assert_eq!(read_all(&history, limit, false), forward);
+            assert_eq!(read_all(&history, limit, true), backward);
+        }
+        assert!(read_all(&[], 3, false).is_empty());
+
+        let address = BlockchainContractAddress::new("0:1");
+        let error = messages_page(
+            &history,
+            &MessagesPage {
+                address: &address,
+                cursor: Some("opaque"),
+                limit: None,
+                from_end: false,
+            },
+        )
+        .unwrap_err();
+        assert!(matches!(
+            error.downcast_ref::<BundleError>(),
+            Some(BundleError::UnknownCursor(_))
+        ));
+    }
+
+    #[test]
+    fn ensure_filter_ids_are_parsed() {
+        let filter = Some(serde_json::json!({ "id": { "in": ["0:1", "0:2"] } }));
+        assert_eq!(filter_ids(&filter), vec!["0:1", "0:2"]);
+        let filter = Some(serde_json::json!({ "id": { "eq": "0:3" } }));
+        assert_eq!(filter_ids(&filter), vec!["0:3"]);
+        assert!(filter_ids(&None).is_empty());
+    }
+}
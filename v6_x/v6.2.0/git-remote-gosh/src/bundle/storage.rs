@@ -0,0 +1,62 @@
+use super::{ipfs_key, Bundle, BundleError};
+use crate::ipfs::{
+    service::{FileLoad, FileSave, FileStorage},
+    IpfsService,
+};
+use async_trait::async_trait;
+use std::path::Path;
+
+/// `FileStorage` of a bundle: IPFS content is loaded from `ipfs` and
+/// recorded while the bundle is exported, and served from the bundle
+/// afterwards
+#[derive(Debug, Clone)]
+pub struct BundleStorage {
+    bundle: Bundle,
+    ipfs: Option<IpfsService>,
+}
+
+impl BundleStorage {
+    pub fn recording(bundle: Bundle, ipfs: IpfsService) -> Self {
+        Self {
+            bundle,
+            ipfs: Some(ipfs),
+        }
+    }
+
+    pub fn replaying(bundle: Bundle) -> Self {
+        Self { bundle, ipfs: None }
+    }
+}
+
+#[async_trait]
+impl FileLoad for BundleStorage {
+    #[instrument(level = "trace", skip_all)]
+    async fn load(&self, cid: &str) -> anyhow::Result<Vec<u8>> {
+        tracing::debug!("bundle load: cid={cid}");
+        self.bundle
+            .entry(&ipfs_key(cid), || async {
+                match &self.ipfs {
+                    Some(ipfs) => ipfs.load(cid).await,
+                    None => Err(BundleError::Missing(ipfs_key(cid)).into()),
+                }
+            })
+            .await
+    }
+}
+
+#[async_trait]
+impl FileSave for BundleStorage {
+    async fn save_blob(&self, _blob: &[u8]) -> anyhow::Result<String> {
+        Err(BundleError::ReadOnly("IPFS upload").into())
+    }
+
+    async fn save_file(&self, _path: impl AsRef<Path> + Send + Sync) -> anyhow::Result<String> {
+        Err(BundleError::ReadOnly("IPFS upload").into())
+    }
+
+    async fn is_pinned(&self, _cid: &str) -> anyhow::Result<bool> {
+        Err(BundleError::ReadOnly("IPFS pin check").into())
+    }
+}
+
+impl FileStorage for BundleStorage {}
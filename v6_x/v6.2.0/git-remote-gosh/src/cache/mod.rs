@@ -39,4 +39,5 @@ impl CacheKey for &git_hash::ObjectId {
 }
 
 pub mod memcached_impl;
+pub mod object_cache;
 pub mod proxy;
@@ -0,0 +1,370 @@
+//! Local cache of objects that can't change: IPFS payloads by CID, restored
+//! blobs by sha1, constructor data of snapshots and complete pages of diffs.
+//! It is kept under `$GIT_DIR`, or in `GOSH_OBJECT_CACHE_DIR`, and least
+//! recently used entries are evicted above `GOSH_OBJECT_CACHE_SIZE` megabytes.
+
+use crate::blockchain::BlockchainContractAddress;
+use crate::utilities::stats;
+use once_cell::sync::OnceCell;
+use rocksdb::{BoundColumnFamily, DBWithThreadMode, IteratorMode, MultiThreaded, WriteBatch};
+use serde::{Serialize, de::DeserializeOwned};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+const OBJECT_CACHE_FOLDER_NAME: &str = "gosh_object_cache";
+const GOSH_OBJECT_CACHE_DIR: &str = "GOSH_OBJECT_CACHE_DIR";
+const GOSH_OBJECT_CACHE_SIZE: &str = "GOSH_OBJECT_CACHE_SIZE";
+const DEFAULT_OBJECT_CACHE_SIZE_MB: u64 = 512;
+// Eviction frees a bit more than necessary, so that it doesn't run on every put
+const EVICTION_TARGET_PERCENT: u64 = 90;
+// Values bigger than this share of the limit would evict too much
+const MAX_VALUE_PERCENT: u64 = 25;
+
+const DATA_CF: &str = "Data";
+// key -> (sequence number, value size)
+const ENTRIES_CF: &str = "Entries";
+// sequence number -> key, ordered from the least recently used
+const LRU_CF: &str = "Lru";
+const META_CF: &str = "Meta";
+const COLUMN_FAMILIES: [&str; 4] = [DATA_CF, ENTRIES_CF, LRU_CF, META_CF];
+const TOTAL_SIZE_KEY: &str = "total_size";
+
+static OBJECT_CACHE: OnceCell<ObjectCache> = OnceCell::new();
+
+pub fn object_cache() -> &'static ObjectCache {
+    OBJECT_CACHE.get_or_init(ObjectCache::open)
+}
+
+/// Disables the cache for the rest of the process, e.g. while a bundle is
+/// exported or served: cached objects would be missing in the bundle, and
+/// cursors of pages served from a bundle mean nothing to the network
+pub fn disable() {
+    if OBJECT_CACHE.set(ObjectCache::with_db(None, 0)).is_err() {
+        tracing::trace!("Object cache is already in use");
+    }
+}
+
+fn get_cache_size_limit() -> u64 {
+    std::env::var(GOSH_OBJECT_CACHE_SIZE)
+        .ok()
+        .and_then(|value| value.parse::<u64>().ok())
+        .unwrap_or(DEFAULT_OBJECT_CACHE_SIZE_MB)
+        * 1024
+        * 1024
+}
+
+fn get_cache_path() -> anyhow::Result<PathBuf> {
+    if let Ok(dir) = std::env::var(GOSH_OBJECT_CACHE_DIR) {
+        if !dir.is_empty() {
+            return Ok(PathBuf::from(dir));
+        }
+    }
+    let mut path = PathBuf::from(std::env::var("GIT_DIR")?);
+    path.push(OBJECT_CACHE_FOLDER_NAME);
+    Ok(path)
+}
+
+fn open_db(path: &Path) -> anyhow::Result<DBWithThreadMode<MultiThreaded>> {
+    let mut db_options = rocksdb::Options::default();
+    db_options.create_if_missing(true);
+    db_options.create_missing_column_families(true);
+    DBWithThreadMode::<MultiThreaded>::open_cf(&db_options, path, COLUMN_FAMILIES)
+        .map_err(|e| anyhow::format_err!("Failed to open object cache: {e}"))
+}
+
+pub fn ipfs_key(cid: &str) -> String {
+    format!("ipfs|{cid}")
+}
+
+pub fn blob_key(blob_id: &git_hash::ObjectId) -> String {
+    format!("blob|{blob_id}")
+}
+
+pub fn constructor_key(snapshot_address: &BlockchainContractAddress) -> String {
+    format!("constructor|{snapshot_address}")
+}
+
+pub fn diff_page_key(
+    snapshot_address: &BlockchainContractAddress,
+    cursor: &Option<String>,
+    from_end_to_start: bool,
+    page_size: u32,
+) -> String {
+    let direction = if from_end_to_start { "before" } else { "after" };
+    let cursor = cursor.as_deref().unwrap_or("");
+    format!("diffs|{snapshot_address}|{direction}{page_size}|{cursor}")
+}
+
+fn encode_entry(sequence: u64, size: u64) -> Vec<u8> {
+    let mut entry = sequence.to_be_bytes().to_vec();
+    entry.extend_from_slice(&size.to_be_bytes());
+    entry
+}
+
+fn decode_entry(entry: &[u8]) -> Option<(u64, u64)> {
+    if entry.len() != 16 {
+        return None;
+    }
+    let sequence = u64::from_be_bytes(entry[..8].try_into().ok()?);
+    let size = u64::from_be_bytes(entry[8..].try_into().ok()?);
+    Some((sequence, size))
+}
+
+#[derive(Debug, Default)]
+struct CacheState {
+    next_sequence: u64,
+    total_size: u64,
+}
+
+pub struct ObjectCache {
+    db: Option<DBWithThreadMode<MultiThreaded>>,
+    size_limit: u64,
+    // serializes updates of the bookkeeping column families
+    state: Mutex<CacheState>,
+}
+
+impl ObjectCache {
+    fn open() -> Self {
+        let size_limit = get_cache_size_limit();
+        if size_limit == 0 {
+            tracing::trace!("Object cache is disabled");
+            return Self::with_db(None, size_limit);
+        }
+        let db = get_cache_path()
+            .and_then(|path| open_db(&path))
+            .map_err(|e| {
+                // the cache is an optimisation, e.g. another fetch may hold the lock
+                tracing::trace!("Object cache is not used: {e}");
+                e
+            })
+            .ok();
+        Self::with_db(db, size_limit)
+    }
+
+    fn with_db(db: Option<DBWithThreadMode<MultiThreaded>>, size_limit: u64) -> Self {
+        let mut state = CacheState::default();
+        if let Some(db) = db.as_ref() {
+            if let Some(meta) = db.cf_handle(META_CF) {
+                state.total_size = db
+                    .get_cf(&meta, TOTAL_SIZE_KEY)
+                    .ok()
+                    .flatten()
+                    .and_then(|value| Some(u64::from_be_bytes(value.as_slice().try_into().ok()?)))
+                    .unwrap_or(0);
+            }
+            if let Some(lru) = db.cf_handle(LRU_CF) {
+                state.next_sequence = db
+                    .iterator_cf(&lru, IteratorMode::End)
+                    .next()
+                    .and_then(|item| item.ok())
+                    .and_then(|(sequence, _)| {
+                        Some(u64::from_be_bytes(sequence.as_ref().try_into().ok()?) + 1)
+                    })
+                    .unwrap_or(0);
+            }
+        }
+        Self {
+            db,
+            size_limit,
+            state: Mutex::new(state),
+        }
+    }
+
+    fn cf<'a>(
+        db: &'a DBWithThreadMode<MultiThreaded>,
+        name: &str,
+    ) -> anyhow::Result<Arc<BoundColumnFamily<'a>>> {
+        db.cf_handle(name)
+            .ok_or_else(|| anyhow::format_err!("Object cache has no column family {name}"))
+    }
+
+    pub fn get(&self, key: &str) -> Option<Vec<u8>> {
+        let db = self.db.as_ref()?;
+        match self.get_inner(db, key) {
+            Ok(Some(value)) => {
+                tracing::trace!("Object cache hit: {key}");
+                stats::record_cache_hit();
+                Some(value)
+            }
+            Ok(None) => None,
+            Err(e) => {
+                tracing::trace!("Object cache read failed: {e}");
+                None
+            }
+        }
+    }
+
+    pub fn put(&self, key: &str, value: &[u8]) {
+        let db = match self.db.as_ref() {
+            Some(db) => db,
+            None => return,
+        };
+        if value.len() as u64 > self.size_limit / 100 * MAX_VALUE_PERCENT {
+            tracing::trace!("Object cache: {key} is too big to be cached");
+            return;
+        }
+        if let Err(e) = self.put_inner(db, key, value) {
+            tracing::trace!("Object cache write failed: {e}");
+        }
+    }
+
+    pub fn get_serialized<T: DeserializeOwned>(&self, key: &str) -> Option<T> {
+        let value = self.get(key)?;
+        rmp_serde::from_slice(&value)
+            .map_err(|e| tracing::trace!("Object cache: failed to decode {key}: {e}"))
+            .ok()
+    }
+
+    pub fn put_serialized<T: Serialize>(&self, key: &str, value: &T) {
+        match rmp_serde::to_vec(value) {
+            Ok(value) => self.put(key, &value),
+            Err(e) => tracing::trace!("Object cache: failed to encode {key}: {e}"),
+        }
+    }
+
+    fn get_inner(
+        &self,
+        db: &DBWithThreadMode<MultiThreaded>,
+        key: &str,
+    ) -> anyhow::Result<Option<Vec<u8>>> {
+        let value = match db.get_cf(&Self::cf(db, DATA_CF)?, key)? {
+            Some(value) => value,
+            None => return Ok(None),
+        };
+        // mark the entry as the most recently used one
+        let mut state = self.state.lock().unwrap();
+        let entries = Self::cf(db, ENTRIES_CF)?;
+        let lru = Self::cf(db, LRU_CF)?;
+        if let Some((sequence, size)) = db
+            .get_cf(&entries, key)?
+            .and_then(|entry| decode_entry(&entry))
+        {
+            let mut batch = WriteBatch::default();
+            batch.delete_cf(&lru, sequence.to_be_bytes());
+            batch.put_cf(&lru, state.next_sequence.to_be_bytes(), key);
+            batch.put_cf(&entries, key, encode_entry(state.next_sequence, size));
+            db.write(batch)?;
+            state.next_sequence += 1;
+        }
+        Ok(Some(value))
+    }
+
+    fn put_inner(
+        &self,
+        db: &DBWithThreadMode<MultiThreaded>,
+        key: &str,
+        value: &[u8],
+    ) -> anyhow::Result<()> {
+        let mut state = self.state.lock().unwrap();
+        let entries = Self::cf(db, ENTRIES_CF)?;
+        let lru = Self::cf(db, LRU_CF)?;
+        let mut batch = WriteBatch::default();
+        let mut total_size = state.total_size;
+        if let Some((sequence, size)) = db
+            .get_cf(&entries, key)?
+            .and_then(|entry| decode_entry(&entry))
+        {
+            batch.delete_cf(&lru, sequence.to_be_bytes());
+            total_size = total_size.saturating_sub(size);
+        }
+        let size = value.len() as u64;
+        total_size += size;
+        batch.put_cf(&Self::cf(db, DATA_CF)?, key, value);
+        batch.put_cf(&entries, key, encode_entry(state.next_sequence, size));
+        batch.put_cf(&lru, state.next_sequence.to_be_bytes(), key);
+        batch.put_cf(
+            &Self::cf(db, META_CF)?,
+            TOTAL_SIZE_KEY,
+            total_size.to_be_bytes(),
+        );
+        db.write(batch)?;
+        state.next_sequence += 1;
+        state.total_size = total_size;
+
+        if state.total_size > self.size_limit {
+            self.evict(db, &mut state)?;
+        }
+        Ok(())
+    }
+
+    fn evict(
+        &self,
+        db: &DBWithThreadMode<MultiThreaded>,
+        state: &mut CacheState,
+    ) -> anyhow::Result<()> {
+        let target = self.size_limit / 100 * EVICTION_TARGET_PERCENT;
+        let data = Self::cf(db, DATA_CF)?;
+        let entries = Self::cf(db, ENTRIES_CF)?;
+        let lru = Self::cf(db, LRU_CF)?;
+        let mut batch = WriteBatch::default();
+        let mut total_size = state.total_size;
+        let mut evicted = 0;
+        for item in db.iterator_cf(&lru, IteratorMode::Start) {
+            if total_size <= target {
+                break;
+            }
+            let (sequence, key) = item?;
+            let size = db
+                .get_cf(&entries, &key)?
+                .and_then(|entry| decode_entry(&entry))
+                .map(|(_, size)| size)
+                .unwrap_or(0);
+            batch.delete_cf(&lru, sequence);
+            batch.delete_cf(&entries, &key);
+            batch.delete_cf(&data, &key);
+            total_size = total_size.saturating_sub(size);
+            evicted += 1;
+        }
+        batch.put_cf(
+            &Self::cf(db, META_CF)?,
+            TOTAL_SIZE_KEY,
+            total_size.to_be_bytes(),
+        );
+        db.write(batch)?;
+        state.total_size = total_size;
+        tracing::trace!("Object cache: evicted {evicted} entries, {total_size} bytes left");
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ensure_least_recently_used_entries_are_evicted() {
+        let path =
+            std::env::temp_dir().join(format!("gosh_object_cache_test_{}", std::process::id()));
+        let cache = ObjectCache::with_db(Some(open_db(&path).unwrap()), 1000);
+
+        cache.put("first", &[1; 200]);
+        cache.put("second", &[2; 200]);
+        cache.put("third", &[3; 200]);
+        assert_eq!(cache.get("first").unwrap(), vec![1; 200]);
+        cache.put("fourth", &[4; 200]);
+        // exceeds the limit and evicts down to 900 bytes
+        cache.put("fifth", &[5; 250]);
+
+        assert!(cache.get("second").is_none());
+        assert!(cache.get("first").is_some());
+        assert!(cache.get("fifth").is_some());
+        assert_eq!(cache.state.lock().unwrap().total_size, 850);
+
+        // too big for the cache
+        cache.put("sixth", &[6; 300]);
+        assert!(cache.get("sixth").is_none());
+
+        // bookkeeping survives reopening
+        drop(cache);
+        let cache = ObjectCache::with_db(Some(open_db(&path).unwrap()), 1000);
+        assert_eq!(cache.state.lock().unwrap().total_size, 850);
+        cache.put_serialized("page", &(vec![7u8; 3], Some("cursor".to_owned())));
+        let page: (Vec<u8>, Option<String>) = cache.get_serialized("page").unwrap();
+        assert_eq!(page.1.as_deref(), Some("cursor"));
+
+        drop(cache);
+        let mut db_options = rocksdb::Options::default();
+        db_options.create_if_missing(true);
+        DBWithThreadMode::<MultiThreaded>::destroy(&db_options, &path).unwrap();
+    }
+}
@@ -7,6 +7,9 @@ use std::{
 };
 
 mod defaults;
+pub mod retry;
+
+use retry::{Operation, RetrySettings};
 
 pub const IPFS_CONTENT_THRESHOLD: usize = 63 * 1024; // 63kb (1kb buffer)
 const SET_COMMIT_TIMEOUT: u64 = 60; // in secs
@@ -48,6 +51,9 @@ pub struct Config {
 
     #[serde(rename = "networks")]
     networks: HashMap<String, NetworkConfig>,
+
+    #[serde(rename = "retries")]
+    retries: HashMap<Operation, RetrySettings>,
 }
 
 impl fmt::Debug for UserWalletConfig {
@@ -74,6 +80,7 @@ impl Default for Config {
                 })
                 .collect(),
             primary_network: defaults::PRIMARY_NETWORK.to_string(),
+            retries: HashMap::new(),
         }
     }
 }
@@ -98,12 +105,14 @@ impl Config {
             env::var("GOSH_CONFIG_PATH").unwrap_or_else(|_| defaults::CONFIG_LOCATION.to_string());
         let config_path = shellexpand::tilde(&config_path).into_owned();
         let config_path = Path::new(&config_path);
-        if !config_path.exists() {
-            return Ok(Self::default());
-        }
-        let config_file = std::fs::File::open(config_path)?;
-        let config_reader = BufReader::new(config_file);
-        Self::load(config_reader)
+        let config = if config_path.exists() {
+            let config_file = std::fs::File::open(config_path)?;
+            Self::load(BufReader::new(config_file))?
+        } else {
+            Self::default()
+        };
+        retry::install(&config.retries);
+        Ok(config)
     }
 
     pub fn use_cache(&self) -> Option<String> {
@@ -137,6 +146,19 @@ impl Config {
         }
     }
 
+    /// Configured and default networks
+    pub fn networks(&self) -> Vec<String> {
+        let mut networks: Vec<String> = self
+            .networks
+            .keys()
+            .chain(defaults::NETWORK_ENDPOINTS.keys())
+            .cloned()
+            .collect();
+        networks.sort();
+        networks.dedup();
+        networks
+    }
+
     pub fn find_network_user_wallet(&self, network: &str) -> Option<UserWalletConfig> {
         tracing::debug!("Networks: {:?}", self.networks);
         self.networks
@@ -149,6 +171,7 @@ impl Config {
 #[cfg(test)]
 pub mod tests {
     use super::*;
+    use retry::RetryPolicy;
 
     pub fn load_from(s: &str) -> Config {
         Config::load(s.as_bytes()).unwrap()
@@ -244,6 +267,33 @@ pub mod tests {
         );
     }
 
+    #[test]
+    fn ensure_retry_policies_are_taken_from_config() {
+        let config = load_from(
+            r#"
+            {
+                "retries": {
+                    "ipfs": { "max-retries": 5, "backoff": "fixed" },
+                    "redeploy": { "initial-delay-ms": 500 }
+                }
+            }
+        "#,
+        );
+        let policy = |operation| RetryPolicy::resolve(operation, config.retries.get(&operation));
+        let ipfs = policy(Operation::Ipfs);
+        assert_eq!(ipfs.max_retries, 5);
+        assert_eq!(ipfs.backoff, retry::Backoff::Fixed);
+        let redeploy = policy(Operation::Redeploy);
+        assert_eq!(
+            redeploy.initial_delay,
+            std::time::Duration::from_millis(500)
+        );
+        assert_eq!(
+            policy(Operation::Fetch),
+            RetryPolicy::default_for(Operation::Fetch)
+        );
+    }
+
     #[test]
     fn ensure_added_network_does_not_drop_defaults() {
         let config = load_from(
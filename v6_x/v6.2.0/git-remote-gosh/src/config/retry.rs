@@ -0,0 +1,292 @@
+//! Retry policies of network operations by class, set in the `retries`
+//! section of the config, e.g. `"retries": { "ipfs": { "max-retries": 5 } }`.
+//! `Config::init` installs them for the whole process.
+
+use crate::utilities::{env::parse_env_or, stats};
+use once_cell::sync::Lazy;
+use std::{
+    collections::HashMap,
+    fmt,
+    future::Future,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        RwLock,
+    },
+    time::Duration,
+};
+use tokio_retry::RetryIf;
+
+const GOSH_DEPLOY_RETRIES: &str = "GOSH_DEPLOY_RETRIES";
+
+static INSTALLED_SETTINGS: Lazy<RwLock<HashMap<Operation, RetrySettings>>> =
+    Lazy::new(|| RwLock::new(HashMap::new()));
+
+/// Class of operations that share a retry policy
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Operation {
+    /// Deploys and calls of the push made through the wallet
+    Blockchain,
+    /// Uploads and downloads of IPFS content
+    Ipfs,
+    /// Restore of a set of blobs during the fetch
+    Fetch,
+    /// Redeploys of contracts that didn't appear after the push
+    Redeploy,
+    /// Resends of expired messages by the SDK, only `max-retries` is used
+    Message,
+    /// Network requests retried by the SDK, only `max-retries` is used
+    Network,
+}
+
+impl fmt::Display for Operation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Operation::Blockchain => "blockchain",
+            Operation::Ipfs => "ipfs",
+            Operation::Fetch => "fetch",
+            Operation::Redeploy => "redeploy",
+            Operation::Message => "message",
+            Operation::Network => "network",
+        };
+        f.write_str(name)
+    }
+}
+
+/// How delays between attempts grow
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Backoff {
+    Fixed,
+    Fibonacci,
+    /// Delays are multiplied by `factor`
+    Exponential,
+}
+
+/// Overrides of a retry policy in the config
+#[derive(Debug, Clone, Default, PartialEq, serde::Deserialize, serde::Serialize)]
+pub struct RetrySettings {
+    #[serde(rename = "max-retries")]
+    pub max_retries: Option<usize>,
+    #[serde(rename = "initial-delay-ms")]
+    pub initial_delay_ms: Option<u64>,
+    #[serde(rename = "max-delay-ms")]
+    pub max_delay_ms: Option<u64>,
+    pub backoff: Option<Backoff>,
+    pub factor: Option<u32>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RetryPolicy {
+    pub operation: Operation,
+    /// Attempts after the first one
+    pub max_retries: usize,
+    pub initial_delay: Duration,
+    pub max_delay: Duration,
+    pub backoff: Backoff,
+    pub factor: u32,
+}
+
+impl RetryPolicy {
+    pub fn default_for(operation: Operation) -> Self {
+        let (max_retries, initial_delay_ms, max_delay_ms, backoff, factor) = match operation {
+            Operation::Blockchain => (20, 100, 60_000, Backoff::Fibonacci, 1),
+            Operation::Ipfs => (20, 100, 30_000, Backoff::Exponential, 3),
+            Operation::Fetch => (3, 5_000, 5_000, Backoff::Fixed, 1),
+            Operation::Redeploy => (2, 1_000, 30_000, Backoff::Exponential, 2),
+            Operation::Message => (10, 0, 0, Backoff::Fixed, 1),
+            Operation::Network => (5, 0, 0, Backoff::Fixed, 1),
+        };
+        Self {
+            operation,
+            max_retries,
+            initial_delay: Duration::from_millis(initial_delay_ms),
+            max_delay: Duration::from_millis(max_delay_ms),
+            backoff,
+            factor,
+        }
+    }
+
+    fn with(mut self, settings: &RetrySettings) -> Self {
+        if let Some(max_retries) = settings.max_retries {
+            self.max_retries = max_retries;
+        }
+        if let Some(initial_delay_ms) = settings.initial_delay_ms {
+            self.initial_delay = Duration::from_millis(initial_delay_ms);
+        }
+        if let Some(max_delay_ms) = settings.max_delay_ms {
+            self.max_delay = Duration::from_millis(max_delay_ms);
+        }
+        if let Some(backoff) = settings.backoff {
+            self.backoff = backoff;
+        }
+        if let Some(factor) = settings.factor {
+            self.factor = factor;
+        }
+        self
+    }
+
+    /// Policy of the operation with the config section applied
+    pub fn resolve(operation: Operation, settings: Option<&RetrySettings>) -> Self {
+        let mut policy = Self::default_for(operation);
+        if let Some(settings) = settings {
+            policy = policy.with(settings);
+        }
+        if operation == Operation::Redeploy {
+            // the variable counts the first deploy too
+            let attempts = policy.max_retries.saturating_add(1);
+            match parse_env_or(GOSH_DEPLOY_RETRIES, attempts) {
+                Ok(attempts) => policy.max_retries = attempts.saturating_sub(1),
+                Err(e) => tracing::trace!("{e}"),
+            }
+        }
+        policy
+    }
+
+    /// Delay before the retry with the number `retry`, starting from 1
+    pub fn delay(&self, retry: usize) -> Duration {
+        let retry = retry.max(1);
+        let delay = match self.backoff {
+            Backoff::Fixed => self.initial_delay,
+            Backoff::Fibonacci => {
+                let (mut current, mut next) = (self.initial_delay, self.initial_delay);
+                for _ in 1..retry {
+                    if current >= self.max_delay {
+                        break;
+                    }
+                    (current, next) = (next, current.saturating_add(next));
+                }
+                current
+            }
+            Backoff::Exponential => {
+                let exponent = u32::try_from(retry - 1).unwrap_or(u32::MAX);
+                let multiplier = self.factor.checked_pow(exponent).unwrap_or(u32::MAX);
+                self.initial_delay.saturating_mul(multiplier)
+            }
+        };
+        delay.min(self.max_delay)
+    }
+
+    /// Delays before every retry, each of them is recorded and traced
+    fn delays(&self) -> impl Iterator<Item = Duration> {
+        let policy = self.clone();
+        (1..=self.max_retries).map(move |retry| {
+            let delay = policy.delay(retry);
+            tracing::trace!(
+                "{}: retry {retry}/{} in {delay:?}",
+                policy.operation,
+                policy.max_retries
+            );
+            stats::record_retry();
+            delay
+        })
+    }
+
+    /// Runs `action` until it succeeds, returns an error that is not
+    /// `retryable` or runs out of retries
+    pub async fn run<A, F, T, C>(&self, mut action: A, retryable: C) -> anyhow::Result<T>
+    where
+        A: FnMut() -> F,
+        F: Future<Output = anyhow::Result<T>>,
+        C: Fn(&anyhow::Error) -> bool,
+    {
+        let operation = self.operation;
+        let attempt = AtomicUsize::new(0);
+        let attempt_ref = &attempt;
+        let action = || {
+            let number = attempt_ref.fetch_add(1, Ordering::SeqCst) + 1;
+            tracing::trace!("{operation}: attempt {number}");
+            action()
+        };
+        let condition = |e: &anyhow::Error| {
+            let number = attempt_ref.load(Ordering::SeqCst);
+            let is_retryable = retryable(e);
+            tracing::trace!(
+                "{operation}: attempt {number} failed, retryable={is_retryable}: {e:#}"
+            );
+            is_retryable
+        };
+        RetryIf::spawn(self.delays(), action, condition).await
+    }
+}
+
+/// Sets the retry sections of the config for the process
+pub fn install(settings: &HashMap<Operation, RetrySettings>) {
+    *INSTALLED_SETTINGS.write().unwrap() = settings.clone();
+}
+
+/// Policy of the operation with the installed config applied
+pub fn policy(operation: Operation) -> RetryPolicy {
+    let settings = INSTALLED_SETTINGS.read().unwrap();
+    RetryPolicy::resolve(operation, settings.get(&operation))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ensure_delays_grow_by_backoff_and_are_capped() {
+        let fibonacci = RetryPolicy::default_for(Operation::Blockchain);
+        let delays: Vec<u64> = (1..=6)
+            .map(|retry| fibonacci.delay(retry).as_millis() as u64)
+            .collect();
+        assert_eq!(delays, vec![100, 100, 200, 300, 500, 800]);
+        assert_eq!(fibonacci.delay(usize::MAX), fibonacci.max_delay);
+
+        let exponential = RetryPolicy::default_for(Operation::Ipfs);
+        assert_eq!(exponential.delay(1), Duration::from_millis(100));
+        assert_eq!(exponential.delay(3), Duration::from_millis(900));
+        assert_eq!(exponential.delay(usize::MAX), Duration::from_secs(30));
+
+        let fixed = RetryPolicy::default_for(Operation::Fetch);
+        assert_eq!(fixed.delay(1), fixed.delay(3));
+        assert_eq!(fixed.delays().count(), 3);
+    }
+
+    #[test]
+    fn ensure_settings_override_only_given_fields() {
+        let settings = RetrySettings {
+            max_retries: Some(5),
+            backoff: Some(Backoff::Fixed),
+            ..Default::default()
+        };
+        let policy = RetryPolicy::resolve(Operation::Ipfs, Some(&settings));
+        let default = RetryPolicy::default_for(Operation::Ipfs);
+        assert_eq!(policy.max_retries, 5);
+        assert_eq!(policy.backoff, Backoff::Fixed);
+        assert_eq!(policy.initial_delay, default.initial_delay);
+        assert_eq!(policy.max_delay, default.max_delay);
+    }
+
+    #[tokio::test]
+    async fn ensure_fatal_errors_are_not_retried() {
+        let mut policy = RetryPolicy::default_for(Operation::Blockchain);
+        policy.initial_delay = Duration::from_millis(1);
+        let attempts = AtomicUsize::new(0);
+        let result: anyhow::Result<()> = policy
+            .run(
+                || async {
+                    attempts.fetch_add(1, Ordering::SeqCst);
+                    anyhow::bail!("fatal")
+                },
+                |e| e.to_string() != "fatal",
+            )
+            .await;
+        assert!(result.is_err());
+        assert_eq!(attempts.load(Ordering::SeqCst), 1);
+
+        policy.max_retries = 2;
+        let result: anyhow::Result<()> = policy
+            .run(
+                || async {
+                    attempts.fetch_add(1, Ordering::SeqCst);
+                    anyhow::bail!("transient")
+                },
+                |_| true,
+            )
+            .await;
+        assert!(result.is_err());
+        assert_eq!(attempts.load(Ordering::SeqCst), 4);
+    }
+}
@@ -0,0 +1,128 @@
+use crate::blockchain::BlockchainContractAddress;
+use crate::database::{GoshDB, JOURNAL_CF};
+use std::collections::HashSet;
+
+// Reserved key of the journal column that stores the ref and the commit
+// the journal was created for. Contract addresses can't clash with it.
+const PUSH_TARGET_KEY: &str = "push_target";
+const CONFIRMED: &[u8] = b"confirmed";
+
+impl GoshDB {
+    /// Opens the database in `local_git_dir` as a journal of the push to
+    /// `target`. Journal left by the interrupted push of the same target is
+    /// reused and `true` is returned, journal of any other push is stale and
+    /// gets discarded
+    pub fn open_push_journal(local_git_dir: &str, target: &str) -> anyhow::Result<(Self, bool)> {
+        let mut db = GoshDB::open_in(local_git_dir)?;
+        match db.get_push_target()? {
+            Some(journal_target) if journal_target == target => return Ok((db, true)),
+            Some(journal_target) => {
+                tracing::info!("Discarding stale push journal: {journal_target}");
+                db.delete()?;
+                db = GoshDB::open_in(local_git_dir)?;
+            }
+            None => {}
+        }
+        db.set_push_target(target)?;
+        Ok((db, false))
+    }
+
+    pub fn get_push_target(&self) -> anyhow::Result<Option<String>> {
+        tracing::trace!("get push target");
+        let value = self.db().get_cf(&self.cf(JOURNAL_CF), PUSH_TARGET_KEY)?;
+        Ok(value.map(|v| String::from_utf8_lossy(&v).to_string()))
+    }
+
+    pub fn set_push_target(&self, target: &str) -> anyhow::Result<()> {
+        tracing::trace!("set push target {target}");
+        let db = self.db();
+        db.put_cf(&self.cf(JOURNAL_CF), PUSH_TARGET_KEY, target)?;
+        db.flush()?;
+        Ok(())
+    }
+
+    fn mark_confirmed(&self, address: &str) -> anyhow::Result<()> {
+        tracing::trace!("mark confirmed {address}");
+        self.db().put_cf(&self.cf(JOURNAL_CF), address, CONFIRMED)?;
+        Ok(())
+    }
+
+    /// Records all expected contracts except not ready ones as confirmed,
+    /// so the next run of the same push doesn't deploy them again
+    pub fn confirm_deployed(
+        &self,
+        expected: impl IntoIterator<Item = String>,
+        not_ready: &[BlockchainContractAddress],
+    ) -> anyhow::Result<()> {
+        let not_ready: HashSet<String> = not_ready.iter().map(String::from).collect();
+        for address in expected {
+            if !not_ready.contains(&address) {
+                self.mark_confirmed(&address)?;
+            }
+        }
+        self.db().flush_cf(&self.cf(JOURNAL_CF))?;
+        Ok(())
+    }
+
+    pub fn is_confirmed(&self, address: &str) -> anyhow::Result<bool> {
+        self.value_exists(address, JOURNAL_CF)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::DB_FOLDER_NAME;
+
+    fn git_dir(name: &str) -> String {
+        let dir = std::env::temp_dir().join(format!("{name}_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir.to_str().unwrap().to_owned()
+    }
+
+    #[test]
+    fn ensure_journal_of_the_same_target_is_resumed() {
+        let git_dir = git_dir("gosh_journal_resume");
+        let (db, resumed) = GoshDB::open_push_journal(&git_dir, "main:abc").unwrap();
+        assert!(!resumed);
+        db.confirm_deployed(vec!["0:01".to_owned()], &[]).unwrap();
+        drop(db);
+
+        let (mut db, resumed) = GoshDB::open_push_journal(&git_dir, "main:abc").unwrap();
+        assert!(resumed);
+        assert!(db.is_confirmed("0:01").unwrap());
+        db.delete().unwrap();
+        std::fs::remove_dir_all(&git_dir).unwrap();
+    }
+
+    #[test]
+    fn ensure_journal_of_another_target_is_discarded() {
+        let git_dir = git_dir("gosh_journal_discard");
+        let (db, _) = GoshDB::open_push_journal(&git_dir, "main:abc").unwrap();
+        db.confirm_deployed(vec!["0:01".to_owned()], &[]).unwrap();
+        drop(db);
+
+        let (mut db, resumed) = GoshDB::open_push_journal(&git_dir, "main:def").unwrap();
+        assert!(!resumed);
+        assert!(!db.is_confirmed("0:01").unwrap());
+        assert_eq!(db.get_push_target().unwrap(), Some("main:def".to_owned()));
+        db.delete().unwrap();
+        assert!(!std::path::Path::new(&git_dir).join(DB_FOLDER_NAME).exists());
+        std::fs::remove_dir_all(&git_dir).unwrap();
+    }
+
+    #[test]
+    fn ensure_not_ready_contracts_are_not_confirmed() {
+        let git_dir = git_dir("gosh_journal_confirm");
+        let (mut db, _) = GoshDB::open_push_journal(&git_dir, "main:abc").unwrap();
+        let expected = vec!["0:01".to_owned(), "0:02".to_owned(), "0:03".to_owned()];
+        let not_ready = [BlockchainContractAddress::new("0:02")];
+        db.confirm_deployed(expected, &not_ready).unwrap();
+        assert!(db.is_confirmed("0:01").unwrap());
+        assert!(!db.is_confirmed("0:02").unwrap());
+        assert!(db.is_confirmed("0:03").unwrap());
+        db.delete().unwrap();
+        std::fs::remove_dir_all(&git_dir).unwrap();
+    }
+}
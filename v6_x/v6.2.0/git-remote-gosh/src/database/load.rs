@@ -82,7 +82,7 @@ impl GoshDB {
         Ok(diffs)
     }
 
-    fn value_exists(&self, value: &str, cf: &str) -> anyhow::Result<bool> {
+    pub(super) fn value_exists(&self, value: &str, cf: &str) -> anyhow::Result<bool> {
         tracing::trace!("Check value {value} exists in {cf}");
         Ok(self
             .db()
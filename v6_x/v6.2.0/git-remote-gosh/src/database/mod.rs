@@ -1,3 +1,4 @@
+mod journal;
 mod load;
 mod save;
 mod types;
@@ -13,9 +14,22 @@ const TREE_CF: &str = "Tree";
 const COMMIT_CF: &str = "Commit";
 const SNAPSHOT_CF: &str = "Snapshot";
 const DANGLING_DIFF_CF: &str = "DanglingDiff";
+const JOURNAL_CF: &str = "Journal";
+const COLUMN_FAMILIES: [&str; 6] = [
+    DIFF_CF,
+    TREE_CF,
+    SNAPSHOT_CF,
+    COMMIT_CF,
+    DANGLING_DIFF_CF,
+    JOURNAL_CF,
+];
 
 fn get_db_path() -> anyhow::Result<String> {
     let local_git_dir = std::env::var("GIT_DIR")?;
+    get_db_path_in(&local_git_dir)
+}
+
+fn get_db_path_in(local_git_dir: &str) -> anyhow::Result<String> {
     let mut path = PathBuf::from(local_git_dir);
     path.push(DB_FOLDER_NAME);
     let res = path
@@ -46,20 +60,25 @@ fn get_db_options() -> rocksdb::Options {
     db_options
 }
 
-fn create_db() -> anyhow::Result<DBWithThreadMode<MultiThreaded>> {
+fn create_db(db_path: &str) -> anyhow::Result<DBWithThreadMode<MultiThreaded>> {
     tracing::trace!("create db");
     tracing::trace!("Create local database");
     let db_options = get_db_options();
-    let db_path = get_db_path()?;
-    let db = if Path::new(&db_path).exists() {
-        let cfs = DBWithThreadMode::<MultiThreaded>::list_cf(&db_options, &db_path)?;
-        DBWithThreadMode::<MultiThreaded>::open_cf(&db_options, &db_path, cfs.clone())
-            .map_err(|e| anyhow::format_err!("Failed to open DB: {e}"))?
+    let db = if Path::new(db_path).exists() {
+        let cfs = DBWithThreadMode::<MultiThreaded>::list_cf(&db_options, db_path)?;
+        let db = DBWithThreadMode::<MultiThreaded>::open_cf(&db_options, db_path, cfs.clone())
+            .map_err(|e| anyhow::format_err!("Failed to open DB: {e}"))?;
+        // database can be left by the previous version without some columns
+        for cf in COLUMN_FAMILIES {
+            if !cfs.iter().any(|existing| existing == cf) {
+                db.create_cf(cf, &db_options)?;
+            }
+        }
+        db
     } else {
-        let db = DBWithThreadMode::<MultiThreaded>::open(&db_options, &db_path)
+        let db = DBWithThreadMode::<MultiThreaded>::open(&db_options, db_path)
             .map_err(|e| anyhow::format_err!("Failed to open temporary database: {e}"))?;
-        let cfs = vec![DIFF_CF, TREE_CF, SNAPSHOT_CF, COMMIT_CF, DANGLING_DIFF_CF];
-        for cf in cfs {
+        for cf in COLUMN_FAMILIES {
             db.create_cf(cf, &db_options)?;
         }
         db
@@ -73,7 +92,13 @@ pub struct GoshDB {
 
 impl GoshDB {
     pub fn new() -> anyhow::Result<Self> {
-        let db = create_db()?;
+        let db = create_db(&get_db_path()?)?;
+        Ok(GoshDB { db: Some(db) })
+    }
+
+    /// Opens the database of the repository with the git dir `local_git_dir`
+    pub fn open_in(local_git_dir: &str) -> anyhow::Result<Self> {
+        let db = create_db(&get_db_path_in(local_git_dir)?)?;
         Ok(GoshDB { db: Some(db) })
     }
 
@@ -98,4 +123,15 @@ impl GoshDB {
         }
         Ok(())
     }
+
+    /// Removes the database left by an interrupted push without opening it
+    pub fn discard(local_git_dir: &str) -> anyhow::Result<bool> {
+        let db_path = get_db_path_in(local_git_dir)?;
+        tracing::trace!("discard db: {db_path}");
+        if !Path::new(&db_path).exists() {
+            return Ok(false);
+        }
+        DBWithThreadMode::<MultiThreaded>::destroy(&get_db_options(), &db_path)?;
+        Ok(true)
+    }
 }
@@ -0,0 +1,424 @@
+//! Health scores of network endpoints: latency and error rate as moving
+//! averages, persisted in `GOSH_ENDPOINT_HEALTH_PATH` and refreshed by
+//! probes at most once per `GOSH_ENDPOINT_PROBE_INTERVAL_SEC`. The client
+//! gets healthy endpoints from the fastest one.
+
+use super::ever_client::create_client_with_endpoints;
+use crate::{blockchain::EverClient, config::Config, utilities::env::parse_env_or};
+use futures::future::join_all;
+use once_cell::sync::Lazy;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use ton_client::{error::ClientError, net::ParamsOfQuery};
+
+const GOSH_ENDPOINT_HEALTH_PATH: &str = "GOSH_ENDPOINT_HEALTH_PATH";
+const GOSH_ENDPOINT_PROBE_INTERVAL_SEC: &str = "GOSH_ENDPOINT_PROBE_INTERVAL_SEC";
+const GOSH_ENDPOINT_PROBE_TIMEOUT_SEC: &str = "GOSH_ENDPOINT_PROBE_TIMEOUT_SEC";
+const DEFAULT_PROBE_INTERVAL: Duration = Duration::from_secs(300);
+const DEFAULT_PROBE_TIMEOUT: Duration = Duration::from_secs(5);
+// endpoints failing more often are excluded
+const MAX_ERROR_RATE: f64 = 0.5;
+// weight of the last probe in the moving averages
+const SMOOTHING: f64 = 0.3;
+const PROBE_QUERY: &str = "query { info { version } }";
+// errors of the SDK net module that mean the endpoint didn't serve the
+// request: query failed, wait for failed, invalid server response, wait for
+// timeout, websocket disconnected, websocket init error, connection error
+const ENDPOINT_ERROR_CODES: &[u32] = &[601, 603, 605, 607, 610, 613, 617];
+
+// number of failed requests and the last error by the URL they were sent
+// to, saved to the scores once when the helper finishes
+static REQUEST_ERRORS: Lazy<Mutex<HashMap<String, (u64, String)>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+static TEMP_FILE_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+fn get_health_path() -> PathBuf {
+    match std::env::var(GOSH_ENDPOINT_HEALTH_PATH) {
+        Ok(path) if !path.is_empty() => PathBuf::from(path),
+        _ => PathBuf::from(shellexpand::tilde("~").into_owned())
+            .join(".gosh")
+            .join("endpoint_health.json"),
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or(0)
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct EndpointHealth {
+    pub latency_ms: f64,
+    pub error_rate: f64,
+    pub probes: u64,
+    /// unix time of the last probe
+    pub last_probe: u64,
+    pub last_error: Option<String>,
+}
+
+impl EndpointHealth {
+    /// `probed_at` is `None` for failures of the requests, they don't
+    /// postpone the next probe
+    fn record(&mut self, result: &Result<Duration, String>, probed_at: Option<u64>) {
+        let (latency_ms, failed) = match result {
+            Ok(latency) => (latency.as_millis() as f64, 0.0),
+            // failures don't change the latency
+            Err(_) => (self.latency_ms, 1.0),
+        };
+        if self.probes == 0 {
+            self.latency_ms = latency_ms;
+            self.error_rate = failed;
+        } else {
+            self.latency_ms += SMOOTHING * (latency_ms - self.latency_ms);
+            self.error_rate += SMOOTHING * (failed - self.error_rate);
+        }
+        self.probes += 1;
+        if let Some(probed_at) = probed_at {
+            self.last_probe = probed_at;
+        }
+        self.last_error = result.as_ref().err().cloned();
+    }
+
+    pub fn is_healthy(&self) -> bool {
+        self.probes == 0 || self.error_rate < MAX_ERROR_RATE
+    }
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct HealthScores {
+    endpoints: HashMap<String, EndpointHealth>,
+}
+
+impl HealthScores {
+    /// Scores are an optimisation, missing or broken file gives empty scores
+    pub fn load(path: &Path) -> Self {
+        std::fs::read(path)
+            .map_err(anyhow::Error::from)
+            .and_then(|data| Ok(serde_json::from_slice(&data)?))
+            .unwrap_or_else(|e| {
+                tracing::trace!("Endpoint health scores are not loaded: {e}");
+                Self::default()
+            })
+    }
+
+    /// Writes the scores to a temporary file that replaces `path`, so
+    /// concurrent helpers never read a partially written file
+    pub fn save(&self, path: &Path) -> anyhow::Result<()> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let mut temp_path = path.as_os_str().to_owned();
+        temp_path.push(format!(
+            ".{}.{}.tmp",
+            std::process::id(),
+            TEMP_FILE_COUNTER.fetch_add(1, Ordering::Relaxed)
+        ));
+        let temp_path = PathBuf::from(temp_path);
+        let result = std::fs::write(&temp_path, serde_json::to_vec_pretty(self)?)
+            .and_then(|_| std::fs::rename(&temp_path, path));
+        if result.is_err() {
+            if let Err(e) = std::fs::remove_file(&temp_path) {
+                tracing::trace!("Failed to remove {}: {e}", temp_path.display());
+            }
+        }
+        Ok(result?)
+    }
+
+    pub fn get(&self, endpoint: &str) -> Option<&EndpointHealth> {
+        self.endpoints.get(endpoint)
+    }
+
+    fn needs_probe(&self, endpoints: &[String], probed_at: u64, interval: Duration) -> bool {
+        endpoints
+            .iter()
+            .any(|endpoint| match self.endpoints.get(endpoint) {
+                Some(health) => health.last_probe + interval.as_secs() <= probed_at,
+                None => true,
+            })
+    }
+
+    fn record(&mut self, endpoint: &str, result: &Result<Duration, String>, probed_at: u64) {
+        self.endpoints
+            .entry(endpoint.to_owned())
+            .or_default()
+            .record(result, Some(probed_at));
+    }
+
+    /// Records the failure of a request sent to `url`, the URL the SDK
+    /// built from one of the endpoints. Returns `false` when the endpoint
+    /// is not known
+    fn record_request_error(&mut self, url: &str, error: &str) -> bool {
+        let url = normalize_endpoint(url);
+        match self
+            .endpoints
+            .iter_mut()
+            .find(|(endpoint, _)| normalize_endpoint(endpoint) == url)
+        {
+            Some((_, health)) => {
+                health.record(&Err(error.to_owned()), None);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Healthy endpoints from the fastest one, endpoints that were not
+    /// probed yet keep their order after them. When no endpoint is healthy
+    /// all of them are returned from the least failing one
+    pub fn rank(&self, endpoints: Vec<String>) -> Vec<String> {
+        let health = |endpoint: &String| self.endpoints.get(endpoint).cloned().unwrap_or_default();
+        let mut healthy: Vec<String> = endpoints
+            .iter()
+            .filter(|endpoint| health(endpoint).is_healthy())
+            .cloned()
+            .collect();
+        if healthy.is_empty() {
+            let mut all = endpoints;
+            all.sort_by(|a, b| health(a).error_rate.total_cmp(&health(b).error_rate));
+            return all;
+        }
+        healthy.sort_by(|a, b| {
+            let (a, b) = (health(a), health(b));
+            let latency = |h: &EndpointHealth| {
+                if h.probes == 0 {
+                    f64::MAX
+                } else {
+                    h.latency_ms
+                }
+            };
+            latency(&a).total_cmp(&latency(&b))
+        });
+        healthy
+    }
+}
+
+/// `https://host/graphql` and `host` are the same endpoint
+fn normalize_endpoint(endpoint: &str) -> &str {
+    let endpoint = endpoint.trim_end_matches('/');
+    let endpoint = endpoint.strip_suffix("/graphql").unwrap_or(endpoint);
+    endpoint
+        .split_once("://")
+        .map_or(endpoint, |(_, address)| address)
+}
+
+async fn probe_endpoint(endpoint: &str, timeout: Duration) -> Result<Duration, String> {
+    let client =
+        create_client_with_endpoints(vec![endpoint.to_owned()]).map_err(|e| e.to_string())?;
+    let started_at = Instant::now();
+    let query = ton_client::net::query(
+        Arc::clone(&client),
+        ParamsOfQuery {
+            query: PROBE_QUERY.to_owned(),
+            ..Default::default()
+        },
+    );
+    match tokio::time::timeout(timeout, query).await {
+        Ok(Ok(_)) => Ok(started_at.elapsed()),
+        Ok(Err(e)) => Err(e.to_string()),
+        Err(_) => Err(format!("no response in {}s", timeout.as_secs())),
+    }
+}
+
+async fn probe_all(scores: &mut HealthScores, endpoints: &[String]) -> anyhow::Result<()> {
+    let timeout = parse_env_or(GOSH_ENDPOINT_PROBE_TIMEOUT_SEC, DEFAULT_PROBE_TIMEOUT)?;
+    let results = join_all(
+        endpoints
+            .iter()
+            .map(|endpoint| probe_endpoint(endpoint, timeout)),
+    )
+    .await;
+    let probed_at = now();
+    for (endpoint, result) in endpoints.iter().zip(results) {
+        tracing::trace!("Probe of {endpoint}: {result:?}");
+        scores.record(endpoint, &result, probed_at);
+    }
+    Ok(())
+}
+
+/// Probes endpoints of the network when their scores are outdated
+#[instrument(level = "info", skip_all)]
+pub async fn refresh(config: &Config, network: &str) -> anyhow::Result<()> {
+    let endpoints = match config.find_network_endpoints(network) {
+        Some(endpoints) => endpoints,
+        None => return Ok(()),
+    };
+    let path = get_health_path();
+    let mut scores = HealthScores::load(&path);
+    let interval = parse_env_or(GOSH_ENDPOINT_PROBE_INTERVAL_SEC, DEFAULT_PROBE_INTERVAL)?;
+    if !scores.needs_probe(&endpoints, now(), interval) {
+        return Ok(());
+    }
+    probe_all(&mut scores, &endpoints).await?;
+    scores.save(&path)
+}
+
+fn record_request_error(url: &str, error: &ClientError) {
+    let mut errors = REQUEST_ERRORS.lock().unwrap();
+    let (count, last_error) = errors.entry(url.to_owned()).or_default();
+    *count += 1;
+    *last_error = error.to_string();
+}
+
+fn apply_request_errors(scores: &mut HealthScores, errors: HashMap<String, (u64, String)>) {
+    for (url, (count, error)) in errors {
+        for _ in 0..count {
+            if !scores.record_request_error(&url, &error) {
+                tracing::trace!("Endpoint {url} has no health score");
+                break;
+            }
+        }
+    }
+}
+
+/// Saves failures of the requests made by the helper to the scores
+pub fn save_request_errors() {
+    let errors = std::mem::take(&mut *REQUEST_ERRORS.lock().unwrap());
+    if errors.is_empty() {
+        return;
+    }
+    let path = get_health_path();
+    let mut scores = HealthScores::load(&path);
+    apply_request_errors(&mut scores, errors);
+    if let Err(e) = scores.save(&path) {
+        tracing::trace!("Endpoint health is not saved: {e}");
+    }
+}
+
+/// Passes the result of a request made by `context` through. Its network
+/// error counts as a failure of the endpoint the request was sent to
+pub async fn tracked<T>(
+    context: &EverClient,
+    result: Result<T, ClientError>,
+) -> Result<T, ClientError> {
+    if let Err(error) = &result {
+        if ENDPOINT_ERROR_CODES.contains(&error.code) {
+            match ton_client::net::get_endpoints(Arc::clone(context)).await {
+                Ok(endpoints) => record_request_error(&endpoints.query, error),
+                Err(e) => tracing::trace!("Endpoint of the failed request is unknown: {e}"),
+            }
+        }
+    }
+    result
+}
+
+/// Orders endpoints by the persisted scores
+pub fn rank(endpoints: Vec<String>) -> Vec<String> {
+    let ranked = HealthScores::load(&get_health_path()).rank(endpoints);
+    tracing::trace!("Ranked endpoints: {ranked:?}");
+    ranked
+}
+
+/// Probes endpoints of the network, or of all known networks, and describes
+/// their status
+pub async fn report(config: &Config, network: Option<&str>) -> anyhow::Result<Vec<String>> {
+    let networks = match network {
+        Some(network) => vec![network.to_owned()],
+        None => config.networks(),
+    };
+    let path = get_health_path();
+    let mut scores = HealthScores::load(&path);
+    let mut lines = vec![];
+    for network in networks {
+        let endpoints = config
+            .find_network_endpoints(&network)
+            .ok_or_else(|| anyhow::format_err!("Unknown network {network}"))?;
+        probe_all(&mut scores, &endpoints).await?;
+        lines.push(network);
+        let ranked = scores.rank(endpoints.clone());
+        for endpoint in endpoints {
+            let health = scores.get(&endpoint).cloned().unwrap_or_default();
+            let status = if !ranked.contains(&endpoint) {
+                "excluded"
+            } else if health.last_error.is_some() {
+                "degraded"
+            } else {
+                "ok"
+            };
+            let mut line = format!(
+                "  {endpoint} {status}: latency {:.0}ms, error rate {:.2}, {} probes",
+                health.latency_ms, health.error_rate, health.probes
+            );
+            if let Some(error) = health.last_error {
+                line.push_str(&format!(", last error: {error}"));
+            }
+            lines.push(line);
+        }
+    }
+    scores.save(&path)?;
+    Ok(lines)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn endpoints() -> Vec<String> {
+        vec!["a".to_owned(), "b".to_owned(), "c".to_owned()]
+    }
+
+    #[test]
+    fn ensure_endpoints_are_ranked_by_health() {
+        let mut scores = HealthScores::default();
+        assert_eq!(scores.rank(endpoints()), endpoints());
+        assert!(scores.needs_probe(&endpoints(), 1000, Duration::from_secs(300)));
+
+        scores.record("a", &Ok(Duration::from_millis(900)), 1000);
+        scores.record("b", &Ok(Duration::from_millis(100)), 1000);
+        scores.record("c", &Err("timeout".to_owned()), 1000);
+        assert_eq!(scores.rank(endpoints()), ["b", "a"]);
+        assert!(!scores.needs_probe(&endpoints(), 1200, Duration::from_secs(300)));
+        assert!(scores.needs_probe(&endpoints(), 1300, Duration::from_secs(300)));
+
+        // a single success doesn't bring the failing endpoint back
+        scores.record("c", &Ok(Duration::from_millis(10)), 1300);
+        assert!(!scores.get("c").unwrap().is_healthy());
+        scores.record("c", &Ok(Duration::from_millis(10)), 1600);
+        assert_eq!(scores.rank(endpoints()), ["c", "b", "a"]);
+
+        // the least failing endpoint is kept when all of them fail
+        let mut scores = HealthScores::default();
+        scores.record("a", &Err("timeout".to_owned()), 1000);
+        scores.record("b", &Err("timeout".to_owned()), 1000);
+        scores.record("b", &Ok(Duration::from_millis(100)), 1300);
+        assert_eq!(scores.rank(vec!["a".to_owned(), "b".to_owned()])[0], "b");
+    }
+
+    #[test]
+    fn ensure_request_errors_are_recorded_and_saved() {
+        let mut scores = HealthScores::default();
+        scores.record("https://a.gosh.sh", &Ok(Duration::from_millis(100)), 1000);
+        assert!(scores.record_request_error("https://a.gosh.sh/graphql", "timeout"));
+        assert!(!scores.record_request_error("https://b.gosh.sh/graphql", "timeout"));
+        let health = scores.get("https://a.gosh.sh").unwrap();
+        assert_eq!(health.probes, 2);
+        assert_eq!(health.last_probe, 1000);
+        assert_eq!(health.last_error.as_deref(), Some("timeout"));
+
+        // errors kept during the run are applied one by one
+        apply_request_errors(
+            &mut scores,
+            HashMap::from([
+                ("https://a.gosh.sh/graphql".to_owned(), (2, "refused".to_owned())),
+                ("https://b.gosh.sh/graphql".to_owned(), (1, "refused".to_owned())),
+            ]),
+        );
+        let health = scores.get("https://a.gosh.sh").unwrap();
+        assert_eq!(health.probes, 4);
+        assert_eq!(health.last_error.as_deref(), Some("refused"));
+        assert!(scores.get("https://b.gosh.sh").is_none());
+
+        let dir = std::env::temp_dir().join(format!("gosh_endpoint_health_{}", std::process::id()));
+        let path = dir.join("endpoint_health.json");
+        scores.save(&path).unwrap();
+        scores.save(&path).unwrap();
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 1);
+        let loaded = HealthScores::load(&path);
+        assert_eq!(loaded.get("https://a.gosh.sh"), scores.get("https://a.gosh.sh"));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
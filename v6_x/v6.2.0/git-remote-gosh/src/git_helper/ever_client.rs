@@ -1,4 +1,12 @@
-use crate::{blockchain::EverClient, config::Config, utilities::env::parse_env_or};
+use super::endpoint_health;
+use crate::{
+    blockchain::EverClient,
+    config::{
+        retry::{self, Operation},
+        Config,
+    },
+    utilities::env::parse_env_or,
+};
 use std::{env, sync::Arc, time::Duration};
 use ton_client::{net::NetworkQueriesProtocol, ClientConfig, ClientContext};
 
@@ -15,6 +23,31 @@ pub fn create_client(config: &Config, network: &str) -> anyhow::Result<EverClien
     let endpoints = config
         .find_network_endpoints(network)
         .expect("Unknown network");
+    // degraded endpoints are left out, so the SDK fails over to a healthy one
+    create_client_with_endpoints(endpoint_health::rank(endpoints))
+}
+
+/// Creates a separate client for every endpoint of the network, so that
+/// the same read can be compared across endpoints
+#[instrument(level = "info", skip_all)]
+pub fn create_endpoint_clients(
+    config: &Config,
+    network: &str,
+) -> anyhow::Result<Vec<(String, EverClient)>> {
+    tracing::trace!("create_endpoint_clients: network={network}");
+    let endpoints = config
+        .find_network_endpoints(network)
+        .expect("Unknown network");
+    endpoints
+        .into_iter()
+        .map(|endpoint| {
+            let client = create_client_with_endpoints(vec![endpoint.clone()])?;
+            Ok((endpoint, client))
+        })
+        .collect()
+}
+
+pub(super) fn create_client_with_endpoints(endpoints: Vec<String>) -> anyhow::Result<EverClient> {
     let proto = env::var("GOSH_PROTO")
         .unwrap_or_else(|_| ".git".to_string())
         .to_lowercase();
@@ -37,8 +70,8 @@ pub fn create_client(config: &Config, network: &str) -> anyhow::Result<EverClien
             } else {
                 NetworkQueriesProtocol::WS
             },
-            network_retries_count: 5,
-            message_retries_count: 10,
+            network_retries_count: retry::policy(Operation::Network).max_retries.try_into()?,
+            message_retries_count: retry::policy(Operation::Message).max_retries.try_into()?,
             message_processing_timeout: message_processing_timeout.as_millis().try_into()?,
             wait_for_timeout: wait_for_timeout.as_millis().try_into()?,
             query_timeout: query_timeout.as_millis().try_into()?,
@@ -2,14 +2,17 @@ use super::GitHelper;
 use crate::{
     blockchain,
     blockchain::{
-        calculate_contract_address, get_contract_code, tag::load::TagObject,
-        BlockchainContractAddress, BlockchainService, GetContractCodeResult,
+        consistency, contract::GoshContract, tag::load::TagObject, BlockchainContractAddress,
+        BlockchainService,
     },
+    ipfs::service::FileStorage,
 };
+use futures::stream::{self, StreamExt, TryStreamExt};
 use git_odb::{Find, Write};
 use tokio::sync::Mutex;
 
 use crate::blockchain::Tree;
+use crate::git_helper::report::{self, ReportBuilder};
 
 use bstr::ByteSlice;
 use std::{
@@ -19,14 +22,157 @@ use std::{
     sync::Arc,
 };
 
-use crate::blockchain::tree::load::type_obj_to_entry_mod;
+use crate::blockchain::tree::load::{type_obj_to_entry_mod, TreeComponent};
 use git_object::tree::EntryMode;
 
 mod restore_blobs;
+mod verify;
 
-impl<Blockchain> GitHelper<Blockchain>
+// Max number of objects loaded simultaneously during the fetch
+const GOSH_FETCH_CONCURRENCY: &str = "GOSH_FETCH_CONCURRENCY";
+
+const DEFAULT_FETCH_CONCURRENCY: usize = 32;
+
+fn get_fetch_concurrency() -> usize {
+    std::env::var(GOSH_FETCH_CONCURRENCY)
+        .ok()
+        .and_then(|num| num.parse::<usize>().ok())
+        .filter(|num| *num > 0)
+        .unwrap_or(DEFAULT_FETCH_CONCURRENCY)
+}
+
+enum TreeEntryToLoad {
+    Tree {
+        path: String,
+        oid: git_hash::ObjectId,
+        address: BlockchainContractAddress,
+    },
+    Blob {
+        oid: git_hash::ObjectId,
+        snapshot_address: BlockchainContractAddress,
+    },
+    Submodule,
+}
+
+/// Calculates the address of the contract that holds the tree entry
+async fn tree_entry_to_load(
+    client: &blockchain::EverClient,
+    repo_contract: &GoshContract,
+    branch: &str,
+    tree_id: &git_hash::ObjectId,
+    path_to_node: &str,
+    tree_component: &TreeComponent,
+) -> anyhow::Result<TreeEntryToLoad> {
+    let mode: EntryMode = type_obj_to_entry_mod(tree_component.type_obj.as_str());
+    let oid = git_hash::ObjectId::from_hex(tree_component.git_sha.as_bytes())
+        .expect("SHA1 must be correct");
+    let entry = match mode {
+        git_object::tree::EntryMode::Tree => {
+            tracing::debug!("branch={branch}: Tree entry: tree {tree_id}->{oid}");
+            let sha_inner_tree = tree_component
+                .tvm_sha_tree
+                .clone()
+                .ok_or(anyhow::format_err!(
+                    "Failed to get sha of inner tree: {}",
+                    tree_component.git_sha
+                ))?;
+            let address = Tree::calculate_address(client, repo_contract, &sha_inner_tree).await?;
+            TreeEntryToLoad::Tree {
+                path: format!("{}{}/", path_to_node, tree_component.name),
+                oid,
+                address,
+            }
+        }
+        git_object::tree::EntryMode::Blob
+        | git_object::tree::EntryMode::BlobExecutable
+        | git_object::tree::EntryMode::Link => {
+            tracing::debug!("branch={branch}: Tree entry: blob {tree_id}->{oid}");
+            let file_path = format!("{}{}", path_to_node, &tree_component.name);
+            let snapshot_address = blockchain::Snapshot::calculate_address(
+                client,
+                repo_contract,
+                &tree_component.commit,
+                &file_path,
+            )
+            .await?;
+            // TODO can we exclude blob here if it already has visited?
+            tracing::debug!(
+                "branch={branch}: Adding a blob to search for. Path: {}, id: {}, snapshot: {}",
+                file_path,
+                oid,
+                snapshot_address
+            );
+            TreeEntryToLoad::Blob {
+                oid,
+                snapshot_address,
+            }
+        }
+        git_object::tree::EntryMode::Commit => TreeEntryToLoad::Submodule,
+        _ => {
+            unreachable!()
+        }
+    };
+    Ok(entry)
+}
+
+/// Confirms the commit on other endpoints when consistency checks are enabled
+async fn verify_commit_consistency(
+    id: &git_hash::ObjectId,
+    address: &BlockchainContractAddress,
+    commit: &blockchain::GoshCommit,
+) -> anyhow::Result<()> {
+    if let Some(checker) = consistency::checker() {
+        checker
+            .verify(&format!("commit {id}"), commit, |client| async move {
+                blockchain::GoshCommit::load(&client, address).await
+            })
+            .await?;
+    }
+    Ok(())
+}
+
+/// Confirms the tree on other endpoints when consistency checks are enabled
+async fn verify_tree_consistency(
+    address: &BlockchainContractAddress,
+    tree: &Tree,
+) -> anyhow::Result<()> {
+    if let Some(checker) = consistency::checker() {
+        checker
+            .verify(&format!("tree {address}"), tree, |client| async move {
+                Tree::load(&client, address).await
+            })
+            .await?;
+    }
+    Ok(())
+}
+
+/// Loads the commit and the address of its tree from the same account state.
+/// Commits deployed by the upgrade have no tree in the current version.
+/// Failure to read the tree address is reported separately, since a missing
+/// commit is looked for in the previous versions instead.
+async fn load_commit_with_tree_address(
+    client: &blockchain::EverClient,
+    address: &BlockchainContractAddress,
+    boc: &str,
+) -> anyhow::Result<(
+    blockchain::GoshCommit,
+    anyhow::Result<Option<BlockchainContractAddress>>,
+)> {
+    let commit = blockchain::GoshCommit::load_with_boc(client, address, boc).await?;
+    let tree_address = if commit.initupgrade {
+        Ok(None)
+    } else {
+        Tree::get_address_from_commit_with_boc(client, address, boc)
+            .await
+            .map(Some)
+    };
+    Ok((commit, tree_address))
+}
+
+impl<Blockchain, FileProvider> GitHelper<Blockchain, FileProvider>
 where
     Blockchain: BlockchainService,
+    FileProvider: FileStorage + Clone + Send + Sync + 'static,
 {
     pub async fn calculate_commit_address(
         &mut self,
@@ -63,18 +209,19 @@ where
         Ok(object_id)
     }
 
-    fn write_git_object(
-        &mut self,
-        obj: impl git_object::WriteTo,
-    ) -> anyhow::Result<git_hash::ObjectId> {
-        tracing::info!("Writing git object");
+    fn write_git_commit(&mut self, raw_commit: &[u8]) -> anyhow::Result<git_hash::ObjectId> {
+        tracing::info!("Writing git commit object");
         let store = &self.local_repository().objects;
         // It should refresh once even if the refresh mode is never, just to initialize the index
         //store.refresh_never();
-        let object_id = store.write(obj).map_err(|e| {
-            tracing::error!("Write git object failed  with: {}", e);
-            e
-        })?;
+        // Raw bytes are written as is: decoding and encoding the commit again
+        // does not guarantee the same object (e.g. signature formatting)
+        let object_id = store
+            .write_buf(git_object::Kind::Commit, raw_commit)
+            .map_err(|e| {
+                tracing::error!("Write git object failed  with: {}", e);
+                e
+            })?;
         tracing::info!("Writing git object - success, {}", object_id);
         Ok(object_id)
     }
@@ -90,17 +237,21 @@ where
             anyhow::bail!("Error. Can not fetch an object without refs/heads/ prefix");
         }
         tracing::info!("Fetching sha: {} name: {}", sha, name);
+        let mut report = ReportBuilder::start("fetch", name);
         let branch: &str = {
             let mut iter = name.chars();
             iter.by_ref().nth(REFS_HEAD_PREFIX.len() - 1);
             iter.as_str()
         };
         tracing::debug!("Calculate branch: {}", branch);
+        let signature_required = verify::get_branches_requiring_signature()
+            .iter()
+            .any(|b| b == branch);
+        tracing::debug!("branch={branch}: signature_required={signature_required}");
 
         let context = self.blockchain.client();
         let remote_branches: Vec<String> = blockchain::branch_list(context, &self.repo_addr)
             .await?
-            .branch_ref
             .iter()
             .map(|b| b.branch_name.clone())
             .collect();
@@ -140,29 +291,84 @@ where
         let sha = git_hash::ObjectId::from_str(sha)?;
         commits_queue.push_front(sha);
 
+        let concurrency = get_fetch_concurrency();
+        let client = self.blockchain.client().clone();
+        let repo_contract = self.blockchain.repo_contract().clone();
+
         let mut dangling_trees = vec![];
         let mut dangling_commits = vec![];
         let mut next_commit_of_prev_version = vec![];
-        loop {
+        // Commits are loaded by waves: all queued commits are loaded
+        // concurrently, their parents make the next wave
+        while !commits_queue.is_empty() {
             tracing::trace!("commits_queue={:?}", commits_queue);
-            if let Some(id) = commits_queue.pop_back() {
+            let mut wave = vec![];
+            for id in commits_queue.drain(..) {
                 guard!(id);
-                let address = &self.calculate_commit_address(&id).await?;
-                let onchain_commit =
-                    match blockchain::GoshCommit::load(&self.blockchain.client(), address).await {
-                        Ok(commit) => commit,
-                        Err(e) => {
-                            tracing::trace!("Failed to load commit in current version: {e}");
-                            let version = self.find_commit(&id.to_string()).await?.version;
-                            tracing::trace!(
-                                "push to next_commit_of_prev_version=({},{})",
-                                id,
-                                version
-                            );
-                            next_commit_of_prev_version.push((version, id.to_string()));
-                            continue;
+                wave.push(id);
+            }
+            let addresses: Vec<(git_hash::ObjectId, BlockchainContractAddress)> =
+                stream::iter(wave)
+                    .map(|id| {
+                        let client = client.clone();
+                        let mut repo_contract = repo_contract.clone();
+                        async move {
+                            let address = blockchain::get_commit_address(
+                                &client,
+                                &mut repo_contract,
+                                &id.to_string(),
+                            )
+                            .await?;
+                            anyhow::Ok((id, address))
+                        }
+                    })
+                    .buffered(concurrency)
+                    .try_collect()
+                    .await?;
+            let bocs = blockchain::load_accounts_bocs(
+                &client,
+                &addresses
+                    .iter()
+                    .map(|(_, address)| address.clone())
+                    .collect::<Vec<_>>(),
+            )
+            .await?;
+            let loaded_commits: Vec<_> = stream::iter(addresses)
+                .map(|(id, address)| {
+                    let client = client.clone();
+                    let boc = bocs.get(&address).cloned();
+                    async move {
+                        let loaded = match boc {
+                            None => Err(anyhow::format_err!("account {address} not found")),
+                            Some(boc) => {
+                                load_commit_with_tree_address(&client, &address, &boc).await
+                            }
+                        };
+                        if let Ok((onchain_commit, _)) = &loaded {
+                            verify_commit_consistency(&id, &address, onchain_commit).await?;
                         }
-                    };
+                        anyhow::Ok((id, address, loaded))
+                    }
+                })
+                .buffered(concurrency)
+                .try_collect()
+                .await?;
+
+            for (id, address, loaded) in loaded_commits {
+                let (onchain_commit, tree_address) = match loaded {
+                    Ok((onchain_commit, tree_address)) => (onchain_commit, tree_address?),
+                    Err(e) => {
+                        tracing::trace!("Failed to load commit in current version: {e}");
+                        let version = self.find_commit(&id.to_string()).await?.version;
+                        tracing::trace!(
+                            "push to next_commit_of_prev_version=({},{})",
+                            id,
+                            version
+                        );
+                        next_commit_of_prev_version.push((version, id.to_string()));
+                        continue;
+                    }
+                };
                 tracing::debug!("branch={branch}: loaded onchain commit {}", id);
                 tracing::debug!(
                     "branch={branch} commit={id} addr={address}: data {:?}",
@@ -175,126 +381,143 @@ where
                 let obj = git_object::Object::from(data.decode()?).into_commit();
                 tracing::debug!("Received commit {}", id);
 
-                if onchain_commit.initupgrade {
-                    // Object can be first in the tree and have no parents
-                    let prev_version = onchain_commit.parents[0].clone().version;
-                    tracing::trace!(
-                        "push to next_commit_of_prev_version=({},{})",
-                        id,
-                        prev_version
-                    );
-                    next_commit_of_prev_version.push((prev_version, id.to_string()));
-                } else {
-                    let tree_address =
-                        Tree::get_address_from_commit(self.blockchain.client(), &address).await?;
-
-                    let to_load = TreeObjectsQueueItem {
-                        path: "".to_owned(),
-                        oid: obj.tree,
-                        address: tree_address,
-                    };
-                    tracing::debug!("New tree root: {}", &to_load.oid);
-                    tree_obj_queue.push_front(to_load);
-                    for parent_id in &obj.parents {
-                        commits_queue.push_front(*parent_id);
+                match tree_address {
+                    None => {
+                        // Object can be first in the tree and have no parents
+                        let prev_version = onchain_commit.parents[0].clone().version;
+                        tracing::trace!(
+                            "push to next_commit_of_prev_version=({},{})",
+                            id,
+                            prev_version
+                        );
+                        next_commit_of_prev_version.push((prev_version, id.to_string()));
+                    }
+                    Some(tree_address) => {
+                        verify::verify_object_id(
+                            git_object::Kind::Commit,
+                            onchain_commit.content.as_bytes(),
+                            &id,
+                        )?;
+                        if signature_required && !verify::has_signature(&obj) {
+                            anyhow::bail!(
+                                "Commit {id} is not signed. Branch {branch} accepts signed commits only"
+                            );
+                        }
+                        let to_load = TreeObjectsQueueItem {
+                            path: "".to_owned(),
+                            oid: obj.tree,
+                            address: tree_address,
+                        };
+                        tracing::debug!("New tree root: {}", &to_load.oid);
+                        tree_obj_queue.push_back(to_load);
+                        for parent_id in &obj.parents {
+                            commits_queue.push_back(*parent_id);
+                        }
+                        tracing::trace!("Push to dangling commits: {}", id);
+                        dangling_commits.push(onchain_commit.content);
                     }
-                    tracing::trace!("Push to dangling commits: {}", id);
-                    dangling_commits.push(obj);
                 }
-                continue;
             }
+        }
 
-            if !dangling_commits.is_empty() {
-                tracing::trace!("Writing dangling commits");
-                for obj in dangling_commits.iter().rev() {
-                    self.write_git_object(obj)?;
+        if !dangling_commits.is_empty() {
+            tracing::trace!("Writing dangling commits");
+            for raw_commit in dangling_commits.iter().rev() {
+                let commit_id = self.write_git_commit(raw_commit.as_bytes())?;
+                report.objects.commits += 1;
+                if signature_required {
+                    verify::verify_commit_signature(&commit_id)?;
                 }
-                dangling_commits.clear();
-                continue;
             }
-            break;
+            dangling_commits.clear();
         }
+        report.end_phase("commits");
 
+        // Trees are loaded by waves as well, blobs found in a wave
+        // are restored before the next one
         loop {
             if blobs_restore_plan.is_available() {
                 let visited_ref = Arc::clone(&visited);
                 let visited_ipfs_ref = Arc::clone(&visited_ipfs);
                 tracing::debug!("branch={branch}: Restoring blobs");
+                report.end_phase("trees");
                 blobs_restore_plan
                     .restore(self, visited_ref, visited_ipfs_ref, branch)
                     .await?;
+                report.end_phase("blobs");
                 blobs_restore_plan = restore_blobs::BlobsRebuildingPlan::new();
                 continue;
             }
-            if let Some(tree_node_to_load) = tree_obj_queue.pop_front() {
+            if tree_obj_queue.is_empty() {
+                break;
+            }
+            let mut wave = vec![];
+            for tree_node_to_load in tree_obj_queue.drain(..) {
                 tracing::debug!("branch={branch}: Loading tree: {:?}", tree_node_to_load);
                 let id = tree_node_to_load.oid;
-                tracing::debug!("branch={branch}: Loading tree: {}", id);
                 guard!(id);
                 tracing::debug!("branch={branch}: Ok. Guard passed. Loading tree: {}", id);
+                wave.push(tree_node_to_load);
+            }
+            let bocs = blockchain::load_accounts_bocs(
+                &client,
+                &wave
+                    .iter()
+                    .map(|item| item.address.clone())
+                    .collect::<Vec<_>>(),
+            )
+            .await?;
+            let loaded_trees: Vec<(TreeObjectsQueueItem, Tree)> = stream::iter(wave)
+                .map(|tree_node_to_load| {
+                    let client = client.clone();
+                    let boc = bocs.get(&tree_node_to_load.address).cloned();
+                    async move {
+                        let address = &tree_node_to_load.address;
+                        let boc = boc.ok_or_else(|| {
+                            anyhow::format_err!("account with address {address} not found")
+                        })?;
+                        let tree = Tree::load_with_boc(&client, address, &boc).await?;
+                        verify_tree_consistency(address, &tree).await?;
+                        anyhow::Ok((tree_node_to_load, tree))
+                    }
+                })
+                .buffered(concurrency)
+                .try_collect()
+                .await?;
+
+            for (tree_node_to_load, onchain_tree_object) in loaded_trees {
+                let id = tree_node_to_load.oid;
                 let path_to_node = tree_node_to_load.path;
                 let tree_object_id = format!("{}", tree_node_to_load.oid);
-                let address = tree_node_to_load.address;
-
-                let onchain_tree_object =
-                    blockchain::Tree::load(self.blockchain.client(), &address).await?;
-
-                for (_, tree_component) in &onchain_tree_object.objects {
-                    let mode: EntryMode = type_obj_to_entry_mod(tree_component.type_obj.as_str());
-                    let oid = git_hash::ObjectId::from_hex(tree_component.git_sha.as_bytes())
-                        .expect("SHA1 must be correct");
-                    match mode {
-                        git_object::tree::EntryMode::Tree => {
-                            tracing::debug!("branch={branch}: Tree entry: tree {}->{}", id, oid);
-                            let repo_contract = self.blockchain.repo_contract().clone();
-                            let sha_inner_tree =
-                                tree_component
-                                    .tvm_sha_tree
-                                    .clone()
-                                    .ok_or(anyhow::format_err!(
-                                        "Failed to get sha of inner tree: {}",
-                                        tree_component.git_sha
-                                    ))?;
-                            let sub_tree_address = Tree::calculate_address(
-                                self.blockchain.client(),
-                                &repo_contract,
-                                &sha_inner_tree,
-                            )
-                            .await?;
-                            let to_load = TreeObjectsQueueItem {
-                                path: format!("{}{}/", path_to_node, tree_component.name),
-                                oid,
-                                address: sub_tree_address,
-                            };
-                            tree_obj_queue.push_back(to_load);
+
+                // addresses of subtrees and snapshots are calculated concurrently
+                let entries: Vec<TreeEntryToLoad> = stream::iter(&onchain_tree_object.objects)
+                    .map(|(_, tree_component)| {
+                        tree_entry_to_load(
+                            &client,
+                            &repo_contract,
+                            branch,
+                            &id,
+                            &path_to_node,
+                            tree_component,
+                        )
+                    })
+                    .buffered(concurrency)
+                    .try_collect()
+                    .await?;
+
+                for entry in entries {
+                    match entry {
+                        TreeEntryToLoad::Tree { path, oid, address } => {
+                            tree_obj_queue.push_back(TreeObjectsQueueItem { path, oid, address });
                         }
-                        git_object::tree::EntryMode::Blob
-                        | git_object::tree::EntryMode::BlobExecutable
-                        | git_object::tree::EntryMode::Link => {
-                            tracing::debug!("branch={branch}: Tree entry: blob {}->{}", id, oid);
-                            let file_path = format!("{}{}", path_to_node, &tree_component.name);
-                            let mut repo_contract = self.blockchain.repo_contract().clone();
-
-                            let snapshot_address = blockchain::Snapshot::calculate_address(
-                                &Arc::clone(self.blockchain.client()),
-                                &mut repo_contract,
-                                &tree_component.commit,
-                                &file_path,
-                            )
-                            .await?;
-                            // TODO can we exclude blob here if it already has visited?
-                            tracing::debug!(
-                                "branch={branch}: Adding a blob to search for. Path: {}, id: {}, snapshot: {}",
-                                file_path,
-                                oid,
-                                snapshot_address
-                            );
+                        TreeEntryToLoad::Blob {
+                            oid,
+                            snapshot_address,
+                        } => {
                             blobs_restore_plan.mark_blob_to_restore(snapshot_address, oid);
                         }
-                        git_object::tree::EntryMode::Commit => (),
-                        _ => {
-                            unreachable!()
-                        }
+                        TreeEntryToLoad::Submodule => (),
                     }
                 }
 
@@ -302,21 +525,22 @@ where
 
                 tracing::trace!("Push to dangling tree: {}", tree_object_id);
                 dangling_trees.push(tree_object);
-                continue;
             }
-            if !dangling_trees.is_empty() {
-                tracing::trace!("Writing dangling trees");
-                for obj in dangling_trees.iter().rev() {
-                    self.write_git_tree(obj)?;
-                }
-                dangling_trees.clear();
+        }
+        if !dangling_trees.is_empty() {
+            tracing::trace!("Writing dangling trees");
+            for obj in dangling_trees.iter().rev() {
+                self.write_git_tree(obj)?;
+                report.objects.trees += 1;
             }
-            break;
+            dangling_trees.clear();
         }
+        report.end_phase("trees");
         tracing::trace!(
             "next_commit_of_prev_version={:?}",
             next_commit_of_prev_version
         );
+        report::publish(report.finish());
 
         Ok(next_commit_of_prev_version)
     }
@@ -328,20 +552,13 @@ where
         tag_name: &str,
     ) -> anyhow::Result<Vec<(String, String)>> {
         let client = self.blockchain.client();
-        let GetContractCodeResult { code } =
-            get_contract_code(client, &self.repo_addr, blockchain::ContractKind::Tag).await?;
-
-        let address = calculate_contract_address(
-            client,
-            blockchain::ContractKind::Tag,
-            &code,
-            Some(serde_json::json!({ "_nametag": tag_name })),
-        )
-        .await?;
+        let address = blockchain::address::tag_address(client, &self.repo_addr, tag_name).await?;
 
         let tag = crate::blockchain::tag::load::get_content(client, &address).await?;
 
         if let TagObject::Annotated(obj) = tag {
+            let expected_id = git_hash::ObjectId::from_str(sha)?;
+            verify::verify_object_id(git_object::Kind::Tag, &obj.content, &expected_id)?;
             let tag_object = git_object::Data::new(git_object::Kind::Tag, &obj.content);
             let store = self.local_repository().clone().objects;
             let tag_id = store.write_buf(tag_object.kind, tag_object.data)?;
@@ -1,7 +1,11 @@
 use super::GitHelper;
+use super::verify::{self, BlobIntegrityError};
 
 use crate::blockchain::{gosh_abi, ZERO_SHA};
+use crate::cache::object_cache::{self, object_cache};
+use crate::config::retry::{self, Operation};
 use crate::ipfs::build_ipfs;
+use crate::utilities::stats;
 use crate::{
     blockchain::{
         self, snapshot::diffs::DiffMessage, BlockchainContractAddress, BlockchainService,
@@ -24,8 +28,6 @@ use std::{
 use tokio::sync::Mutex;
 use tracing::Instrument;
 
-const FETCH_MAX_TRIES: i32 = 3;
-
 pub struct BlobsRebuildingPlan {
     snapshot_address_to_blob_sha: HashMap<BlockchainContractAddress, HashSet<ObjectId>>,
 }
@@ -36,6 +38,20 @@ async fn load_data_from_ipfs(
     ipfs_address: &str,
 ) -> anyhow::Result<Vec<u8>> {
     tracing::trace!("load_data_from_ipfs: ipfs_address={ipfs_address}");
+    let cache_key = object_cache::ipfs_key(ipfs_address);
+    if let Some(data) = object_cache().get(&cache_key) {
+        return Ok(data);
+    }
+    let data = load_data_from_ipfs_endpoint(ipfs_client, ipfs_address).await?;
+    object_cache().put(&cache_key, &data);
+
+    Ok(data)
+}
+
+async fn load_data_from_ipfs_endpoint(
+    ipfs_client: &impl FileLoad,
+    ipfs_address: &str,
+) -> anyhow::Result<Vec<u8>> {
     let ipfs_data = ipfs_client.load(ipfs_address).await?;
     let compressed_data = base64::decode(&ipfs_data)?;
     let data = ton_client::utils::decompress_zstd(&compressed_data)?;
@@ -43,6 +59,105 @@ async fn load_data_from_ipfs(
     Ok(data)
 }
 
+/// Blobs that don't match their diffs in every source won't match on a
+/// retry of the fetch either
+fn is_retryable(e: &anyhow::Error) -> bool {
+    !e.chain().any(|cause| cause.is::<BlobIntegrityError>())
+}
+
+/// Checks the blob restored from the diff message. Content stored in IPFS
+/// is loaded again, bypassing the object cache, from the main and the
+/// fallback endpoints when it doesn't match the diff
+#[instrument(level = "trace", skip_all)]
+async fn verified_blob(
+    es_client: &EverClient,
+    ipfs_client: &impl FileLoad,
+    snapshot_address: &BlockchainContractAddress,
+    message: &DiffMessage,
+    blob_data: Vec<u8>,
+) -> anyhow::Result<Vec<u8>> {
+    let error = match verify::verify_restored_blob(es_client, snapshot_address, message, &blob_data)
+        .await
+    {
+        Ok(()) => return Ok(blob_data),
+        Err(e) => e,
+    };
+    let ipfs = match &message.diff.ipfs {
+        Some(ipfs) if !message.diff.remove_ipfs && error.is::<BlobIntegrityError>() => ipfs,
+        _ => return Err(error),
+    };
+    tracing::trace!("{error}, loading {ipfs} from alternative sources");
+    // `None` stands for the file storage of the helper
+    let mut endpoints = vec![None];
+    endpoints.extend(verify::get_ipfs_fallback_endpoints().into_iter().map(Some));
+    for endpoint in endpoints {
+        let loaded = match &endpoint {
+            Some(endpoint) => load_data_from_ipfs_endpoint(&build_ipfs(endpoint)?, ipfs).await,
+            None => load_data_from_ipfs_endpoint(ipfs_client, ipfs).await,
+        };
+        let endpoint = endpoint.as_deref().unwrap_or("the file storage");
+        let blob_data = match loaded {
+            Ok(blob_data) => blob_data,
+            Err(e) => {
+                tracing::trace!("Failed to load {ipfs} from {endpoint}: {e}");
+                continue;
+            }
+        };
+        if verify::verify_restored_blob(es_client, snapshot_address, message, &blob_data)
+            .await
+            .is_ok()
+        {
+            tracing::trace!("Blob {ipfs} was restored from {endpoint}");
+            object_cache().put(&object_cache::ipfs_key(ipfs), &blob_data);
+            return Ok(blob_data);
+        }
+    }
+    Err(error)
+}
+
+/// Writes blobs found in the object cache and removes them from `blobs`
+#[instrument(level = "debug", skip_all)]
+async fn restore_blobs_from_cache(
+    repo: &mut git_repository::Repository,
+    blobs: &mut HashSet<git_hash::ObjectId>,
+    visited: Arc<Mutex<HashSet<git_hash::ObjectId>>>,
+) -> anyhow::Result<()> {
+    let mut restored = vec![];
+    for blob_id in blobs.iter() {
+        let blob_data = match object_cache().get(&object_cache::blob_key(blob_id)) {
+            Some(blob_data) => blob_data,
+            None => continue,
+        };
+        if let Err(e) = verify::verify_object_id(git_object::Kind::Blob, &blob_data, blob_id) {
+            tracing::trace!("Object cache: {e}, skip it");
+            continue;
+        }
+        let blob = git_object::Data::new(git_object::Kind::Blob, &blob_data);
+        let restored_id = write_git_data(repo, blob).await?;
+        restored.push(restored_id);
+    }
+    let mut visited = visited.lock().await;
+    for blob_id in restored {
+        visited.insert(blob_id);
+        blobs.remove(&blob_id);
+    }
+    Ok(())
+}
+
+/// Content after the change made by the diff message,
+/// or before it when the patch is reversed
+fn apply_patch(message: &DiffMessage, content: &[u8], reverse: bool) -> anyhow::Result<Vec<u8>> {
+    message.diff.with_patch(|patch| match patch {
+        Some(patch) if reverse => Ok(diffy::apply_bytes(content, &patch.reverse())?),
+        Some(patch) => Ok(diffy::apply_bytes(content, patch)?),
+        None => anyhow::bail!("Broken diff detected: neither ipfs nor patch exists"),
+    })
+}
+
+fn cache_blob(blob_id: &git_hash::ObjectId, blob_data: &[u8]) {
+    object_cache().put(&object_cache::blob_key(blob_id), blob_data);
+}
+
 #[instrument(level = "debug", skip(odb))]
 fn load_data_from_local(odb: &OdbHandle, blob_id: &ObjectId) -> anyhow::Result<Vec<u8>> {
     tracing::trace!("load_data_from_local: blob_id={blob_id}");
@@ -85,7 +200,7 @@ async fn write_git_object(
 
 async fn restore_a_set_of_blobs(
     es_client: &EverClient,
-    ipfs_endpoint: &str,
+    ipfs_client: &impl FileLoad,
     repo: &mut git_repository::Repository,
     repo_contract: &mut GoshContract,
     snapshot_address: &blockchain::BlockchainContractAddress,
@@ -100,7 +215,7 @@ async fn restore_a_set_of_blobs(
         Ok(true) => {
             restore_a_set_of_blobs_from_a_known_snapshot(
                 es_client,
-                ipfs_endpoint,
+                ipfs_client,
                 repo,
                 repo_contract,
                 &snapshot_address,
@@ -114,7 +229,7 @@ async fn restore_a_set_of_blobs(
         _ => {
             restore_a_set_of_blobs_from_a_deleted_snapshot(
                 es_client,
-                ipfs_endpoint,
+                ipfs_client,
                 repo,
                 repo_contract,
                 &snapshot_address,
@@ -131,7 +246,7 @@ async fn restore_a_set_of_blobs(
 #[instrument(level = "trace", skip_all)]
 async fn restore_a_set_of_blobs_from_a_known_snapshot(
     es_client: &EverClient,
-    ipfs_endpoint: &str,
+    ipfs_client: &impl FileLoad,
     repo: &mut git_repository::Repository,
     repo_contract: &mut GoshContract,
     snapshot_address: &blockchain::BlockchainContractAddress,
@@ -145,6 +260,7 @@ async fn restore_a_set_of_blobs_from_a_known_snapshot(
         let visited = visited.lock().await;
         blobs.retain(|e| !visited.contains(e));
     }
+    restore_blobs_from_cache(repo, blobs, visited.clone()).await?;
     tracing::info!("remaining: {:?}", blobs);
     if blobs.is_empty() {
         return Ok(blobs.to_owned());
@@ -157,7 +273,7 @@ async fn restore_a_set_of_blobs_from_a_known_snapshot(
     // future changes that might break logic unnoticed
     let current_snapshot_state = BlobsRebuildingPlan::restore_snapshot_blob(
         es_client,
-        ipfs_endpoint,
+        ipfs_client,
         repo,
         snapshot_address,
         visited_ipfs.clone(),
@@ -201,6 +317,7 @@ async fn restore_a_set_of_blobs_from_a_known_snapshot(
     let mut transition_content: Option<Vec<u8>> = None;
     let mut parsed = vec![];
     let mut visited_ipfs_hash: Option<String> = None;
+    let mut is_last = false;
     while !blobs.is_empty() {
         tracing::info!("Still expecting to restore blobs: {:?}", blobs);
 
@@ -217,6 +334,7 @@ async fn restore_a_set_of_blobs_from_a_known_snapshot(
                     if parsed.contains(&message) {
                         break;
                     }
+                    stats::record_diff_loaded(message.diff.patch_size());
                     message
                 }
             }
@@ -224,56 +342,86 @@ async fn restore_a_set_of_blobs_from_a_known_snapshot(
         tracing::trace!("got message: {:?}", message);
         parsed.push(message.clone());
         let blob_data: Vec<u8> = if message.diff.remove_ipfs {
-            let data = match message.diff.get_patch_data() {
-                Some(content) => content,
-                None => panic!("Broken diff detected: content doesn't exist"),
-            };
-            data
+            let data = message.diff.get_patch_data().ok_or_else(|| {
+                anyhow::format_err!("Broken diff detected: content doesn't exist")
+            })?;
+            verified_blob(es_client, ipfs_client, snapshot_address, &message, data).await?
         } else if let Some(ipfs) = &message.diff.ipfs {
             transition_content = message.diff.get_patch_data();
 
             let visited = visited_ipfs.lock().await;
             let blob_id: Option<ObjectId> = visited.get(ipfs).copied();
 
-            match blob_id {
+            let data = match blob_id {
                 Some(blob_id) => load_data_from_local(&repo.objects, &blob_id)?,
                 None => {
                     visited_ipfs_hash = Some(ipfs.to_owned());
-                    load_data_from_ipfs(&build_ipfs(&ipfs_endpoint)?, ipfs).await?
+                    load_data_from_ipfs(ipfs_client, ipfs).await?
                 }
-            }
+            };
+            drop(visited);
+            verified_blob(es_client, ipfs_client, snapshot_address, &message, data).await?
         } else if let Some(content) = transition_content.clone() {
+            // content before the change stored in IPFS is the result of this message
+            let content =
+                verified_blob(es_client, ipfs_client, snapshot_address, &message, content).await?;
             // we won't use the message, so we'll store it for the next iteration
             preserved_message = Some(message);
             transition_content = None;
             content
         } else {
-            let patched_blob_sha = &message
-                .diff
-                .modified_blob_sha1
-                .as_ref()
-                .expect("Option on this should be reverted. It must always be there");
+            let patched_blob_sha = message.diff.modified_blob_sha1.as_ref().ok_or_else(|| {
+                anyhow::format_err!("Broken diff detected: sha1 of the patched blob doesn't exist")
+            })?;
             let patched_blob_sha = git_hash::ObjectId::from_str(patched_blob_sha)?;
-            let content = last_restored_snapshots
+            let patched_blob = last_restored_snapshots
                 .get(&patched_blob_sha)
-                .expect("It is a sequence of changes. Sha must be correct. Fail otherwise");
-            let patched_blob = content.to_vec();
-
-            message
-                .diff
-                .with_patch::<_, anyhow::Result<Vec<u8>>>(|e| match e {
-                    Some(patch) => {
-                        let blob_data =
-                            diffy::apply_bytes(&patched_blob, &patch.clone().reverse())?;
-                        Ok(blob_data)
-                    }
-                    None => panic!("Broken diff detected: neither ipfs nor patch exists"),
+                .ok_or_else(|| {
+                    anyhow::format_err!(
+                        "Blob {patched_blob_sha} changed by the diff is not restored"
+                    )
                 })?
+                .to_vec();
+            let blob_data = apply_patch(&message, &patched_blob, true)?;
+            // content before the change is the result of the previous message
+            match messages.next(&es_client).await? {
+                Some(previous) if !parsed.contains(&previous) => {
+                    stats::record_diff_loaded(previous.diff.patch_size());
+                    let blob_data = verified_blob(
+                        es_client,
+                        ipfs_client,
+                        snapshot_address,
+                        &previous,
+                        blob_data,
+                    )
+                    .await?;
+                    preserved_message = Some(previous);
+                    blob_data
+                }
+                _ => {
+                    // no message describes the content before the first change,
+                    // so it is only taken if git asked for exactly this blob
+                    let blob_id = git_object::compute_hash(
+                        git_hash::Kind::Sha1,
+                        git_object::Kind::Blob,
+                        &blob_data,
+                    );
+                    if !blobs.contains(&blob_id) {
+                        tracing::trace!(
+                            "Content before the first diff is not requested: {blob_id}"
+                        );
+                        break;
+                    }
+                    is_last = true;
+                    blob_data
+                }
+            }
         };
 
         let blob = git_object::Data::new(git_object::Kind::Blob, &blob_data);
         let blob_id = write_git_data(repo, blob).await?;
         tracing::info!("Restored blob {}", blob_id);
+        cache_blob(&blob_id, &blob_data);
         last_restored_snapshots.put(blob_id, blob_data);
         {
             let mut visited = visited.lock().await;
@@ -287,6 +435,9 @@ async fn restore_a_set_of_blobs_from_a_known_snapshot(
             }
         }
         blobs.remove(&blob_id);
+        if is_last {
+            break;
+        }
     }
     Ok(blobs.to_owned())
 }
@@ -294,7 +445,7 @@ async fn restore_a_set_of_blobs_from_a_known_snapshot(
 #[instrument(level = "trace", skip_all)]
 async fn restore_a_set_of_blobs_from_a_deleted_snapshot(
     es_client: &EverClient,
-    ipfs_endpoint: &str,
+    ipfs_client: &impl FileLoad,
     repo: &mut git_repository::Repository,
     repo_contract: &mut GoshContract,
     snapshot_address: &blockchain::BlockchainContractAddress,
@@ -308,6 +459,7 @@ async fn restore_a_set_of_blobs_from_a_deleted_snapshot(
         let visited = visited.lock().await;
         blobs.retain(|e| !visited.contains(e));
     }
+    restore_blobs_from_cache(repo, blobs, visited.clone()).await?;
     tracing::info!("remaining: {:?}", blobs);
     if blobs.is_empty() {
         return Ok(blobs.to_owned());
@@ -315,7 +467,7 @@ async fn restore_a_set_of_blobs_from_a_deleted_snapshot(
 
     let (blob_id, blob) = BlobsRebuildingPlan::restore_snapshot_from_constructor(
         es_client,
-        ipfs_endpoint,
+        ipfs_client,
         repo,
         snapshot_address,
         visited_ipfs.clone(),
@@ -347,56 +499,45 @@ async fn restore_a_set_of_blobs_from_a_deleted_snapshot(
         branch.to_string(),
         false,
     );
-    let mut preserved_message: Option<DiffMessage> = None;
-    let mut transition_content: Option<Vec<u8>> = None;
     let mut parsed = vec![];
     let mut visited_ipfs_hash: Option<String> = None;
     while !blobs.is_empty() {
         tracing::info!("Still expecting to restore blobs: {:?}", blobs);
 
-        // take next a chunk of messages and reverse it on a snapshot
+        // take next a chunk of messages and apply it to a snapshot
         // remove matching blob ids
         //
-        let message = if let Some(unused_message) = preserved_message.clone() {
-            preserved_message = None;
-            unused_message
-        } else {
-            match messages.next(&es_client).await? {
-                None => break,
-                Some(message) => {
-                    if parsed.contains(&message) {
-                        break;
-                    }
-                    message
+        let message = match messages.next(&es_client).await? {
+            None => break,
+            Some(message) => {
+                if parsed.contains(&message) {
+                    break;
                 }
+                stats::record_diff_loaded(message.diff.patch_size());
+                message
             }
         };
         tracing::trace!("got message: {:?}", message);
         parsed.push(message.clone());
         let blob_data: Vec<u8> = if message.diff.remove_ipfs {
-            let data = match message.diff.get_patch_data() {
-                Some(content) => content,
-                None => panic!("Broken diff detected: content doesn't exist"),
-            };
-            data
+            let data = message.diff.get_patch_data().ok_or_else(|| {
+                anyhow::format_err!("Broken diff detected: content doesn't exist")
+            })?;
+            verified_blob(es_client, ipfs_client, snapshot_address, &message, data).await?
         } else if let Some(ipfs) = &message.diff.ipfs {
-            transition_content = message.diff.get_patch_data();
-
+            // content before the change was restored from the previous message
             let visited = visited_ipfs.lock().await;
             let blob_id: Option<ObjectId> = visited.get(ipfs).copied();
 
-            match blob_id {
+            let data = match blob_id {
                 Some(blob_id) => load_data_from_local(&repo.objects, &blob_id)?,
                 None => {
                     visited_ipfs_hash = Some(ipfs.to_owned());
-                    load_data_from_ipfs(&build_ipfs(&ipfs_endpoint)?, ipfs).await?
+                    load_data_from_ipfs(ipfs_client, ipfs).await?
                 }
-            }
-        } else if let Some(content) = transition_content.clone() {
-            // we won't use the message, so we'll store it for the next iteration
-            preserved_message = Some(message);
-            transition_content = None;
-            content
+            };
+            drop(visited);
+            verified_blob(es_client, ipfs_client, snapshot_address, &message, data).await?
         } else {
             // let patched_blob_sha = &message
             //     .diff
@@ -410,20 +551,14 @@ async fn restore_a_set_of_blobs_from_a_deleted_snapshot(
             // let patched_blob = content.to_vec();
             let patched_blob = last_restored_blob_content;
 
-            message
-                .diff
-                .with_patch::<_, anyhow::Result<Vec<u8>>>(|e| match e {
-                    Some(patch) => {
-                        let blob_data = diffy::apply_bytes(&patched_blob, &patch.clone())?;
-                        Ok(blob_data)
-                    }
-                    None => panic!("Broken diff detected: neither ipfs nor patch exists"),
-                })?
+            let data = apply_patch(&message, &patched_blob, false)?;
+            verified_blob(es_client, ipfs_client, snapshot_address, &message, data).await?
         };
 
         let blob = git_object::Data::new(git_object::Kind::Blob, &blob_data);
         let blob_id = write_git_data(repo, blob).await?;
         tracing::info!("Restored blob {}", blob_id);
+        cache_blob(&blob_id, &blob_data);
         last_restored_snapshots.put(blob_id, blob_data.clone());
         last_restored_blod_id = blob_id;
         last_restored_blob_content = blob_data;
@@ -503,19 +638,20 @@ impl BlobsRebuildingPlan {
     #[instrument(level = "info", skip_all)]
     async fn restore_snapshot_blob(
         es_client: &EverClient,
-        ipfs_endpoint: &str,
+        ipfs_client: &impl FileLoad,
         repo: &mut git_repository::Repository,
         snapshot_address: &BlockchainContractAddress,
         visited_ipfs: Arc<Mutex<HashMap<String, git_hash::ObjectId>>>,
     ) -> anyhow::Result<(Option<(ObjectId, Vec<u8>)>, Option<(ObjectId, Vec<u8>)>)> {
         tracing::trace!(
-            "restore_snapshot_blob: ipfs_endpoint={}, repo={:?}, snapshot_address={}",
-            ipfs_endpoint,
+            "restore_snapshot_blob: repo={:?}, snapshot_address={}",
             repo,
             snapshot_address,
         );
-        let ipfs_client = build_ipfs(ipfs_endpoint)?;
         let snapshot = blockchain::Snapshot::load(&es_client, snapshot_address).await?;
+        stats::record_snapshot_loaded(
+            snapshot.next_content.len() + snapshot.current_content.len(),
+        );
         tracing::info!("Loaded a snapshot: {:?}", snapshot);
         let snapshot_next_commit_sha = ObjectId::from_str(&snapshot.next_commit);
         let snapshot_current_commit_sha = ObjectId::from_str(&snapshot.current_commit);
@@ -529,7 +665,7 @@ impl BlobsRebuildingPlan {
                     Some(blob_id) => load_data_from_local(&repo.objects, &blob_id)?,
                     None => {
                         new_loading = true;
-                        load_data_from_ipfs(&build_ipfs(&ipfs_endpoint)?, &ipfs_hash).await?
+                        load_data_from_ipfs(ipfs_client, &ipfs_hash).await?
                     }
                 };
                 (data, None)
@@ -537,10 +673,11 @@ impl BlobsRebuildingPlan {
                 (snapshot.next_content, snapshot.next_ipfs.clone())
             };
 
-            let (blob, blob_data) = convert_snapshot_into_blob(&ipfs_client, &content, &ipfs_hash)
+            let (blob, blob_data) = convert_snapshot_into_blob(ipfs_client, &content, &ipfs_hash)
                 .instrument(info_span!("convert_next_snapshot_into_blob").or_current())
                 .await?;
             let blob_oid = write_git_object(repo, blob).await?;
+            cache_blob(&blob_oid, &blob_data);
             if new_loading {
                 let mut visited = visited_ipfs.lock().await;
                 visited.insert(snapshot.next_ipfs.clone().unwrap(), blob_oid);
@@ -555,13 +692,14 @@ impl BlobsRebuildingPlan {
                 None // snapshot_next.clone()
             } else if snapshot_current_commit_sha.is_ok() {
                 let (blob, blob_data) = convert_snapshot_into_blob(
-                    &ipfs_client,
+                    ipfs_client,
                     &snapshot.current_content,
                     &snapshot.current_ipfs,
                 )
                 .instrument(info_span!("convert_current_snapshot_into_blob").or_current())
                 .await?;
                 let blob_oid = write_git_object(repo, blob).await?;
+                cache_blob(&blob_oid, &blob_data);
                 Some((blob_oid, blob_data))
             } else {
                 None
@@ -577,7 +715,7 @@ impl BlobsRebuildingPlan {
     #[instrument(level = "info", skip_all)]
     async fn restore_snapshot_from_constructor(
         es_client: &EverClient,
-        ipfs_endpoint: &str,
+        ipfs_client: &impl FileLoad,
         repo: &mut git_repository::Repository,
         snapshot_address: &BlockchainContractAddress,
         visited_ipfs: Arc<Mutex<HashMap<String, git_hash::ObjectId>>>,
@@ -587,7 +725,6 @@ impl BlobsRebuildingPlan {
             repo,
             snapshot_address,
         );
-        let ipfs_client = build_ipfs(ipfs_endpoint)?;
 
         let (data, ipfs) =
             blockchain::snapshot::diffs::load_constructor(&es_client, snapshot_address).await?;
@@ -601,7 +738,7 @@ impl BlobsRebuildingPlan {
                 Some(blob_id) => load_data_from_local(&repo.objects, &blob_id)?,
                 None => {
                     new_loading = true;
-                    load_data_from_ipfs(&build_ipfs(&ipfs_endpoint)?, &ipfs_hash).await?
+                    load_data_from_ipfs(ipfs_client, &ipfs_hash).await?
                 }
             };
             (data, None)
@@ -609,10 +746,11 @@ impl BlobsRebuildingPlan {
             (data, ipfs)
         };
 
-        let (blob, blob_data) = convert_snapshot_into_blob(&ipfs_client, &content, &ipfs_hash)
+        let (blob, blob_data) = convert_snapshot_into_blob(ipfs_client, &content, &ipfs_hash)
             .instrument(info_span!("convert_next_snapshot_into_blob").or_current())
             .await?;
         let blob_oid = write_git_object(repo, blob).await?;
+        cache_blob(&blob_oid, &blob_data);
         if new_loading {
             let mut visited = visited_ipfs.lock().await;
             visited.insert(ipfs_hash.clone().unwrap(), blob_oid);
@@ -622,7 +760,10 @@ impl BlobsRebuildingPlan {
 
     pub async fn restore<'a, 'b>(
         &'b mut self,
-        git_helper: &mut GitHelper<impl BlockchainService>,
+        git_helper: &mut GitHelper<
+            impl BlockchainService,
+            impl FileLoad + Clone + Send + Sync + 'static,
+        >,
         visited: Arc<Mutex<HashSet<git_hash::ObjectId>>>,
         visited_ipfs: Arc<Mutex<HashMap<String, git_hash::ObjectId>>>,
         branch: &str,
@@ -652,44 +793,47 @@ impl BlobsRebuildingPlan {
 
         for (snapshot_address, blobs) in self.snapshot_address_to_blob_sha.iter_mut() {
             let es_client = Arc::clone(git_helper.blockchain.client());
-            let ipfs_http_endpoint = git_helper.config.ipfs_http_endpoint().to_string();
-            let mut repo = git_helper.local_repository().clone();
-            let mut repo_contract = git_helper.blockchain.repo_contract().clone();
+            let file_provider = git_helper.file_provider.clone();
+            let repo = git_helper.local_repository().clone();
+            let repo_contract = git_helper.blockchain.repo_contract().clone();
             let snapshot_address_clone = snapshot_address.clone();
-            let mut blobs_to_restore = blobs.clone();
+            let blobs_to_restore = blobs.clone();
             let visited_ref = Arc::clone(&visited);
             let visited_ipfs_ref = Arc::clone(&visited_ipfs);
             let branch_ref = branch.to_string();
             fetched_blobs.push(tokio::spawn(
                 async move {
-                    let mut attempt = 0;
-                    let result = loop {
-                        attempt += 1;
-                        let result = restore_a_set_of_blobs(
-                            &es_client,
-                            &ipfs_http_endpoint,
-                            &mut repo,
-                            &mut repo_contract,
-                            &snapshot_address_clone,
-                            &mut blobs_to_restore,
-                            visited_ref.clone(),
-                            visited_ipfs_ref.clone(),
-                            &branch_ref,
-                        )
-                        .await;
-                        if result.is_ok() || attempt > FETCH_MAX_TRIES {
-                            break result;
-                        } else {
-                            tracing::trace!(
-                                "restore_a_set_of_blobs <{:#?}> error {:?}",
-                                snapshot_address_clone,
-                                result.unwrap_err()
-                            );
-                            tokio::time::sleep(std::time::Duration::from_secs(5)).await;
-                            // panic!("stopped");
-                        }
+                    // blobs restored by a failed attempt are not loaded again
+                    let state = Mutex::new((repo, repo_contract, blobs_to_restore));
+                    let condition = |e: &anyhow::Error| {
+                        tracing::trace!(
+                            "restore_a_set_of_blobs <{:#?}> error {:?}",
+                            snapshot_address_clone,
+                            e
+                        );
+                        is_retryable(e)
                     };
-                    result.map_err(|e| anyhow::Error::from(e))
+                    retry::policy(Operation::Fetch)
+                        .run(
+                            || async {
+                                let mut state = state.lock().await;
+                                let (repo, repo_contract, blobs_to_restore) = &mut *state;
+                                restore_a_set_of_blobs(
+                                    &es_client,
+                                    &file_provider,
+                                    repo,
+                                    repo_contract,
+                                    &snapshot_address_clone,
+                                    blobs_to_restore,
+                                    visited_ref.clone(),
+                                    visited_ipfs_ref.clone(),
+                                    &branch_ref,
+                                )
+                                .await
+                            },
+                            condition,
+                        )
+                        .await
                 }
                 .instrument(info_span!("tokio::spawn::restore_a_set_of_blobs").or_current()),
             ));
@@ -700,10 +844,10 @@ impl BlobsRebuildingPlan {
         while let Some(finished_task) = fetched_blobs.next().await {
             match finished_task {
                 Err(e) => {
-                    panic!("restore_a_set_of_blobs joih-handler: {}", e);
+                    anyhow::bail!("restore_a_set_of_blobs join-handler: {e}");
                 }
                 Ok(Err(e)) => {
-                    panic!("restore_a_set_of_blobs inner: {}", e);
+                    return Err(e);
                 }
                 Ok(Ok(blobs)) => {
                     tracing::trace!("Blobs after restore: {blobs:?}");
@@ -719,7 +863,7 @@ impl BlobsRebuildingPlan {
         for blob in unvisited_blobs {
             let vis = visited.lock().await;
             if !vis.contains(&blob) {
-                panic!("Failed to restore: {blob}");
+                anyhow::bail!("Failed to restore: {blob}");
             }
         }
         Ok(())
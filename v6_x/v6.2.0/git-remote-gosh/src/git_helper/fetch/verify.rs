@@ -0,0 +1,233 @@
+use crate::blockchain::{
+    BlockchainContractAddress, EverClient, snapshot::diffs::DiffMessage, tvm_hash,
+};
+use git_hash::ObjectId;
+use std::process::Command;
+use std::str::FromStr;
+
+// Comma separated list of branches that accept only signed commits on fetch
+const GOSH_REQUIRE_SIGNED_BRANCHES: &str = "GOSH_REQUIRE_SIGNED_BRANCHES";
+
+// Comma separated list of IPFS endpoints to load blobs from when the content
+// loaded from the main endpoint doesn't match the diff
+const GOSH_IPFS_FALLBACK_ENDPOINTS: &str = "GOSH_IPFS_FALLBACK_ENDPOINTS";
+
+#[derive(Debug, thiserror::Error)]
+#[error(
+    "Restored blob of {file_path} at commit {commit} from snapshot {snapshot_address} doesn't match the diff created at lt {diff_lt}: {reason}"
+)]
+pub(super) struct BlobIntegrityError {
+    pub file_path: String,
+    pub commit: String,
+    pub snapshot_address: BlockchainContractAddress,
+    pub diff_lt: u64,
+    pub reason: String,
+}
+
+const SIGNATURE_HEADERS: [&str; 2] = ["gpgsig", "gpgsig-sha256"];
+
+/// Ensures that the raw object restored from the blockchain hashes
+/// exactly to the id git asked for. Any re-encoding on the way
+/// (lost signature header, changed whitespace, etc) changes the hash.
+pub(super) fn verify_object_id(
+    kind: git_object::Kind,
+    data: &[u8],
+    expected: &ObjectId,
+) -> anyhow::Result<()> {
+    let actual = git_object::compute_hash(expected.kind(), kind, data);
+    tracing::trace!("verify_object_id: kind={kind}, expected={expected}, actual={actual}");
+    if &actual != expected {
+        anyhow::bail!("Restored {kind} {expected} is corrupted: its content hashes to {actual}");
+    }
+    Ok(())
+}
+
+/// Checks the blob produced by the diff message against the hashes stored
+/// in the diff: git blob id and sha256 of the content (tvm hash for the
+/// content stored on chain)
+#[instrument(level = "trace", skip_all)]
+pub(super) async fn verify_restored_blob(
+    client: &EverClient,
+    snapshot_address: &BlockchainContractAddress,
+    message: &DiffMessage,
+    data: &[u8],
+) -> anyhow::Result<()> {
+    let diff = &message.diff;
+    let integrity_error = |reason: String| BlobIntegrityError {
+        file_path: diff.snapshot_file_path.clone(),
+        commit: diff.commit.clone(),
+        snapshot_address: snapshot_address.clone(),
+        diff_lt: message.created_lt,
+        reason,
+    };
+    if let Some(sha1) = &diff.modified_blob_sha1 {
+        let expected = ObjectId::from_str(sha1)?;
+        let actual = git_object::compute_hash(expected.kind(), git_object::Kind::Blob, data);
+        if actual != expected {
+            return Err(integrity_error(format!("expected blob {expected}, got {actual}")).into());
+        }
+    }
+    let expected_sha256 = diff.sha256.trim_start_matches("0x");
+    if !expected_sha256.is_empty() {
+        let actual_sha256 = if diff.ipfs.is_some() {
+            sha256::digest(data)
+        } else {
+            tvm_hash(client, data).await?
+        };
+        if !actual_sha256.eq_ignore_ascii_case(expected_sha256) {
+            return Err(integrity_error(format!(
+                "expected sha256 {expected_sha256}, got {actual_sha256}"
+            ))
+            .into());
+        }
+    }
+    Ok(())
+}
+
+pub(super) fn get_branches_requiring_signature() -> Vec<String> {
+    std::env::var(GOSH_REQUIRE_SIGNED_BRANCHES)
+        .map(|value| parse_list(&value))
+        .unwrap_or_default()
+}
+
+pub(super) fn get_ipfs_fallback_endpoints() -> Vec<String> {
+    std::env::var(GOSH_IPFS_FALLBACK_ENDPOINTS)
+        .map(|value| parse_list(&value))
+        .unwrap_or_default()
+}
+
+fn parse_list(value: &str) -> Vec<String> {
+    value
+        .split(',')
+        .map(|item| item.trim())
+        .filter(|item| !item.is_empty())
+        .map(|item| item.to_owned())
+        .collect()
+}
+
+pub(super) fn has_signature(commit: &git_object::Commit) -> bool {
+    commit
+        .extra_headers
+        .iter()
+        .any(|(name, _)| SIGNATURE_HEADERS.iter().any(|header| name == header))
+}
+
+/// Checks commit signature with the local git installation.
+/// Commit object must be already written to the local repository.
+#[instrument(level = "trace", skip_all)]
+pub(super) fn verify_commit_signature(commit_id: &ObjectId) -> anyhow::Result<()> {
+    tracing::trace!("verify_commit_signature: commit_id={commit_id}");
+    let output = Command::new("git")
+        .arg("verify-commit")
+        .arg(commit_id.to_string())
+        .output()?;
+    if !output.status.success() {
+        anyhow::bail!(
+            "Signature verification failed for commit {commit_id}: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SIGNED_COMMIT: &str = "tree 4b825dc642cb6eb9a060e54bf8d69288fbee4904
+author A U Thor <author@example.com> 1112912053 -0700
+committer C O Mitter <committer@example.com> 1112912053 -0700
+gpgsig -----BEGIN PGP SIGNATURE-----
+
+ iQEzBAABCAAdFiEEexample
+ =abcd
+ -----END PGP SIGNATURE-----
+
+signed commit
+";
+
+    const UNSIGNED_COMMIT: &str = "tree 4b825dc642cb6eb9a060e54bf8d69288fbee4904
+author A U Thor <author@example.com> 1112912053 -0700
+committer C O Mitter <committer@example.com> 1112912053 -0700
+
+unsigned commit
+";
+
+    fn decode(raw: &str) -> git_object::Commit {
+        let data = git_object::Data::new(git_object::Kind::Commit, raw.as_bytes());
+        git_object::Object::from(data.decode().unwrap()).into_commit()
+    }
+
+    #[test]
+    fn ensure_raw_commit_keeps_its_id() {
+        let expected = git_object::compute_hash(
+            git_hash::Kind::Sha1,
+            git_object::Kind::Commit,
+            SIGNED_COMMIT.as_bytes(),
+        );
+        verify_object_id(
+            git_object::Kind::Commit,
+            SIGNED_COMMIT.as_bytes(),
+            &expected,
+        )
+        .unwrap();
+
+        let stripped = SIGNED_COMMIT.replace("signed commit", "signed  commit");
+        assert!(
+            verify_object_id(git_object::Kind::Commit, stripped.as_bytes(), &expected).is_err()
+        );
+    }
+
+    #[test]
+    fn ensure_signature_is_detected() {
+        assert!(has_signature(&decode(SIGNED_COMMIT)));
+        assert!(!has_signature(&decode(UNSIGNED_COMMIT)));
+    }
+
+    #[tokio::test]
+    async fn ensure_restored_blob_is_checked_against_diff() {
+        let client = std::sync::Arc::new(
+            ton_client::ClientContext::new(ton_client::ClientConfig::default()).unwrap(),
+        );
+        let content = b"restored content";
+        let blob_id =
+            git_object::compute_hash(git_hash::Kind::Sha1, git_object::Kind::Blob, content);
+        let message = DiffMessage {
+            diff: serde_json::from_value(serde_json::json!({
+                "snap": "0:00",
+                "nameSnap": "main/README.md",
+                "commit": "5b4c5ba1",
+                "patch": null,
+                "ipfs": "QmCid",
+                "removeIpfs": false,
+                "sha1": blob_id.to_string(),
+                "sha256": format!("0x{}", sha256::digest(&content[..])),
+            }))
+            .unwrap(),
+            created_at: 0,
+            created_lt: 42,
+        };
+        let snapshot_address = BlockchainContractAddress::new("0:01");
+
+        verify_restored_blob(&client, &snapshot_address, &message, content)
+            .await
+            .unwrap();
+
+        let error = verify_restored_blob(&client, &snapshot_address, &message, b"broken")
+            .await
+            .unwrap_err();
+        let error = error.downcast_ref::<BlobIntegrityError>().unwrap();
+        assert_eq!(error.file_path, "main/README.md");
+        assert_eq!(error.commit, "5b4c5ba1");
+        assert_eq!(error.diff_lt, 42);
+    }
+
+    #[test]
+    fn ensure_branch_list_parses_correctly() {
+        assert_eq!(
+            parse_list("main, release ,,dev"),
+            vec!["main", "release", "dev"]
+        );
+        assert!(parse_list("").is_empty());
+    }
+}
@@ -1,7 +1,7 @@
 use super::GitHelper;
 use std::fmt;
 
-impl<Blockchain> fmt::Debug for GitHelper<Blockchain> {
+impl<Blockchain, FileProvider> fmt::Debug for GitHelper<Blockchain, FileProvider> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.debug_struct("GitHelper")
             .field("repo_addr", &self.repo_addr)
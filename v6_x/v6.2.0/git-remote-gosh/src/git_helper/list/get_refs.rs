@@ -1,6 +1,6 @@
+use crate::abi::bindings::commit;
 use crate::blockchain::{
-    branch_list, contract::GoshContract, gosh_abi, BlockchainContractAddress, EverClient,
-    GetNameCommitResult,
+    branch_list, contract::TypedContract, BlockchainContractAddress, EverClient,
 };
 
 const ZERO_COMMIT: &str = "0000000000000000000000000000000000000000";
@@ -13,8 +13,7 @@ pub async fn get_refs(
 ) -> anyhow::Result<Option<Vec<String>>> {
     let _list = branch_list(context, repo_addr)
         .await
-        .map_err(|e| anyhow::Error::from(e))?
-        .branch_ref;
+        .map_err(|e| anyhow::Error::from(e))?;
     if _list.is_empty() {
         return Ok(None);
     }
@@ -26,13 +25,13 @@ pub async fn get_refs(
         //     .unwrap()
         //     .unwrap();
         // TODO: get commit can fail due to changes in versions
-        let commit_contract = GoshContract::new(&branch.commit_address, gosh_abi::COMMIT);
-        let sha: GetNameCommitResult = commit_contract
-            .run_local(context, "getNameCommit", None)
+        let commit_contract = TypedContract::<commit::Contract>::new(&branch.commit_address);
+        let sha = commit_contract
+            .run_local_fn(context, commit::GetNameCommit {})
             .await?;
         tracing::trace!("Commit sha: {sha:?}");
-        if sha.name != ZERO_COMMIT {
-            ref_list.push(format!("{} refs/heads/{}", sha.name, branch.branch_name));
+        if sha.value0 != ZERO_COMMIT {
+            ref_list.push(format!("{} refs/heads/{}", sha.value0, branch.branch_name));
         }
     }
     Ok(Some(ref_list))
@@ -3,28 +3,34 @@
 use std::collections::HashMap;
 use std::env;
 
-use serde_json::Value;
+use async_trait::async_trait;
 use std::sync::Arc;
 use tokio::io::{self, AsyncBufReadExt, AsyncWriteExt, BufReader};
 
-use crate::blockchain::contract::ContractRead;
 use crate::blockchain::get_commit_address;
+use crate::cache::object_cache;
 use crate::cache::proxy::CacheProxy;
 use crate::database::GoshDB;
-use crate::git_helper::push::GetPreviousResult;
 use crate::{
     abi as gosh_abi,
+    abi::bindings::{goshdao, repository, systemcontract, versioncontroller},
     blockchain::{
-        contract::GoshContract, get_head, get_repo_address, BlockchainContractAddress,
-        BlockchainService, EverClient, EverscaleBuilder, Tree,
+        consistency,
+        contract::{GoshContract, TypedContract},
+        get_head, get_repo_address,
+        BlockchainContractAddress, BlockchainService, EverClient, EverscaleBuilder, Tree,
     },
+    bundle::{self, Bundle, BundleBlockchain, BundleError, BundleStorage},
     config::Config,
-    git_helper::ever_client::create_client,
+    git_helper::ever_client::{
+        create_client, create_client_with_endpoints, create_endpoint_clients,
+    },
     ipfs::{build_ipfs, service::FileStorage},
     logger::set_log_verbosity,
     utilities::Remote,
 };
 
+pub(crate) mod endpoint_health;
 pub mod ever_client;
 #[cfg(test)]
 mod test_utils;
@@ -62,18 +68,6 @@ pub struct GitHelper<
     database: Option<Arc<GoshDB>>,
 }
 
-#[derive(Deserialize, Debug)]
-struct GetAddrDaoResult {
-    #[serde(rename = "value0")]
-    pub address: BlockchainContractAddress,
-}
-
-#[derive(Deserialize, Debug)]
-struct GetTombstoneResult {
-    #[serde(rename = "value0")]
-    pub tombstone: bool,
-}
-
 // Note: this module implements fetch method on GitHelper
 mod fetch;
 
@@ -82,6 +76,8 @@ pub(crate) mod push;
 
 mod list;
 
+mod report;
+
 mod fmt;
 
 pub fn supported_contract_version() -> String {
@@ -116,21 +112,23 @@ where
     async fn build(
         config: Config,
         url: &str,
+        local_git_dir: &str,
         blockchain: Blockchain,
         file_provider: FileProvider,
     ) -> anyhow::Result<Self> {
         tracing::trace!("build: config={config:?}, url={url}");
         // TODO: remove duplicate logic
         let remote = Remote::new(url, &config)?;
-        let ever_client = create_client(&config, &remote.network)?;
+        let ever_client = Arc::clone(blockchain.client());
 
-        let gosh_root_contract = GoshContract::new(&remote.gosh, gosh_abi::GOSH);
+        let gosh_root_contract = TypedContract::<systemcontract::Contract>::new(&remote.gosh);
 
-        let dao: GetAddrDaoResult = gosh_root_contract
-            .run_static(
+        let dao = gosh_root_contract
+            .run_static_fn(
                 &ever_client,
-                "getAddrDao",
-                Some(serde_json::json!({ "name": remote.dao })),
+                systemcontract::GetAddrDao {
+                    name: remote.dao.clone(),
+                },
             )
             .await?;
 
@@ -138,8 +136,7 @@ where
             get_repo_address(&ever_client, &remote.gosh, &remote.dao, &remote.repo).await?;
         let repo_contract = GoshContract::new(&repo_addr, gosh_abi::REPO);
 
-        let local_git_dir = env::var("GIT_DIR")?;
-        let local_repository = Arc::new(git_repository::open(&local_git_dir)?);
+        let local_repository = Arc::new(git_repository::open(local_git_dir)?);
         tracing::info!("Opening repo at {}", local_git_dir);
         let mut cache = CacheProxy::new();
         let cache_str = config.use_cache();
@@ -161,7 +158,7 @@ where
             file_provider,
             blockchain,
             remote,
-            dao_addr: dao.address,
+            dao_addr: dao.value0,
             repo_addr,
             local_repository,
             pushed_commits: HashMap::new(),
@@ -175,6 +172,18 @@ where
         Ok(())
     }
 
+    /// Opens the database in the local repository as a journal of the push to `target`
+    pub fn open_push_journal(&mut self, target: &str) -> anyhow::Result<()> {
+        let local_git_dir = self.local_repository().path().to_string_lossy().to_string();
+        let (database, resumed) = GoshDB::open_push_journal(&local_git_dir, target)?;
+        if resumed {
+            tracing::info!("Resuming push from the journal: {target}");
+            eprintln!("Resuming interrupted push of {target}");
+        }
+        self.database = Some(Arc::new(database));
+        Ok(())
+    }
+
     pub fn get_db(&self) -> anyhow::Result<Arc<GoshDB>> {
         if let Some(db) = &self.database {
             Ok(db.clone())
@@ -215,14 +224,18 @@ where
         let mut all_versions = vec![];
         for version in versions {
             let address = BlockchainContractAddress::new(version.1.clone());
-            let system_contract = GoshContract::new(address, gosh_abi::GOSH);
-            let args = json!({"dao": self.remote.dao, "name": self.remote.repo});
-            let repo_addr: GetAddrDaoResult = system_contract
-                .run_static(self.blockchain.client(), "getAddrRepository", Some(args))
+            let system_contract = TypedContract::<systemcontract::Contract>::new(address);
+            let args = systemcontract::GetAddrRepository {
+                name: self.remote.repo.clone(),
+                dao: self.remote.dao.clone(),
+            };
+            let repo_addr = system_contract
+                .run_static_fn(self.blockchain.client(), args)
                 .await?;
-            let repo_contract = GoshContract::new(repo_addr.address.clone(), gosh_abi::REPO);
-            let res: anyhow::Result<Value> = repo_contract
-                .run_static(self.blockchain.client(), "getVersion", None)
+            let repo_contract =
+                TypedContract::<repository::Contract>::new(repo_addr.value0.clone());
+            let res = repo_contract
+                .run_static_fn(self.blockchain.client(), repository::GetVersion {})
                 .await;
             if res.is_err() {
                 continue;
@@ -230,44 +243,29 @@ where
             all_versions.push(RepoVersion {
                 version: version.0,
                 system_address: BlockchainContractAddress::new(version.1),
-                repo_address: repo_addr.address,
+                repo_address: repo_addr.value0,
             });
         }
         Ok(all_versions)
     }
 
     async fn get_all_system_contracts(&self) -> anyhow::Result<Vec<(String, String)>> {
-        let version_controller_address: GetAddrDaoResult = self
-            .blockchain
-            .root_contract()
-            .run_static(self.blockchain.client(), "getCreator", None)
+        let root_address = &self.blockchain.root_contract().address;
+        let root_contract = TypedContract::<systemcontract::Contract>::new(root_address);
+        let version_controller_address = root_contract
+            .run_static_fn(self.blockchain.client(), systemcontract::GetCreator {})
             .await?;
 
-        let version_controller = GoshContract::new(
-            version_controller_address.address,
-            gosh_abi::VERSION_CONTROLLER,
+        let version_controller = TypedContract::<versioncontroller::Contract>::new(
+            version_controller_address.value0,
         );
 
-        let versions: Value = version_controller
-            .run_static(self.blockchain.client(), "getVersionAddrMap", None)
-            .await?;
-
-        let versions: Vec<(String, String)> = versions
-            .as_object()
-            .unwrap()
-            .values()
-            .next()
-            .unwrap()
-            .as_array()
-            .unwrap()
-            .iter()
-            .map(|ver| {
-                let map = ver.as_object().unwrap();
-                (
-                    map.get("Key").unwrap().as_str().unwrap().to_string(),
-                    map.get("Value").unwrap().as_str().unwrap().to_string(),
-                )
-            })
+        let versions: Vec<(String, String)> = version_controller
+            .run_static_fn(self.blockchain.client(), versioncontroller::GetVersionAddrMap {})
+            .await?
+            .value0
+            .into_iter()
+            .map(|ver| (ver.key, String::from(&ver.value)))
             .collect();
 
         tracing::trace!("Available system contract versions: {versions:?}");
@@ -297,26 +295,29 @@ where
                 repo_address: self.repo_addr.clone(),
                 system_address: BlockchainContractAddress::new(system_contract.1.clone()),
             });
-            let mut previous: GetPreviousResult = self
-                .blockchain
-                .repo_contract()
-                .read_state(self.blockchain.client(), "getPrevious", None)
-                .await?;
-
-            while let Some(prev_repo) = &previous.previous {
+            let repo_address = &self.blockchain.repo_contract().address;
+            let repo_contract = TypedContract::<repository::Contract>::new(repo_address);
+            let mut previous = repo_contract
+                .read_state_fn(self.blockchain.client(), repository::GetPrevious {})
+                .await?
+                .value0;
+
+            while let Some(prev_repo) = &previous {
                 let system_contract = versions.iter().find(|v| v.0 == prev_repo.version).ok_or(
                     anyhow::format_err!("Failed to get prev version system contract"),
                 )?;
 
                 self.repo_versions.push(RepoVersion {
                     version: prev_repo.version.clone(),
-                    repo_address: prev_repo.address.clone(),
+                    repo_address: prev_repo.addr.clone(),
                     system_address: BlockchainContractAddress::new(system_contract.1.clone()),
                 });
-                let prev_repo_contract = GoshContract::new(&prev_repo.address, gosh_abi::REPO);
+                let prev_repo_contract =
+                    TypedContract::<repository::Contract>::new(&prev_repo.addr);
                 previous = prev_repo_contract
-                    .read_state(self.blockchain.client(), "getPrevious", None)
-                    .await?;
+                    .read_state_fn(self.blockchain.client(), repository::GetPrevious {})
+                    .await?
+                    .value0;
             }
         }
         self.repo_versions
@@ -326,28 +327,36 @@ where
     }
 
     async fn get_dao_tombstone(&self) -> anyhow::Result<Vec<String>> {
-        let dao_address: GetAddrDaoResult = self
-            .blockchain
-            .root_contract()
-            .run_static(
+        let root_address = &self.blockchain.root_contract().address;
+        let root_contract = TypedContract::<systemcontract::Contract>::new(root_address);
+        let dao_address = root_contract
+            .run_static_fn(
                 self.blockchain.client(),
-                "getAddrDao",
-                Some(serde_json::json!({ "name": self.remote.dao })),
+                systemcontract::GetAddrDao {
+                    name: self.remote.dao.clone(),
+                },
             )
             .await?;
 
-        let dao_contract = GoshContract::new(dao_address.address, gosh_abi::DAO);
+        let dao_contract = TypedContract::<goshdao::Contract>::new(dao_address.value0);
 
-        let tombstone: GetTombstoneResult = dao_contract
-            .run_static(self.blockchain.client(), "getTombstone", None)
+        let tombstone = dao_contract
+            .run_static_fn(self.blockchain.client(), goshdao::GetTombstone {})
             .await?;
-        Ok(vec![format!("{}", tombstone.tombstone), "".to_string()])
+        Ok(vec![format!("{}", tombstone.value0), "".to_string()])
     }
 
     #[instrument(level = "trace", skip_all)]
     async fn list(&self, for_push: bool) -> anyhow::Result<Vec<String>> {
         tracing::debug!("list: for_push={for_push}");
         let refs = list::get_refs(&self.blockchain.client(), &self.repo_addr).await?;
+        if let Some(checker) = consistency::checker() {
+            checker
+                .verify("branch heads", &refs, |client| async move {
+                    list::get_refs(&client, &self.repo_addr).await
+                })
+                .await?;
+        }
         let mut ref_list: Vec<String> = if refs.is_none() {
             Vec::new()
         } else {
@@ -410,49 +419,186 @@ where
     }
 }
 
+/// Builds the blockchain service, reads of its client are recorded to or
+/// served from `bundle` when it is given
 async fn build_blockchain(
     config: &Config,
     url: &str,
+    bundle: Option<&Bundle>,
 ) -> anyhow::Result<crate::blockchain::Everscale> {
     // concrete implementation for Ever in this case
     let mut blockchain_builder = EverscaleBuilder::default();
     let remote = Remote::new(url, &config)?;
-    let ever_client = create_client(&config, &remote.network)?;
+    let is_replaying = bundle.is_some_and(Bundle::is_replaying);
+    let ever_client = if is_replaying {
+        // reads are served from the bundle, the client runs contracts locally
+        create_client_with_endpoints(vec![])?
+    } else {
+        // stale scores only make failover slower, they never stop the helper
+        if let Err(e) = endpoint_health::refresh(&config, &remote.network).await {
+            tracing::trace!("Endpoint health is not refreshed: {e}");
+        }
+        create_client(&config, &remote.network)?
+    };
+    if let Some(bundle) = bundle {
+        bundle.attach(&ever_client);
+    }
     blockchain_builder.ever_client(Arc::clone(&ever_client));
+    // reads served from a bundle can't be compared across endpoints
+    if let Some(quorum) = consistency::get_quorum().filter(|_| !is_replaying) {
+        consistency::init(create_endpoint_clients(&config, &remote.network)?, quorum)?;
+    }
 
-    let gosh_root_contract = GoshContract::new(&remote.gosh, gosh_abi::GOSH);
-    let dao: GetAddrDaoResult = gosh_root_contract
-        .run_static(
+    let gosh_root_contract = TypedContract::<systemcontract::Contract>::new(&remote.gosh);
+    let dao = gosh_root_contract
+        .run_static_fn(
             &ever_client,
-            "getAddrDao",
-            Some(serde_json::json!({ "name": remote.dao })),
+            systemcontract::GetAddrDao {
+                name: remote.dao.clone(),
+            },
         )
         .await?;
-    blockchain_builder.root_contract(gosh_root_contract);
+    blockchain_builder.root_contract(gosh_root_contract.contract().clone());
 
     let repo_addr = get_repo_address(&ever_client, &remote.gosh, &remote.dao, &remote.repo).await?;
     let repo_contract = GoshContract::new(&repo_addr, gosh_abi::REPO);
     blockchain_builder.repo_contract(repo_contract);
 
-    let local_git_dir = env::var("GIT_DIR")?;
-    let local_git_repository = git_repository::open(&local_git_dir)?;
-    tracing::info!("Opening repo at {}", local_git_dir);
-
     tracing::trace!("Searching for a wallet at {}", &remote.network);
     blockchain_builder.wallet_config(config.find_network_user_wallet(&remote.network));
 
     Ok(blockchain_builder.build()?)
 }
 
+/// Probes endpoints of the network, or of all known networks, and describes
+/// their health
+#[instrument(level = "info", skip_all)]
+pub async fn doctor(config: Config, network: Option<&str>) -> anyhow::Result<Vec<String>> {
+    tracing::trace!("doctor: network={network:?}");
+    endpoint_health::report(&config, network).await
+}
+
+/// Persists what the helper learned about the endpoints during the run
+pub fn save_endpoint_health() {
+    endpoint_health::save_request_errors();
+}
+
+/// Fetches all refs of the repository into a temporary repository and
+/// writes everything that was read from the network to the bundle at `path`
+#[instrument(level = "info", skip_all)]
+pub async fn export_bundle(config: Config, url: &str, path: &str) -> anyhow::Result<()> {
+    tracing::trace!("export_bundle: url={url}, path={path}");
+    // cached objects would be missing in the bundle
+    object_cache::disable();
+    let bundle = Bundle::create(path, url)?;
+    let git_dir = env::temp_dir().join(format!("gosh_bundle_export_{}", std::process::id()));
+    git_repository::init_bare(&git_dir)?;
+    let result = export_refs(config, url, &git_dir, &bundle).await;
+    // a leftover directory must not hide the result of the export
+    if let Err(e) = std::fs::remove_dir_all(&git_dir) {
+        eprintln!("Warning: failed to remove {}: {e}", git_dir.display());
+    }
+    let exported_refs = result?;
+    bundle.finish()?;
+    eprintln!("Exported {exported_refs} refs of {url} to {path}");
+    Ok(())
+}
+
+async fn export_refs(
+    config: Config,
+    url: &str,
+    git_dir: &std::path::Path,
+    bundle: &Bundle,
+) -> anyhow::Result<usize> {
+    let git_dir = git_dir
+        .to_str()
+        .ok_or_else(|| anyhow::format_err!("Invalid path {}", git_dir.display()))?;
+    let blockchain = BundleBlockchain::new(build_blockchain(&config, url, Some(bundle)).await?);
+    let file_provider =
+        BundleStorage::recording(bundle.clone(), build_ipfs(config.ipfs_http_endpoint())?);
+    let mut helper = GitHelper::build(config, url, git_dir, blockchain, file_provider).await?;
+    helper.load_repo_versions().await?;
+    // symbolic refs (`@refs/heads/main HEAD`) point to the listed ones
+    let refs: Vec<(String, String)> = helper
+        .list(false)
+        .await?
+        .iter()
+        .filter(|line| !line.starts_with('@'))
+        .filter_map(|line| line.split_once(' '))
+        .map(|(sha, name)| (sha.to_owned(), name.to_owned()))
+        .collect();
+    for (sha, name) in refs.iter() {
+        tracing::trace!("export_refs: fetch {name}");
+        if !helper.fetch(sha, name).await?.is_empty() {
+            anyhow::bail!(
+                "{name} contains commits of previous GOSH versions, it can't be exported"
+            );
+        }
+    }
+    Ok(refs.len())
+}
+
+/// Removes the journal left by an interrupted push
+pub fn discard_push_journal(local_git_dir: &str) -> anyhow::Result<bool> {
+    GoshDB::discard(local_git_dir)
+}
+
 // Implement protocol defined here:
 // https://github.com/git/git/blob/master/Documentation/gitremote-helpers.txt
 #[instrument(level = "info", skip_all)]
 pub async fn run(config: Config, url: &str, dispatcher_call: bool) -> anyhow::Result<()> {
     tracing::trace!("run: url={url}");
-    let blockchain = build_blockchain(&config, url).await?;
+    let local_git_dir = env::var("GIT_DIR")?;
+    if let Some(path) = bundle::get_bundle_path() {
+        // cursors of pages served from the bundle mean nothing to the network
+        object_cache::disable();
+        let bundle = Bundle::open(&path, url)?;
+        let blockchain =
+            BundleBlockchain::new(build_blockchain(&config, url, Some(&bundle)).await?);
+        let file_provider = BundleStorage::replaying(bundle);
+        let helper =
+            GitHelper::build(config, url, &local_git_dir, blockchain, file_provider).await?;
+        return serve(helper, dispatcher_call).await;
+    }
+    let blockchain = build_blockchain(&config, url, None).await?;
     let file_provider = build_ipfs(config.ipfs_http_endpoint())?;
 
-    let mut helper = GitHelper::build(config, url, blockchain, file_provider).await?;
+    let helper = GitHelper::build(config, url, &local_git_dir, blockchain, file_provider).await?;
+    serve(helper, dispatcher_call).await
+}
+
+/// `push` command of the protocol, helpers over a bundle can't push
+#[async_trait(?Send)]
+trait PushRefs {
+    async fn push_refs(&mut self, refs: &str) -> anyhow::Result<String>;
+}
+
+#[async_trait(?Send)]
+impl<Blockchain> PushRefs for GitHelper<Blockchain>
+where
+    Blockchain: BlockchainService + 'static,
+{
+    async fn push_refs(&mut self, refs: &str) -> anyhow::Result<String> {
+        self.push(refs).await
+    }
+}
+
+#[async_trait(?Send)]
+impl PushRefs for GitHelper<BundleBlockchain, BundleStorage> {
+    async fn push_refs(&mut self, _refs: &str) -> anyhow::Result<String> {
+        Err(BundleError::ReadOnly("Push").into())
+    }
+}
+
+async fn serve<Blockchain, FileProvider>(
+    mut helper: GitHelper<Blockchain, FileProvider>,
+    dispatcher_call: bool,
+) -> anyhow::Result<()>
+where
+    Blockchain: BlockchainService,
+    FileProvider: FileStorage + Clone + Send + Sync + 'static,
+    GitHelper<Blockchain, FileProvider>: PushRefs,
+{
     helper.load_repo_versions().await?;
     let mut lines = BufReader::new(io::stdin()).lines();
     let mut stdout = io::stdout();
@@ -511,7 +657,7 @@ pub async fn run(config: Config, url: &str, dispatcher_call: bool) -> anyhow::Re
             (Some("option"), Some(arg1), Some(arg2)) => helper.option(arg1, arg2).await?,
             (Some("push"), Some(ref_arg), None) => {
                 is_batching_push_in_progress = true;
-                let push_result = helper.push(ref_arg).await?;
+                let push_result = helper.push_refs(ref_arg).await?;
                 batch_response.push(push_result);
                 vec![]
             }
@@ -2,8 +2,8 @@ use std::{fmt::Debug, str::FromStr};
 
 use crate::{
     blockchain::{
-        branch::DeployBranch, snapshot::wait_snapshots_until_ready, user_wallet::WalletError,
-        BlockchainContractAddress, Snapshot,
+        branch::DeployBranch, snapshot::wait_snapshots_until_ready, BlockchainContractAddress,
+        Snapshot,
     },
     git_helper::GitHelper,
 };
@@ -11,12 +11,11 @@ use git_hash::ObjectId;
 use git_odb::Find;
 use git_traverse::tree::recorder;
 use tokio::task::{JoinError, JoinSet};
-use tokio_retry::RetryIf;
 use tracing::Instrument;
 
 use super::{
-    push_diff::push_new_branch_snapshot, utilities::retry::default_retry_strategy,
-    BlockchainService, ZERO_SHA,
+    push_diff::push_new_branch_snapshot, utilities::retry::retry_blockchain, BlockchainService,
+    ZERO_SHA,
 };
 
 #[derive(Debug)]
@@ -124,37 +123,24 @@ where
             let ancestor_commit = self.ancestor_commit.clone();
             let new_branch = self.new_branch.clone();
 
-            let condition = |e: &anyhow::Error| {
-                if e.is::<WalletError>() {
-                    false
-                } else {
-                    tracing::warn!("Attempt failed with {:#?}", e);
-                    true
-                }
-            };
-
             snapshot_handlers.spawn(
                 async move {
-                    RetryIf::spawn(
-                        default_retry_strategy(),
-                        || async {
-                            tracing::debug!("attempt to push a new snapshot");
-                            push_new_branch_snapshot(
-                                &blockchain,
-                                &file_provider,
-                                &remote_network,
-                                &dao_addr,
-                                &repo_addr,
-                                &expected_snapshot_addr,
-                                &ancestor_commit,
-                                &new_branch,
-                                &file_path,
-                                &content,
-                            )
-                            .await
-                        },
-                        condition,
-                    )
+                    retry_blockchain(|| async {
+                        tracing::debug!("attempt to push a new snapshot");
+                        push_new_branch_snapshot(
+                            &blockchain,
+                            &file_provider,
+                            &remote_network,
+                            &dao_addr,
+                            &repo_addr,
+                            &expected_snapshot_addr,
+                            &ancestor_commit,
+                            &new_branch,
+                            &file_path,
+                            &content,
+                        )
+                        .await
+                    })
                     .await
                 }
                 .instrument(info_span!("tokio::spawn::push_new_branch_snapshot").or_current()),
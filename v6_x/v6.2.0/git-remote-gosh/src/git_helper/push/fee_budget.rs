@@ -0,0 +1,260 @@
+use crate::blockchain::fees::FeeCounters;
+use crate::blockchain::BlockchainService;
+use crate::git_helper::report::FeesReport;
+use crate::git_helper::GitHelper;
+use crate::utilities::env::parse_env;
+
+// Max amount of nanotokens a single push is allowed to spend
+const GOSH_PUSH_FEE_BUDGET: &str = "GOSH_PUSH_FEE_BUDGET";
+// Expected cost of a single message in nanotokens, used for estimation
+const GOSH_MESSAGE_FEE_ESTIMATE: &str = "GOSH_MESSAGE_FEE_ESTIMATE";
+
+// rough cost of a message, used only to warn when nothing better is known
+const DEFAULT_MESSAGE_FEE_ESTIMATE: u64 = 100_000_000;
+const NANOTOKENS_PER_TOKEN: u64 = 1_000_000_000;
+// every commit deploys at least a commit, a tree and a diff
+const MESSAGES_PER_COMMIT: u64 = 3;
+
+/// `None` when the variable is not set, a value that can't be parsed fails
+/// the push instead of turning the limit off
+fn get_optional_env(key: &str) -> anyhow::Result<Option<u64>> {
+    match std::env::var_os(key) {
+        None => Ok(None),
+        Some(_) => parse_env(key).map(Some),
+    }
+}
+
+fn get_push_fee_budget() -> anyhow::Result<Option<u64>> {
+    get_optional_env(GOSH_PUSH_FEE_BUDGET)
+}
+
+/// Configured cost of a message or the average fee of the calls already
+/// processed by this helper
+fn get_message_fee_estimate() -> anyhow::Result<Option<u64>> {
+    let estimate = get_optional_env(GOSH_MESSAGE_FEE_ESTIMATE)?.or_else(|| {
+        let counters = FeeCounters::current();
+        (counters.calls > 0).then(|| counters.call_fees / counters.calls)
+    });
+    Ok(estimate)
+}
+
+fn format_tokens(nanotokens: u64) -> String {
+    format!(
+        "{}.{:09}",
+        nanotokens / NANOTOKENS_PER_TOKEN,
+        nanotokens % NANOTOKENS_PER_TOKEN
+    )
+}
+
+/// Lower bound of the fees: setCommit and the minimal set of contracts per commit
+fn estimate_push_fees(number_of_commits: u64, message_fee_estimate: u64) -> u64 {
+    (number_of_commits * MESSAGES_PER_COMMIT + 1).saturating_mul(message_fee_estimate)
+}
+
+fn check_funds(estimate: u64, balance: u64, budget: Option<u64>) -> anyhow::Result<()> {
+    if estimate > balance {
+        anyhow::bail!(
+            "Not enough funds for the push: it needs at least {} tokens, while the wallet and its mirrors have {} tokens",
+            format_tokens(estimate),
+            format_tokens(balance)
+        );
+    }
+    if let Some(budget) = budget {
+        if estimate > budget {
+            anyhow::bail!(
+                "Push needs at least {} tokens, which exceeds the fee budget of {} tokens ({GOSH_PUSH_FEE_BUDGET})",
+                format_tokens(estimate),
+                format_tokens(budget)
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Without a known message fee the estimate is a guess, so a shortfall is
+/// returned as a warning instead of failing the push. The budget is still
+/// enforced against the fees actually spent during the push
+fn check_funds_before_push(
+    number_of_commits: u64,
+    message_fee_estimate: Option<u64>,
+    balance: u64,
+    budget: Option<u64>,
+) -> anyhow::Result<Option<String>> {
+    let enforced = message_fee_estimate.is_some();
+    let estimate = estimate_push_fees(
+        number_of_commits,
+        message_fee_estimate.unwrap_or(DEFAULT_MESSAGE_FEE_ESTIMATE),
+    );
+    tracing::trace!(
+        "fee budget: commits={number_of_commits}, estimate={estimate}, balance={balance}, budget={budget:?}, enforced={enforced}"
+    );
+    match check_funds(estimate, balance, budget) {
+        Ok(()) => Ok(None),
+        Err(e) if !enforced => Ok(Some(format!(
+            "{e} (rough estimate, set {GOSH_MESSAGE_FEE_ESTIMATE} for a precise check)"
+        ))),
+        Err(e) => Err(e),
+    }
+}
+
+async fn get_wallets_balance<B>(context: &GitHelper<B>) -> anyhow::Result<u64>
+where
+    B: BlockchainService + 'static,
+{
+    let balances = context
+        .blockchain
+        .user_wallet(&context.dao_addr, &context.remote.network)
+        .await?
+        .balances(context.blockchain.client())
+        .await?;
+    Ok(balances.iter().map(|(_, _, balance)| balance).sum())
+}
+
+/// Tracks the fees spent by the push and keeps them within the budget
+pub struct FeeBudget {
+    budget: Option<u64>,
+    initial_balance: u64,
+    initial_counters: FeeCounters,
+}
+
+impl FeeBudget {
+    /// Checks that the wallets can afford the push before anything is deployed
+    #[instrument(level = "info", skip_all)]
+    pub async fn start<B>(context: &GitHelper<B>, number_of_commits: usize) -> anyhow::Result<Self>
+    where
+        B: BlockchainService + 'static,
+    {
+        let budget = get_push_fee_budget()?;
+        let message_fee_estimate = get_message_fee_estimate()?;
+        let initial_balance = get_wallets_balance(context).await?;
+        if let Some(warning) = check_funds_before_push(
+            number_of_commits as u64,
+            message_fee_estimate,
+            initial_balance,
+            budget,
+        )? {
+            eprintln!("Warning: {warning}");
+        }
+        Ok(Self {
+            budget,
+            initial_balance,
+            initial_counters: FeeCounters::current(),
+        })
+    }
+
+    async fn get_spent<B>(&self, context: &GitHelper<B>) -> anyhow::Result<(u64, FeeCounters)>
+    where
+        B: BlockchainService + 'static,
+    {
+        let balance = get_wallets_balance(context).await?;
+        let counters = FeeCounters::current().since(&self.initial_counters);
+        // wallets can be topped up during the push,
+        // fees of processed calls are spent anyway
+        let spent = std::cmp::max(
+            self.initial_balance.saturating_sub(balance),
+            counters.call_fees,
+        );
+        tracing::trace!("fee budget: spent={spent}, balance={balance}, counters={counters:?}");
+        Ok((spent, counters))
+    }
+
+    /// Fails if the push has already spent more than the budget
+    pub async fn check<B>(&self, context: &GitHelper<B>) -> anyhow::Result<()>
+    where
+        B: BlockchainService + 'static,
+    {
+        let budget = match self.budget {
+            None => return Ok(()),
+            Some(budget) => budget,
+        };
+        let (spent, _) = self.get_spent(context).await?;
+        if spent > budget {
+            anyhow::bail!(
+                "Push aborted: spent {} tokens, which exceeds the fee budget of {} tokens ({GOSH_PUSH_FEE_BUDGET})",
+                format_tokens(spent),
+                format_tokens(budget)
+            );
+        }
+        Ok(())
+    }
+
+    pub async fn report<B>(&self, context: &GitHelper<B>) -> anyhow::Result<FeesReport>
+    where
+        B: BlockchainService + 'static,
+    {
+        let (spent, counters) = self.get_spent(context).await?;
+        Ok(FeesReport {
+            spent,
+            calls: counters.calls,
+            call_fees: counters.call_fees,
+            messages_sent: counters.messages_sent,
+        })
+    }
+
+    pub async fn summary<B>(&self, context: &GitHelper<B>) -> anyhow::Result<String>
+    where
+        B: BlockchainService + 'static,
+    {
+        let (spent, counters) = self.get_spent(context).await?;
+        let mut summary = format!(
+            "Push fees: {} tokens spent, {} messages sent, {} calls processed with {} tokens of fees",
+            format_tokens(spent),
+            counters.messages_sent,
+            counters.calls,
+            format_tokens(counters.call_fees)
+        );
+        if let Some(budget) = self.budget {
+            summary.push_str(&format!(", budget {} tokens", format_tokens(budget)));
+        }
+        Ok(summary)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ensure_tokens_are_formatted() {
+        assert_eq!(format_tokens(0), "0.000000000");
+        assert_eq!(format_tokens(1_500_000_000), "1.500000000");
+        assert_eq!(format_tokens(42), "0.000000042");
+    }
+
+    #[test]
+    fn ensure_funds_are_checked_against_estimate() {
+        let estimate = estimate_push_fees(2, 100);
+        assert_eq!(estimate, 700);
+        assert!(check_funds(estimate, 700, None).is_ok());
+        assert!(check_funds(estimate, 699, None).is_err());
+        assert!(check_funds(estimate, 1000, Some(700)).is_ok());
+        let err = check_funds(estimate, 1000, Some(500)).unwrap_err();
+        assert!(err.to_string().contains("exceeds the fee budget"));
+    }
+
+    #[test]
+    fn ensure_guessed_estimate_only_warns() {
+        let rough = estimate_push_fees(2, DEFAULT_MESSAGE_FEE_ESTIMATE);
+        let warning = check_funds_before_push(2, None, rough - 1, None).unwrap();
+        assert!(warning.unwrap().contains(GOSH_MESSAGE_FEE_ESTIMATE));
+        let warning = check_funds_before_push(2, None, rough, None).unwrap();
+        assert!(warning.is_none());
+        // only a known fee makes the check strict
+        assert!(check_funds_before_push(2, Some(100), 699, None).is_err());
+        let warning = check_funds_before_push(2, Some(100), 700, None).unwrap();
+        assert!(warning.is_none());
+        assert!(check_funds_before_push(2, Some(100), 1000, Some(500)).is_err());
+        let warning = check_funds_before_push(2, None, u64::MAX, Some(rough - 1)).unwrap();
+        assert!(warning.unwrap().contains("exceeds the fee budget"));
+    }
+
+    #[test]
+    fn ensure_invalid_budget_fails() {
+        std::env::set_var(GOSH_PUSH_FEE_BUDGET, "1.5");
+        assert!(get_push_fee_budget().is_err());
+        std::env::set_var(GOSH_PUSH_FEE_BUDGET, "1500000000");
+        assert_eq!(get_push_fee_budget().unwrap(), Some(1_500_000_000));
+        std::env::remove_var(GOSH_PUSH_FEE_BUDGET);
+        assert_eq!(get_push_fee_budget().unwrap(), None);
+    }
+}
@@ -1,10 +1,11 @@
 use super::GitHelper;
 use crate::{
+    abi::bindings::{commit, goshwallet, repository},
     blockchain::{
         branch::DeleteBranch,
-        contract::{ContractRead, GoshContract},
+        contract::{GoshContract, TypedContract},
         get_commit_address, gosh_abi, AddrVersion, BlockchainContractAddress, BlockchainService,
-        GetNameCommitResult, MAX_ACCOUNTS_ADDRESSES_PER_QUERY, ZERO_SHA,
+        MAX_ACCOUNTS_ADDRESSES_PER_QUERY, ZERO_SHA,
     },
     git_helper::push::create_branch::CreateBranchOperation,
 };
@@ -12,7 +13,8 @@ use git_hash::{self, ObjectId};
 use git_odb::Find;
 use std::collections::VecDeque;
 use std::{
-    collections::{HashMap, HashSet},
+    cmp::Reverse,
+    collections::{BinaryHeap, HashMap, HashSet},
     str::FromStr,
     sync::Arc,
     vec::Vec,
@@ -33,6 +35,8 @@ mod push_tree;
 use push_tag::push_tag;
 mod delete_tag;
 pub(crate) mod parallel_snapshot_upload_support;
+mod scheduler;
+mod fee_budget;
 
 use crate::blockchain::{branch_list, get_commit_by_addr, Snapshot, Tree, tree};
 use crate::git_helper::push::parallel_snapshot_upload_support::{
@@ -43,13 +47,14 @@ use crate::git_helper::supported_contract_version;
 use delete_tag::delete_tag;
 use parallel_diffs_upload_support::{ParallelDiff, ParallelDiffsUploadSupport};
 use push_tree::push_tree;
+use scheduler::PushScheduler;
+use fee_budget::FeeBudget;
+use crate::git_helper::report::{self, ObjectCounts, ReportBuilder};
 
-use crate::blockchain::tree::load::{construct_map_of_snapshots, GetTreeResult, SnapshotMonitor};
+use crate::blockchain::tree::load::{construct_map_of_snapshots, SnapshotMonitor};
 use crate::git_helper::push::push_diff::save_data_to_ipfs;
 
 static PARALLEL_PUSH_LIMIT: usize = 1 << 6;
-static MAX_REDEPLOY_ATTEMPTS: i32 = 3;
-const GOSH_DEPLOY_RETRIES: &str = "GOSH_DEPLOY_RETRIES";
 
 #[derive(Default)]
 struct PushBlobStatistics {
@@ -71,12 +76,6 @@ impl PushBlobStatistics {
     } */
 }
 
-#[derive(Deserialize, Debug)]
-pub struct GetPreviousResult {
-    #[serde(rename = "value0")]
-    pub previous: Option<AddrVersion>,
-}
-
 #[derive(Deserialize, Debug, Clone)]
 pub struct AccountStatus {
     #[serde(rename = "id")]
@@ -85,12 +84,6 @@ pub struct AccountStatus {
     pub status: u8,
 }
 
-#[derive(Deserialize, Debug)]
-struct GetLimitedResult {
-    #[serde(rename = "_limited")]
-    pub limited: bool,
-}
-
 impl<Blockchain> GitHelper<Blockchain>
 where
     Blockchain: BlockchainService + 'static,
@@ -199,7 +192,7 @@ where
                 // } else {
             }
 
-            parallel_snapshot_uploads.push_expected(snapshot_addr.clone());
+            parallel_snapshot_uploads.push_expected(snapshot_addr.clone(), commit_id.to_string());
             snapshot_addr
         };
         if !upgrade_commit {
@@ -220,6 +213,140 @@ where
         Ok(())
     }
 
+    /// Pushes a file copied or moved from `source_path`. When the source content
+    /// is stored in IPFS the new snapshot is seeded with its CID and the diff
+    /// carries only the changes made after the copy, exact copies need no diff.
+    /// Other files are pushed as new ones, seeding gives nothing for them.
+    #[instrument(level = "info", skip_all)]
+    async fn push_copied_blob(
+        &mut self,
+        source_path: &str,
+        source_blob_id: &ObjectId,
+        file_path: &str,
+        blob_id: &ObjectId,
+        commit_id: &ObjectId,
+        branch_name: &str,
+        ancestor_commits: &[String],
+        statistics: &mut PushBlobStatistics,
+        parallel_diffs_upload_support: &mut ParallelDiffsUploadSupport,
+        parallel_snapshot_uploads: &mut ParallelSnapshotUploadSupport,
+        snapshot_to_commit: &mut HashMap<String, Vec<SnapshotMonitor>>,
+    ) -> anyhow::Result<()> {
+        tracing::trace!("push_copied_blob: source_path={source_path}, source_blob_id={source_blob_id}, file_path={file_path}, blob_id={blob_id}, commit_id={commit_id}, branch_name={branch_name}");
+        let file_diff = utilities::generate_blob_diff(
+            &self.local_repository().objects,
+            Some(source_blob_id),
+            Some(blob_id),
+        )
+        .await?;
+        let source_ipfs = if is_going_to_ipfs(&file_diff.original) {
+            self.find_snapshot_ipfs(source_path, ancestor_commits, snapshot_to_commit)
+                .await?
+        } else {
+            None
+        };
+        let source_ipfs = match source_ipfs {
+            Some(ipfs) => ipfs,
+            None => {
+                tracing::trace!("no ipfs content of {source_path} to reuse for {file_path}");
+                return self
+                    .push_new_blob(
+                        file_path,
+                        blob_id,
+                        commit_id,
+                        branch_name,
+                        statistics,
+                        parallel_diffs_upload_support,
+                        parallel_snapshot_uploads,
+                        false,
+                        snapshot_to_commit,
+                    )
+                    .await;
+            }
+        };
+        tracing::trace!("reuse ipfs {source_ipfs} of {source_path} for {file_path}");
+        let repo_contract = self.blockchain.repo_contract().clone();
+        let snapshot_addr = Snapshot::calculate_address(
+            self.blockchain.client(),
+            &repo_contract,
+            &commit_id.to_string(),
+            file_path,
+        )
+        .await?;
+        let snapshot_addr = String::from(snapshot_addr);
+        if !self.get_db()?.snapshot_exists(&snapshot_addr)? {
+            let snapshot = ParallelSnapshot::new(
+                file_path.to_string(),
+                false,
+                commit_id.to_string(),
+                "".to_string(),
+                Some(source_ipfs),
+            );
+            self.get_db()?
+                .put_snapshot(&snapshot, snapshot_addr.clone())?;
+        }
+        parallel_snapshot_uploads.push_expected(snapshot_addr.clone(), commit_id.to_string());
+        statistics.new_snapshots += 1;
+
+        if source_blob_id == blob_id {
+            // the seeded snapshot already holds the content
+            return Ok(());
+        }
+        let diff = ParallelDiff::new(
+            *commit_id,
+            branch_name.to_string(),
+            *blob_id,
+            file_path.to_string(),
+            file_diff.original,
+            file_diff.patch,
+            file_diff.after_patch,
+            snapshot_addr,
+        );
+        parallel_diffs_upload_support.push(self, diff).await?;
+        statistics.diffs += 1;
+        Ok(())
+    }
+
+    /// IPFS CID of the file content stored in its on-chain snapshot
+    /// as of the latest ancestor commit that changed the file
+    async fn find_snapshot_ipfs(
+        &self,
+        file_path: &str,
+        ancestor_commits: &[String],
+        snapshot_to_commit: &HashMap<String, Vec<SnapshotMonitor>>,
+    ) -> anyhow::Result<Option<String>> {
+        let snap_mon = match snapshot_to_commit
+            .get(file_path)
+            .and_then(|v| v.iter().find(|m| ancestor_commits.contains(&m.latest_commit)))
+        {
+            Some(snap_mon) => snap_mon,
+            None => return Ok(None),
+        };
+        let repo_contract = self.blockchain.repo_contract().clone();
+        let snapshot_address = Snapshot::calculate_address(
+            self.blockchain.client(),
+            &repo_contract,
+            &snap_mon.base_commit,
+            file_path,
+        )
+        .await?;
+        let snapshot = match Snapshot::load(self.blockchain.client(), &snapshot_address).await {
+            Ok(snapshot) => snapshot,
+            Err(e) => {
+                tracing::trace!("Failed to load snapshot {snapshot_address}: {e}");
+                return Ok(None);
+            }
+        };
+        let ipfs = if snapshot.next_commit == snap_mon.latest_commit {
+            snapshot.next_ipfs
+        } else if snapshot.current_commit == snap_mon.latest_commit {
+            snapshot.current_ipfs
+        } else {
+            None
+        };
+        Ok(ipfs.filter(|ipfs| !ipfs.is_empty()))
+    }
+
     #[instrument(level = "info", skip_all)]
     async fn push_blob_remove(
         &mut self,
@@ -316,12 +443,12 @@ where
         //     .await?
         //     .unwrap();
         // TODO: get commit can fail due to changes in versions
-        let commit_contract = GoshContract::new(&remote_commit_addr, gosh_abi::COMMIT);
-        let sha: GetNameCommitResult = commit_contract
-            .run_local(self.blockchain.client(), "getNameCommit", None)
+        let commit_contract = TypedContract::<commit::Contract>::new(&remote_commit_addr);
+        let sha = commit_contract
+            .run_local_fn(self.blockchain.client(), commit::GetNameCommit {})
             .await?;
         tracing::trace!("Commit sha: {sha:?}");
-        let sha = sha.name;
+        let sha = sha.value0;
         let prev_commit_id = Some(ObjectId::from_str(&sha)?);
 
         Ok((
@@ -510,12 +637,14 @@ where
                         &id.to_string(),
                     )
                         .await?;
-                    let commit_contract = GoshContract::new(&parent, gosh_abi::COMMIT);
-                    if !commit_contract.is_active(self.blockchain.client()).await? {
+                    let commit_contract = TypedContract::<commit::Contract>::new(&parent);
+                    if !commit_contract.contract().is_active(self.blockchain.client()).await? {
                         None
                     } else {
-                        let result: GetTreeResult = commit_contract.run_local(self.blockchain.client(), "gettree", None).await?;
-                        let tree_address = result.address;
+                        let tree_address = commit_contract
+                            .run_local_fn(self.blockchain.client(), commit::Gettree {})
+                            .await?
+                            .value0;
                         match Tree::load(
                             self.blockchain.client(),
                             &tree_address,
@@ -559,7 +688,6 @@ where
 
         let branches = branch_list(self.blockchain.client(), &self.repo_addr).await?;
         let prev_zero_commit = branches
-            .branch_ref
             .iter()
             .find(|_ref| _ref.branch_name == local_branch_name)
             .ok_or(anyhow::format_err!(
@@ -595,40 +723,9 @@ where
         )
         .await?;
 
-        let mut expected_contracts = vec![];
-
-        let mut attempts = 0;
-        let mut last_rest_cnt = 0;
-        let redeploy_attempts = get_redeploy_attempts();
-
-        while attempts < redeploy_attempts {
-            if attempts == redeploy_attempts {
-                anyhow::bail!(
-                "Failed to deploy all commits. Undeployed commits: {expected_contracts:?}"
-            )
-            }
-            expected_contracts = push_commits
-                .wait_all_commits(self.blockchain.clone())
-                .await?;
-            tracing::trace!("Wait all commits result: {expected_contracts:?}");
-            if expected_contracts.is_empty() {
-                break;
-            }
-            if expected_contracts.len() != last_rest_cnt {
-                attempts = 0;
-            }
-            last_rest_cnt = expected_contracts.len();
-            tracing::trace!("Restart deploy on undeployed commits");
-            let expected = push_commits.get_expected().to_owned();
-            push_commits = ParallelCommitUploadSupport::new();
-            for address in expected_contracts.clone() {
-                tracing::trace!("Get params of undeployed tree: {}", address,);
-                push_commits
-                    .add_to_push_list(self, String::from(address), push_semaphore.clone())
-                    .await?;
-            }
-            attempts += 1;
-        }
+        let mut scheduler = PushScheduler::new(push_semaphore.clone());
+        scheduler.run(self, &mut [&mut push_commits], None).await?;
+        scheduler.ensure_all_deployed()?;
 
         self.blockchain
             .notify_commit(
@@ -749,6 +846,50 @@ where
             entry.push(snap_mon);
         }
 
+        // must be handled before deletions, that drop snapshots of the moved files
+        for (source, copied) in tree_diff.copied {
+            let file_path = copied.filepath.to_string();
+            if upgrade_commit {
+                // snapshots of the upgraded commit are deployed with full content anyway
+                self.push_new_blob(
+                    &file_path,
+                    &copied.oid,
+                    &object_id,
+                    local_branch_name,
+                    statistics,
+                    parallel_diffs_upload_support,
+                    parallel_snapshot_uploads,
+                    upgrade_commit,
+                    snapshot_to_commit,
+                )
+                .await?;
+            } else {
+                self.push_copied_blob(
+                    &source.filepath.to_string(),
+                    &source.oid,
+                    &file_path,
+                    &copied.oid,
+                    &object_id,
+                    local_branch_name,
+                    &ancestor_commits,
+                    statistics,
+                    parallel_diffs_upload_support,
+                    parallel_snapshot_uploads,
+                    snapshot_to_commit,
+                )
+                .await?;
+            }
+            let snap_mon = SnapshotMonitor {
+                base_commit: object_id.to_string(),
+                latest_commit: object_id.to_string(),
+            };
+            let entry = snapshot_to_commit.entry(file_path).or_insert(vec![]);
+            if upgrade_commit {
+                entry.clear();
+            }
+            entry.push(snap_mon);
+        }
+
         for update in tree_diff.updated {
             tracing::trace!("push update diff");
             // Commit modifies file but snapshot can be updated in the other branch
@@ -851,6 +992,7 @@ where
             }
         }
 
+        let first_tree = parallel_tree_upload_support.get_expected().len();
         let (tree_addr, tree_sha) = push_tree(
             self,
             &tree_id,
@@ -878,6 +1020,8 @@ where
             )
             .await?;
             let commit_address = String::from(commit_address);
+            let trees = parallel_tree_upload_support.get_expected()[first_tree..].to_vec();
+            push_commits.depends_on_trees(&commit_address, trees);
             if !self.get_db()?.commit_exists(&commit_address)? {
                 self.get_db()?.put_commit(commit, commit_address.clone())?;
 
@@ -955,21 +1099,17 @@ where
         tracing::trace!("Failed to get contract version: {res:?}");
 
         // 3) Get address of the previous version of the repo
-        let previous: GetPreviousResult = self
-            .blockchain
-            .repo_contract()
-            .read_state(self.blockchain.client(), "getPrevious", None)
-            .await?;
+        let previous = TypedContract::<repository::Contract>::new(&repo_contract.address)
+            .read_state_fn(self.blockchain.client(), repository::GetPrevious {})
+            .await?
+            .value0
+            .ok_or(anyhow::format_err!(
+                "Failed to get previous version of the repo"
+            ))?;
         tracing::trace!("prev repo addr: {previous:?}");
 
         // 4) Get address of the ancestor commit of previous version
-        let previous_repo_addr = previous
-            .previous
-            .clone()
-            .ok_or(anyhow::format_err!(
-                "Failed to get previous version of the repo"
-            ))?
-            .address;
+        let previous_repo_addr = previous.addr;
         let mut prev_repo_contract = GoshContract::new(&previous_repo_addr, gosh_abi::REPO);
         let prev_ancestor_address = get_commit_address(
             &self.blockchain.client(),
@@ -988,7 +1128,7 @@ where
         // 6) For new version ancestor commit set parent to the ancestor commit of previous version
         let parents_for_upgrade = vec![AddrVersion {
             address: prev_ancestor_address.clone(),
-            version: previous.previous.unwrap().version,
+            version: previous.version,
         }];
         let ancestor_id = self
             .local_repository()
@@ -1007,7 +1147,8 @@ where
 
         // 8) Get list of objects to push with the ancestor commit
         tracing::trace!("Find objects till: {till_id:?}");
-        let commit_objects_list = get_list_of_commit_objects(ancestor_id, till_id)?;
+        let commit_objects_list =
+            get_list_of_commit_objects(self.local_repository(), ancestor_id.detach(), till_id)?;
         if self.pushed_commits.contains_key(&commit_objects_list[0]) {
             return Ok(());
         }
@@ -1132,71 +1273,20 @@ where
         }
 
         tracing::trace!("Start of wait for contracts to be deployed");
-        let mut expected_contracts = vec![];
-        let mut attempts = 0;
-        let mut last_rest_cnt = 0;
-        let redeploy_attempts = get_redeploy_attempts();
-        while attempts < redeploy_attempts {
-            if attempts == redeploy_attempts {
-                anyhow::bail!("Failed to deploy all trees. Undeployed trees: {expected_contracts:?}")
-            }
-            expected_contracts = parallel_tree_uploads
-                .wait_all_trees(self.blockchain.clone())
-                .await?;
-            tracing::trace!("Wait all trees result: {expected_contracts:?}");
-            if expected_contracts.is_empty() {
-                break;
-            }
-            if expected_contracts.len() != last_rest_cnt {
-                attempts = 0;
-            }
-            last_rest_cnt = expected_contracts.len();
-            tracing::trace!("Restart deploy on undeployed trees");
-            let expected = parallel_tree_uploads.get_expected().to_owned();
-            parallel_tree_uploads = ParallelTreeUploadSupport::new();
-            for address in expected_contracts.clone() {
-                tracing::trace!("Get params of undeployed tree: {}", address,);
-                parallel_tree_uploads.push_expected(String::from(&address));
-                parallel_tree_uploads
-                    .add_to_push_list(self, String::from(&address), push_semaphore.clone())
-                    .await?;
-            }
-            attempts += 1;
-        }
-
-        let mut attempts = 0;
-        let mut last_rest_cnt = 0;
-        while attempts < redeploy_attempts {
-            if attempts == redeploy_attempts {
-                anyhow::bail!(
-                "Failed to deploy all commits. Undeployed commits: {expected_contracts:?}"
-            )
-            }
-            expected_contracts = push_commits
-                .wait_all_commits(self.blockchain.clone())
-                .await?;
-            tracing::trace!("Wait all commits result: {expected_contracts:?}");
-            if expected_contracts.is_empty() {
-                break;
-            }
-            if expected_contracts.len() != last_rest_cnt {
-                attempts = 0;
-            }
-            last_rest_cnt = expected_contracts.len();
-            tracing::trace!("Restart deploy on undeployed commits");
-            let expected = push_commits.get_expected().to_owned();
-            push_commits = ParallelCommitUploadSupport::new();
-            for address in expected_contracts.clone() {
-                tracing::trace!("Get params of undeployed tree: {}", address,);
-                push_commits
-                    .add_to_push_list(self, String::from(address), push_semaphore.clone())
-                    .await?;
-            }
-            attempts += 1;
-        }
-
         let files_cnt = parallel_snapshot_uploads.get_expected().len();
-        parallel_snapshot_uploads.start_push(self).await?;
+        let mut scheduler = PushScheduler::new(push_semaphore.clone());
+        scheduler
+            .run(
+                self,
+                &mut [
+                    &mut parallel_tree_uploads,
+                    &mut push_commits,
+                    &mut parallel_snapshot_uploads,
+                ],
+                None,
+            )
+            .await?;
+        scheduler.ensure_all_deployed()?;
 
         let stored_snapshot_addresses = parallel_snapshot_uploads.get_expected().clone();
         let db = self.get_db()?;
@@ -1206,15 +1296,15 @@ where
 
         if set_commit {
             let branches = branch_list(self.blockchain.client(), &self.repo_addr).await?;
-            for branch_ref in branches.branch_ref {
+            for branch_ref in branches {
                 // if branch_ref.branch_name == local_branch_name {
                 let commit_contract =
-                    GoshContract::new(&branch_ref.commit_address, gosh_abi::COMMIT);
-                let sha: GetNameCommitResult = commit_contract
-                    .run_local(self.blockchain.client(), "getNameCommit", None)
+                    TypedContract::<commit::Contract>::new(&branch_ref.commit_address);
+                let sha = commit_contract
+                    .run_local_fn(self.blockchain.client(), commit::GetNameCommit {})
                     .await?;
                 tracing::trace!("Commit sha: {sha:?}");
-                if sha.name == latest_commit_id.to_string() {
+                if sha.value0 == latest_commit_id.to_string() {
                     // 10) call set commit to the new version of the ancestor commit
                     self.blockchain
                         .notify_commit(
@@ -1275,8 +1365,20 @@ where
         // This led to a problem that some files were copied from one place to another
         // and snapshots were not created since git didn't count them as changed.
         // Our second attempt is to calculated tree diff from one commit to another.
+        // Copied and moved files are detected in the tree diff, their snapshots
+        // are seeded with the source content instead of uploading it again.
         tracing::debug!("push_ref {} : {}", local_ref, remote_ref);
-        self.open_db()?;
+        let mut report = ReportBuilder::start("push", remote_ref);
+        // the journal of interrupted push is reused only for the same target
+        let push_target = format!(
+            "{} {remote_ref} {}",
+            self.repo_addr,
+            self.local_repository()
+                .find_reference(local_ref)?
+                .into_fully_peeled_id()?
+                .detach()
+        );
+        self.open_push_journal(&push_target)?;
         let local_branch_name: &str = get_ref_name(local_ref)?;
         let remote_branch_name: &str = get_ref_name(remote_ref)?;
 
@@ -1313,21 +1415,15 @@ where
             .find_reference(local_ref)?
             .into_fully_peeled_id()?;
         tracing::trace!("latest_commit={latest_commit:?}");
-        // get list of git objects in local repo, excluding ancestor ones
-        // TODO: list of commits is not in right order in case of merge commit with commits at the same time
-        //
-        // C
-        // |\
-        // | B
-        // |/
-        // A
-        // B can come before A and remote can't find parent version for B
         let mut parents_of_commits: HashMap<String, Vec<String>> =
             HashMap::from([(ZERO_SHA.to_owned(), vec![]), ("".to_owned(), vec![])]);
 
         // map of base commit for snapshot
         let mut snapshot_to_commit = HashMap::new();
 
+        // wallets are checked for funds before anything is deployed
+        let mut fee_budget = None;
+
         // 3. If branch needs to be created do so
         if prev_commit_id.is_none() {
             //    ---
@@ -1348,6 +1444,9 @@ where
             let branching_point = self.get_parent_id(&originating_commit)?;
             tracing::trace!("branching_point={branching_point:?}");
             ancestor_commit_object = Some(branching_point);
+            let number_of_commits =
+                self.count_commits_to_push(local_ref, ancestor_commit_object)?;
+            fee_budget = Some(FeeBudget::start(self, number_of_commits).await?);
             let mut create_branch_op =
                 CreateBranchOperation::new(branching_point, remote_branch_name, self);
             let is_first_ever_branch = create_branch_op.run().await?;
@@ -1365,6 +1464,14 @@ where
             };
             tracing::trace!("prev_commit_id={prev_commit_id:?}");
         }
+        let fee_budget = match fee_budget {
+            Some(fee_budget) => fee_budget,
+            None => {
+                let number_of_commits =
+                    self.count_commits_to_push(local_ref, ancestor_commit_object)?;
+                FeeBudget::start(self, number_of_commits).await?
+            }
+        };
 
         // create collections for spawned tasks and statistics
         let mut push_commits = ParallelCommitUploadSupport::new();
@@ -1395,8 +1502,11 @@ where
 
         // TODO: change to list of commits without extra objects
         // get list of git objects in local repo, excluding ancestor ones
-        let commit_list =
-            get_list_of_commit_objects(latest_commit, ancestor_commit_object)?;
+        let commit_list = get_list_of_commit_objects(
+            self.local_repository(),
+            latest_commit.detach(),
+            ancestor_commit_object,
+        )?;
 
         // 4. Do prepare commit for all commits
         // 5. Deploy tree objects of all commits
@@ -1464,6 +1574,7 @@ where
             }
         }
 
+        report.end_phase("prepare");
         let mut number_of_commits = 0;
         tracing::trace!("commit_list:{commit_list:?}");
         // iterate through the git objects list and push them
@@ -1542,77 +1653,28 @@ where
         // push dangling diffs
         parallel_diffs_upload_support.push_dangling(self).await?;
         let number_of_files_changed = parallel_diffs_upload_support.get_parallels_number();
+        report.end_phase("prepare_objects");
 
         tracing::trace!("Start of wait for contracts to be deployed");
-        let mut expected_contracts = vec![];
-        let mut attempts = 0;
-        let mut last_rest_cnt = 0;
-        let redeploy_attempts = get_redeploy_attempts();
-        while attempts < redeploy_attempts {
-            if attempts == redeploy_attempts {
-                anyhow::bail!("Failed to deploy all trees. Undeployed trees: {expected_contracts:?}")
-            }
-            expected_contracts = parallel_tree_uploads
-                .wait_all_trees(self.blockchain.clone())
-                .await?;
-            tracing::trace!("Wait all trees result: {expected_contracts:?}");
-            if expected_contracts.is_empty() {
-                break;
-            }
-            if expected_contracts.len() != last_rest_cnt {
-                attempts = 0;
-            }
-            last_rest_cnt = expected_contracts.len();
-            tracing::trace!("Restart deploy on undeployed trees");
-            let expected = parallel_tree_uploads.get_expected().to_owned();
-            parallel_tree_uploads = ParallelTreeUploadSupport::new();
-            for address in expected_contracts.clone() {
-                tracing::trace!("Get params of undeployed tree: {}", address,);
-                parallel_tree_uploads.push_expected(String::from(&address));
-                parallel_tree_uploads
-                    .add_to_push_list(self, String::from(&address), push_semaphore.clone())
-                    .await?;
-            }
-            attempts += 1;
-        }
-
-        let mut attempts = 0;
-        let mut last_rest_cnt = 0;
-        while attempts < redeploy_attempts {
-            if attempts == redeploy_attempts {
-                anyhow::bail!(
-                "Failed to deploy all commits. Undeployed commits: {expected_contracts:?}"
+        fee_budget.check(self).await?;
+        // trees, commits, diffs and snapshots are deployed as soon as
+        // the contracts they depend on are ready
+        let mut scheduler = PushScheduler::new(push_semaphore.clone());
+        scheduler
+            .run(
+                self,
+                &mut [
+                    &mut parallel_tree_uploads,
+                    &mut push_commits,
+                    &mut parallel_diffs_upload_support,
+                    &mut parallel_snapshot_uploads,
+                ],
+                Some(&fee_budget),
             )
-            }
-            expected_contracts = push_commits
-                .wait_all_commits(self.blockchain.clone())
-                .await?;
-            tracing::trace!("Wait all commits result: {expected_contracts:?}");
-            if expected_contracts.is_empty() {
-                break;
-            }
-            if expected_contracts.len() != last_rest_cnt {
-                attempts = 0;
-            }
-            last_rest_cnt = expected_contracts.len();
-            tracing::trace!("Restart deploy on undeployed commits");
-            let expected = push_commits.get_expected().to_owned();
-            push_commits = ParallelCommitUploadSupport::new();
-            for address in expected_contracts.clone() {
-                tracing::trace!("Get params of undeployed tree: {}", address,);
-                push_commits
-                    .add_to_push_list(self, String::from(address), push_semaphore.clone())
-                    .await?;
-            }
-            attempts += 1;
-        }
-
-        // After we have all commits and trees deployed, start push of diffs
-        parallel_diffs_upload_support.start_push(self).await?;
-        parallel_snapshot_uploads.start_push(self).await?;
-
-        // clear database after all objects were deployed
-        self.delete_db()?;
+            .await?;
+        scheduler.ensure_all_deployed()?;
+        report.end_phase("deploy");
+        fee_budget.check(self).await?;
 
         // 9. Set commit (move HEAD)
         ancestor_commit_id = match ancestor_commit_object {
@@ -1634,6 +1696,29 @@ where
             )
             .await?;
 
+        report.end_phase("set_commit");
+
+        // clear the journal only after the push is completed,
+        // otherwise the next run resumes from it
+        self.delete_db()?;
+
+        match fee_budget.summary(self).await {
+            Ok(summary) => eprintln!("{summary}"),
+            Err(e) => tracing::debug!("Failed to get fee summary: {e}"),
+        }
+        report.objects = ObjectCounts {
+            commits: push_commits.get_expected().len() as u64,
+            trees: parallel_tree_uploads.get_expected().len() as u64,
+            snapshots: statistics.new_snapshots as u64,
+            diffs: statistics.diffs as u64,
+        };
+        report.fees = fee_budget
+            .report(self)
+            .await
+            .map_err(|e| tracing::debug!("Failed to get fees for the report: {e}"))
+            .ok();
+        report::publish(report.finish());
+
         // 10. move HEAD
         //
         let result_ok = format!("ok {remote_ref}\n");
@@ -1693,6 +1778,20 @@ where
         Ok(result_ok)
     }
 
+    fn count_commits_to_push(
+        &self,
+        local_ref: &str,
+        till: Option<ObjectId>,
+    ) -> anyhow::Result<usize> {
+        let latest_commit = self
+            .local_repository()
+            .find_reference(local_ref)?
+            .into_fully_peeled_id()?;
+        let commits =
+            get_list_of_commit_objects(self.local_repository(), latest_commit.detach(), till)?;
+        Ok(commits.len())
+    }
+
     async fn check_if_wallet_is_limited(&self) -> anyhow::Result<()> {
         tracing::trace!("start check whether wallet is limited");
         let wallet = self
@@ -1703,11 +1802,11 @@ where
             .await
             .map_err(|_| anyhow::format_err!("Seems like you are not a member of DAO. Only DAO members can push to the repositories."))?;
         tracing::trace!("Zero wallet address: {:?}", wallet.address);
-        let res: GetLimitedResult = wallet
-            .run_local(self.blockchain.client(), "_limited", None)
+        let res = TypedContract::<goshwallet::Contract>::new(&wallet.address)
+            .run_local_fn(self.blockchain.client(), goshwallet::_Limited {})
             .await?;
         tracing::trace!("wallet _limited: {:?}", res);
-        if res.limited {
+        if res._limited {
             anyhow::bail!("Seems like you are not a member of DAO. Only DAO members can push to the repositories.");
         }
         tracing::trace!("wallet is valid");
@@ -1809,67 +1908,73 @@ fn calculate_left_distance(m: HashMap<String, Vec<String>>, from: &str, till: &s
 }
 
 #[instrument(level = "trace")]
+/// Returns commits reachable from `start` but not from `till`,
+/// ordered so that every commit comes after all of its parents.
+/// Commits that are ready at the same time are ordered by commit time.
 fn get_list_of_commit_objects(
-    start: git_repository::Id,
+    repo: &git_repository::Repository,
+    start: ObjectId,
     till: Option<ObjectId>,
 ) -> anyhow::Result<Vec<String>> {
     tracing::trace!("get_list_of_commit_objects: start:{start:?} till:{till:?}");
-    let walk = start
-        .ancestors()
-        .all()?
-        .map(|e| e.expect("all entities should be present"))
-        .into_iter();
-
-    let commits: Vec<git_repository::Id> = match till {
-        None => walk.into_iter().collect(),
-        Some(id) => walk
-            .take_while(|e| e.object().expect("object should exist").id != id)
-            .into_iter()
-            .collect(),
-    };
-
-    tracing::trace!("commits:{commits:?}");
-
-    let mut commit_objects: HashMap<String, git_repository::Commit> = HashMap::new();
-    for commit in commits.iter().rev() {
-        let commit = commit.object()?.into_commit();
-        commit_objects.insert(commit.id.to_string(), commit);
+    // commits already in the remote repo
+    let mut excluded: HashSet<ObjectId> = HashSet::new();
+    if let Some(till) = till {
+        for id in repo.find_object(till)?.id().ancestors().all()? {
+            excluded.insert(id?.detach());
+        }
     }
 
-    // Hashmap commits -> number of children
-    // TODO: change to heap to increase speed
-    let mut child_map: HashMap<String, Vec<String>> = HashMap::from_iter(
-        commits.into_iter().map(|el| (el.to_string(), vec![]))
-    );
-
-    for (_, commit) in &commit_objects {
-        for parent in commit.parent_ids() {
-            match child_map.get_mut(&parent.to_string()) {
-                Some(val) => {
-                    val.push(commit.id.to_string());
-                },
-                None => {},
-            }
+    // commit -> (commit time, parents to push)
+    let mut commits: HashMap<ObjectId, (u32, Vec<ObjectId>)> = HashMap::new();
+    let mut queue = VecDeque::from([start]);
+    while let Some(id) = queue.pop_front() {
+        if excluded.contains(&id) || commits.contains_key(&id) {
+            continue;
+        }
+        let commit = repo.find_object(id)?.into_commit();
+        let time = commit.time()?.seconds_since_unix_epoch;
+        let parents: Vec<ObjectId> = commit
+            .parent_ids()
+            .map(|parent| parent.detach())
+            .filter(|parent| !excluded.contains(parent))
+            .collect();
+        queue.extend(parents.iter().cloned());
+        commits.insert(id, (time, parents));
+    }
+    tracing::trace!("commits to push: {}", commits.len());
+
+    let mut children: HashMap<ObjectId, Vec<ObjectId>> = HashMap::new();
+    let mut pending_parents: HashMap<ObjectId, usize> = HashMap::new();
+    let mut ready = BinaryHeap::new();
+    for (id, (time, parents)) in &commits {
+        pending_parents.insert(*id, parents.len());
+        for parent in parents {
+            children.entry(*parent).or_default().push(*id);
+        }
+        if parents.is_empty() {
+            ready.push(Reverse((*time, *id)));
         }
     }
 
-    let mut res: Vec<String> = vec![];
-
-    while !child_map.is_empty() {
-        let mut zero_child_commits: Vec<String> = child_map.iter().filter(|(k, v)| v.is_empty()).map(|val| val.0.to_owned()).collect();
-        zero_child_commits.sort_by_key(|commit| commit_objects.get(commit).unwrap().time().unwrap());
-        zero_child_commits.reverse();
-        for commit in &zero_child_commits {
-            child_map.remove(commit);
-            res.push(commit.to_string());
-            for (_, children) in &mut child_map {
-                if children.contains(commit) {
-                    children.retain(|c| c != commit);
-                }
+    let mut res: Vec<String> = Vec::with_capacity(commits.len());
+    while let Some(Reverse((_, id))) = ready.pop() {
+        res.push(id.to_string());
+        for child in children.get(&id).into_iter().flatten() {
+            let pending = pending_parents.get_mut(child).unwrap();
+            *pending -= 1;
+            if *pending == 0 {
+                ready.push(Reverse((commits[child].0, *child)));
             }
         }
     }
-    res.reverse();
+    if res.len() != commits.len() {
+        anyhow::bail!(
+            "Failed to order commits: {} of {} commits were left unordered",
+            commits.len() - res.len(),
+            commits.len()
+        );
+    }
     Ok(res)
 }
 
@@ -1878,7 +1983,10 @@ mod tests {
     use super::*;
     use crate::logger::test_utils::{init_logger, shutdown_logger};
     use crate::{
-        blockchain::{self, service::tests::MockEverscale},
+        blockchain::{
+            self, branch::DeployBranch, service::tests::MockEverscale, simulator::Simulator,
+            user_wallet::BlockchainUserWalletService,
+        },
         git_helper::{test_utils::setup_repo, tests::setup_test_helper},
     };
 
@@ -1974,6 +2082,86 @@ mod tests {
         assert_eq!(dist, 1);
     }
 
+    fn find_commit_id(repo: &git_repository::Repository, name: &str) -> ObjectId {
+        repo.find_reference(name)
+            .unwrap()
+            .into_fully_peeled_id()
+            .unwrap()
+            .detach()
+    }
+
+    fn assert_parents_come_first(repo: &git_repository::Repository, commits: &[String]) {
+        let positions: HashMap<&String, usize> = commits
+            .iter()
+            .enumerate()
+            .map(|(position, commit)| (commit, position))
+            .collect();
+        for (position, commit) in commits.iter().enumerate() {
+            let commit_object = repo
+                .find_object(ObjectId::from_str(commit).unwrap())
+                .unwrap()
+                .into_commit();
+            for parent in commit_object.parent_ids() {
+                if let Some(parent_position) = positions.get(&parent.to_string()) {
+                    assert!(
+                        *parent_position < position,
+                        "parent {parent} of {commit} comes after it"
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn ensure_octopus_merge_is_ordered() {
+        let repo = setup_repo(
+            "test_octopus_merge_order",
+            "tests/fixtures/make_octopus_merge_repo.sh",
+        )
+        .unwrap();
+        let head = repo.head_commit().unwrap().id;
+        let base = find_commit_id(&repo, "refs/tags/base");
+
+        let commits = get_list_of_commit_objects(&repo, head, Some(base)).unwrap();
+        assert_eq!(commits.len(), 9);
+        assert!(!commits.contains(&base.to_string()));
+        assert_eq!(commits.last(), Some(&head.to_string()));
+        assert_parents_come_first(&repo, &commits);
+
+        // commits of the branch that is already in the remote are skipped
+        let branch_a = find_commit_id(&repo, "refs/heads/a");
+        let commits = get_list_of_commit_objects(&repo, head, Some(branch_a)).unwrap();
+        assert_eq!(commits.len(), 7);
+        assert!(!commits.contains(&branch_a.to_string()));
+        assert_parents_come_first(&repo, &commits);
+    }
+
+    #[test]
+    fn ensure_criss_cross_merge_is_ordered() {
+        let repo = setup_repo(
+            "test_criss_cross_merge_order",
+            "tests/fixtures/make_criss_cross_repo.sh",
+        )
+        .unwrap();
+        let head = repo.head_commit().unwrap().id;
+        let base = find_commit_id(&repo, "refs/tags/base");
+        let x1 = find_commit_id(&repo, "refs/tags/x1");
+
+        let commits = get_list_of_commit_objects(&repo, head, None).unwrap();
+        assert_eq!(commits.len(), 6);
+        assert_eq!(commits.first(), Some(&base.to_string()));
+        assert_parents_come_first(&repo, &commits);
+
+        let commits = get_list_of_commit_objects(&repo, head, Some(base)).unwrap();
+        assert_eq!(commits.len(), 5);
+        assert_parents_come_first(&repo, &commits);
+
+        let commits = get_list_of_commit_objects(&repo, head, Some(x1)).unwrap();
+        assert_eq!(commits.len(), 4);
+        assert!(!commits.contains(&x1.to_string()));
+        assert_parents_come_first(&repo, &commits);
+    }
+
     #[tokio::test]
     async fn test_push_parotected_ref() {
         init_logger().await;
@@ -2069,11 +2257,60 @@ mod tests {
         }
         shutdown_logger().await;
     }
-}
 
-pub fn get_redeploy_attempts() -> i32 {
-    std::env::var(GOSH_DEPLOY_RETRIES)
-        .ok()
-        .and_then(|num| i32::from_str_radix(&num, 10).ok())
-        .unwrap_or(MAX_REDEPLOY_ATTEMPTS)
-}
\ No newline at end of file
+    fn setup_simulated_helper(name: &str, simulator: &Simulator) -> GitHelper<Simulator> {
+        let repo = setup_repo(name, "tests/fixtures/make_remote_repo.sh").unwrap();
+        let mut helper = setup_test_helper(
+            json!({
+                "ipfs": "foo.endpoint"
+            }),
+            "gosh://0:0/test_dao/test_repo",
+            repo,
+            simulator.clone(),
+        );
+        helper.repo_addr = simulator.repo_contract().address.clone();
+        helper
+    }
+
+    #[tokio::test]
+    async fn ensure_simulated_push_to_protected_branch_fails() {
+        let simulator = Simulator::new("test_repo").unwrap();
+        simulator.protect_branch("main");
+        let mut helper = setup_simulated_helper("test_simulated_protected_push", &simulator);
+
+        let error = helper
+            .push_ref("refs/heads/main", "refs/heads/main")
+            .await
+            .unwrap_err();
+        assert!(error.to_string().contains("protected"));
+        assert_eq!(simulator.branch_head("main").unwrap(), ZERO_SHA);
+        // the journal is kept in the local repository
+        assert!(helper.local_repository().path().join("gosh_db").exists());
+    }
+
+    #[tokio::test]
+    async fn ensure_simulated_branch_is_deleted() {
+        let simulator = Simulator::new("test_repo").unwrap();
+        let wallet = simulator
+            .user_wallet(&BlockchainContractAddress::new("0:0"), "localhost")
+            .await
+            .unwrap();
+        simulator
+            .deploy_branch(
+                &wallet,
+                "test_repo".to_owned(),
+                "dev".to_owned(),
+                ZERO_SHA.to_owned(),
+            )
+            .await
+            .unwrap();
+        simulator.protect_branch("main");
+        let mut helper = setup_simulated_helper("test_simulated_branch_delete", &simulator);
+
+        let result = helper.delete_remote_ref("refs/heads/dev").await.unwrap();
+        assert_eq!(result, "ok refs/heads/dev\n");
+        assert!(simulator.branch_head("dev").is_none());
+        assert!(helper.delete_remote_ref("refs/heads/main").await.is_err());
+        assert_eq!(simulator.branch_head("main").unwrap(), ZERO_SHA);
+    }
+}
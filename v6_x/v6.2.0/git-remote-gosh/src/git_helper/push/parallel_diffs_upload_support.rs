@@ -4,13 +4,14 @@ use crate::git_helper::push::push_diff::{diff_address, is_diff_deployed, push_di
 use crate::git_helper::GitHelper;
 
 use crate::blockchain::snapshot::diffs::wait_diffs_ready::wait_diffs_until_ready;
-use anyhow::bail;
+use crate::git_helper::push::scheduler::{Dependency, DeployStage, DeployTasks, PushStage};
+use async_trait::async_trait;
+use futures::future::{FutureExt, LocalBoxFuture};
 use std::collections::HashMap;
+use std::sync::Arc;
 use std::vec::Vec;
-use tokio::task::JoinSet;
+use tokio::sync::Semaphore;
 use tracing::Instrument;
-use crate::git_helper::push::get_redeploy_attempts;
-use crate::git_helper::push::parallel_snapshot_upload_support::get_push_chunk;
 
 const MAX_RETRIES_FOR_DIFFS_TO_APPEAR: i32 = 20; // x 3sec
 
@@ -21,7 +22,9 @@ pub struct ParallelDiffsUploadSupport {
     next_parallel_index: u32,
     last_commit_id: git_hash::ObjectId,
     expecting_deployed_contacts_addresses: Vec<String>,
-    pushed_blobs: JoinSet<anyhow::Result<()>>,
+    deploys: DeployTasks,
+    // diff address -> id of the commit the diff belongs to
+    commits: HashMap<String, String>,
 }
 
 #[derive(Clone, Debug)]
@@ -74,7 +77,8 @@ impl ParallelDiffsUploadSupport {
             next_parallel_index: 0,
             last_commit_id: *last_commit_id,
             expecting_deployed_contacts_addresses: vec![],
-            pushed_blobs: JoinSet::new(),
+            deploys: DeployTasks::new(),
+            commits: HashMap::new(),
         }
     }
 
@@ -82,48 +86,11 @@ impl ParallelDiffsUploadSupport {
         &self.expecting_deployed_contacts_addresses
     }
 
-    pub fn push_expected(&mut self, value: String) {
+    pub fn push_expected(&mut self, value: String, commit_id: &git_hash::ObjectId) {
+        self.commits.insert(value.clone(), commit_id.to_string());
         self.expecting_deployed_contacts_addresses.push(value);
     }
 
-    pub async fn start_push(
-        &mut self,
-        context: &mut GitHelper<impl BlockchainService + 'static>,
-    ) -> anyhow::Result<()> {
-        let chunk_size = get_push_chunk();
-        let max_attempts = get_redeploy_attempts();
-        tracing::trace!("Start push of diffs, chunk_size={chunk_size}, max_attempts={max_attempts}");
-        let mut exp: Vec<BlockchainContractAddress> = self.expecting_deployed_contacts_addresses.iter().map(|addr| BlockchainContractAddress::new(addr)).collect();
-        let mut attempt = 0;
-        let mut last_rest_cnt = 0;
-        loop {
-            if attempt == max_attempts {
-                anyhow::bail!("Failed to deploy snapshots. Undeployed snapshots: {exp:?}");
-            }
-            let mut rest = vec![];
-            for chunk in exp.chunks(chunk_size) {
-                for addr in chunk {
-                    self.add_to_push_list(context, &String::from(addr)).await?;
-                }
-                self.finish_push().await?;
-                let mut tmp_rest = wait_diffs_until_ready(&context.blockchain, chunk).await?;
-                rest.append(&mut tmp_rest);
-            }
-            exp = rest;
-            if exp.is_empty() {
-                break;
-            }
-            if exp.len() != last_rest_cnt {
-                attempt = 0;
-            }
-            last_rest_cnt = exp.len();
-
-            attempt += 1;
-        }
-        Ok(())
-    }
-
-
     pub async fn add_to_push_list(
         &mut self,
         context: &mut GitHelper<impl BlockchainService + 'static>,
@@ -142,7 +109,8 @@ impl ParallelDiffsUploadSupport {
 
         // self.expecting_deployed_contacts_addresses
         //     .push(diff_address.clone());
-        self.pushed_blobs.spawn(
+        self.deploys.spawn(
+            diff_address.clone(),
             async move {
                 push_diff(
                     &blockchain,
@@ -190,22 +158,7 @@ impl ParallelDiffsUploadSupport {
                     // } else {
                     //     self.push_expected(diff_contract_address);
                 }
-                self.push_expected(diff_contract_address);
-            }
-        }
-        Ok(())
-    }
-
-    async fn finish_push(&mut self) -> anyhow::Result<()> {
-        while let Some(finished_task) = self.pushed_blobs.join_next().await {
-            match finished_task {
-                Err(e) => {
-                    bail!("diffs join-handler: {}", e);
-                }
-                Ok(Err(e)) => {
-                    bail!("diffs inner: {}", e);
-                }
-                Ok(Ok(_)) => {}
+                self.push_expected(diff_contract_address, &parallel_diff.commit_id);
             }
         }
         Ok(())
@@ -254,7 +207,7 @@ impl ParallelDiffsUploadSupport {
                     // } else {
                     //     self.push_expected(diff_contract_address);
                 }
-                self.push_expected(diff_contract_address);
+                self.push_expected(diff_contract_address, &parallel_diff.commit_id);
             }
         }
         Ok(())
@@ -282,3 +235,49 @@ impl ParallelDiffsUploadSupport {
         }
     }
 }
+
+#[async_trait(?Send)]
+impl<B> DeployStage<B> for ParallelDiffsUploadSupport
+where
+    B: BlockchainService + 'static,
+{
+    fn stage(&self) -> PushStage {
+        PushStage::Diffs
+    }
+
+    fn expected(&self) -> Vec<String> {
+        self.expecting_deployed_contacts_addresses.clone()
+    }
+
+    fn deploys_on_discovery(&self) -> bool {
+        false
+    }
+
+    fn dependencies(&self, address: &str) -> Vec<Dependency> {
+        self.commits
+            .get(address)
+            .map(|commit_id| vec![Dependency::Commit(commit_id.clone())])
+            .unwrap_or_default()
+    }
+
+    fn tasks(&mut self) -> &mut DeployTasks {
+        &mut self.deploys
+    }
+
+    async fn deploy(
+        &mut self,
+        context: &mut GitHelper<B>,
+        address: &str,
+        _push_semaphore: Arc<Semaphore>,
+    ) -> anyhow::Result<()> {
+        self.add_to_push_list(context, &address.to_owned()).await
+    }
+
+    fn wait_ready(
+        &self,
+        blockchain: B,
+        addresses: Vec<BlockchainContractAddress>,
+    ) -> LocalBoxFuture<'static, anyhow::Result<Vec<BlockchainContractAddress>>> {
+        async move { wait_diffs_until_ready(&blockchain, &addresses).await }.boxed_local()
+    }
+}
@@ -3,27 +3,26 @@ use crate::{
     blockchain::{
         contract::wait_contracts_deployed::wait_contracts_deployed,
         tree::{load::check_if_tree_is_ready},
-        user_wallet::WalletError,
         AddrVersion, BlockchainContractAddress, BlockchainService,
     },
     git_helper::{
         push::{
             push_diff::push_initial_snapshot, push_tree::inner_deploy_tree,
-            utilities::retry::default_retry_strategy,
+            utilities::retry::retry_blockchain,
         },
         GitHelper,
     },
 };
-use anyhow::bail;
+use futures::future::{FutureExt, LocalBoxFuture};
 use git_hash::ObjectId;
 use std::time::Duration;
 use std::{collections::HashMap, sync::Arc, vec::Vec};
 use tokio::time::sleep;
 use tokio::{sync::Semaphore, task::JoinSet};
-use tokio_retry::RetryIf;
 use tracing::Instrument;
 use crate::blockchain::tree::load::TreeComponent;
-use crate::git_helper::push::get_redeploy_attempts;
+use crate::git_helper::push::scheduler::{Dependency, DeployStage, DeployTasks, PushStage};
+use async_trait::async_trait;
 
 const WAIT_TREE_READY_MAX_ATTEMPTS: i32 = 4;
 const GOSH_PUSH_CHUNK: &str = "GOSH_PUSH_CHUNK";
@@ -41,7 +40,9 @@ pub fn get_push_chunk() -> usize {
 
 pub struct ParallelSnapshotUploadSupport {
     expecting_deployed_contacts_addresses: Vec<String>,
-    pushed_blobs: JoinSet<anyhow::Result<()>>,
+    deploys: DeployTasks,
+    // snapshot address -> id of the commit it is deployed for
+    commits: HashMap<String, String>,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -84,7 +85,8 @@ impl ParallelSnapshotUploadSupport {
     pub fn new() -> Self {
         Self {
             expecting_deployed_contacts_addresses: vec![],
-            pushed_blobs: JoinSet::new(),
+            deploys: DeployTasks::new(),
+            commits: HashMap::new(),
         }
     }
 
@@ -92,62 +94,11 @@ impl ParallelSnapshotUploadSupport {
         &self.expecting_deployed_contacts_addresses
     }
 
-    pub fn push_expected(&mut self, value: String) {
+    pub fn push_expected(&mut self, value: String, commit_id: String) {
+        self.commits.insert(value.clone(), commit_id);
         self.expecting_deployed_contacts_addresses.push(value);
     }
 
-    pub async fn start_push(
-        &mut self,
-        context: &mut GitHelper<impl BlockchainService + 'static>,
-    ) -> anyhow::Result<()> {
-        let chunk_size = get_push_chunk();
-        let max_attempts = get_redeploy_attempts();
-        tracing::trace!("Start push of snapshots, chunk_size={chunk_size}, max_attempts={max_attempts}");
-        let mut exp: Vec<BlockchainContractAddress> = self.expecting_deployed_contacts_addresses.iter().map(|addr| BlockchainContractAddress::new(addr)).collect();
-        let mut attempt = 0;
-        let mut last_rest_cnt = 0;
-        loop {
-            if attempt == max_attempts {
-                anyhow::bail!("Failed to deploy snapshots. Undeployed snapshots: {exp:?}");
-            }
-            let mut rest = vec![];
-            for chunk in exp.chunks(chunk_size) {
-                for addr in chunk {
-                    self.add_to_push_list(context, &String::from(addr)).await?;
-                }
-                self.finish_push().await?;
-                let mut tmp_rest = wait_snapshots_until_ready(&context.blockchain, chunk).await?;
-                rest.append(&mut tmp_rest);
-            }
-            exp = rest;
-            if exp.is_empty() {
-                break;
-            }
-            if exp.len() != last_rest_cnt {
-                attempt = 0;
-            }
-            last_rest_cnt = exp.len();
-
-            attempt += 1;
-        }
-        Ok(())
-    }
-
-    async fn finish_push(&mut self) -> anyhow::Result<()> {
-        while let Some(finished_task) = self.pushed_blobs.join_next().await {
-            match finished_task {
-                Err(e) => {
-                    bail!("snapshots join-handler: {}", e);
-                }
-                Ok(Err(e)) => {
-                    bail!("snapshots inner: {}", e);
-                }
-                Ok(Ok(_)) => {}
-            }
-        }
-        Ok(())
-    }
-
     #[instrument(level = "info", skip_all)]
     pub async fn add_to_push_list(
         &mut self,
@@ -166,27 +117,73 @@ impl ParallelSnapshotUploadSupport {
         //     .push(snapshot_address.to_string());
 
         let database = context.get_db()?.clone();
-        self.pushed_blobs.spawn(
-            async move {
-                push_initial_snapshot(
-                    blockchain,
-                    repo_address,
-                    dao_address,
-                    remote_network,
-                    snapshot_address,
-                    database,
-                )
-                .await
-            }
+        self.deploys.spawn(
+            snapshot_address.clone(),
+            push_initial_snapshot(
+                blockchain,
+                repo_address,
+                dao_address,
+                remote_network,
+                snapshot_address,
+                database,
+            )
             .instrument(info_span!("tokio::spawn::push_initial_snapshot").or_current()),
         );
         Ok(())
     }
 }
 
+#[async_trait(?Send)]
+impl<B> DeployStage<B> for ParallelSnapshotUploadSupport
+where
+    B: BlockchainService + 'static,
+{
+    fn stage(&self) -> PushStage {
+        PushStage::Snapshots
+    }
+
+    fn expected(&self) -> Vec<String> {
+        self.expecting_deployed_contacts_addresses.clone()
+    }
+
+    fn deploys_on_discovery(&self) -> bool {
+        false
+    }
+
+    fn dependencies(&self, address: &str) -> Vec<Dependency> {
+        self.commits
+            .get(address)
+            .map(|commit_id| vec![Dependency::Commit(commit_id.clone())])
+            .unwrap_or_default()
+    }
+
+    fn tasks(&mut self) -> &mut DeployTasks {
+        &mut self.deploys
+    }
+
+    async fn deploy(
+        &mut self,
+        context: &mut GitHelper<B>,
+        address: &str,
+        _push_semaphore: Arc<Semaphore>,
+    ) -> anyhow::Result<()> {
+        self.add_to_push_list(context, &address.to_owned()).await
+    }
+
+    fn wait_ready(
+        &self,
+        blockchain: B,
+        addresses: Vec<BlockchainContractAddress>,
+    ) -> LocalBoxFuture<'static, anyhow::Result<Vec<BlockchainContractAddress>>> {
+        async move { wait_snapshots_until_ready(&blockchain, &addresses).await }.boxed_local()
+    }
+}
+
 pub struct ParallelCommitUploadSupport {
     expecting_deployed_contacts_addresses: Vec<String>,
-    pushed_blobs: JoinSet<anyhow::Result<()>>,
+    deploys: DeployTasks,
+    // commit address -> addresses of the trees of the commit
+    trees: HashMap<String, Vec<String>>,
 }
 
 #[derive(Clone, Debug)]
@@ -221,7 +218,8 @@ impl ParallelCommitUploadSupport {
     pub fn new() -> Self {
         Self {
             expecting_deployed_contacts_addresses: vec![],
-            pushed_blobs: JoinSet::new(),
+            deploys: DeployTasks::new(),
+            trees: HashMap::new(),
         }
     }
 
@@ -233,12 +231,32 @@ impl ParallelCommitUploadSupport {
         self.expecting_deployed_contacts_addresses.push(value);
     }
 
+    /// The commit is deployed after its trees are ready
+    pub fn depends_on_trees(&mut self, commit_address: &str, trees: Vec<String>) {
+        self.trees.insert(commit_address.to_owned(), trees);
+    }
+
     #[instrument(level = "info", skip_all)]
     pub async fn add_to_push_list(
         &mut self,
         context: &mut GitHelper<impl BlockchainService + 'static>,
         commit_address: String,
         push_semaphore: Arc<Semaphore>,
+    ) -> anyhow::Result<()> {
+        if context.get_db()?.is_confirmed(&commit_address)? {
+            tracing::trace!("Commit {commit_address} is confirmed in the push journal, skip");
+            return Ok(());
+        }
+        self.expecting_deployed_contacts_addresses
+            .push(commit_address.clone());
+        self.spawn_push(context, commit_address, push_semaphore).await
+    }
+
+    async fn spawn_push(
+        &mut self,
+        context: &mut GitHelper<impl BlockchainService + 'static>,
+        commit_address: String,
+        push_semaphore: Arc<Semaphore>,
     ) -> anyhow::Result<()> {
         let blockchain = context.blockchain.clone();
         let dao_address: BlockchainContractAddress = context.dao_addr.clone();
@@ -247,31 +265,17 @@ impl ParallelCommitUploadSupport {
 
         tracing::trace!("Start push of commit: address: {commit_address:?}");
 
-        self.expecting_deployed_contacts_addresses
-            .push(commit_address.clone());
-
         let permit = push_semaphore.acquire_owned().await?;
 
-        let condition = |e: &anyhow::Error| {
-            if e.is::<WalletError>() {
-                false
-            } else {
-                tracing::warn!("Attempt failed with {:#?}", e);
-                true
-            }
-        };
-
-        self.pushed_blobs.spawn(
+        let address = commit_address.clone();
+        self.deploys.spawn(
+            address,
             async move {
-                let res = RetryIf::spawn(
-                    default_retry_strategy(),
-                    || async {
-                        blockchain
-                            .push_commit(&commit_address, &remote, &dao_address, database.clone())
-                            .await
-                    },
-                    condition,
-                )
+                let res = retry_blockchain(|| async {
+                    blockchain
+                        .push_commit(&commit_address, &remote, &dao_address, database.clone())
+                        .await
+                })
                 .await;
 
                 drop(permit);
@@ -281,44 +285,62 @@ impl ParallelCommitUploadSupport {
         );
         Ok(())
     }
+}
 
-    pub async fn wait_all_commits<B>(
+#[async_trait(?Send)]
+impl<B> DeployStage<B> for ParallelCommitUploadSupport
+where
+    B: BlockchainService + 'static,
+{
+    fn stage(&self) -> PushStage {
+        PushStage::Commits
+    }
+
+    fn expected(&self) -> Vec<String> {
+        self.expecting_deployed_contacts_addresses.clone()
+    }
+
+    fn deploys_on_discovery(&self) -> bool {
+        true
+    }
+
+    fn dependencies(&self, address: &str) -> Vec<Dependency> {
+        self.trees
+            .get(address)
+            .map(|trees| {
+                trees
+                    .iter()
+                    .map(|tree| Dependency::Contract(PushStage::Trees, tree.clone()))
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    fn tasks(&mut self) -> &mut DeployTasks {
+        &mut self.deploys
+    }
+
+    async fn deploy(
         &mut self,
+        context: &mut GitHelper<B>,
+        address: &str,
+        push_semaphore: Arc<Semaphore>,
+    ) -> anyhow::Result<()> {
+        self.spawn_push(context, address.to_owned(), push_semaphore).await
+    }
+
+    fn wait_ready(
+        &self,
         blockchain: B,
-    ) -> anyhow::Result<Vec<BlockchainContractAddress>>
-    where
-        B: BlockchainService + 'static,
-    {
-        // TODO:
-        // - Let user know if we reached it
-        // - Make it configurable
-        let addresses = self
-            .expecting_deployed_contacts_addresses
-            .iter()
-            .map(|addr| BlockchainContractAddress::new(addr))
-            .collect::<Vec<BlockchainContractAddress>>();
-        // tracing::debug!(
-        //     "Expecting the following commit contracts to be deployed: {:?}",
-        //     addresses
-        // );
-        while let Some(finished_task) = self.pushed_blobs.join_next().await {
-            match finished_task {
-                Err(e) => {
-                    bail!("commits join-handler: {}", e);
-                }
-                Ok(Err(e)) => {
-                    bail!("commits inner: {}", e);
-                }
-                Ok(Ok(_)) => {}
-            }
-        }
-        wait_contracts_deployed(&blockchain, &addresses).await
+        addresses: Vec<BlockchainContractAddress>,
+    ) -> LocalBoxFuture<'static, anyhow::Result<Vec<BlockchainContractAddress>>> {
+        async move { wait_contracts_deployed(&blockchain, &addresses).await }.boxed_local()
     }
 }
 
 pub struct ParallelTreeUploadSupport {
     expecting_deployed_contacts_addresses: Vec<String>,
-    pushed_blobs: JoinSet<anyhow::Result<()>>,
+    deploys: DeployTasks,
     pub tree_item_to_base_commit_cache: HashMap<String, String>,
 }
 
@@ -349,7 +371,7 @@ impl ParallelTreeUploadSupport {
     pub fn new() -> Self {
         Self {
             expecting_deployed_contacts_addresses: vec![],
-            pushed_blobs: JoinSet::new(),
+            deploys: DeployTasks::new(),
             tree_item_to_base_commit_cache: HashMap::new(),
         }
     }
@@ -368,46 +390,46 @@ impl ParallelTreeUploadSupport {
         context: &mut GitHelper<impl BlockchainService + 'static>,
         tree_address: String,
         push_semaphore: Arc<Semaphore>,
+    ) -> anyhow::Result<()> {
+        if context.get_db()?.is_confirmed(&tree_address)? {
+            tracing::trace!("Tree {tree_address} is confirmed in the push journal, skip");
+            return Ok(());
+        }
+        self.expecting_deployed_contacts_addresses
+            .push(tree_address.clone());
+        self.spawn_push(context, tree_address, push_semaphore).await
+    }
+
+    async fn spawn_push(
+        &mut self,
+        context: &mut GitHelper<impl BlockchainService + 'static>,
+        tree_address: String,
+        push_semaphore: Arc<Semaphore>,
     ) -> anyhow::Result<()> {
         let blockchain = context.blockchain.clone();
         let dao_address: BlockchainContractAddress = context.dao_addr.clone();
         let repo_address = context.repo_addr.clone();
         let remote_network = context.remote.network.clone();
         let repo = context.remote.repo.clone();
-
-        self.expecting_deployed_contacts_addresses
-            .push(tree_address.clone());
+        let database = context.get_db()?.clone();
 
         let permit = push_semaphore.clone().acquire_owned().await?;
 
-        let condition = |e: &anyhow::Error| {
-            if e.is::<WalletError>() {
-                false
-            } else {
-                tracing::warn!("Attempt failed with {:#?}", e);
-                true
-            }
-        };
-
-        let database = context.get_db()?.clone();
-
-        self.pushed_blobs.spawn(
+        let address = tree_address.clone();
+        self.deploys.spawn(
+            address,
             async move {
-                let res = RetryIf::spawn(
-                    default_retry_strategy(),
-                    || async {
-                        inner_deploy_tree(
-                            &blockchain,
-                            &remote_network,
-                            &dao_address,
-                            &repo,
-                            &tree_address,
-                            database.clone(),
-                        )
-                        .await
-                    },
-                    condition,
-                )
+                let res = retry_blockchain(|| async {
+                    inner_deploy_tree(
+                        &blockchain,
+                        &remote_network,
+                        &dao_address,
+                        &repo,
+                        &tree_address,
+                        database.clone(),
+                    )
+                    .await
+                })
                 .await;
                 drop(permit);
                 res
@@ -416,40 +438,48 @@ impl ParallelTreeUploadSupport {
         );
         Ok(())
     }
+}
+
+#[async_trait(?Send)]
+impl<B> DeployStage<B> for ParallelTreeUploadSupport
+where
+    B: BlockchainService + 'static,
+{
+    fn stage(&self) -> PushStage {
+        PushStage::Trees
+    }
+
+    fn expected(&self) -> Vec<String> {
+        self.expecting_deployed_contacts_addresses.clone()
+    }
+
+    fn deploys_on_discovery(&self) -> bool {
+        true
+    }
+
+    fn tasks(&mut self) -> &mut DeployTasks {
+        &mut self.deploys
+    }
 
-    pub async fn wait_all_trees<B>(
+    async fn deploy(
         &mut self,
+        context: &mut GitHelper<B>,
+        address: &str,
+        push_semaphore: Arc<Semaphore>,
+    ) -> anyhow::Result<()> {
+        self.spawn_push(context, address.to_owned(), push_semaphore).await
+    }
+
+    fn wait_ready(
+        &self,
         blockchain: B,
-    ) -> anyhow::Result<Vec<BlockchainContractAddress>>
-    where
-        B: BlockchainService + 'static,
-    {
-        // TODO:
-        // - Let user know if we reached it
-        // - Make it configurable
-        let addresses = self
-            .expecting_deployed_contacts_addresses
-            .iter()
-            .map(|addr| BlockchainContractAddress::new(addr))
-            .collect::<Vec<BlockchainContractAddress>>();
-        // tracing::debug!(
-        //     "Expecting the following tree contracts to be deployed: {:?}",
-        //     addresses
-        // );
-        while let Some(finished_task) = self.pushed_blobs.join_next().await {
-            match finished_task {
-                Err(e) => {
-                    bail!("trees join-handler: {}", e);
-                }
-                Ok(Err(e)) => {
-                    bail!("trees inner: {}", e);
-                }
-                Ok(Ok(_)) => {}
-            }
+        addresses: Vec<BlockchainContractAddress>,
+    ) -> LocalBoxFuture<'static, anyhow::Result<Vec<BlockchainContractAddress>>> {
+        async move {
+            let _ = wait_contracts_deployed(&blockchain, &addresses).await?;
+            wait_trees_until_ready(&blockchain, addresses).await
         }
-        let _ = wait_contracts_deployed(&blockchain, &addresses).await?;
-
-        wait_trees_until_ready(&blockchain, addresses).await
+        .boxed_local()
     }
 }
 
@@ -1,40 +1,64 @@
-use crate::blockchain::user_wallet::{UserWallet, WalletError};
-use crate::ipfs::{build_ipfs, IpfsError};
+use crate::blockchain::user_wallet::UserWallet;
+use crate::config::retry::{self, Operation};
+use crate::ipfs::build_ipfs;
 use std::sync::Arc;
 use std::time::Duration;
 use tokio::time::sleep;
 
 use crate::database::GoshDB;
+use crate::utilities::stats;
 use crate::{
+    abi::bindings::diff,
     blockchain::{
-        contract::{ContractRead, GoshContract},
+        contract::{GoshContract, TypedContract},
         gosh_abi,
-        snapshot::{
-            save::{Diff, GetDiffAddrResult, GetVersionResult},
-            PushDiffCoordinate,
-        },
+        snapshot::{save::Diff, PushDiffCoordinate},
         tvm_hash, BlockchainContractAddress, BlockchainService, EverClient, EMPTY_BLOB_SHA1,
         EMPTY_BLOB_SHA256,
     },
-    ipfs::{service::FileSave, IpfsService},
+    ipfs::{
+        cid_index::{cid_index, content_key},
+        service::FileSave,
+        IpfsConfig, IpfsService,
+    },
 };
-use tokio_retry::RetryIf;
 use ton_client::utils::compress_zstd;
 
 use super::is_going_to_ipfs;
-use super::utilities::retry::default_retry_strategy;
+use super::utilities::retry::{is_retryable, retry_blockchain};
 
 // const PUSH_DIFF_MAX_TRIES: i32 = 3;
 // const PUSH_SNAPSHOT_MAX_TRIES: i32 = 3;
 
 const WAIT_FOR_DELETE_SNAPSHOT_TRIES: i32 = 20;
 
+#[derive(Debug)]
 enum BlobDst {
     Ipfs(String),
     Patch(String),
     SetContent(String),
 }
 
+/// Content kept on chain is replaced as a whole when the previous one is in
+/// IPFS or when the patch is not smaller, which is common for binaries
+fn on_chain_blob_dst(
+    compressed_patch: &[u8],
+    new_snapshot_content: &[u8],
+    is_previous_oversized: bool,
+) -> anyhow::Result<BlobDst> {
+    let compressed = compress_zstd(new_snapshot_content, None)?;
+    if is_previous_oversized || compressed_patch.len() >= compressed.len() {
+        tracing::trace!(
+            "set content: patch={}, content={}, is_previous_oversized={is_previous_oversized}",
+            compressed_patch.len(),
+            compressed.len()
+        );
+        Ok(BlobDst::SetContent(hex::encode(compressed)))
+    } else {
+        Ok(BlobDst::Patch(hex::encode(compressed_patch)))
+    }
+}
+
 #[instrument(level = "info", skip_all)]
 pub async fn push_diff<'a, B>(
     blockchain: &B,
@@ -55,31 +79,18 @@ where
     let blockchain = blockchain.clone();
     let last_commit_id = *last_commit_id;
 
-    let condition = |e: &anyhow::Error| {
-        if e.is::<WalletError>() || e.is::<IpfsError>() {
-            false
-        } else {
-            tracing::warn!("Attempt failed with {:#?}", e);
-            true
-        }
-    };
-
-    RetryIf::spawn(
-        default_retry_strategy(),
-        || async {
-            inner_push_diff(
-                &blockchain,
-                repo_name.to_string(),
-                wallet.clone(),
-                &ipfs_endpoint,
-                &last_commit_id,
-                &diff_address,
-                database.clone(),
-            )
-            .await
-        },
-        condition,
-    )
+    retry_blockchain(|| async {
+        inner_push_diff(
+            &blockchain,
+            repo_name.to_string(),
+            wallet.clone(),
+            &ipfs_endpoint,
+            &last_commit_id,
+            &diff_address,
+            database.clone(),
+        )
+        .await
+    })
     .await?;
     Ok(())
 }
@@ -115,12 +126,7 @@ pub async fn inner_push_diff(
     let blob_dst = {
         let is_going_to_ipfs = is_going_to_ipfs(new_snapshot_content);
         if !is_going_to_ipfs {
-            if is_previous_oversized {
-                let compressed = compress_zstd(new_snapshot_content, None)?;
-                BlobDst::SetContent(hex::encode(compressed))
-            } else {
-                BlobDst::Patch(hex::encode(diff))
-            }
+            on_chain_blob_dst(&diff, new_snapshot_content, is_previous_oversized)?
         } else {
             tracing::debug!("inner_push_diff->save_data_to_ipfs");
             let ipfs = save_data_to_ipfs(&ipfs_client, new_snapshot_content)
@@ -190,6 +196,9 @@ pub async fn inner_push_diff(
         },
     };
 
+    if let Some(patch) = &diff.patch {
+        stats::record_bytes_uploaded(patch.len() / 2);
+    }
     if diff.ipfs.is_some() {
         tracing::debug!("push_diff: {:?}", diff);
     } else {
@@ -217,12 +226,21 @@ pub async fn save_data_to_ipfs(
     ipfs_client: &IpfsService,
     content: &[u8],
 ) -> anyhow::Result<String> {
-    tracing::trace!("Uploading blob to IPFS");
-    let content: Vec<u8> = ton_client::utils::compress_zstd(content, None)?;
-    let content = base64::encode(&content);
-    let content = content.as_bytes().to_vec();
-
-    ipfs_client.save_blob(&content).await
+    let key = content_key(ipfs_client.ipfs_endpoint(), content);
+    cid_index()
+        .get_or_upload(
+            &key,
+            || async {
+                tracing::trace!("Uploading blob to IPFS");
+                let content: Vec<u8> = ton_client::utils::compress_zstd(content, None)?;
+                let content = base64::encode(&content);
+                let content = content.as_bytes().to_vec();
+
+                ipfs_client.save_blob(&content).await
+            },
+            |cid| async move { ipfs_client.is_pinned(&cid).await },
+        )
+        .await
 }
 
 #[instrument(level = "info", skip_all)]
@@ -231,9 +249,10 @@ pub async fn is_diff_deployed(
     contract_address: &BlockchainContractAddress,
 ) -> anyhow::Result<bool> {
     tracing::trace!("is_diff_deployed: contract_address={contract_address}");
-    let diff_contract = GoshContract::new(contract_address, gosh_abi::DIFF);
-    let result: anyhow::Result<GetVersionResult> =
-        diff_contract.read_state(context, "getVersion", None).await;
+    let diff_contract = TypedContract::<diff::Contract>::new(contract_address);
+    let result = diff_contract
+        .read_state_fn(context, diff::GetVersion {})
+        .await;
     tracing::trace!("is_diff_deployed result: {:?}", result);
     Ok(result.is_ok())
 }
@@ -246,15 +265,14 @@ pub async fn diff_address(
     diff_coordinate: &PushDiffCoordinate,
 ) -> anyhow::Result<BlockchainContractAddress> {
     tracing::trace!("diff_address: repo_contract.address={}, last_commit_id={last_commit_id}, diff_coordinate={diff_coordinate:?}", repo_contract.address);
-    let params = serde_json::json!({
-        "commitName": last_commit_id.to_string(),
-        "index1": diff_coordinate.index_of_parallel_thread,
-        "index2": diff_coordinate.order_of_diff_in_the_parallel_thread,
-    });
-    let result: GetDiffAddrResult = repo_contract
-        .run_static(&context, "getDiffAddr", Some(params))
-        .await?;
-    Ok(result.address)
+    crate::blockchain::address::diff_address(
+        context,
+        repo_contract,
+        &last_commit_id.to_string(),
+        diff_coordinate.index_of_parallel_thread,
+        diff_coordinate.order_of_diff_in_the_parallel_thread,
+    )
+    .await
 }
 
 #[instrument(level = "info", skip_all)]
@@ -311,6 +329,7 @@ pub async fn push_new_branch_snapshot(
         tracing::trace!("compressed to {} size", compressed.len());
         (hex::encode(compressed), None)
     };
+    stats::record_bytes_uploaded(content.len() / 2);
 
     blockchain
         .deploy_new_snapshot(
@@ -351,29 +370,60 @@ where
     let wallet = blockchain.user_wallet(&dao_addr, &remote_network).await?;
 
     let condition = |e: &anyhow::Error| {
-        if e.is::<WalletError>() {
-            false
-        } else {
-            tracing::debug!("inner_push_snapshot error <path: {file_path}>");
-            true
-        }
+        tracing::debug!("inner_push_snapshot error <path: {file_path}>");
+        is_retryable(e)
     };
 
-    RetryIf::spawn(
-        default_retry_strategy(),
-        || async {
-            blockchain
-                .deploy_new_snapshot(
-                    &wallet,
-                    repo_addr.clone(),
-                    commit_id.clone(),
-                    file_path.clone(),
-                    content.clone(),
-                    ipfs.clone(),
-                )
-                .await
-        },
-        condition,
-    )
-    .await
+    let policy = retry::policy(Operation::Blockchain);
+    policy
+        .run(
+            || async {
+                blockchain
+                    .deploy_new_snapshot(
+                        &wallet,
+                        repo_addr.clone(),
+                        commit_id.clone(),
+                        file_path.clone(),
+                        content.clone(),
+                        ipfs.clone(),
+                    )
+                    .await
+            },
+            condition,
+        )
+        .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::git_helper::push::utilities::create_blob_patch;
+
+    fn binary_blob(len: usize, mut seed: u32) -> Vec<u8> {
+        (0..len)
+            .map(|_| {
+                seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+                (seed >> 16) as u8
+            })
+            .collect()
+    }
+
+    fn blob_dst(original: &[u8], modified: &[u8]) -> BlobDst {
+        let patch = compress_zstd(&create_blob_patch(original, modified), None).unwrap();
+        on_chain_blob_dst(&patch, modified, false).unwrap()
+    }
+
+    #[test]
+    fn ensure_rewritten_binary_replaces_content() {
+        let original = binary_blob(4 * 1024, 1);
+        let rewritten = binary_blob(4 * 1024, 2);
+        assert!(matches!(
+            blob_dst(&original, &rewritten),
+            BlobDst::SetContent(_)
+        ));
+
+        let mut modified = original.clone();
+        modified[100] ^= 0xff;
+        assert!(matches!(blob_dst(&original, &modified), BlobDst::Patch(_)));
+    }
 }
@@ -0,0 +1,758 @@
+//! Deploys the contracts of the push as a DAG: tree -> commit -> diff and
+//! snapshot. A node is deployed as soon as its dependencies are ready and is
+//! retried on its own, setCommit is sent only when every node is ready.
+
+use crate::blockchain::{get_commit_address, BlockchainContractAddress, BlockchainService};
+use crate::config::retry::{self, Operation, RetryPolicy};
+use crate::git_helper::push::fee_budget::FeeBudget;
+use crate::git_helper::push::parallel_snapshot_upload_support::get_push_chunk;
+use crate::git_helper::GitHelper;
+use crate::utilities::stats;
+use async_trait::async_trait;
+use futures::future::{self, LocalBoxFuture};
+use futures::stream::{FuturesUnordered, StreamExt};
+use futures::FutureExt;
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap, HashSet, VecDeque};
+use std::fmt;
+use std::future::Future;
+use std::sync::Arc;
+use std::time::Instant;
+use tokio::sync::Semaphore;
+use tokio::task::JoinSet;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PushStage {
+    Trees,
+    Commits,
+    Diffs,
+    Snapshots,
+}
+
+impl fmt::Display for PushStage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            PushStage::Trees => "tree",
+            PushStage::Commits => "commit",
+            PushStage::Diffs => "diff",
+            PushStage::Snapshots => "snapshot",
+        };
+        write!(f, "{name}")
+    }
+}
+
+type NodeKey = (PushStage, String);
+
+/// Node that has to be ready before a contract is deployed
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Dependency {
+    /// Contract of another stage
+    Contract(PushStage, String),
+    /// Commit contract of the commit with the id
+    Commit(String),
+}
+
+/// Deployments spawned by a stage
+pub struct DeployTasks {
+    tasks: JoinSet<(String, anyhow::Result<()>)>,
+    addresses: HashSet<String>,
+}
+
+impl DeployTasks {
+    pub fn new() -> Self {
+        Self {
+            tasks: JoinSet::new(),
+            addresses: HashSet::new(),
+        }
+    }
+
+    pub fn spawn<F>(&mut self, address: String, deploy: F)
+    where
+        F: Future<Output = anyhow::Result<()>> + Send + 'static,
+    {
+        self.addresses.insert(address.clone());
+        self.tasks.spawn(async move { (address, deploy.await) });
+    }
+
+    pub fn contains(&self, address: &str) -> bool {
+        self.addresses.contains(address)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tasks.is_empty()
+    }
+
+    /// Waits for the next finished deployment
+    pub async fn join_next(&mut self) -> anyhow::Result<Option<(String, anyhow::Result<()>)>> {
+        match self.tasks.join_next().await {
+            None => Ok(None),
+            Some(Err(e)) => anyhow::bail!("deploy join-handler: {e}"),
+            Some(Ok((address, result))) => {
+                self.addresses.remove(&address);
+                Ok(Some((address, result)))
+            }
+        }
+    }
+}
+
+/// Set of contracts deployed on one stage of the push
+#[async_trait(?Send)]
+pub trait DeployStage<B>
+where
+    B: BlockchainService + 'static,
+{
+    fn stage(&self) -> PushStage;
+
+    /// Addresses of all contracts expected on this stage
+    fn expected(&self) -> Vec<String>;
+
+    /// Stages that start deployment while objects are discovered
+    /// don't deploy their nodes for the first time
+    fn deploys_on_discovery(&self) -> bool;
+
+    /// Nodes that must be ready before the contract is deployed
+    fn dependencies(&self, _address: &str) -> Vec<Dependency> {
+        vec![]
+    }
+
+    fn tasks(&mut self) -> &mut DeployTasks;
+
+    /// Spawns deployment of a single contract
+    async fn deploy(
+        &mut self,
+        context: &mut GitHelper<B>,
+        address: &str,
+        push_semaphore: Arc<Semaphore>,
+    ) -> anyhow::Result<()>;
+
+    /// Returns contracts that are not deployed or not ready yet.
+    /// The check doesn't borrow the stage, so deployments go on while it runs
+    fn wait_ready(
+        &self,
+        blockchain: B,
+        addresses: Vec<BlockchainContractAddress>,
+    ) -> LocalBoxFuture<'static, anyhow::Result<Vec<BlockchainContractAddress>>>;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum NodeState {
+    Waiting,
+    Deploying,
+    Checking,
+    Ready,
+    Failed,
+}
+
+const NODE_STATES: usize = 5;
+
+#[derive(Debug)]
+struct Node {
+    key: NodeKey,
+    state: NodeState,
+    attempts: i32,
+    next_attempt_at: Instant,
+    /// Dependencies that are not ready yet
+    blocked_by: HashSet<usize>,
+    dependents: Vec<usize>,
+    /// Node is in the ready queue or in the retries
+    queued: bool,
+}
+
+/// Nodes are queued when they become deployable, entries of the nodes that
+/// were blocked or changed their state since then are dropped when taken
+#[derive(Debug, Default)]
+struct PushGraph {
+    nodes: Vec<Node>,
+    index: HashMap<NodeKey, usize>,
+    counts: [usize; NODE_STATES],
+    ready: VecDeque<usize>,
+    retries: BinaryHeap<Reverse<(Instant, usize)>>,
+}
+
+impl PushGraph {
+    fn add_node(&mut self, key: NodeKey) -> usize {
+        if let Some(id) = self.index.get(&key) {
+            return *id;
+        }
+        let id = self.nodes.len();
+        self.index.insert(key.clone(), id);
+        self.nodes.push(Node {
+            key,
+            state: NodeState::Waiting,
+            attempts: 0,
+            next_attempt_at: Instant::now(),
+            blocked_by: HashSet::new(),
+            dependents: vec![],
+            queued: false,
+        });
+        self.counts[NodeState::Waiting as usize] += 1;
+        self.enqueue(id);
+        id
+    }
+
+    fn id(&self, key: &NodeKey) -> Option<usize> {
+        self.index.get(key).copied()
+    }
+
+    /// Contracts that are not nodes of the DAG are deployed already
+    fn add_dependency(&mut self, id: usize, dependency: &NodeKey) {
+        let dependency = match self.id(dependency) {
+            Some(dependency) if dependency != id => dependency,
+            _ => return,
+        };
+        if self.nodes[dependency].state != NodeState::Ready
+            && self.nodes[id].blocked_by.insert(dependency)
+        {
+            self.nodes[dependency].dependents.push(id);
+        }
+    }
+
+    fn is_deployable(&self, id: usize) -> bool {
+        let node = &self.nodes[id];
+        node.state == NodeState::Waiting && node.blocked_by.is_empty()
+    }
+
+    fn enqueue(&mut self, id: usize) {
+        if self.nodes[id].queued || !self.is_deployable(id) {
+            return;
+        }
+        let node = &mut self.nodes[id];
+        node.queued = true;
+        if node.next_attempt_at <= Instant::now() {
+            self.ready.push_back(id);
+        } else {
+            self.retries.push(Reverse((node.next_attempt_at, id)));
+        }
+    }
+
+    fn set_state(&mut self, id: usize, state: NodeState) {
+        let node = &mut self.nodes[id];
+        self.counts[node.state as usize] -= 1;
+        self.counts[state as usize] += 1;
+        node.state = state;
+    }
+
+    /// Takes up to `limit` deployable nodes that are due at `now`
+    /// and marks them as deploying
+    fn start_deploying(&mut self, now: Instant, limit: usize) -> Vec<usize> {
+        while let Some(Reverse((at, id))) = self.retries.peek().copied() {
+            if at > now {
+                break;
+            }
+            self.retries.pop();
+            self.ready.push_back(id);
+        }
+        let mut started = vec![];
+        while started.len() < limit {
+            let id = match self.ready.pop_front() {
+                Some(id) => id,
+                None => break,
+            };
+            self.nodes[id].queued = false;
+            if self.is_deployable(id) {
+                self.nodes[id].attempts += 1;
+                self.set_state(id, NodeState::Deploying);
+                started.push(id);
+            }
+        }
+        started
+    }
+
+    fn next_attempt_at(&self) -> Option<Instant> {
+        if !self.ready.is_empty() {
+            return Some(Instant::now());
+        }
+        self.retries.peek().map(|Reverse((at, _))| *at)
+    }
+
+    fn count(&self, state: NodeState) -> usize {
+        self.counts[state as usize]
+    }
+
+    fn is_finished(&self) -> bool {
+        self.count(NodeState::Ready) + self.count(NodeState::Failed) == self.nodes.len()
+    }
+
+    fn set_ready(&mut self, id: usize) {
+        self.set_state(id, NodeState::Ready);
+        for dependent in std::mem::take(&mut self.nodes[id].dependents) {
+            self.nodes[dependent].blocked_by.remove(&id);
+            self.enqueue(dependent);
+        }
+    }
+
+    /// Deploys the node again at `at`
+    fn retry_at(&mut self, id: usize, at: Instant) {
+        self.nodes[id].next_attempt_at = at;
+        self.set_state(id, NodeState::Waiting);
+        self.enqueue(id);
+    }
+
+    /// Fails the node and everything that depends on it.
+    /// Returns the dependents that will not be deployed
+    fn set_failed(&mut self, id: usize) -> Vec<usize> {
+        self.set_state(id, NodeState::Failed);
+        let mut skipped = vec![];
+        let mut queue = self.nodes[id].dependents.clone();
+        while let Some(dependent) = queue.pop() {
+            if matches!(
+                self.nodes[dependent].state,
+                NodeState::Ready | NodeState::Failed
+            ) {
+                continue;
+            }
+            self.set_state(dependent, NodeState::Failed);
+            queue.extend(self.nodes[dependent].dependents.iter().copied());
+            skipped.push(dependent);
+        }
+        skipped
+    }
+}
+
+#[derive(Debug)]
+pub struct NodeFailure {
+    pub stage: PushStage,
+    pub address: String,
+    pub attempts: i32,
+    pub reason: String,
+}
+
+impl fmt::Display for NodeFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {} failed after {} attempt(s): {}",
+            self.stage, self.address, self.attempts, self.reason
+        )
+    }
+}
+
+enum Event {
+    Deployed(
+        PushStage,
+        anyhow::Result<Option<(String, anyhow::Result<()>)>>,
+    ),
+    Checked(
+        PushStage,
+        Vec<usize>,
+        anyhow::Result<Vec<BlockchainContractAddress>>,
+    ),
+    WakeUp,
+}
+
+pub struct PushScheduler {
+    push_semaphore: Arc<Semaphore>,
+    retry_policy: RetryPolicy,
+    max_attempts: i32,
+    chunk_size: usize,
+    failures: Vec<NodeFailure>,
+    skipped: Vec<NodeKey>,
+}
+
+impl PushScheduler {
+    pub fn new(push_semaphore: Arc<Semaphore>) -> Self {
+        let retry_policy = retry::policy(Operation::Redeploy);
+        // the first deploy is an attempt too
+        let attempts = retry_policy.max_retries.saturating_add(1);
+        let max_attempts = i32::try_from(attempts).unwrap_or(i32::MAX);
+        Self {
+            push_semaphore,
+            retry_policy,
+            max_attempts,
+            chunk_size: get_push_chunk(),
+            failures: vec![],
+            skipped: vec![],
+        }
+    }
+
+    /// Deploys all nodes of the stages in the order of their dependencies.
+    /// Nodes that ran out of attempts are remembered and don't stop the
+    /// independent nodes, check them with `ensure_all_deployed`.
+    #[instrument(level = "info", skip_all)]
+    pub async fn run<B>(
+        &mut self,
+        context: &mut GitHelper<B>,
+        stages: &mut [&mut dyn DeployStage<B>],
+        fee_budget: Option<&FeeBudget>,
+    ) -> anyhow::Result<()>
+    where
+        B: BlockchainService + 'static,
+    {
+        let mut to_check: HashMap<PushStage, Vec<usize>> = HashMap::new();
+        let mut graph = self.build_graph(context, stages, &mut to_check).await?;
+        tracing::trace!(
+            "run: nodes={}, max_attempts={}, chunk_size={}",
+            graph.nodes.len(),
+            self.max_attempts,
+            self.chunk_size
+        );
+
+        let blockchain = context.blockchain.clone();
+        let mut checks = FuturesUnordered::new();
+        let mut checking_stages = HashSet::new();
+        loop {
+            let deploying = graph.count(NodeState::Deploying);
+            let limit = self.chunk_size.saturating_sub(deploying);
+            for id in graph.start_deploying(Instant::now(), limit) {
+                let (kind, address) = graph.nodes[id].key.clone();
+                let stage = stages
+                    .iter_mut()
+                    .find(|stage| stage.stage() == kind)
+                    .ok_or_else(|| {
+                        anyhow::format_err!("{kind} {address} has no stage to deploy")
+                    })?;
+                stage
+                    .deploy(context, &address, self.push_semaphore.clone())
+                    .await?;
+            }
+
+            // a single batch of a stage is checked at a time,
+            // nodes deployed meanwhile are checked with the next one
+            for stage in stages.iter() {
+                let kind = stage.stage();
+                if checking_stages.contains(&kind) {
+                    continue;
+                }
+                let batch = match to_check.remove(&kind) {
+                    Some(batch) if !batch.is_empty() => batch,
+                    _ => continue,
+                };
+                let addresses = batch
+                    .iter()
+                    .map(|id| BlockchainContractAddress::new(&graph.nodes[*id].key.1))
+                    .collect();
+                let check = stage.wait_ready(blockchain.clone(), addresses);
+                checks.push(async move { (kind, batch, check.await) }.boxed_local());
+                checking_stages.insert(kind);
+            }
+
+            if graph.is_finished() {
+                break;
+            }
+            let next_attempt_at = graph.next_attempt_at();
+            let is_deploying = stages.iter_mut().any(|stage| !stage.tasks().is_empty());
+            if !is_deploying && checks.is_empty() && next_attempt_at.is_none() {
+                anyhow::bail!(
+                    "Push scheduler stalled with {} contract(s) not deployed",
+                    graph.nodes.len() - graph.count(NodeState::Ready)
+                );
+            }
+            // due nodes wait for a free slot, deployments that finish free it
+            let next_attempt_at =
+                next_attempt_at.filter(|_| graph.count(NodeState::Deploying) < self.chunk_size);
+
+            let event = {
+                let deployed: Vec<_> = stages
+                    .iter_mut()
+                    .filter_map(|stage| {
+                        let kind = stage.stage();
+                        let tasks = stage.tasks();
+                        if tasks.is_empty() {
+                            None
+                        } else {
+                            Some(Box::pin(async move { (kind, tasks.join_next().await) }))
+                        }
+                    })
+                    .collect();
+                let next_deployed = async move {
+                    if deployed.is_empty() {
+                        return future::pending().await;
+                    }
+                    let (deployed, _, _) = future::select_all(deployed).await;
+                    deployed
+                };
+                let wake_up = async move {
+                    match next_attempt_at {
+                        Some(at) => tokio::time::sleep_until(at.into()).await,
+                        None => future::pending().await,
+                    }
+                };
+                tokio::select! {
+                    (kind, result) = next_deployed => Event::Deployed(kind, result),
+                    Some((kind, batch, result)) = checks.next() => {
+                        Event::Checked(kind, batch, result)
+                    }
+                    _ = wake_up => Event::WakeUp,
+                }
+            };
+
+            match event {
+                Event::Deployed(kind, result) => {
+                    let (address, result) = match result? {
+                        Some(deployed) => deployed,
+                        None => continue,
+                    };
+                    let id = match graph.id(&(kind, address.clone())) {
+                        Some(id) => id,
+                        None => {
+                            tracing::trace!("{kind} {address} is not a node of the push, skip");
+                            continue;
+                        }
+                    };
+                    match result {
+                        Ok(()) => {
+                            graph.set_state(id, NodeState::Checking);
+                            to_check.entry(kind).or_default().push(id);
+                        }
+                        Err(e) => {
+                            tracing::trace!("{kind} {address} inner: {e}");
+                            self.attempt_failed(&mut graph, id, format!("{e}"));
+                        }
+                    }
+                }
+                Event::Checked(kind, batch, result) => {
+                    checking_stages.remove(&kind);
+                    let not_ready = result?;
+                    context.get_db()?.confirm_deployed(
+                        batch.iter().map(|id| graph.nodes[*id].key.1.clone()),
+                        &not_ready,
+                    )?;
+                    let not_ready: HashSet<String> = not_ready.iter().map(String::from).collect();
+                    for id in batch {
+                        if not_ready.contains(&graph.nodes[id].key.1) {
+                            self.attempt_failed(
+                                &mut graph,
+                                id,
+                                "contract is not deployed or not ready after waiting".to_owned(),
+                            );
+                        } else {
+                            graph.set_ready(id);
+                        }
+                    }
+                    if let Some(fee_budget) = fee_budget {
+                        fee_budget.check(context).await?;
+                    }
+                }
+                Event::WakeUp => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// Adds nodes of all stages that are not confirmed in the push journal
+    /// and links them with their dependencies
+    async fn build_graph<B>(
+        &self,
+        context: &mut GitHelper<B>,
+        stages: &mut [&mut dyn DeployStage<B>],
+        to_check: &mut HashMap<PushStage, Vec<usize>>,
+    ) -> anyhow::Result<PushGraph>
+    where
+        B: BlockchainService + 'static,
+    {
+        let database = context.get_db()?;
+        let mut graph = PushGraph::default();
+        for stage in stages.iter_mut() {
+            let kind = stage.stage();
+            for address in stage.expected() {
+                let key = (kind, address);
+                if graph.id(&key).is_some() {
+                    continue;
+                }
+                if database.is_confirmed(&key.1)? {
+                    tracing::trace!("{kind} {} is confirmed in the push journal, skip", key.1);
+                    continue;
+                }
+                let is_deploying = stage.tasks().contains(&key.1);
+                let id = graph.add_node(key);
+                if stage.deploys_on_discovery() {
+                    // deployment was spawned while objects were discovered,
+                    // the rest of the nodes were deployed on a previous run
+                    graph.nodes[id].attempts = 1;
+                    if is_deploying {
+                        graph.set_state(id, NodeState::Deploying);
+                    } else {
+                        graph.set_state(id, NodeState::Checking);
+                        to_check.entry(kind).or_default().push(id);
+                    }
+                }
+            }
+        }
+
+        let mut commit_addresses = HashMap::new();
+        let mut repo_contract = context.blockchain.repo_contract().clone();
+        for stage in stages.iter() {
+            let kind = stage.stage();
+            for address in stage.expected() {
+                let id = match graph.id(&(kind, address.clone())) {
+                    Some(id) => id,
+                    None => continue,
+                };
+                for dependency in stage.dependencies(&address) {
+                    let dependency = match dependency {
+                        Dependency::Contract(stage, address) => (stage, address),
+                        Dependency::Commit(commit_id) => {
+                            if !commit_addresses.contains_key(&commit_id) {
+                                let commit_address = get_commit_address(
+                                    context.blockchain.client(),
+                                    &mut repo_contract,
+                                    &commit_id,
+                                )
+                                .await?;
+                                commit_addresses
+                                    .insert(commit_id.clone(), String::from(commit_address));
+                            }
+                            (PushStage::Commits, commit_addresses[&commit_id].clone())
+                        }
+                    };
+                    graph.add_dependency(id, &dependency);
+                }
+            }
+        }
+        Ok(graph)
+    }
+
+    fn attempt_failed(&mut self, graph: &mut PushGraph, id: usize, reason: String) {
+        let (stage, address) = graph.nodes[id].key.clone();
+        let attempts = graph.nodes[id].attempts;
+        if attempts < self.max_attempts {
+            let delay = self.retry_policy.delay(attempts as usize);
+            tracing::trace!(
+                "{stage} {address} will be redeployed in {delay:?}, attempt {attempts}: {reason}"
+            );
+            stats::record_retry();
+            graph.retry_at(id, Instant::now() + delay);
+            return;
+        }
+        tracing::trace!("{stage} {address} failed: {reason}");
+        self.failures.push(NodeFailure {
+            stage,
+            address,
+            attempts,
+            reason,
+        });
+        for skipped in graph.set_failed(id) {
+            self.skipped.push(graph.nodes[skipped].key.clone());
+        }
+    }
+
+    /// setCommit can be sent only when every node of the DAG is deployed
+    pub fn ensure_all_deployed(&self) -> anyhow::Result<()> {
+        if self.failures.is_empty() {
+            return Ok(());
+        }
+        let mut report = self
+            .failures
+            .iter()
+            .map(|failure| format!("  {failure}"))
+            .collect::<Vec<String>>()
+            .join("\n");
+        if !self.skipped.is_empty() {
+            report.push_str(&format!(
+                "\n{} contract(s) that depend on them were not deployed",
+                self.skipped.len()
+            ));
+        }
+        anyhow::bail!(
+            "Failed to deploy {} contract(s):\n{report}",
+            self.failures.len()
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn key(stage: PushStage, address: &str) -> NodeKey {
+        (stage, address.to_owned())
+    }
+
+    #[test]
+    fn ensure_retry_delay_grows_and_is_capped() {
+        let policy = RetryPolicy::default_for(Operation::Redeploy);
+        let max_delay = Duration::from_secs(30);
+        assert_eq!(policy.delay(1), Duration::from_secs(1));
+        assert_eq!(policy.delay(2), Duration::from_secs(2));
+        assert_eq!(policy.delay(3), Duration::from_secs(4));
+        assert_eq!(policy.delay(10), max_delay);
+        assert_eq!(policy.delay(i32::MAX as usize), max_delay);
+    }
+
+    #[test]
+    fn ensure_nodes_are_deployed_when_dependencies_are_ready() {
+        let mut graph = PushGraph::default();
+        let tree_a = graph.add_node(key(PushStage::Trees, "tree_a"));
+        let tree_b = graph.add_node(key(PushStage::Trees, "tree_b"));
+        let commit_a = graph.add_node(key(PushStage::Commits, "commit_a"));
+        let commit_b = graph.add_node(key(PushStage::Commits, "commit_b"));
+        let diff_a = graph.add_node(key(PushStage::Diffs, "diff_a"));
+        graph.add_dependency(commit_a, &key(PushStage::Trees, "tree_a"));
+        graph.add_dependency(commit_b, &key(PushStage::Trees, "tree_b"));
+        graph.add_dependency(diff_a, &key(PushStage::Commits, "commit_a"));
+        // dependencies that are not nodes are deployed already
+        graph.add_dependency(diff_a, &key(PushStage::Commits, "deployed"));
+
+        let now = Instant::now();
+        assert_eq!(graph.start_deploying(now, 1), vec![tree_a]);
+        assert_eq!(graph.start_deploying(now, usize::MAX), vec![tree_b]);
+        assert_eq!(graph.count(NodeState::Deploying), 2);
+        assert!(graph.start_deploying(now, usize::MAX).is_empty());
+
+        // commit_a doesn't wait for tree_b
+        graph.set_ready(tree_a);
+        assert_eq!(graph.start_deploying(now, usize::MAX), vec![commit_a]);
+        graph.set_ready(commit_a);
+        assert_eq!(graph.start_deploying(now, usize::MAX), vec![diff_a]);
+        graph.set_ready(diff_a);
+        assert!(!graph.is_finished());
+        graph.set_ready(tree_b);
+        assert_eq!(graph.start_deploying(now, usize::MAX), vec![commit_b]);
+        graph.set_ready(commit_b);
+        assert!(graph.is_finished());
+        assert_eq!(graph.count(NodeState::Ready), 5);
+        assert_eq!(graph.next_attempt_at(), None);
+    }
+
+    #[test]
+    fn ensure_retried_nodes_wait_for_their_time() {
+        let mut graph = PushGraph::default();
+        let tree = graph.add_node(key(PushStage::Trees, "tree"));
+        let now = Instant::now();
+        assert_eq!(graph.start_deploying(now, usize::MAX), vec![tree]);
+
+        let at = now + Duration::from_secs(60);
+        graph.retry_at(tree, at);
+        assert_eq!(graph.count(NodeState::Waiting), 1);
+        assert_eq!(graph.next_attempt_at(), Some(at));
+        assert!(graph.start_deploying(now, usize::MAX).is_empty());
+        assert_eq!(graph.start_deploying(at, usize::MAX), vec![tree]);
+        assert_eq!(graph.nodes[tree].attempts, 2);
+    }
+
+    #[test]
+    fn ensure_failed_nodes_skip_their_dependents() {
+        let mut scheduler = PushScheduler::new(Arc::new(Semaphore::new(1)));
+        scheduler.max_attempts = 2;
+        scheduler.retry_policy.initial_delay = Duration::ZERO;
+        let mut graph = PushGraph::default();
+        let tree = graph.add_node(key(PushStage::Trees, "0:01"));
+        let commit = graph.add_node(key(PushStage::Commits, "0:02"));
+        let snapshot = graph.add_node(key(PushStage::Snapshots, "0:03"));
+        let other = graph.add_node(key(PushStage::Diffs, "0:04"));
+        graph.add_dependency(commit, &key(PushStage::Trees, "0:01"));
+        graph.add_dependency(snapshot, &key(PushStage::Commits, "0:02"));
+
+        assert_eq!(graph.start_deploying(Instant::now(), 1), vec![tree]);
+        scheduler.attempt_failed(&mut graph, tree, "trees inner: timeout".to_owned());
+        assert_eq!(graph.nodes[tree].state, NodeState::Waiting);
+        assert!(scheduler.ensure_all_deployed().is_ok());
+
+        assert_eq!(graph.start_deploying(Instant::now(), 1), vec![tree]);
+        scheduler.attempt_failed(&mut graph, tree, "trees inner: timeout".to_owned());
+        assert_eq!(graph.nodes[commit].state, NodeState::Failed);
+        assert_eq!(graph.nodes[snapshot].state, NodeState::Failed);
+        assert_eq!(graph.nodes[other].state, NodeState::Waiting);
+        assert_eq!(graph.count(NodeState::Failed), 3);
+        assert_eq!(
+            graph.start_deploying(Instant::now(), usize::MAX),
+            vec![other]
+        );
+
+        let report = scheduler.ensure_all_deployed().unwrap_err().to_string();
+        assert!(report.contains("Failed to deploy 1 contract(s)"));
+        assert!(report.contains("tree 0:01 failed after 2 attempt(s): trees inner: timeout"));
+        assert!(report.contains("2 contract(s) that depend on them were not deployed"));
+    }
+}
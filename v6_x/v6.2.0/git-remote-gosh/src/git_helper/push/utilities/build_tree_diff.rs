@@ -6,16 +6,29 @@ use git_repository::{self, Repository};
 use git_traverse::tree::recorder;
 
 use std::{
-    collections::{HashMap},
+    collections::{HashMap, HashSet},
+    io::Write,
+    process::{Command, Stdio},
     str::FromStr,
     vec::Vec,
 };
 
+// Minimal share of the common content for a deleted and an added file
+// to be treated as a rename, in percents (git uses the same default)
+const RENAME_SIMILARITY_THRESHOLD: usize = 50;
+// Inexact rename detection compares every deleted file with every added one
+const RENAME_LIMIT: usize = 1000;
+// Files bigger than that are matched only by exact content
+const RENAME_MAX_FILE_SIZE: usize = 1 << 20;
+
 pub struct TreeDiff {
     pub added: Vec<recorder::Entry>,
     pub deleted: Vec<recorder::Entry>,
     // updated: from -> to
     pub updated: Vec<(recorder::Entry, recorder::Entry)>,
+    // copied or moved: source in the original tree -> added file.
+    // Sources of moved files are listed in `deleted` as well
+    pub copied: Vec<(recorder::Entry, recorder::Entry)>,
 }
 
 impl TreeDiff {
@@ -24,6 +37,7 @@ impl TreeDiff {
             added: vec![],
             deleted: vec![],
             updated: vec![],
+            copied: vec![],
         }
     }
 }
@@ -88,6 +102,12 @@ pub fn build_tree_diff(
 
     let mut next_files_state = all_files(repository, next_tree_root_id)?;
 
+    // blob -> files of the original tree that can be a source of a copy
+    let mut original_blobs: HashMap<ObjectId, Vec<recorder::Entry>> = HashMap::new();
+    for entry in original_files_state.values() {
+        original_blobs.entry(entry.oid).or_default().push(entry.clone());
+    }
+
     let mut tree_diff = TreeDiff::new();
     for next_tree_entry in next_files_state.drain(..) {
         let full_path = next_tree_entry.filepath.to_string();
@@ -102,5 +122,243 @@ pub fn build_tree_diff(
         }
     }
     tree_diff.deleted = original_files_state.drain().map(|e| e.1).collect();
+    detect_copies(repository, &mut tree_diff, &original_blobs)?;
     Ok(tree_diff)
 }
+
+/// Moves added files that reuse content of the original tree from `added` to `copied`.
+/// Exact copies are searched among all original files, moves with changes only
+/// among the deleted ones.
+fn detect_copies(
+    repository: &Repository,
+    tree_diff: &mut TreeDiff,
+    original_blobs: &HashMap<ObjectId, Vec<recorder::Entry>>,
+) -> anyhow::Result<()> {
+    let deleted_paths: HashSet<String> = tree_diff
+        .deleted
+        .iter()
+        .map(|e| e.filepath.to_string())
+        .collect();
+    let mut added = vec![];
+    for entry in tree_diff.added.drain(..) {
+        match original_blobs.get(&entry.oid) {
+            Some(sources) => {
+                // prefer the moved file over the one that is still in place
+                let source = sources
+                    .iter()
+                    .find(|source| deleted_paths.contains(&source.filepath.to_string()))
+                    .unwrap_or(&sources[0]);
+                tracing::trace!("detected copy: {} -> {}", source.filepath, entry.filepath);
+                tree_diff.copied.push((source.clone(), entry));
+            }
+            None => added.push(entry),
+        }
+    }
+
+    let exact_sources: HashSet<String> = tree_diff
+        .copied
+        .iter()
+        .map(|(source, _)| source.filepath.to_string())
+        .collect();
+    let candidates: Vec<&recorder::Entry> = tree_diff
+        .deleted
+        .iter()
+        .filter(|e| !exact_sources.contains(&e.filepath.to_string()))
+        .collect();
+    if candidates.is_empty()
+        || added.is_empty()
+        || candidates.len() * added.len() > RENAME_LIMIT * RENAME_LIMIT
+    {
+        tree_diff.added = added;
+        return Ok(());
+    }
+
+    let oids: Vec<ObjectId> = candidates
+        .iter()
+        .map(|e| e.oid)
+        .chain(added.iter().map(|e| e.oid))
+        .collect();
+    let sizes = object_sizes(repository, &oids)?;
+    let read_blob = |oid: ObjectId| -> anyhow::Result<Option<Vec<u8>>> {
+        match sizes.get(&oid) {
+            Some(size) if *size <= RENAME_MAX_FILE_SIZE => {
+                Ok(Some(repository.find_object(oid)?.data.to_vec()))
+            }
+            _ => Ok(None),
+        }
+    };
+    let mut sources = vec![];
+    for source in candidates {
+        if let Some(data) = read_blob(source.oid)? {
+            sources.push((source, data));
+        }
+    }
+    let mut scores = vec![];
+    for (target_index, target) in added.iter().enumerate() {
+        let data = match read_blob(target.oid)? {
+            Some(data) => data,
+            None => continue,
+        };
+        for (source_index, (_, source_data)) in sources.iter().enumerate() {
+            let score = similarity(source_data, &data);
+            if score >= RENAME_SIMILARITY_THRESHOLD {
+                scores.push((score, source_index, target_index));
+            }
+        }
+    }
+    // the best pairs win, every file takes part in one rename at most
+    scores.sort_by(|a, b| b.0.cmp(&a.0).then(a.1.cmp(&b.1)).then(a.2.cmp(&b.2)));
+    let mut used_sources = HashSet::new();
+    let mut renamed_targets = HashMap::new();
+    for (score, source_index, target_index) in scores {
+        if used_sources.contains(&source_index) || renamed_targets.contains_key(&target_index) {
+            continue;
+        }
+        used_sources.insert(source_index);
+        renamed_targets.insert(target_index, source_index);
+        tracing::trace!(
+            "detected rename ({score}%): {} -> {}",
+            sources[source_index].0.filepath,
+            added[target_index].filepath
+        );
+    }
+    for (target_index, entry) in added.into_iter().enumerate() {
+        match renamed_targets.get(&target_index) {
+            Some(source_index) => {
+                let source = sources[*source_index].0.clone();
+                tree_diff.copied.push((source, entry));
+            }
+            None => tree_diff.added.push(entry),
+        }
+    }
+    Ok(())
+}
+
+/// Share of the common lines of two files weighted by their size, in percents
+/// Sizes of the objects from their headers, the content is not loaded
+fn object_sizes(
+    repository: &Repository,
+    oids: &[ObjectId],
+) -> anyhow::Result<HashMap<ObjectId, usize>> {
+    let mut child = Command::new("git")
+        .arg("--git-dir")
+        .arg(repository.path())
+        .args(["cat-file", "--batch-check=%(objectname) %(objectsize)"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+    let input: String = oids.iter().map(|oid| format!("{oid}\n")).collect();
+    let mut stdin = child
+        .stdin
+        .take()
+        .ok_or_else(|| anyhow::format_err!("Failed to open stdin of git cat-file"))?;
+    // the output is read meanwhile, so the pipes never fill up
+    let writer = std::thread::spawn(move || stdin.write_all(input.as_bytes()));
+    let output = child.wait_with_output()?;
+    writer
+        .join()
+        .map_err(|_| anyhow::format_err!("Failed to write to git cat-file"))??;
+    if !output.status.success() {
+        anyhow::bail!(
+            "git cat-file failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    let mut sizes = HashMap::new();
+    for line in String::from_utf8_lossy(&output.stdout).lines() {
+        // missing objects are reported as `<oid> missing`
+        if let Some((oid, size)) = line.split_once(' ') {
+            if let (Ok(oid), Ok(size)) = (ObjectId::from_str(oid), size.parse()) {
+                sizes.insert(oid, size);
+            }
+        }
+    }
+    Ok(sizes)
+}
+
+fn similarity(a: &[u8], b: &[u8]) -> usize {
+    let max_len = std::cmp::max(a.len(), b.len());
+    if max_len == 0 {
+        return 100;
+    }
+    let mut lines: HashMap<&[u8], usize> = HashMap::new();
+    for line in a.split_inclusive(|c| *c == b'\n') {
+        *lines.entry(line).or_default() += 1;
+    }
+    let mut common = 0;
+    for line in b.split_inclusive(|c| *c == b'\n') {
+        if let Some(count) = lines.get_mut(line) {
+            if *count > 0 {
+                *count -= 1;
+                common += line.len();
+            }
+        }
+    }
+    common * 100 / max_len
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::git_helper::test_utils::setup_repo;
+
+    #[test]
+    fn ensure_similarity_is_calculated() {
+        assert_eq!(similarity(b"", b""), 100);
+        assert_eq!(similarity(b"a\nb\n", b"a\nb\n"), 100);
+        assert_eq!(similarity(b"a\nb\n", b"c\nd\n"), 0);
+        assert_eq!(similarity(b"a\nb\nc\nd\n", b"a\nb\nc\nx\n"), 75);
+    }
+
+    #[test]
+    fn ensure_renames_and_copies_are_detected() {
+        let repo = setup_repo("test_rename_detection", "tests/fixtures/make_rename_repo.sh").unwrap();
+        let original = repo
+            .find_reference("refs/tags/before")
+            .unwrap()
+            .into_fully_peeled_id()
+            .unwrap()
+            .detach();
+        let next = repo.head_commit().unwrap().id;
+        let tree_diff = build_tree_diff_from_commits(&repo, Some(original), next).unwrap();
+
+        let mut copied: Vec<(String, String)> = tree_diff
+            .copied
+            .iter()
+            .map(|(source, target)| (source.filepath.to_string(), target.filepath.to_string()))
+            .collect();
+        copied.sort();
+        assert_eq!(
+            copied,
+            vec![
+                ("dir/one".to_owned(), "moved/one".to_owned()),
+                ("dir/two".to_owned(), "moved/two".to_owned()),
+                ("edited".to_owned(), "edited_and_moved".to_owned()),
+                ("kept".to_owned(), "kept_copy".to_owned()),
+            ]
+        );
+        let added: Vec<String> = tree_diff
+            .added
+            .iter()
+            .map(|e| e.filepath.to_string())
+            .collect();
+        assert_eq!(added, vec!["brand_new".to_owned()]);
+        assert_eq!(tree_diff.deleted.len(), 3);
+    }
+
+    #[test]
+    fn ensure_object_sizes_are_read_from_headers() {
+        let repo = setup_repo("test_object_sizes", "tests/fixtures/make_rename_repo.sh").unwrap();
+        let files = all_files(&repo, repo.head_commit().unwrap().tree().unwrap().id).unwrap();
+        let missing = ObjectId::from_str("1111111111111111111111111111111111111111").unwrap();
+        let mut oids: Vec<ObjectId> = files.iter().map(|e| e.oid).collect();
+        oids.push(missing);
+        let sizes = object_sizes(&repo, &oids).unwrap();
+        assert!(!sizes.contains_key(&missing));
+        for file in files {
+            let data = repo.find_object(file.oid).unwrap().data.to_vec();
+            assert_eq!(sizes[&file.oid], data.len());
+        }
+    }
+}
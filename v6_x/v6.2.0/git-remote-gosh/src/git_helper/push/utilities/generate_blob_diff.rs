@@ -1,14 +1,31 @@
-use diffy::create_patch_bytes;
+use diffy::{create_patch_bytes, DiffOptions};
 use git_hash::ObjectId;
 use git_odb::FindExt;
 use git_repository::OdbHandle;
 
+use super::ipfs_content::is_binary;
+
 pub struct GenerateBlobDiffResult {
     pub original: Vec<u8>,
     pub patch: Vec<u8>,
     pub after_patch: Vec<u8>,
 }
 
+/// Creates a patch applied by the snapshot contract.
+/// Binary content is split into segments by `\n` bytes just as text is split
+/// into lines, but its segments are long and hunks are located by position,
+/// so context is omitted to keep byte patches compact
+pub fn create_blob_patch(prev_content: &[u8], next_content: &[u8]) -> Vec<u8> {
+    if is_binary(prev_content) || is_binary(next_content) {
+        DiffOptions::new()
+            .set_context_len(0)
+            .create_patch_bytes(prev_content, next_content)
+            .to_bytes()
+    } else {
+        create_patch_bytes(prev_content, next_content).to_bytes()
+    }
+}
+
 #[instrument(level = "debug", skip(odb))]
 pub async fn generate_blob_diff(
     odb: &OdbHandle,
@@ -26,7 +43,7 @@ pub async fn generate_blob_diff(
         None => &blob_to_buffer,
         Some(blob_id_to) => odb.find_blob(blob_id_to, &mut blob_to_buffer)?.data,
     };
-    let diff: Vec<u8> = create_patch_bytes(prev_content, next_content).to_bytes();
+    let diff = create_blob_patch(prev_content, next_content);
 
     Ok(GenerateBlobDiffResult {
         original: prev_content.to_vec(),
@@ -34,3 +51,45 @@ pub async fn generate_blob_diff(
         after_patch: next_content.to_vec(),
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn binary_blob(len: usize, mut seed: u32) -> Vec<u8> {
+        (0..len)
+            .map(|_| {
+                seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+                (seed >> 16) as u8
+            })
+            .collect()
+    }
+
+    #[test]
+    fn ensure_binary_patch_roundtrips() {
+        let original = binary_blob(8 * 1024, 42);
+        let mut modified = original.clone();
+        modified[100] ^= 0xff;
+        modified.splice(5000..5010, [0u8, 159, 146, 150]);
+        modified.extend_from_slice(&[0xde, 0xad, 0xbe, 0xef]);
+
+        let patch = create_blob_patch(&original, &modified);
+        assert!(patch.len() < original.len() / 2);
+
+        let patch = diffy::Patch::from_bytes(&patch).unwrap();
+        assert_eq!(diffy::apply_bytes(&original, &patch).unwrap(), modified);
+        // restore_blobs walks diffs backwards
+        assert_eq!(
+            diffy::apply_bytes(&modified, &patch.reverse()).unwrap(),
+            original
+        );
+    }
+
+    #[test]
+    fn ensure_binary_patch_from_empty_blob() {
+        let content = binary_blob(1024, 7);
+        let patch = create_blob_patch(&[], &content);
+        let patch = diffy::Patch::from_bytes(&patch).unwrap();
+        assert_eq!(diffy::apply_bytes(&[], &patch).unwrap(), content);
+    }
+}
@@ -1,8 +1,48 @@
+// Max size of a non-UTF-8 blob that is kept on chain and updated with byte patches
+const GOSH_BINARY_ON_CHAIN_THRESHOLD: &str = "GOSH_BINARY_ON_CHAIN_THRESHOLD";
+
+const DEFAULT_BINARY_ON_CHAIN_THRESHOLD: usize = 16 * 1024;
+
+fn get_binary_on_chain_threshold() -> usize {
+    std::env::var(GOSH_BINARY_ON_CHAIN_THRESHOLD)
+        .ok()
+        .and_then(|num| num.parse::<usize>().ok())
+        .map(|num| std::cmp::min(num, crate::config::IPFS_CONTENT_THRESHOLD))
+        .unwrap_or(DEFAULT_BINARY_ON_CHAIN_THRESHOLD)
+}
+
+pub fn is_binary(content: &[u8]) -> bool {
+    std::str::from_utf8(content).is_err()
+}
+
 pub fn is_going_to_ipfs(new_content: &[u8]) -> bool {
-    let mut is_going_to_ipfs = new_content.len() > crate::config::IPFS_CONTENT_THRESHOLD;
-    if !is_going_to_ipfs {
-        is_going_to_ipfs = std::str::from_utf8(new_content).is_err();
-    };
+    if new_content.len() > crate::config::IPFS_CONTENT_THRESHOLD {
+        return true;
+    }
+    // Snapshot contract applies patches to raw bytes, so small binaries
+    // stay on chain as well
+    is_binary(new_content) && new_content.len() > get_binary_on_chain_threshold()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ensure_small_binaries_stay_on_chain() {
+        let icon: Vec<u8> = (0..=255u8).cycle().take(4 * 1024).collect();
+        assert!(is_binary(&icon));
+        assert!(!is_going_to_ipfs(&icon));
+
+        let archive: Vec<u8> = (0..=255u8)
+            .cycle()
+            .take(DEFAULT_BINARY_ON_CHAIN_THRESHOLD + 1)
+            .collect();
+        assert!(is_going_to_ipfs(&archive));
 
-    is_going_to_ipfs
+        let text = vec![b'a'; DEFAULT_BINARY_ON_CHAIN_THRESHOLD + 1];
+        assert!(!is_going_to_ipfs(&text));
+        let text = vec![b'a'; crate::config::IPFS_CONTENT_THRESHOLD + 1];
+        assert!(is_going_to_ipfs(&text));
+    }
 }
@@ -7,6 +7,7 @@ macro_rules! provide {
 }
 
 provide!(generate_blob_diff);
+pub use generate_blob_diff::create_blob_patch;
 provide!(build_tree_diff);
 pub use build_tree_diff::{all_files, build_tree_diff_from_commits};
 
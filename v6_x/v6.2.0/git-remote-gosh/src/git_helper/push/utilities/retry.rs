@@ -1,6 +1,69 @@
-use std::time::Duration;
-use tokio_retry::strategy::FibonacciBackoff;
+use crate::blockchain::{
+    error::{exit_codes, ContractError},
+    user_wallet::WalletError,
+};
+use crate::config::retry::{self, Operation};
+use crate::ipfs::IpfsError;
+use std::future::Future;
 
-pub fn default_retry_strategy() -> impl Iterator<Item = Duration> {
-    FibonacciBackoff::from_millis(100).take(20)
+// contract failures that are the same on every attempt
+const FATAL_EXIT_CODES: &[i32] = &[
+    exit_codes::ERR_NOT_OWNER,
+    exit_codes::ERR_BRANCH_PROTECTED,
+    exit_codes::ERR_WALLET_LIMITED,
+    exit_codes::ERR_TOMBSTONE,
+    exit_codes::ERR_NEED_SMV,
+];
+
+/// Missing wallets, IPFS failures that outlived the IPFS retries and
+/// contract failures listed in `FATAL_EXIT_CODES` are not retried
+pub fn is_retryable(e: &anyhow::Error) -> bool {
+    if e.is::<WalletError>() || e.is::<IpfsError>() {
+        return false;
+    }
+    let is_fatal_contract_error = e.chain().any(|cause| {
+        cause
+            .downcast_ref::<ContractError>()
+            .is_some_and(|error| FATAL_EXIT_CODES.contains(&error.code))
+    });
+    !is_fatal_contract_error
+}
+
+/// Retries a deploy or a call of the push with the `blockchain` policy
+pub async fn retry_blockchain<A, F, T>(action: A) -> anyhow::Result<T>
+where
+    A: FnMut() -> F,
+    F: Future<Output = anyhow::Result<T>>,
+{
+    retry::policy(Operation::Blockchain)
+        .run(action, is_retryable)
+        .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blockchain::{BlockchainContractAddress, ContractKind};
+
+    #[test]
+    fn ensure_only_transient_errors_are_retryable() {
+        let address = BlockchainContractAddress::new("0:01");
+        let contract_error = |code| {
+            anyhow::Error::from(ContractError::new(
+                Some(ContractKind::Repo),
+                &address,
+                "deleteBranch",
+                code,
+            ))
+        };
+        let protected = contract_error(exit_codes::ERR_BRANCH_PROTECTED);
+        assert!(!is_retryable(&protected));
+        let not_owner = contract_error(exit_codes::ERR_NOT_OWNER).context("push failed");
+        assert!(!is_retryable(&not_owner));
+        let not_ready = contract_error(exit_codes::ERR_REPOSITORY_NOT_READY);
+        assert!(is_retryable(&not_ready));
+        let ipfs_error = anyhow::Error::from(IpfsError::SaveToIpfsError);
+        assert!(!is_retryable(&ipfs_error));
+        assert!(is_retryable(&anyhow::format_err!("timeout")));
+    }
 }
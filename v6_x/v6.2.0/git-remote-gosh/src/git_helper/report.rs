@@ -0,0 +1,209 @@
+//! Summaries of pushes and fetches, printed after every operation and
+//! written as a JSON array to `GOSH_REPORT_PATH` when it is set.
+
+use crate::utilities::stats::TransferCounters;
+use once_cell::sync::Lazy;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+const GOSH_REPORT_PATH: &str = "GOSH_REPORT_PATH";
+
+static REPORTS: Lazy<Mutex<Vec<OperationReport>>> = Lazy::new(|| Mutex::new(vec![]));
+
+fn get_report_path() -> Option<String> {
+    std::env::var(GOSH_REPORT_PATH)
+        .ok()
+        .filter(|path| !path.is_empty())
+}
+
+fn as_millis(duration: Duration) -> u64 {
+    duration.as_millis() as u64
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ObjectCounts {
+    pub commits: u64,
+    pub trees: u64,
+    pub snapshots: u64,
+    pub diffs: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PhaseReport {
+    pub name: String,
+    pub elapsed_ms: u64,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct FeesReport {
+    /// nanotokens
+    pub spent: u64,
+    pub calls: u64,
+    /// nanotokens
+    pub call_fees: u64,
+    pub messages_sent: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct OperationReport {
+    pub operation: String,
+    #[serde(rename = "ref")]
+    pub git_ref: String,
+    pub objects: ObjectCounts,
+    pub ipfs_uploads: u64,
+    pub ipfs_downloads: u64,
+    pub bytes_uploaded: u64,
+    pub bytes_downloaded: u64,
+    pub retries: u64,
+    pub cache_hits: u64,
+    pub divergences: u64,
+    pub account_cache_hits: u64,
+    pub account_cache_misses: u64,
+    pub elapsed_ms: u64,
+    pub phases: Vec<PhaseReport>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fees: Option<FeesReport>,
+}
+
+impl OperationReport {
+    pub fn summary(&self) -> String {
+        let phases = self
+            .phases
+            .iter()
+            .map(|phase| format!("{} {}ms", phase.name, phase.elapsed_ms))
+            .collect::<Vec<String>>()
+            .join(", ");
+        format!(
+            "{} {}: {} commits, {} trees, {} snapshots, {} diffs, {} IPFS uploads, {} IPFS downloads, {} bytes uploaded, {} bytes downloaded, {} retries, {} cache hits in {}ms ({phases})",
+            self.operation,
+            self.git_ref,
+            self.objects.commits,
+            self.objects.trees,
+            self.objects.snapshots,
+            self.objects.diffs,
+            self.ipfs_uploads,
+            self.ipfs_downloads,
+            self.bytes_uploaded,
+            self.bytes_downloaded,
+            self.retries,
+            self.cache_hits,
+            self.elapsed_ms,
+        )
+    }
+}
+
+/// Collects the report of a single operation
+pub struct ReportBuilder {
+    operation: &'static str,
+    git_ref: String,
+    started_at: Instant,
+    phase_started_at: Instant,
+    phases: Vec<PhaseReport>,
+    initial_counters: TransferCounters,
+    pub objects: ObjectCounts,
+    pub fees: Option<FeesReport>,
+}
+
+impl ReportBuilder {
+    pub fn start(operation: &'static str, git_ref: &str) -> Self {
+        let now = Instant::now();
+        Self {
+            operation,
+            git_ref: git_ref.to_owned(),
+            started_at: now,
+            phase_started_at: now,
+            phases: vec![],
+            initial_counters: TransferCounters::current(),
+            objects: ObjectCounts::default(),
+            fees: None,
+        }
+    }
+
+    /// Finishes the current phase. Time of phases with the same name is summed up
+    pub fn end_phase(&mut self, name: &str) {
+        let now = Instant::now();
+        let elapsed = as_millis(now - self.phase_started_at);
+        self.phase_started_at = now;
+        match self.phases.iter_mut().find(|phase| phase.name == name) {
+            Some(phase) => phase.elapsed_ms += elapsed,
+            None => self.phases.push(PhaseReport {
+                name: name.to_owned(),
+                elapsed_ms: elapsed,
+            }),
+        }
+    }
+
+    /// Snapshots and diffs loaded by fetch are taken from the transfer counters
+    pub fn finish(self) -> OperationReport {
+        let counters = TransferCounters::current().since(&self.initial_counters);
+        let mut objects = self.objects;
+        objects.snapshots += counters.snapshots_loaded;
+        objects.diffs += counters.diffs_loaded;
+        OperationReport {
+            operation: self.operation.to_owned(),
+            git_ref: self.git_ref,
+            objects,
+            ipfs_uploads: counters.ipfs_uploads,
+            ipfs_downloads: counters.ipfs_downloads,
+            bytes_uploaded: counters.bytes_uploaded,
+            bytes_downloaded: counters.bytes_downloaded,
+            retries: counters.retries,
+            cache_hits: counters.cache_hits,
+            divergences: counters.divergences,
+            account_cache_hits: counters.account_cache_hits,
+            account_cache_misses: counters.account_cache_misses,
+            elapsed_ms: as_millis(self.started_at.elapsed()),
+            phases: self.phases,
+            fees: self.fees,
+        }
+    }
+}
+
+/// Prints the summary of the operation and writes all reports of the run
+/// to `GOSH_REPORT_PATH`
+/// The operation has already succeeded at this point,
+/// so a report that can't be written doesn't fail it
+pub fn publish(report: OperationReport) {
+    eprintln!("{}", report.summary());
+    tracing::trace!("operation report: {report:?}");
+    let mut reports = REPORTS.lock().unwrap();
+    reports.push(report);
+    if let Some(path) = get_report_path() {
+        if let Err(e) = write_reports(&path, &reports) {
+            eprintln!("Warning: {e:#}");
+        }
+    }
+}
+
+fn write_reports(path: &str, reports: &[OperationReport]) -> anyhow::Result<()> {
+    let json = serde_json::to_string_pretty(reports)?;
+    std::fs::write(path, json)
+        .map_err(|e| anyhow::format_err!("Failed to write report to {path}: {e}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ensure_report_is_serialized() {
+        let mut builder = ReportBuilder::start("push", "refs/heads/main");
+        builder.objects.commits = 2;
+        builder.end_phase("trees");
+        builder.end_phase("commits");
+        builder.end_phase("trees");
+        let report = builder.finish();
+        assert_eq!(report.phases.len(), 2);
+        assert!(
+            report
+                .summary()
+                .starts_with("push refs/heads/main: 2 commits")
+        );
+
+        let json = serde_json::to_value(&report).unwrap();
+        assert_eq!(json["ref"], "refs/heads/main");
+        assert_eq!(json["objects"]["commits"], 2);
+        assert_eq!(json["phases"][0]["name"], "trees");
+        assert!(json.get("fees").is_none());
+    }
+}
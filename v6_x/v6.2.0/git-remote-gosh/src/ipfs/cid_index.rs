@@ -0,0 +1,263 @@
+//! CIDs of uploaded IPFS content by its sha256 and endpoint, kept for the
+//! current push in memory and for the following ones under `$GIT_DIR`.
+//! Parallel uploads of the same content wait for the first one.
+
+use once_cell::sync::Lazy;
+use rocksdb::{DBWithThreadMode, MultiThreaded};
+use std::collections::HashMap;
+use std::future::Future;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+const CID_INDEX_FOLDER_NAME: &str = "gosh_cid_index";
+
+// Flag that enables the check of the pin status before a CID recorded
+// by a previous push is reused
+const GOSH_IPFS_CHECK_PIN: &str = "GOSH_IPFS_CHECK_PIN";
+
+static CID_INDEX: Lazy<CidIndex> = Lazy::new(CidIndex::open);
+
+fn check_pin_enabled() -> bool {
+    std::env::var(GOSH_IPFS_CHECK_PIN)
+        .map(|value| value == "1" || value.eq_ignore_ascii_case("true"))
+        .unwrap_or(false)
+}
+
+pub fn cid_index() -> &'static CidIndex {
+    &CID_INDEX
+}
+
+/// Key of the content in the index. CIDs are valid only for the endpoint
+/// the content was uploaded to
+pub fn content_key(ipfs_endpoint: &str, content: &[u8]) -> String {
+    format!("{ipfs_endpoint}|{}", sha256::digest(content))
+}
+
+/// Lock of the upload in progress, removed from the index
+/// when the last task interested in the key is done
+struct InFlight<'a> {
+    index: &'a CidIndex,
+    key: &'a str,
+    lock: Arc<tokio::sync::Mutex<()>>,
+}
+
+impl Drop for InFlight<'_> {
+    fn drop(&mut self) {
+        let mut in_flight = self.index.in_flight.lock().unwrap();
+        // the map and this task hold the only references
+        if Arc::strong_count(&self.lock) == 2 {
+            in_flight.remove(self.key);
+        }
+    }
+}
+
+pub struct CidIndex {
+    // CIDs known in this process
+    known: Mutex<HashMap<String, String>>,
+    in_flight: Mutex<HashMap<String, Arc<tokio::sync::Mutex<()>>>>,
+    db: Option<DBWithThreadMode<MultiThreaded>>,
+}
+
+impl CidIndex {
+    fn open() -> Self {
+        let db = std::env::var("GIT_DIR")
+            .map_err(anyhow::Error::from)
+            .and_then(|git_dir| {
+                let mut path = PathBuf::from(git_dir);
+                path.push(CID_INDEX_FOLDER_NAME);
+                let mut db_options = rocksdb::Options::default();
+                db_options.create_if_missing(true);
+                DBWithThreadMode::<MultiThreaded>::open(&db_options, path)
+                    .map_err(|e| anyhow::format_err!("Failed to open CID index: {e}"))
+            })
+            .map_err(|e| {
+                // the index is an optimisation, e.g. another push may hold the lock
+                tracing::trace!("CID index is kept in memory only: {e}");
+                e
+            })
+            .ok();
+        Self::with_db(db)
+    }
+
+    fn with_db(db: Option<DBWithThreadMode<MultiThreaded>>) -> Self {
+        Self {
+            known: Mutex::new(HashMap::new()),
+            in_flight: Mutex::new(HashMap::new()),
+            db,
+        }
+    }
+
+    fn get_persisted(&self, key: &str) -> Option<String> {
+        let db = self.db.as_ref()?;
+        match db.get(key) {
+            Ok(value) => value.map(|v| String::from_utf8_lossy(&v).to_string()),
+            Err(e) => {
+                tracing::trace!("CID index read failed: {e}");
+                None
+            }
+        }
+    }
+
+    fn insert(&self, key: &str, cid: &str) {
+        self.known
+            .lock()
+            .unwrap()
+            .insert(key.to_owned(), cid.to_owned());
+        if let Some(db) = self.db.as_ref() {
+            if let Err(e) = db.put(key, cid) {
+                tracing::trace!("CID index write failed: {e}");
+            }
+        }
+    }
+
+    /// Returns the CID of the content uploaded earlier or uploads it.
+    /// CIDs recorded by previous pushes are checked with `is_pinned`
+    /// when `GOSH_IPFS_CHECK_PIN` is set
+    pub async fn get_or_upload<U, UF, P, PF>(
+        &self,
+        key: &str,
+        upload: U,
+        is_pinned: P,
+    ) -> anyhow::Result<String>
+    where
+        U: FnOnce() -> UF,
+        UF: Future<Output = anyhow::Result<String>>,
+        P: FnOnce(String) -> PF,
+        PF: Future<Output = anyhow::Result<bool>>,
+    {
+        self.get_or_upload_inner(key, upload, is_pinned, check_pin_enabled())
+            .await
+    }
+
+    async fn get_or_upload_inner<U, UF, P, PF>(
+        &self,
+        key: &str,
+        upload: U,
+        is_pinned: P,
+        check_pin: bool,
+    ) -> anyhow::Result<String>
+    where
+        U: FnOnce() -> UF,
+        UF: Future<Output = anyhow::Result<String>>,
+        P: FnOnce(String) -> PF,
+        PF: Future<Output = anyhow::Result<bool>>,
+    {
+        let in_flight = InFlight {
+            index: self,
+            key,
+            lock: self
+                .in_flight
+                .lock()
+                .unwrap()
+                .entry(key.to_owned())
+                .or_default()
+                .clone(),
+        };
+        let _guard = in_flight.lock.lock().await;
+
+        if let Some(cid) = self.known.lock().unwrap().get(key).cloned() {
+            tracing::trace!("CID index: reuse {cid} uploaded in this push");
+            return Ok(cid);
+        }
+        if let Some(cid) = self.get_persisted(key) {
+            let reuse = if check_pin {
+                is_pinned(cid.clone()).await.unwrap_or_else(|e| {
+                    tracing::trace!("CID index: failed to check pin of {cid}: {e}");
+                    false
+                })
+            } else {
+                true
+            };
+            if reuse {
+                tracing::trace!("CID index: reuse {cid} uploaded earlier");
+                self.known
+                    .lock()
+                    .unwrap()
+                    .insert(key.to_owned(), cid.clone());
+                return Ok(cid);
+            }
+            tracing::trace!("CID index: {cid} is not pinned, upload again");
+        }
+        let cid = upload().await?;
+        self.insert(key, &cid);
+        Ok(cid)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[tokio::test]
+    async fn ensure_identical_content_is_uploaded_once() {
+        let index = Arc::new(CidIndex::with_db(None));
+        let uploads = Arc::new(AtomicUsize::new(0));
+        let key = content_key("http://ipfs", b"vendored file");
+
+        let mut handles = vec![];
+        for _ in 0..8 {
+            let index = index.clone();
+            let uploads = uploads.clone();
+            let key = key.clone();
+            handles.push(tokio::spawn(async move {
+                index
+                    .get_or_upload(
+                        &key,
+                        || async {
+                            uploads.fetch_add(1, Ordering::SeqCst);
+                            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+                            Ok("cid".to_owned())
+                        },
+                        |_| async { Ok(true) },
+                    )
+                    .await
+            }));
+        }
+        for handle in handles {
+            assert_eq!(handle.await.unwrap().unwrap(), "cid");
+        }
+        assert_eq!(uploads.load(Ordering::SeqCst), 1);
+        assert!(index.in_flight.lock().unwrap().is_empty());
+        assert_ne!(key, content_key("http://other-ipfs", b"vendored file"));
+    }
+
+    #[tokio::test]
+    async fn ensure_failed_upload_is_not_kept_in_flight() {
+        let index = CidIndex::with_db(None);
+        let result = index
+            .get_or_upload(
+                "key",
+                || async { anyhow::bail!("upload failed") },
+                |_| async { Ok(true) },
+            )
+            .await;
+        assert!(result.is_err());
+        assert!(index.in_flight.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn ensure_unpinned_cid_is_uploaded_again() {
+        let path = std::env::temp_dir().join(format!("gosh_cid_index_test_{}", std::process::id()));
+        let mut db_options = rocksdb::Options::default();
+        db_options.create_if_missing(true);
+        let db = DBWithThreadMode::<MultiThreaded>::open(&db_options, &path).unwrap();
+        db.put("key", "old_cid").unwrap();
+        let index = CidIndex::with_db(Some(db));
+
+        let cid = index
+            .get_or_upload_inner(
+                "key",
+                || async { Ok("new_cid".to_owned()) },
+                |_| async { Ok(false) },
+                true,
+            )
+            .await
+            .unwrap();
+        assert_eq!(cid, "new_cid");
+        assert_eq!(index.get_persisted("key").unwrap(), "new_cid");
+
+        drop(index);
+        DBWithThreadMode::<MultiThreaded>::destroy(&db_options, &path).unwrap();
+    }
+}
@@ -1,7 +1,7 @@
 use super::IpfsService;
 use crate::ipfs::service::FileLoad;
+use crate::utilities::stats;
 use async_trait::async_trait;
-use tokio_retry::Retry;
 
 #[async_trait]
 impl FileLoad for IpfsService {
@@ -10,14 +10,10 @@ impl FileLoad for IpfsService {
         tracing::debug!("load: cid={cid}");
         let url = format!("{}/ipfs/{cid}", self.ipfs_endpoint_address);
 
-        Retry::spawn(self.retry_strategy(), || async {
-            IpfsService::load_retriable(&self.http_client, &url)
-                .await
-                .map_err(|e| {
-                    tracing::warn!("Attempt failed with {:#?}", e);
-                    e
-                })
-        })
-        .await
+        let data = self
+            .with_retries(|| async { IpfsService::load_retriable(&self.http_client, &url).await })
+            .await?;
+        stats::record_ipfs_download(data.len());
+        Ok(data)
     }
 }
@@ -1,22 +1,20 @@
+pub mod cid_index;
 mod load;
 mod save;
 pub mod service;
 
+use crate::config::retry::{self, Operation, RetryPolicy};
 use reqwest::multipart;
 use reqwest_tracing::{OtelName, TracingMiddleware};
 use serde::Deserialize;
 use std::fmt::Debug;
-use std::{path::Path, time::Duration};
+use std::{future::Future, path::Path};
 use thiserror::Error;
 use tokio::fs::File;
-use tokio_retry::strategy::ExponentialBackoff;
 use tracing::Instrument;
 
 type MiddlewareHttpClient = reqwest_middleware::ClientWithMiddleware;
 
-static MAX_RETRIES: usize = 20;
-static MAX_RETRY_DURATION: Duration = Duration::from_secs(30);
-
 #[derive(Error, Debug)]
 pub enum IpfsError {
     #[error("Failed to access ipfs")]
@@ -29,10 +27,18 @@ struct SaveRes {
     hash: String,
 }
 
+#[derive(Debug, Deserialize)]
+struct PinLsRes {
+    #[serde(alias = "Keys")]
+    keys: std::collections::HashMap<String, serde_json::Value>,
+}
+
 #[derive(Builder, Debug, Clone)]
 pub struct IpfsService<HttpClient = MiddlewareHttpClient> {
     ipfs_endpoint_address: String,
     http_client: HttpClient,
+    #[builder(default = "retry::policy(Operation::Ipfs)")]
+    retry_policy: RetryPolicy,
 }
 
 pub trait IpfsConfig<HttpClient> {
@@ -65,17 +71,28 @@ pub fn build_ipfs(endpoint: &str) -> anyhow::Result<IpfsService> {
     Ok(ipfs_builder.build()?)
 }
 
+/// Missing local files and requests that can't be built, e.g. for a
+/// malformed endpoint, fail the same way on every attempt
+fn is_retryable(e: &anyhow::Error) -> bool {
+    if e.is::<std::io::Error>() {
+        return false;
+    }
+    let request_error = match e.downcast_ref::<reqwest_middleware::Error>() {
+        Some(reqwest_middleware::Error::Reqwest(e)) => Some(e),
+        Some(reqwest_middleware::Error::Middleware(_)) => None,
+        None => e.downcast_ref::<reqwest::Error>(),
+    };
+    !request_error.is_some_and(|e| e.is_builder())
+}
+
 impl IpfsService<MiddlewareHttpClient> {
-    fn retry_strategy(&self) -> impl Iterator<Item = Duration> {
-        // TODO: parametrize the retry strategy via builder and take from self
-        ExponentialBackoff::from_millis(100)
-            .factor(3)
-            .max_delay(MAX_RETRY_DURATION)
-            .map(|d| {
-                tracing::info!("Retry in {d:?} ...");
-                d
-            })
-            .take(MAX_RETRIES)
+    /// Runs the request with the `ipfs` policy, every attempt is traced
+    async fn with_retries<A, F, T>(&self, action: A) -> anyhow::Result<T>
+    where
+        A: FnMut() -> F,
+        F: Future<Output = anyhow::Result<T>>,
+    {
+        self.retry_policy.run(action, is_retryable).await
     }
 
     #[instrument(level = "info", skip_all)]
@@ -118,6 +135,22 @@ impl IpfsService<MiddlewareHttpClient> {
         IpfsService::save_body(cli, url, body).await
     }
 
+    #[instrument(level = "info", skip_all)]
+    async fn is_pinned_retriable(
+        cli: &MiddlewareHttpClient,
+        url: &str,
+        cid: &str,
+    ) -> anyhow::Result<bool> {
+        tracing::trace!("is_pinned_retriable: url={url}");
+        let response = cli.post(url).send().await?;
+        // ipfs responds with an error when the cid is not pinned
+        if !response.status().is_success() {
+            return Ok(false);
+        }
+        let response_body = response.json::<PinLsRes>().await?;
+        Ok(response_body.keys.contains_key(cid))
+    }
+
     #[instrument(level = "trace", skip_all)]
     async fn load_retriable(cli: &MiddlewareHttpClient, url: &str) -> anyhow::Result<Vec<u8>> {
         tracing::info!("loading from: {}", url);
@@ -135,9 +168,19 @@ impl IpfsService<MiddlewareHttpClient> {
 mod tests {
     use super::*;
 
+    #[test]
+    fn ensure_local_file_errors_are_not_retried() {
+        let missing = std::io::Error::from(std::io::ErrorKind::NotFound);
+        assert!(!is_retryable(&anyhow::Error::from(missing)));
+        assert!(is_retryable(&anyhow::format_err!("connection reset")));
+    }
+
     #[test]
     fn ser_test() {
         let s = r#"{"Hash": "1"}"#;
         assert!(serde_json::from_str::<SaveRes>(s).is_ok());
+        let s = r#"{"Keys": {"Qm1": {"Type": "recursive"}}}"#;
+        let res = serde_json::from_str::<PinLsRes>(s).unwrap();
+        assert!(res.keys.contains_key("Qm1"));
     }
 }
@@ -1,9 +1,9 @@
 use super::IpfsService;
 use crate::ipfs::service::FileSave;
 use crate::ipfs::IpfsError;
+use crate::utilities::stats;
 use async_trait::async_trait;
 use std::path::Path;
-use tokio_retry::Retry;
 
 #[async_trait]
 impl FileSave for IpfsService {
@@ -17,20 +17,19 @@ impl FileSave for IpfsService {
         );
 
         // TODO: add condition for expired cert error
-        let res = Retry::spawn(self.retry_strategy(), || async {
-            IpfsService::save_blob_retriable(&self.http_client, &url, blob)
-                .await
-                .map_err(|e| {
-                    tracing::warn!("Attempt failed with {:#?}", e);
-                    e
-                })
-        })
-        .await;
+        let res = self
+            .with_retries(|| async {
+                IpfsService::save_blob_retriable(&self.http_client, &url, blob).await
+            })
+            .await;
         match res {
             Err(_) => {
                 anyhow::bail!(IpfsError::SaveToIpfsError)
             }
-            Ok(res) => Ok(res),
+            Ok(res) => {
+                stats::record_ipfs_upload(blob.len());
+                Ok(res)
+            }
         }
     }
 
@@ -46,13 +45,23 @@ impl FileSave for IpfsService {
             self.ipfs_endpoint_address
         );
 
-        Retry::spawn(self.retry_strategy(), || async {
-            IpfsService::save_file_retriable(&self.http_client, &url, &path)
-                .await
-                .map_err(|e| {
-                    tracing::warn!("Attempt failed with {:#?}", e);
-                    e
-                })
+        self.with_retries(|| async {
+            IpfsService::save_file_retriable(&self.http_client, &url, &path).await
+        })
+        .await
+    }
+
+    #[instrument(level = "info", skip_all)]
+    async fn is_pinned(&self, cid: &str) -> anyhow::Result<bool> {
+        tracing::trace!("Checking pin status in IPFS: {cid}");
+
+        let url = format!(
+            "{}/api/v0/pin/ls?arg={cid}&type=recursive",
+            self.ipfs_endpoint_address
+        );
+
+        self.with_retries(|| async {
+            IpfsService::is_pinned_retriable(&self.http_client, &url, cid).await
         })
         .await
     }
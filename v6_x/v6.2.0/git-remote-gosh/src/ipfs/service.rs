@@ -12,6 +12,7 @@ pub trait FileLoad {
 pub trait FileSave {
     async fn save_blob(&self, blob: &[u8]) -> anyhow::Result<String>;
     async fn save_file(&self, path: impl AsRef<Path> + Send + Sync) -> anyhow::Result<String>;
+    async fn is_pinned(&self, cid: &str) -> anyhow::Result<bool>;
 }
 
 #[async_trait]
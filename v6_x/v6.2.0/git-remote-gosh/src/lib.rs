@@ -29,6 +29,7 @@ extern crate memcache;
 
 pub mod abi;
 pub mod blockchain;
+pub mod bundle;
 pub mod cache;
 pub mod config;
 pub(crate) mod database;
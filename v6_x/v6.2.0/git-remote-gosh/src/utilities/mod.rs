@@ -1,4 +1,5 @@
 pub mod env;
+pub mod stats;
 use crate::{blockchain::BlockchainContractAddress, config::Config};
 
 #[derive(Debug, Clone)]
@@ -0,0 +1,128 @@
+//! Process wide counters of the data moved by the helper, operations report
+//! the difference from a snapshot taken at their start.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+
+static IPFS_UPLOADS: AtomicU64 = AtomicU64::new(0);
+static IPFS_DOWNLOADS: AtomicU64 = AtomicU64::new(0);
+static BYTES_UPLOADED: AtomicU64 = AtomicU64::new(0);
+static BYTES_DOWNLOADED: AtomicU64 = AtomicU64::new(0);
+static SNAPSHOTS_LOADED: AtomicU64 = AtomicU64::new(0);
+static DIFFS_LOADED: AtomicU64 = AtomicU64::new(0);
+static RETRIES: AtomicU64 = AtomicU64::new(0);
+static CACHE_HITS: AtomicU64 = AtomicU64::new(0);
+static DIVERGENCES: AtomicU64 = AtomicU64::new(0);
+static ACCOUNT_CACHE_HITS: AtomicU64 = AtomicU64::new(0);
+static ACCOUNT_CACHE_MISSES: AtomicU64 = AtomicU64::new(0);
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TransferCounters {
+    pub ipfs_uploads: u64,
+    pub ipfs_downloads: u64,
+    /// Payloads sent to IPFS and to the blockchain, bytes
+    pub bytes_uploaded: u64,
+    /// Payloads received from IPFS and from the blockchain, bytes
+    pub bytes_downloaded: u64,
+    pub snapshots_loaded: u64,
+    pub diffs_loaded: u64,
+    pub retries: u64,
+    /// Objects taken from the local object cache instead of the network
+    pub cache_hits: u64,
+    /// Endpoints that didn't confirm a critical read
+    pub divergences: u64,
+    /// Getters run on account states from the account cache
+    pub account_cache_hits: u64,
+    /// Account states loaded from the network
+    pub account_cache_misses: u64,
+}
+
+impl TransferCounters {
+    pub fn current() -> Self {
+        Self {
+            ipfs_uploads: IPFS_UPLOADS.load(Ordering::SeqCst),
+            ipfs_downloads: IPFS_DOWNLOADS.load(Ordering::SeqCst),
+            bytes_uploaded: BYTES_UPLOADED.load(Ordering::SeqCst),
+            bytes_downloaded: BYTES_DOWNLOADED.load(Ordering::SeqCst),
+            snapshots_loaded: SNAPSHOTS_LOADED.load(Ordering::SeqCst),
+            diffs_loaded: DIFFS_LOADED.load(Ordering::SeqCst),
+            retries: RETRIES.load(Ordering::SeqCst),
+            cache_hits: CACHE_HITS.load(Ordering::SeqCst),
+            divergences: DIVERGENCES.load(Ordering::SeqCst),
+            account_cache_hits: ACCOUNT_CACHE_HITS.load(Ordering::SeqCst),
+            account_cache_misses: ACCOUNT_CACHE_MISSES.load(Ordering::SeqCst),
+        }
+    }
+
+    /// Counters gathered since the `earlier` snapshot
+    pub fn since(&self, earlier: &TransferCounters) -> TransferCounters {
+        TransferCounters {
+            ipfs_uploads: self.ipfs_uploads.saturating_sub(earlier.ipfs_uploads),
+            ipfs_downloads: self.ipfs_downloads.saturating_sub(earlier.ipfs_downloads),
+            bytes_uploaded: self.bytes_uploaded.saturating_sub(earlier.bytes_uploaded),
+            bytes_downloaded: self
+                .bytes_downloaded
+                .saturating_sub(earlier.bytes_downloaded),
+            snapshots_loaded: self
+                .snapshots_loaded
+                .saturating_sub(earlier.snapshots_loaded),
+            diffs_loaded: self.diffs_loaded.saturating_sub(earlier.diffs_loaded),
+            retries: self.retries.saturating_sub(earlier.retries),
+            cache_hits: self.cache_hits.saturating_sub(earlier.cache_hits),
+            divergences: self.divergences.saturating_sub(earlier.divergences),
+            account_cache_hits: self
+                .account_cache_hits
+                .saturating_sub(earlier.account_cache_hits),
+            account_cache_misses: self
+                .account_cache_misses
+                .saturating_sub(earlier.account_cache_misses),
+        }
+    }
+}
+
+pub fn record_ipfs_upload(bytes: usize) {
+    IPFS_UPLOADS.fetch_add(1, Ordering::SeqCst);
+    record_bytes_uploaded(bytes);
+}
+
+pub fn record_ipfs_download(bytes: usize) {
+    IPFS_DOWNLOADS.fetch_add(1, Ordering::SeqCst);
+    record_bytes_downloaded(bytes);
+}
+
+pub fn record_bytes_uploaded(bytes: usize) {
+    BYTES_UPLOADED.fetch_add(bytes as u64, Ordering::SeqCst);
+}
+
+pub fn record_bytes_downloaded(bytes: usize) {
+    BYTES_DOWNLOADED.fetch_add(bytes as u64, Ordering::SeqCst);
+}
+
+pub fn record_snapshot_loaded(bytes: usize) {
+    SNAPSHOTS_LOADED.fetch_add(1, Ordering::SeqCst);
+    record_bytes_downloaded(bytes);
+}
+
+pub fn record_diff_loaded(bytes: usize) {
+    DIFFS_LOADED.fetch_add(1, Ordering::SeqCst);
+    record_bytes_downloaded(bytes);
+}
+
+pub fn record_retry() {
+    RETRIES.fetch_add(1, Ordering::SeqCst);
+}
+
+pub fn record_cache_hit() {
+    CACHE_HITS.fetch_add(1, Ordering::SeqCst);
+}
+
+pub fn record_divergence() {
+    DIVERGENCES.fetch_add(1, Ordering::SeqCst);
+}
+
+pub fn record_account_cache_hit() {
+    ACCOUNT_CACHE_HITS.fetch_add(1, Ordering::SeqCst);
+}
+
+pub fn record_account_cache_miss() {
+    ACCOUNT_CACHE_MISSES.fetch_add(1, Ordering::SeqCst);
+}
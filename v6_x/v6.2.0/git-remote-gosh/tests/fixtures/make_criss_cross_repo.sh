@@ -0,0 +1,52 @@
+#!/bin/bash
+set -xeu -o pipefail
+
+# Criss-cross merge with commit dates going backwards,
+# so ordering by time alone puts children before parents
+#
+# base - x1 - x2 ---- m
+#      \    X     /
+#       \ y1 - y2 -'
+
+export GIT_AUTHOR_DATE GIT_COMMITTER_DATE
+at() {
+    GIT_AUTHOR_DATE="$1 +0000"
+    GIT_COMMITTER_DATE="$1 +0000"
+}
+
+git init -q
+git config user.name tester
+git config user.email tester@example.com
+
+git checkout -q -b main
+at 1600000000
+echo base >base
+git add base
+git commit -q -m base
+git tag base
+
+git checkout -q -b x
+at 1500000000
+echo x1 >x
+git add x
+git commit -q -m x1
+git tag x1
+
+git checkout -q -b y base
+at 1700000000
+echo y1 >y
+git add y
+git commit -q -m y1
+git tag y1
+
+git checkout -q x
+at 1400000000
+git merge -q --no-edit -m x2 y1
+
+git checkout -q y
+at 1300000000
+git merge -q --no-edit -m y2 x1
+
+git checkout -q main
+at 1200000000
+git merge -q --no-edit -m m x y
@@ -0,0 +1,35 @@
+#!/bin/bash
+set -xeu -o pipefail
+
+# base - a1 - a2 ---------.
+#      \ b1 - b2 ----------+- octopus - c4
+#      \ c1 - c2 ---------/
+#      \ m1 -------------/
+
+git init -q
+git config user.name tester
+git config user.email tester@example.com
+
+git checkout -q -b main
+echo base >base
+git add base
+git commit -q -m base
+git tag base
+
+for branch in a b c; do
+    git checkout -q -b "$branch" base
+    echo "${branch}1" >"$branch"
+    git add "$branch"
+    git commit -q -m "${branch}1"
+    echo "${branch}2" >>"$branch"
+    git commit -q -am "${branch}2"
+done
+
+git checkout -q main
+echo m1 >main
+git add main
+git commit -q -m m1
+
+git merge -q --no-edit a b c
+echo c4 >>main
+git commit -q -am c4
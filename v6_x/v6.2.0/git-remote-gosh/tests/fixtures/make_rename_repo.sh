@@ -0,0 +1,26 @@
+#!/bin/bash
+set -xeu -o pipefail
+
+git init -q
+git config user.name tester
+git config user.email tester@example.com
+
+git checkout -q -b main
+mkdir dir
+seq 1 100 >dir/one
+seq 101 200 >dir/two
+seq 201 300 >edited
+seq 301 400 >kept
+git add .
+git commit -q -m before
+git tag before
+
+# move the whole directory, move a file with changes,
+# copy a file and add an unrelated one
+git mv dir moved
+git mv edited edited_and_moved
+echo 301 >>edited_and_moved
+cp kept kept_copy
+echo "something else" >brand_new
+git add .
+git commit -q -m after